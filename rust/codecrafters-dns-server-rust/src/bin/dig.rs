@@ -0,0 +1,10 @@
+use codecrafters_dns_server::commands::dig;
+
+fn main() {
+  let args = std::env::args().skip(1).collect::<Vec<String>>();
+
+  if let Err(error) = dig(&args) {
+    eprintln!("dig: {}", error);
+    std::process::exit(1);
+  }
+}
@@ -0,0 +1,370 @@
+//! Zone checking in the manner of BIND's named-checkzone:
+//!
+//! ```text
+//! checkzone [-o <origin>] <zone file>
+//! ```
+//!
+//! The master file is parsed and its records are checked for what would make the zone break or
+//! misbehave once served. Every problem is printed on a line of its own as tab separated fields:
+//! severity (`error` or `warning`), check, owner name and a description, e.g. `error`,
+//! `cname-and-other-data`, `www.example.com.` and `CNAME next to A`.
+//!
+//! The exit status is 1 when any error is found. The origin defaults to the zone file's name.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::dnssec::canonical_cmp;
+use crate::messages::*;
+use crate::zones::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+  Error,
+  Warning,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Problem {
+  pub severity: Severity,
+  /// A short stable identifier of the check, e.g. `missing-glue`.
+  pub check: &'static str,
+  pub name: DomainName,
+  pub message: String,
+}
+
+impl Problem {
+  fn error(check: &'static str, name: &DomainName, message: String) -> Self {
+    Self {
+      severity: Severity::Error,
+      check,
+      name: name.clone(),
+      message,
+    }
+  }
+
+  fn warning(check: &'static str, name: &DomainName, message: String) -> Self {
+    Self {
+      severity: Severity::Warning,
+      ..Self::error(check, name, message)
+    }
+  }
+}
+
+/// The tab separated line the command prints.
+impl fmt::Display for Problem {
+  fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let severity = match self.severity {
+      Severity::Error => "error",
+      Severity::Warning => "warning",
+    };
+
+    write!(
+      formatter,
+      "{}\t{}\t{}\t{}",
+      severity, self.check, self.name, self.message
+    )
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CheckZoneOptions {
+  pub zone_file: PathBuf,
+  pub origin: DomainName,
+}
+
+impl CheckZoneOptions {
+  pub fn parse(args: &[String]) -> Result<Self, String> {
+    let (origin, zone_file) = match args {
+      [flag, origin, zone_file] if flag == "-o" => (Some(origin.as_str()), zone_file),
+      [zone_file] if !zone_file.starts_with('-') => (None, zone_file),
+      _ => return Err("Usage: checkzone [-o <origin>] <zone file>".to_string()),
+    };
+    let zone_file = PathBuf::from(zone_file);
+    let origin = match origin {
+      Some(origin) => origin.to_string(),
+      None => zone_file
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default(),
+    };
+
+    Ok(Self {
+      origin: parse_name(&origin, &DomainName::root())?,
+      zone_file,
+    })
+  }
+}
+
+/// The problems of the zone file, a syntax error being the only one when it does not parse.
+pub fn check_zone_file(options: &CheckZoneOptions) -> Vec<Problem> {
+  match load_zone_file(&options.zone_file, &options.origin) {
+    Ok(records) => check_zone(&options.origin, &records),
+    Err(error) => vec![Problem::error("syntax", &options.origin, error.to_string())],
+  }
+}
+
+/// The problems of the records of the zone at `origin`, sorted by name.
+pub fn check_zone(origin: &DomainName, records: &[AnswerProperties]) -> Vec<Problem> {
+  let mut problems = vec![];
+  let (inside, outside): (Vec<&AnswerProperties>, Vec<&AnswerProperties>) = records
+    .iter()
+    .partition(|record| record.domain_name.is_subdomain_of(origin));
+
+  let mut nodes: HashMap<DomainName, Vec<&AnswerProperties>> = HashMap::new();
+  for record in &inside {
+    nodes.entry(record.domain_name.to_lowercase()).or_default().push(record);
+  }
+  let at = |name: &DomainName| nodes.get(&name.to_lowercase()).map(Vec::as_slice).unwrap_or_default();
+  let has =
+    |name: &DomainName, record_type: RecordType| at(name).iter().any(|record| record.record_type == record_type);
+  let is_address = |record: &&AnswerProperties| [RecordType::Host, RecordType::Ipv6Host].contains(&record.record_type);
+
+  match at(origin)
+    .iter()
+    .filter(|record| record.record_type == RecordType::StartOfAuthority)
+    .count()
+  {
+    0 => problems.push(Problem::error(
+      "missing-soa",
+      origin,
+      "No SOA record at the apex".to_string(),
+    )),
+    1 => {}
+    count => problems.push(Problem::error(
+      "missing-soa",
+      origin,
+      format!("{} SOA records at the apex, expected one", count),
+    )),
+  }
+  if !has(origin, RecordType::NameServer) {
+    problems.push(Problem::error(
+      "missing-ns",
+      origin,
+      "No NS records at the apex".to_string(),
+    ));
+  }
+
+  let cuts: Vec<&DomainName> = nodes
+    .keys()
+    .filter(|name| !name.eq_ignore_case(origin) && has(name, RecordType::NameServer))
+    .collect();
+  let below_cut = |name: &DomainName| {
+    cuts
+      .iter()
+      .any(|cut| name.is_subdomain_of(cut) && !name.eq_ignore_case(cut))
+  };
+  let name_servers: HashSet<DomainName> = inside
+    .iter()
+    .filter_map(|record| match record.record_data() {
+      RecordData::NameServer(target) if record.record_type == RecordType::NameServer => Some(target.to_lowercase()),
+      _ => None,
+    })
+    .collect();
+
+  for (name, records) in &nodes {
+    if name.to_bytes().len() > DomainName::MaxLength {
+      problems.push(Problem::error(
+        "name-too-long",
+        name,
+        format!(
+          "Name is {} octets long, at most {} fit",
+          name.to_bytes().len(),
+          DomainName::MaxLength
+        ),
+      ));
+    }
+
+    let mut types: Vec<RecordType> = records.iter().map(|record| record.record_type).collect();
+    types.sort();
+    types.dedup();
+    // Signatures and NSEC records may sit next to a CNAME (RFC 4035 section 2.5).
+    let others: Vec<String> = types
+      .iter()
+      .filter(|record_type| {
+        ![RecordType::CanonicalName, RecordType::Signature, RecordType::NextSecure].contains(record_type)
+      })
+      .map(RecordType::mnemonic)
+      .collect();
+    let cnames = records
+      .iter()
+      .filter(|record| record.record_type == RecordType::CanonicalName)
+      .count();
+    if cnames > 0 && !others.is_empty() {
+      problems.push(Problem::error(
+        "cname-and-other-data",
+        name,
+        format!("CNAME next to {}", others.join(", ")),
+      ));
+    }
+    if cnames > 1 {
+      problems.push(Problem::error(
+        "cname-and-other-data",
+        name,
+        format!("{} CNAME records at one name", cnames),
+      ));
+    }
+
+    let mut ttls: HashMap<(RecordType, Option<RecordType>), HashSet<u32>> = HashMap::new();
+    for record in records {
+      let covered = match record.record_data() {
+        RecordData::Signature { type_covered, .. } => Some(type_covered),
+        _ => None,
+      };
+      ttls
+        .entry((record.record_type, covered))
+        .or_default()
+        .insert(record.time_to_live);
+    }
+    let mut mismatched: Vec<_> = ttls.into_iter().filter(|(_, ttls)| ttls.len() > 1).collect();
+    mismatched.sort_by_key(|((record_type, covered), _)| (*record_type, *covered));
+    for ((record_type, covered), ttls) in mismatched {
+      let mut ttls: Vec<u32> = ttls.into_iter().collect();
+      ttls.sort();
+      let rrset = match covered {
+        Some(covered) => format!("RRSIG {}", covered.mnemonic()),
+        None => record_type.mnemonic(),
+      };
+      problems.push(Problem::warning(
+        "ttl-mismatch",
+        name,
+        format!(
+          "{} RRset has differing TTLs {}",
+          rrset,
+          ttls.iter().map(u32::to_string).collect::<Vec<_>>().join(", ")
+        ),
+      ));
+    }
+
+    if has(name, RecordType::DelegationSigner) && !has(name, RecordType::NameServer) {
+      problems.push(Problem::error(
+        "orphaned-delegation",
+        name,
+        "DS records without the NS records of a delegation".to_string(),
+      ));
+    }
+    if below_cut(name) && !name_servers.contains(name) {
+      problems.push(Problem::warning(
+        "orphaned-delegation",
+        name,
+        "Data below a delegation that no NS record names as glue".to_string(),
+      ));
+    }
+  }
+
+  for target in &name_servers {
+    if target.is_subdomain_of(origin) && !at(target).iter().any(is_address) {
+      problems.push(Problem::error(
+        "missing-glue",
+        target,
+        "Name server inside the zone has no A or AAAA records".to_string(),
+      ));
+    }
+  }
+
+  for record in outside {
+    let glue = is_address(&record) && name_servers.contains(&record.domain_name.to_lowercase());
+    problems.push(Problem::error(
+      match glue {
+        true => "out-of-zone-glue",
+        false => "out-of-zone-data",
+      },
+      &record.domain_name,
+      format!("{} record outside of zone {}", record.record_type.mnemonic(), origin),
+    ));
+  }
+
+  problems.sort_by(|first, second| {
+    canonical_cmp(&first.name, &second.name)
+      .then(first.severity.cmp(&second.severity))
+      .then(first.check.cmp(second.check))
+  });
+  problems.dedup();
+  problems
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn check(text: &str) -> Vec<String> {
+    let origin = DomainName::from("example.com");
+    let records = parse_zone(text, &origin).unwrap();
+
+    check_zone(&origin, &records)
+      .iter()
+      .map(|problem| format!("{} {}", problem.check, problem.name))
+      .collect()
+  }
+
+  #[test]
+  fn it_passes_healthy_zones() {
+    let zone = "$TTL 300
+@ SOA ns1 hostmaster 1 7200 3600 1209600 300
+@ NS ns1
+@ NS ns.example.net.
+ns1 A 192.0.2.1
+www CNAME @
+sub NS ns.sub
+sub DS 12345 13 2 49FD46E6C4B45C55D4AC49FD46E6C4B45C55D4AC49FD46E6C4B45C55D4ACAABB
+ns.sub AAAA 2001:db8::53
+";
+
+    assert_eq!(check(zone), Vec::<String>::new());
+  }
+
+  #[test]
+  fn it_reports_broken_zones() {
+    let long = ["a".repeat(63), "b".repeat(63), "c".repeat(63), "d".repeat(50)].join(".");
+    let zone = format!(
+      "$TTL 300
+www CNAME @
+www A 192.0.2.1
+sub NS ns.sub
+sub NS ns.elsewhere.
+stray.sub A 192.0.2.9
+ns.elsewhere. A 192.0.2.10
+unsigned DS 12345 13 2 49FD46E6C4B45C55D4AC49FD46E6C4B45C55D4AC49FD46E6C4B45C55D4ACAABB
+mail 60 MX 10 mx
+mail 120 MX 20 mx2
+mx A 192.0.2.2
+mx2 A 192.0.2.3
+{} TXT long
+",
+      long
+    );
+
+    assert_eq!(
+      check(&zone),
+      [
+        "missing-ns example.com.",
+        "missing-soa example.com.",
+        format!("name-too-long {}.example.com.", long).as_str(),
+        "ttl-mismatch mail.example.com.",
+        "missing-glue ns.sub.example.com.",
+        "orphaned-delegation stray.sub.example.com.",
+        "orphaned-delegation unsigned.example.com.",
+        "cname-and-other-data www.example.com.",
+        "out-of-zone-glue ns.elsewhere.",
+      ]
+    );
+  }
+
+  #[test]
+  fn it_prints_problems_as_tab_separated_lines() {
+    let problem = Problem::warning(
+      "ttl-mismatch",
+      &DomainName::from("mail.example.com"),
+      "MX RRset has differing TTLs 60, 120".to_string(),
+    );
+    let options = CheckZoneOptions::parse(&["-o".to_string(), "example.org".to_string(), "db".to_string()]);
+
+    assert_eq!(
+      problem.to_string(),
+      "warning\tttl-mismatch\tmail.example.com.\tMX RRset has differing TTLs 60, 120"
+    );
+    assert_eq!(options.unwrap().origin, DomainName::from("example.org"));
+    assert_eq!(CheckZoneOptions::parse(&["-x".to_string()]).is_err(), true);
+  }
+}
@@ -0,0 +1,370 @@
+//! A query client in the manner of dig:
+//!
+//! ```text
+//! dig [@<server>] [-p <port>] [-x <address>] [-f <batch file>] [<name>] [<type>] [<class>]
+//!     [+tcp] [+dnssec] [+norecurse]
+//! ```
+//!
+//! The query goes to the server over UDP, or TCP with `+tcp` or once a UDP response comes back
+//! truncated, and the response is printed section by section in presentation format together with
+//! the query time and message size. Queries carry EDNS, with the DO bit under `+dnssec`, and ask
+//! for recursion unless `+norecurse` is given. `-x` looks up the PTR record of an IPv4 or IPv6
+//! address. A batch file holds one query per line in the same syntax, the command line giving the
+//! defaults. Without `@<server>` the first name server of `/etc/resolv.conf` is asked.
+
+use std::fs;
+use std::io;
+use std::net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use crate::messages::*;
+use crate::server::{read_message, write_message};
+
+const DefaultPort: u16 = 53;
+const DefaultTimeout: Duration = Duration::from_secs(5);
+const ResolverConfiguration: &str = "/etc/resolv.conf";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DigOptions {
+  /// The server as given, a host name or an address.
+  pub server: Option<String>,
+  pub port: u16,
+  pub tcp: bool,
+  pub dnssec: bool,
+  pub recursion: bool,
+  pub name: DomainName,
+  pub record_type: RecordType,
+  pub record_class: RecordClass,
+  pub batch: Option<PathBuf>,
+}
+
+impl Default for DigOptions {
+  fn default() -> Self {
+    Self {
+      server: None,
+      port: DefaultPort,
+      tcp: false,
+      dnssec: false,
+      recursion: true,
+      name: DomainName::root(),
+      record_type: RecordType::Host,
+      record_class: RecordClass::Internet,
+      batch: None,
+    }
+  }
+}
+
+impl DigOptions {
+  /// Reads the arguments over `defaults`. A bare argument is a type or class when it reads as one
+  /// and the name otherwise; the type defaults to NS for the root and A for any other name.
+  pub fn parse(args: &[String], defaults: &DigOptions) -> Result<Self, String> {
+    let mut options = defaults.clone();
+    let mut named = false;
+    let mut typed = false;
+    let mut index = 0;
+    while index < args.len() {
+      let arg = args[index].as_str();
+      let mut value = || {
+        index += 1;
+        args
+          .get(index)
+          .map(String::as_str)
+          .ok_or_else(|| format!("Missing value after {}", arg))
+      };
+
+      match arg {
+        "+tcp" => options.tcp = true,
+        "+notcp" => options.tcp = false,
+        "+dnssec" => options.dnssec = true,
+        "+nodnssec" => options.dnssec = false,
+        "+recurse" => options.recursion = true,
+        "+norecurse" => options.recursion = false,
+        "-p" => options.port = value()?.parse().map_err(|_| format!("Invalid port after {}", arg))?,
+        "-f" => options.batch = Some(PathBuf::from(value()?)),
+        "-x" => {
+          let address: IpAddr = value()?.parse().map_err(|_| format!("Invalid address after {}", arg))?;
+          (options.name, options.record_type) = (reverse_name(address), RecordType::Pointer);
+          (named, typed) = (true, true);
+        }
+        server if server.starts_with('@') => options.server = Some(server[1..].to_string()),
+        option if option.starts_with('+') || option.starts_with('-') => {
+          return Err(format!("Unknown option {}", option));
+        }
+        text => match (RecordType::from_mnemonic(text), RecordClass::from_mnemonic(text)) {
+          (Some(record_type), _) if !typed && named => (options.record_type, typed) = (record_type, true),
+          (_, Some(record_class)) if named => options.record_class = record_class,
+          _ if !named => (options.name, named) = (parse_name(text, &DomainName::root())?, true),
+          _ => return Err(format!("Unexpected argument '{}'", text)),
+        },
+      }
+      index += 1;
+    }
+    if !typed && options.name.is_root() {
+      options.record_type = RecordType::NameServer;
+    }
+
+    Ok(options)
+  }
+
+  /// The address queries go to.
+  pub fn server_address(&self) -> Result<SocketAddr, String> {
+    let server = match &self.server {
+      Some(server) => server.clone(),
+      None => system_name_server().unwrap_or_else(|| "127.0.0.1".to_string()),
+    };
+
+    (server.as_str(), self.port)
+      .to_socket_addrs()
+      .map_err(|error| format!("{}: {}", server, error))?
+      .next()
+      .ok_or_else(|| format!("No address for server {}", server))
+  }
+
+  pub fn query(&self) -> Result<MessageProperties, String> {
+    MessageProperties::query(self.name.clone(), self.record_type)
+      .record_class(self.record_class)
+      .recursion_desired(self.recursion)
+      .edns(EdnsProperties {
+        dnssec_ok: self.dnssec,
+        ..EdnsProperties::new()
+      })
+      .build()
+      .map_err(|error| error.to_string())
+  }
+}
+
+/// What came back for a query, and how.
+#[derive(Clone, Debug)]
+pub struct Exchange {
+  pub response: MessageProperties,
+  pub size: usize,
+  pub elapsed: Duration,
+  pub server: SocketAddr,
+  pub tcp: bool,
+}
+
+/// Runs every query the arguments ask for and prints their responses.
+pub fn dig(args: &[String]) -> Result<(), String> {
+  let options = DigOptions::parse(args, &DigOptions::default())?;
+  let queries = match &options.batch {
+    None => vec![options],
+    Some(path) => {
+      let text = fs::read_to_string(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+      let defaults = DigOptions { batch: None, ..options };
+      text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with(';') && !line.starts_with('#'))
+        .map(|line| {
+          DigOptions::parse(
+            &line.split_whitespace().map(String::from).collect::<Vec<_>>(),
+            &defaults,
+          )
+        })
+        .collect::<Result<Vec<_>, String>>()?
+    }
+  };
+
+  for options in queries {
+    println!(
+      "; <<>> dig <<>> {} {} {}",
+      options.name, options.record_class, options.record_type
+    );
+    let server = options.server_address()?;
+    match exchange(server, &options.query()?, options.tcp) {
+      Ok(exchange) => println!("{}", format_exchange(&exchange)),
+      Err(error) => println!(";; communications error to {}: {}\n", server, error),
+    }
+  }
+
+  Ok(())
+}
+
+/// Sends the query over TCP, or over UDP first when `tcp` is false.
+pub fn exchange(server: SocketAddr, query: &MessageProperties, tcp: bool) -> io::Result<Exchange> {
+  let request: Vec<u8> = query.clone().into();
+  let started = Instant::now();
+
+  let (response, tcp) = match tcp {
+    true => (exchange_tcp(server, &request)?, true),
+    false => {
+      let response = exchange_udp(server, &request, query.header.id)?;
+      match HeaderProperties::try_from(response.as_slice())?.is_truncated() {
+        true => {
+          println!(";; Truncated, retrying in TCP mode.");
+          (exchange_tcp(server, &request)?, true)
+        }
+        false => (response, false),
+      }
+    }
+  };
+
+  Ok(Exchange {
+    size: response.len(),
+    response: MessageProperties::try_from(response.as_slice())?,
+    elapsed: started.elapsed(),
+    server,
+    tcp,
+  })
+}
+
+fn exchange_udp(server: SocketAddr, request: &[u8], id: u16) -> io::Result<Vec<u8>> {
+  let host = match server {
+    SocketAddr::V4(_) => "0.0.0.0",
+    SocketAddr::V6(_) => "::",
+  };
+  let socket = UdpSocket::bind((host, 0))?;
+  socket.connect(server)?;
+  socket.send(request)?;
+
+  let deadline = Instant::now() + DefaultTimeout;
+  let mut buffer = vec![0; u16::MAX as usize];
+  loop {
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    if remaining.is_zero() {
+      return Err(io::Error::new(io::ErrorKind::TimedOut, "No response"));
+    }
+    socket.set_read_timeout(Some(remaining))?;
+
+    let size = socket.recv(&mut buffer)?;
+    if size >= 12 && u16::from_be_bytes([buffer[0], buffer[1]]) == id {
+      return Ok(buffer[..size].to_vec());
+    }
+  }
+}
+
+fn exchange_tcp(server: SocketAddr, request: &[u8]) -> io::Result<Vec<u8>> {
+  let mut stream = TcpStream::connect_timeout(&server, DefaultTimeout)?;
+  stream.set_read_timeout(Some(DefaultTimeout))?;
+  write_message(&mut stream, request)?;
+
+  read_message(&mut stream)?.ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "Connection closed"))
+}
+
+/// The response in dig's layout: header, EDNS, sections, then statistics.
+pub fn format_exchange(exchange: &Exchange) -> String {
+  let mut text = format!(";; Got answer:\n{}\n", exchange.response);
+  text += &format!("\n;; Query time: {} msec\n", exchange.elapsed.as_millis());
+  text += &format!(
+    ";; SERVER: {}#{}({}) ({})\n",
+    exchange.server.ip(),
+    exchange.server.port(),
+    exchange.server.ip(),
+    if exchange.tcp { "TCP" } else { "UDP" }
+  );
+  text += &format!(";; MSG SIZE  rcvd: {}\n", exchange.size);
+  text
+}
+
+/// The name below `in-addr.arpa` or `ip6.arpa` the PTR record of `address` lives at.
+pub fn reverse_name(address: IpAddr) -> DomainName {
+  let mut labels: Vec<String> = match address {
+    IpAddr::V4(address) => address.octets().iter().rev().map(u8::to_string).collect(),
+    IpAddr::V6(address) => address
+      .octets()
+      .iter()
+      .rev()
+      .flat_map(|octet| [octet & 0x0f, octet >> 4])
+      .map(|nibble| format!("{:x}", nibble))
+      .collect(),
+  };
+  labels.extend(match address {
+    IpAddr::V4(_) => ["in-addr", "arpa"].map(String::from),
+    IpAddr::V6(_) => ["ip6", "arpa"].map(String::from),
+  });
+
+  DomainName(labels)
+}
+
+/// The first `nameserver` of the system's resolver configuration.
+fn system_name_server() -> Option<String> {
+  let text = fs::read_to_string(ResolverConfiguration).ok()?;
+
+  text
+    .lines()
+    .find_map(|line| match line.split_whitespace().collect::<Vec<_>>()[..] {
+      ["nameserver", address, ..] => Some(address.to_string()),
+      _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn args(text: &str) -> Vec<String> {
+    text.split_whitespace().map(String::from).collect()
+  }
+
+  #[test]
+  fn it_parses_dig_arguments() {
+    let defaults = DigOptions::default();
+    let query = DigOptions::parse(
+      &args("@127.0.0.1 -p 2053 example.com MX +tcp +dnssec +norecurse"),
+      &defaults,
+    )
+    .unwrap();
+    let reverse = DigOptions::parse(&args("-x 192.0.2.1"), &defaults).unwrap();
+    let root = DigOptions::parse(&args("."), &defaults).unwrap();
+    let batch_line = DigOptions::parse(&args("example.org CH TXT"), &query).unwrap();
+
+    assert_eq!(query.server, Some("127.0.0.1".to_string()));
+    assert_eq!(query.server_address(), Ok("127.0.0.1:2053".parse().unwrap()));
+    assert_eq!(query.name, DomainName::from("example.com"));
+    assert_eq!(query.record_type, RecordType::MailExchange);
+    assert_eq!((query.tcp, query.dnssec, query.recursion), (true, true, false));
+    assert_eq!(reverse.name, DomainName::from("1.2.0.192.in-addr.arpa"));
+    assert_eq!(reverse.record_type, RecordType::Pointer);
+    assert_eq!(root.record_type, RecordType::NameServer);
+    assert_eq!(batch_line.name, DomainName::from("example.org"));
+    assert_eq!(batch_line.record_class, RecordClass::Chaos);
+    assert_eq!(batch_line.record_type, RecordType::Text);
+    assert_eq!(batch_line.tcp, true);
+    assert_eq!(DigOptions::parse(&args("+bogus"), &defaults).is_err(), true);
+  }
+
+  #[test]
+  fn it_reverses_ipv6_addresses_by_nibble() {
+    let name = reverse_name("2001:db8::1".parse().unwrap());
+
+    assert_eq!(name.0[0], "1");
+    assert_eq!(name.0[31], "2");
+    assert_eq!(name.0[32..], ["ip6", "arpa"]);
+  }
+
+  #[test]
+  fn it_prints_responses_received_over_udp() {
+    let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let address = server.local_addr().unwrap();
+    let options = DigOptions::parse(&args("example.com +dnssec"), &DigOptions::default()).unwrap();
+    std::thread::spawn(move || {
+      let mut buffer = [0; 512];
+      let (size, client) = server.recv_from(&mut buffer).unwrap();
+      let mut response = MessageProperties::try_from(&buffer[..size]).unwrap();
+      response.header.flags = Flags::Response | Flags::RecursionDesired | Flags::RecursionAvailable;
+      response.answers.push(AnswerProperties {
+        domain_name: DomainName::from("example.com"),
+        record_type: RecordType::Host,
+        record_class: RecordClass::Internet,
+        time_to_live: 60,
+        data: vec![8, 8, 8, 8],
+      });
+      response.header.answer_record_count = 1;
+      let response: Vec<u8> = response.into();
+      server.send_to(&response, client).unwrap();
+    });
+
+    let exchange = exchange(address, &options.query().unwrap(), false).unwrap();
+    let text = format_exchange(&exchange);
+
+    assert_eq!(exchange.tcp, false);
+    assert!(text.contains(";; ->>HEADER<<- opcode: QUERY, status: NOERROR, id: "));
+    assert!(text.contains(";; flags: qr rd ra; QUERY: 1, ANSWER: 1, AUTHORITY: 0, ADDITIONAL: 1\n"));
+    assert!(text.contains("; EDNS: version: 0, flags: do; udp: 1232\n"));
+    assert!(text.contains(";; QUESTION SECTION:\n;example.com. IN A\n"));
+    assert!(text.contains(";; ANSWER SECTION:\nexample.com. 60 IN A 8.8.8.8\n"));
+    assert!(text.contains(&format!(";; SERVER: 127.0.0.1#{}(127.0.0.1) (UDP)\n", address.port())));
+    assert!(text.contains(&format!(";; MSG SIZE  rcvd: {}\n", exchange.size)));
+  }
+}
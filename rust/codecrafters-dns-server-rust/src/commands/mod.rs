@@ -0,0 +1,13 @@
+//! What the binaries run: `serve` for the name server itself, the `signzone` subcommand for
+//! offline zone signing, `checkzone` for finding problems in master files, and the `dig` query
+//! client built as a binary of its own.
+
+pub mod checkzone;
+pub mod dig;
+pub mod serve;
+pub mod signzone;
+
+pub use checkzone::*;
+pub use dig::*;
+pub use serve::*;
+pub use signzone::*;
@@ -0,0 +1,260 @@
+//! The name server itself, what the binary runs without a subcommand:
+//!
+//! ```text
+//! [--resolver <address> | --recursive] [--port <port>] [--zone <origin>=<file>]...
+//!     [--secondary <origin>=<primary>]... [--tsig-keys <file>] [--dnssec-keys <file>]
+//!     [--validate [--trust-anchors <file> | --trust-anchor <record>...]] [--require-cookies]
+//! ```
+//!
+//! Zones are served authoritatively next to the mode answering everything else: forwarding to a
+//! resolver, resolving recursively from the root, or refusing. The settings of each zone are given
+//! with further `<flag> <origin>=<value>` arguments such as `--allow-transfer`. Queries are answered
+//! over UDP and TCP on 127.0.0.1, while background threads keep secondary zones fresh, reload
+//! changed master files, renew signatures and log the counters of the resolver.
+
+use std::net::{SocketAddr, TcpListener, UdpSocket};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
+use std::time::Duration;
+
+use crate::cookies::*;
+use crate::dnssec::*;
+use crate::messages::*;
+use crate::resolver::*;
+use crate::server::*;
+use crate::tsig::*;
+use crate::zones::*;
+
+const Host: &str = "127.0.0.1";
+const Port: u16 = 2053;
+const CookieSecretRotation: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Starts the server configured by `args` and serves UDP on the calling thread, returning only
+/// when the configuration is invalid or a socket cannot be bound.
+pub fn serve(args: &[String]) -> Result<(), String> {
+  let mut mode = if args.first().is_some_and(|arg| arg == "--resolver") {
+    let resolver = args
+      .get(1)
+      .and_then(|resolver| resolver.parse::<SocketAddr>().ok())
+      .ok_or("Invalid resolver address")?;
+    Mode::Forwarding(resolver, CookieTransport::new(UdpTransport::default()))
+  } else if args.iter().any(|arg| arg == "--recursive") {
+    Mode::Recursive(RecursiveResolver::new(
+      CookieTransport::new(UdpTransport::default()),
+      root_hints(),
+    ))
+  } else {
+    Mode::Authoritative
+  };
+  let keys = match value(args, "--tsig-keys")? {
+    Some(path) => TsigKeys::load(&PathBuf::from(path)).map_err(|error| format!("Invalid TSIG keys: {}", error))?,
+    None => TsigKeys::default(),
+  };
+  let signing_keys = match value(args, "--dnssec-keys")? {
+    Some(path) => SigningKeys::load(&PathBuf::from(path)).map_err(|error| format!("Invalid DNSSEC keys: {}", error))?,
+    None => SigningKeys::default(),
+  };
+  let validator = validator(args).map_err(|error| format!("Invalid trust anchor: {}", error))?;
+  if let (Some(_), Mode::Recursive(recursor)) = (&validator, &mut mode) {
+    recursor.dnssec_ok = true;
+  }
+  let sources = zone_sources(args, &keys, &signing_keys).map_err(|error| format!("Invalid zone: {}", error))?;
+  let mut catalog = Catalog::new();
+  for source in &sources {
+    let zone = source
+      .load()
+      .map_err(|error| format!("Failed to load zone: {}", error))?;
+    println!("Loaded zone {} with serial {}", zone.origin, zone.serial());
+    catalog.insert(zone);
+  }
+  let mut cookies = ServerCookies::new(CookieSecretRotation);
+  cookies.enforce = args.iter().any(|arg| arg == "--require-cookies");
+
+  let secondaries =
+    load_secondaries(args, &keys, &signing_keys).map_err(|error| format!("Invalid secondary zone: {}", error))?;
+  let port = match value(args, "--port")? {
+    Some(port) => port.parse().map_err(|_| format!("Invalid port '{}'", port))?,
+    None => Port,
+  };
+
+  let (notifications, notified) = mpsc::channel();
+  let mut server = Server::new(mode, catalog, cookies);
+  server.secondaries = secondaries.clone();
+  server.notifications = Some(notifications);
+  server.keys = keys;
+  if let Some(upstream) = &server.upstream {
+    upstream.lock().unwrap().validator = validator;
+  }
+  let server = Arc::new(Mutex::new(server));
+
+  let listener = TcpListener::bind((Host, port)).map_err(|error| format!("Failed to bind TCP address: {}", error))?;
+  let socket = UdpSocket::bind((Host, port)).map_err(|error| format!("Failed to bind to address: {}", error))?;
+
+  let tcp_server = server.clone();
+  thread::spawn(move || serve_tcp(listener, tcp_server));
+
+  let refresh_server = server.clone();
+  thread::spawn(move || run_secondaries(refresh_server, secondaries, notified));
+
+  let reload_server = server.clone();
+  thread::spawn(move || run_reloads(reload_server, sources));
+
+  let resign_server = server.clone();
+  thread::spawn(move || run_resigning(resign_server));
+
+  if let Some(upstream) = server.lock().unwrap().upstream.clone() {
+    thread::spawn(move || run_statistics(upstream));
+  }
+
+  serve_udp(socket, server);
+  Ok(())
+}
+
+/// The value after `flag`, if given.
+fn value<'a>(args: &'a [String], flag: &str) -> Result<Option<&'a str>, String> {
+  match args.iter().position(|arg| arg == flag) {
+    Some(index) => match args.get(index + 1) {
+      Some(value) => Ok(Some(value)),
+      None => Err(format!("Missing value after {}", flag)),
+    },
+    None => Ok(None),
+  }
+}
+
+/// The validator enabled by `--validate`, trusting the anchors kept in `--trust-anchors <file>`,
+/// else every `--trust-anchor <record>`, else the root keys. Each
+/// `--negative-trust-anchor <domain>=<duration>` turns validation off below the domain for a while.
+fn validator(args: &[String]) -> Result<Option<Validator>, String> {
+  if !args.iter().any(|arg| arg == "--validate") {
+    return Ok(None);
+  }
+
+  let records: Vec<&str> = args
+    .windows(2)
+    .filter(|pair| pair[0] == "--trust-anchor")
+    .map(|pair| pair[1].as_str())
+    .collect();
+  let file = args
+    .windows(2)
+    .find(|pair| pair[0] == "--trust-anchors")
+    .map(|pair| &pair[1]);
+  let mut anchors = match (file, records.is_empty()) {
+    (Some(path), _) => TrustAnchors::load(&PathBuf::from(path))?,
+    (None, true) => TrustAnchors::default(),
+    (None, false) => TrustAnchors::parse(&records.join("\n"))?,
+  };
+
+  for (domain, duration) in zone_arguments(args, "--negative-trust-anchor")? {
+    let duration = parse_ttl(duration)?;
+    anchors.add_negative(domain, timestamp_now().saturating_add(duration));
+  }
+
+  Ok(Some(Validator::new(anchors)))
+}
+
+/// Values of a repeatable `<flag> <origin>=<value>` argument.
+fn zone_arguments<'a>(args: &'a [String], flag: &str) -> Result<Vec<(DomainName, &'a str)>, String> {
+  args
+    .iter()
+    .enumerate()
+    .filter(|(_, arg)| *arg == flag)
+    .map(|(index, _)| {
+      let argument = args
+        .get(index + 1)
+        .ok_or_else(|| format!("Missing value after {}", flag))?;
+      let (origin, value) = argument
+        .split_once('=')
+        .ok_or_else(|| format!("{} takes <origin>=<value>", flag))?;
+
+      Ok((DomainName::from(origin), value))
+    })
+    .collect()
+}
+
+/// The settings given for a zone with `--allow-transfer <origin>=<networks>`,
+/// `--allow-update <origin>=<networks>`, `--also-notify <origin>=<address>,<address>` and
+/// `--zone-key <origin>=<key name>`, where access lists may admit keys as `key:<name>`, and
+/// `--sign <origin>=nsec|nsec3:<iterations>:<salt>[:opt-out]` with the zone's keys from `--dnssec-keys`.
+fn zone_settings(
+  args: &[String],
+  origin: &DomainName,
+  keys: &TsigKeys,
+  signing_keys: &SigningKeys,
+) -> Result<ZoneSettings, String> {
+  let mut settings = ZoneSettings::default();
+  let given = |flag| -> Result<Vec<&str>, String> {
+    Ok(
+      zone_arguments(args, flag)?
+        .into_iter()
+        .filter(|(name, _)| name.eq_ignore_case(origin))
+        .map(|(_, value)| value)
+        .collect(),
+    )
+  };
+
+  for networks in given("--allow-transfer")? {
+    settings.allow_transfer = AccessList::parse(networks)?;
+  }
+  for networks in given("--allow-update")? {
+    settings.allow_update = AccessList::parse(networks)?;
+  }
+  for addresses in given("--also-notify")? {
+    for address in addresses.split(',').map(str::trim) {
+      let address = address
+        .parse()
+        .map_err(|_| format!("Invalid secondary address '{}'", address))?;
+      settings.also_notify.push(address);
+    }
+  }
+  for name in given("--zone-key")? {
+    let key = keys
+      .find(&DomainName::from(name))
+      .ok_or_else(|| format!("Unknown TSIG key '{}'", name))?;
+    settings.key = Some(key.clone());
+  }
+  for denial in given("--sign")? {
+    let zone_keys = signing_keys.for_zone(origin);
+    if zone_keys.is_empty() {
+      return Err(format!("No DNSSEC keys for zone {}", origin));
+    }
+    settings.signing = Some(SigningPolicy::new(zone_keys, Denial::parse(denial)?));
+  }
+
+  Ok(settings)
+}
+
+/// Every `--zone <origin>=<file>`, loaded from its master file at startup and on changes.
+fn zone_sources(args: &[String], keys: &TsigKeys, signing_keys: &SigningKeys) -> Result<Vec<ZoneSource>, String> {
+  zone_arguments(args, "--zone")?
+    .into_iter()
+    .map(|(origin, path)| {
+      let settings = zone_settings(args, &origin, keys, signing_keys)?;
+
+      Ok(ZoneSource {
+        origin,
+        path: PathBuf::from(path),
+        settings,
+      })
+    })
+    .collect()
+}
+
+/// Every `--secondary <origin>=<primary address>`, pulled from the primary once serving starts.
+fn load_secondaries(args: &[String], keys: &TsigKeys, signing_keys: &SigningKeys) -> Result<Vec<Secondary>, String> {
+  zone_arguments(args, "--secondary")?
+    .into_iter()
+    .map(|(origin, primary)| {
+      let primary = primary
+        .parse()
+        .map_err(|_| format!("Invalid primary address '{}'", primary))?;
+      let settings = zone_settings(args, &origin, keys, signing_keys)?;
+
+      Ok(Secondary {
+        origin,
+        primary,
+        settings,
+      })
+    })
+    .collect()
+}
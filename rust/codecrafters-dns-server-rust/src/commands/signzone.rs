@@ -0,0 +1,229 @@
+//! Offline zone signing in the manner of BIND's dnssec-signzone:
+//!
+//! ```text
+//! signzone [-o <origin>] [-k <key file>] [-a <algorithm>] [-3 <salt>|-] [-H <iterations>] [-A]
+//!          [-e <validity>] [-f <output>] <zone file>
+//! ```
+//!
+//! The origin defaults to the zone file's name, the key file to `K<origin>.keys` and the output to
+//! `<zone file>.signed`. The zone is signed with its keys from the key file; when it has none, a
+//! KSK and a ZSK of the algorithm (ECDSAP256SHA256 by default) are generated and appended to it,
+//! so signing again keeps the same keys. Names are chained with NSEC unless `-3` asks for NSEC3
+//! with a salt, `-H` iterations and `-A` opt-out.
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::dnssec::*;
+use crate::messages::*;
+use crate::zones::*;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SignZoneOptions {
+  pub zone_file: PathBuf,
+  pub origin: DomainName,
+  pub key_file: PathBuf,
+  /// The algorithm of generated keys.
+  pub algorithm: u8,
+  pub denial: Denial,
+  /// How long signatures stay valid, in seconds.
+  pub validity: u32,
+  pub output: PathBuf,
+}
+
+impl SignZoneOptions {
+  pub fn parse(args: &[String]) -> Result<Self, String> {
+    let mut values: Vec<(&str, &str)> = vec![];
+    let mut zone_file = None;
+    let mut opt_out = false;
+    let mut index = 0;
+    while index < args.len() {
+      match args[index].as_str() {
+        "-A" => opt_out = true,
+        flag @ ("-o" | "-k" | "-a" | "-3" | "-H" | "-e" | "-f") => {
+          let value = args
+            .get(index + 1)
+            .ok_or_else(|| format!("Missing value after {}", flag))?;
+          values.push((flag, value));
+          index += 1;
+        }
+        flag if flag.starts_with('-') => return Err(format!("Unknown option {}", flag)),
+        path if zone_file.is_none() => zone_file = Some(PathBuf::from(path)),
+        extra => return Err(format!("Unexpected argument '{}'", extra)),
+      }
+      index += 1;
+    }
+    let value = |flag| {
+      values
+        .iter()
+        .rev()
+        .find(|(given, _)| *given == flag)
+        .map(|(_, value)| *value)
+    };
+
+    let zone_file = zone_file.ok_or_else(|| "Missing zone file".to_string())?;
+    let origin = match value("-o") {
+      Some(origin) => parse_name(origin, &DomainName::root())?,
+      None => {
+        let name = zone_file.file_name().map(|name| name.to_string_lossy().to_string());
+        parse_name(&name.unwrap_or_default(), &DomainName::root())?
+      }
+    };
+    let algorithm = match value("-a") {
+      Some(text) => SecurityAlgorithm::from_mnemonic(text).ok_or_else(|| format!("Unknown algorithm '{}'", text))?,
+      None => SecurityAlgorithm::EcdsaP256Sha256,
+    };
+    let denial = match (value("-3"), value("-H"), opt_out) {
+      (None, None, false) => Denial::Nsec,
+      (None, _, _) => return Err("-H and -A need NSEC3, enabled with -3 <salt>".to_string()),
+      (Some(salt), iterations, opt_out) => {
+        let mut text = format!("nsec3:{}:{}", iterations.unwrap_or("0"), salt);
+        if opt_out {
+          text += ":opt-out";
+        }
+        Denial::parse(&text)?
+      }
+    };
+    let validity = match value("-e") {
+      Some(validity) => parse_ttl(validity)?,
+      None => DefaultSignatureValidity,
+    };
+
+    Ok(Self {
+      key_file: value("-k")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(format!("K{}keys", origin))),
+      output: value("-f")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(format!("{}.signed", zone_file.display()))),
+      zone_file,
+      origin,
+      algorithm,
+      denial,
+      validity,
+    })
+  }
+}
+
+/// Signs the zone file as the options say and writes the result, returning a summary.
+pub fn sign_zone_file(options: &SignZoneOptions) -> Result<String, String> {
+  let records = load_zone_file(&options.zone_file, &options.origin).map_err(|error| error.to_string())?;
+  let zone = Zone::new(options.origin.clone(), records)?;
+  let keys = zone_keys(options)?;
+
+  let mut policy = SigningPolicy::new(keys, options.denial.clone());
+  policy.validity = options.validity;
+  let signed = sign_zone(&zone, &policy, timestamp_now())?;
+  fs::write(&options.output, write_zone(&signed))
+    .map_err(|error| format!("{}: {}", options.output.display(), error))?;
+
+  let signatures = signed
+    .records()
+    .filter(|record| record.record_type == RecordType::Signature)
+    .count();
+  let tags: Vec<String> = policy.keys.iter().map(|key| key.key_tag().to_string()).collect();
+  Ok(format!(
+    "Signed zone {} with keys {}: {} signatures, written to {}",
+    options.origin,
+    tags.join(", "),
+    signatures,
+    options.output.display()
+  ))
+}
+
+/// The zone's keys from the key file, generating a KSK and a ZSK when it has none.
+fn zone_keys(options: &SignZoneOptions) -> Result<Vec<SigningKey>, String> {
+  let path = &options.key_file;
+  let keys = match path.exists() {
+    true => SigningKeys::load(path)?.for_zone(&options.origin),
+    false => vec![],
+  };
+  if !keys.is_empty() {
+    return Ok(keys);
+  }
+
+  let keys = vec![
+    SigningKey::generate(options.origin.clone(), options.algorithm, SigningKey::KeySigningKey)?,
+    SigningKey::generate(options.origin.clone(), options.algorithm, SigningKey::ZoneSigningKey)?,
+  ];
+  append_keys(path, &keys).map_err(|error| format!("{}: {}", path.display(), error))?;
+  Ok(keys)
+}
+
+fn append_keys(path: &Path, keys: &[SigningKey]) -> std::io::Result<()> {
+  let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+  for key in keys {
+    writeln!(file, "{}", key.to_line())?;
+  }
+  file.sync_data()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn args(text: &str) -> Vec<String> {
+    text.split_whitespace().map(String::from).collect()
+  }
+
+  #[test]
+  fn it_parses_options() {
+    let defaults = SignZoneOptions::parse(&args("zones/example.com")).unwrap();
+    let nsec3 = SignZoneOptions::parse(&args("-o example.org -3 aabb -H 5 -A -a ed25519 -e 1d -f out db")).unwrap();
+
+    assert_eq!(defaults.origin, DomainName::from("example.com"));
+    assert_eq!(defaults.key_file, PathBuf::from("Kexample.com.keys"));
+    assert_eq!(defaults.output, PathBuf::from("zones/example.com.signed"));
+    assert_eq!(defaults.denial, Denial::Nsec);
+    assert_eq!(defaults.validity, DefaultSignatureValidity);
+    assert_eq!(nsec3.origin, DomainName::from("example.org"));
+    assert_eq!(nsec3.algorithm, SecurityAlgorithm::Ed25519);
+    assert_eq!(
+      nsec3.denial,
+      Denial::Nsec3 {
+        iterations: 5,
+        salt: vec![0xaa, 0xbb],
+        opt_out: true
+      }
+    );
+    assert_eq!(nsec3.validity, 86400);
+    assert_eq!(nsec3.output, PathBuf::from("out"));
+    assert_eq!(SignZoneOptions::parse(&args("-A db")).is_err(), true);
+    assert_eq!(SignZoneOptions::parse(&args("-x db")).is_err(), true);
+  }
+
+  #[test]
+  fn it_signs_zone_files_with_the_same_keys_again() {
+    let directory = std::env::temp_dir().join(format!("signzone-{}", std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    let zone_file = directory.join("example.com");
+    fs::write(
+      &zone_file,
+      "$TTL 300\n@ SOA ns1 hostmaster 1 7200 3600 1209600 300\n@ NS ns1\nns1 A 192.0.2.1\n",
+    )
+    .unwrap();
+    let mut options = SignZoneOptions::parse(&[zone_file.display().to_string()]).unwrap();
+    options.key_file = directory.join("keys");
+
+    let sign =
+      || sign_zone_file(&options).and_then(|_| fs::read_to_string(&options.output).map_err(|error| error.to_string()));
+    let (first, second) = (sign(), sign());
+    let keys = fs::read_to_string(&options.key_file);
+    fs::remove_dir_all(&directory).unwrap();
+
+    let dnskeys = |text: &str| {
+      let records = parse_zone(text, &DomainName::root()).unwrap();
+      records
+        .into_iter()
+        .filter(|record| record.record_type == RecordType::DnsKey)
+        .collect::<Vec<_>>()
+    };
+    let first = first.unwrap();
+    assert_eq!(dnskeys(&first).len(), 2);
+    assert_eq!(dnskeys(&first), dnskeys(&second.unwrap()));
+    assert_eq!(keys.unwrap().lines().count(), 2);
+    assert_eq!(first.contains("\tNSEC\t"), true);
+    assert_eq!(first.contains("\tRRSIG\t"), true);
+  }
+}
@@ -0,0 +1,183 @@
+//! Client side of DNS cookies: every upstream query carries a client cookie derived from a local
+//! secret and the server address, plus the last server cookie learned from that server. Responses
+//! echoing a different client cookie are discarded, and a BADCOOKIE answer is retried once with
+//! the server cookie it provided.
+
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+
+use rand::random;
+use siphasher::sip::SipHasher24;
+
+use crate::messages::*;
+use crate::resolver::Transport;
+
+pub struct CookieTransport<T: Transport> {
+  inner: T,
+  secret: [u8; 16],
+  server_cookies: HashMap<IpAddr, Vec<u8>>,
+}
+
+impl<T: Transport> CookieTransport<T> {
+  pub fn new(inner: T) -> Self {
+    Self {
+      inner,
+      secret: random(),
+      server_cookies: HashMap::new(),
+    }
+  }
+
+  fn client_cookie(&self, server: IpAddr) -> [u8; 8] {
+    let address = match server {
+      IpAddr::V4(address) => address.octets().to_vec(),
+      IpAddr::V6(address) => address.octets().to_vec(),
+    };
+
+    SipHasher24::new_with_key(&self.secret).hash(&address).to_le_bytes()
+  }
+}
+
+impl<T: Transport> Transport for CookieTransport<T> {
+  fn exchange(&mut self, server: SocketAddr, query: &MessageProperties) -> io::Result<MessageProperties> {
+    let client_cookie = self.client_cookie(server.ip());
+
+    for _ in 0..2 {
+      let mut cookie = client_cookie.to_vec();
+      cookie.extend(self.server_cookies.get(&server.ip()).cloned().unwrap_or_default());
+
+      let mut edns = query.edns().unwrap_or_default();
+      edns.set_option(EdnsOption {
+        code: EdnsOption::Cookie,
+        data: cookie,
+      });
+      let mut query = query.clone();
+      query.set_edns(edns);
+
+      let response = self.inner.exchange(server, &query)?;
+      let echoed = response
+        .edns()
+        .and_then(|edns| edns.option(EdnsOption::Cookie).cloned());
+      if let Some(echoed) = echoed {
+        if echoed.data.len() < 8 || echoed.data[..8] != client_cookie {
+          return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Response carries a foreign client cookie",
+          ));
+        }
+
+        if (16..=40).contains(&echoed.data.len()) {
+          self.server_cookies.insert(server.ip(), echoed.data[8..].to_vec());
+        }
+      }
+
+      if response.response_code() != ResponseCode::BadCookie {
+        return Ok(response);
+      }
+    }
+
+    Err(io::Error::other("Server keeps rejecting our cookies"))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::cookies::*;
+
+  /// Upstream enforcing cookies with a fixed secret, recording the cookies it receives.
+  struct CookieServer {
+    cookies: ServerCookies,
+    received: Vec<Option<Vec<u8>>>,
+    echo: Option<Vec<u8>>,
+  }
+
+  impl Transport for CookieServer {
+    fn exchange(&mut self, server: SocketAddr, query: &MessageProperties) -> io::Result<MessageProperties> {
+      let edns = query.edns();
+      self.received.push(
+        edns
+          .as_ref()
+          .and_then(|edns| edns.option(EdnsOption::Cookie))
+          .map(|o| o.data.clone()),
+      );
+
+      let mut response = query.clone();
+      response.header.flags = Flags::Response;
+      let (code, cookie) = match self.cookies.evaluate(edns.as_ref(), server.ip()) {
+        CookieOutcome::Proceed(cookie) => (ResponseCode::NoError, cookie),
+        CookieOutcome::Reject(code, cookie) => (code, cookie),
+      };
+
+      let mut edns = EdnsProperties::new();
+      if let Some(cookie) = self.echo.clone().or(cookie) {
+        edns.set_option(EdnsOption {
+          code: EdnsOption::Cookie,
+          data: cookie,
+        });
+      }
+      response.set_edns(edns);
+      response.set_response_code(code);
+      Ok(response)
+    }
+  }
+
+  fn query() -> MessageProperties {
+    MessageProperties {
+      header: HeaderProperties {
+        id: 1,
+        flags: Flags::Query,
+        question_count: 0,
+        answer_record_count: 0,
+        authority_record_count: 0,
+        additional_record_count: 0,
+      },
+      questions: vec![],
+      answers: vec![],
+      authorities: vec![],
+      additionals: vec![],
+    }
+  }
+
+  fn server() -> SocketAddr {
+    "192.0.2.1:53".parse().unwrap()
+  }
+
+  #[test]
+  fn it_learns_the_server_cookie_after_badcookie() {
+    let mut cookies = ServerCookies::new(std::time::Duration::MAX);
+    cookies.enforce = true;
+    let upstream = CookieServer {
+      cookies,
+      received: vec![],
+      echo: None,
+    };
+    let mut transport = CookieTransport::new(upstream);
+
+    let first = transport.exchange(server(), &query()).unwrap();
+    let second = transport.exchange(server(), &query()).unwrap();
+
+    assert_eq!(first.response_code(), ResponseCode::NoError);
+    assert_eq!(second.response_code(), ResponseCode::NoError);
+
+    let received = &transport.inner.received;
+    assert_eq!(received.len(), 3);
+    assert_eq!(received[0].as_ref().unwrap().len(), 8);
+    assert_eq!(received[1].as_ref().unwrap().len(), 24);
+    assert_eq!(received[2], received[1]);
+  }
+
+  #[test]
+  fn it_rejects_responses_with_a_foreign_client_cookie() {
+    let upstream = CookieServer {
+      cookies: ServerCookies::new(std::time::Duration::MAX),
+      received: vec![],
+      echo: Some(vec![0; 24]),
+    };
+    let mut transport = CookieTransport::new(upstream);
+
+    let response = transport.exchange(server(), &query());
+
+    assert_eq!(response.unwrap_err().kind(), io::ErrorKind::InvalidData);
+  }
+}
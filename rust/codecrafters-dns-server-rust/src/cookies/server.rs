@@ -0,0 +1,254 @@
+//! Server cookies in the interoperable RFC 9018 format, 16 bytes:
+//! Version - 1 byte - Always 1
+//! Reserved - 3 bytes - Zero
+//! Timestamp - 4 bytes - Seconds since the epoch, serial number arithmetic
+//! Hash - 8 bytes - SipHash-2-4 of client cookie, version, reserved, timestamp and client IP
+
+use std::net::IpAddr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use rand::random;
+use siphasher::sip::SipHasher24;
+
+use crate::messages::*;
+
+const Version: u8 = 1;
+/// Cookies stay valid for an hour and may come from clocks up to 5 minutes ahead.
+const Lifetime: u32 = 3600;
+const ClockSkew: u32 = 300;
+/// Cookies older than half an hour get replaced in the response.
+const RefreshAfter: u32 = 1800;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CookieOutcome {
+  /// Answer normally, attaching the cookie option when present.
+  Proceed(Option<Vec<u8>>),
+  /// Answer with only the response code and the cookie option when present.
+  Reject(u8, Option<Vec<u8>>),
+}
+
+pub struct ServerCookies {
+  current: [u8; 16],
+  previous: [u8; 16],
+  rotated_at: Instant,
+  rotation: Duration,
+  /// Reject queries carrying only a client cookie with BADCOOKIE instead of answering them.
+  pub enforce: bool,
+}
+
+impl ServerCookies {
+  pub fn new(rotation: Duration) -> Self {
+    Self {
+      current: random(),
+      previous: random(),
+      rotated_at: Instant::now(),
+      rotation,
+      enforce: false,
+    }
+  }
+
+  pub fn with_secret(secret: [u8; 16]) -> Self {
+    Self {
+      current: secret,
+      previous: secret,
+      rotated_at: Instant::now(),
+      rotation: Duration::MAX,
+      enforce: false,
+    }
+  }
+
+  /// Handles the COOKIE option of a query following RFC 7873 section 5.2.
+  pub fn evaluate(&mut self, edns: Option<&EdnsProperties>, client: IpAddr) -> CookieOutcome {
+    self.evaluate_at(edns, client, unix_time())
+  }
+
+  pub fn evaluate_at(&mut self, edns: Option<&EdnsProperties>, client: IpAddr, now: u32) -> CookieOutcome {
+    self.rotate();
+
+    let Some(option) = edns.and_then(|edns| edns.option(EdnsOption::Cookie)) else {
+      return CookieOutcome::Proceed(None);
+    };
+
+    let length = option.data.len();
+    if length != 8 && !(16..=40).contains(&length) {
+      return CookieOutcome::Reject(ResponseCode::FormatError, None);
+    }
+
+    let client_cookie: [u8; 8] = option.data[..8].try_into().unwrap();
+    let fresh = self.cookie(&client_cookie, client, now);
+    if length == 8 {
+      return match self.enforce {
+        true => CookieOutcome::Reject(ResponseCode::BadCookie, Some(fresh)),
+        false => CookieOutcome::Proceed(Some(fresh)),
+      };
+    }
+
+    let server_cookie = &option.data[8..];
+    if !self.is_valid(&client_cookie, server_cookie, client, now) {
+      return CookieOutcome::Reject(ResponseCode::BadCookie, Some(fresh));
+    }
+
+    let timestamp = u32::from_be_bytes(server_cookie[4..8].try_into().unwrap());
+    match now.wrapping_sub(timestamp) > RefreshAfter && now.wrapping_sub(timestamp) <= Lifetime {
+      true => CookieOutcome::Proceed(Some(fresh)),
+      false => CookieOutcome::Proceed(Some(option.data.clone())),
+    }
+  }
+
+  /// Client cookie followed by a freshly issued server cookie.
+  pub fn cookie(&self, client_cookie: &[u8; 8], client: IpAddr, now: u32) -> Vec<u8> {
+    let mut cookie = client_cookie.to_vec();
+    cookie.extend([Version, 0, 0, 0]);
+    cookie.extend(now.to_be_bytes());
+    cookie.extend(hash(&self.current, client_cookie, &now.to_be_bytes(), client));
+    cookie
+  }
+
+  fn is_valid(&self, client_cookie: &[u8; 8], server_cookie: &[u8], client: IpAddr, now: u32) -> bool {
+    if server_cookie.len() != 16 || server_cookie[..4] != [Version, 0, 0, 0] {
+      return false;
+    }
+
+    let timestamp: [u8; 4] = server_cookie[4..8].try_into().unwrap();
+    let age = now.wrapping_sub(u32::from_be_bytes(timestamp));
+    if age > Lifetime && age.wrapping_neg() > ClockSkew {
+      return false;
+    }
+
+    [self.current, self.previous]
+      .iter()
+      .any(|secret| hash(secret, client_cookie, &timestamp, client) == server_cookie[8..])
+  }
+
+  fn rotate(&mut self) {
+    if self.rotated_at.elapsed() >= self.rotation {
+      self.previous = self.current;
+      self.current = random();
+      self.rotated_at = Instant::now();
+    }
+  }
+}
+
+fn hash(secret: &[u8; 16], client_cookie: &[u8; 8], timestamp: &[u8; 4], client: IpAddr) -> [u8; 8] {
+  let mut input = client_cookie.to_vec();
+  input.extend([Version, 0, 0, 0]);
+  input.extend(timestamp);
+  match client {
+    IpAddr::V4(address) => input.extend(address.octets()),
+    IpAddr::V6(address) => input.extend(address.octets()),
+  }
+
+  SipHasher24::new_with_key(secret).hash(&input).to_le_bytes()
+}
+
+pub fn unix_time() -> u32 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .unwrap_or_default()
+    .as_secs() as u32
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::macros::*;
+
+  const Secret: [u8; 16] = hex!("e5e973e5a6b2a43f48e7dc849e37bfcf");
+  const ClientCookie: [u8; 8] = hex!("2464c4abcf10c957");
+  const Timestamp: u32 = 1559731985;
+
+  fn client() -> IpAddr {
+    "198.51.100.100".parse().unwrap()
+  }
+
+  fn edns(cookie: &[u8]) -> EdnsProperties {
+    let mut edns = EdnsProperties::new();
+    edns.set_option(EdnsOption {
+      code: EdnsOption::Cookie,
+      data: cookie.to_vec(),
+    });
+    edns
+  }
+
+  #[test]
+  fn it_matches_the_rfc_9018_test_vector() {
+    let cookies = ServerCookies::with_secret(Secret);
+
+    let cookie = cookies.cookie(&ClientCookie, client(), Timestamp);
+
+    assert_eq!(cookie, hex!["2464c4abcf10c957010000005cf79f111f8130c3eee29480"]);
+  }
+
+  #[test]
+  fn it_issues_a_server_cookie_for_client_only_cookies() {
+    let mut cookies = ServerCookies::with_secret(Secret);
+
+    let outcome = cookies.evaluate_at(Some(&edns(&ClientCookie)), client(), Timestamp);
+
+    assert_eq!(
+      outcome,
+      CookieOutcome::Proceed(Some(cookies.cookie(&ClientCookie, client(), Timestamp)))
+    );
+  }
+
+  #[test]
+  fn it_accepts_a_valid_server_cookie() {
+    let mut cookies = ServerCookies::with_secret(Secret);
+    let cookie = cookies.cookie(&ClientCookie, client(), Timestamp);
+
+    let outcome = cookies.evaluate_at(Some(&edns(&cookie)), client(), Timestamp + 60);
+
+    assert_eq!(outcome, CookieOutcome::Proceed(Some(cookie)));
+  }
+
+  #[test]
+  fn it_refreshes_an_ageing_server_cookie() {
+    let mut cookies = ServerCookies::with_secret(Secret);
+    let cookie = cookies.cookie(&ClientCookie, client(), Timestamp);
+
+    let outcome = cookies.evaluate_at(Some(&edns(&cookie)), client(), Timestamp + 2400);
+
+    let fresh = cookies.cookie(&ClientCookie, client(), Timestamp + 2400);
+    assert_eq!(outcome, CookieOutcome::Proceed(Some(fresh)));
+  }
+
+  #[test]
+  fn it_rejects_expired_or_foreign_server_cookies() {
+    let mut cookies = ServerCookies::with_secret(Secret);
+    let cookie = cookies.cookie(&ClientCookie, client(), Timestamp);
+
+    let expired = cookies.evaluate_at(Some(&edns(&cookie)), client(), Timestamp + 7200);
+    let foreign = cookies.evaluate_at(Some(&edns(&cookie)), "192.0.2.1".parse().unwrap(), Timestamp);
+
+    assert!(matches!(
+      expired,
+      CookieOutcome::Reject(ResponseCode::BadCookie, Some(_))
+    ));
+    assert!(matches!(
+      foreign,
+      CookieOutcome::Reject(ResponseCode::BadCookie, Some(_))
+    ));
+  }
+
+  #[test]
+  fn it_rejects_malformed_cookies() {
+    let mut cookies = ServerCookies::with_secret(Secret);
+
+    let outcome = cookies.evaluate_at(Some(&edns(&[1, 2, 3])), client(), Timestamp);
+
+    assert_eq!(outcome, CookieOutcome::Reject(ResponseCode::FormatError, None));
+  }
+
+  #[test]
+  fn it_requires_server_cookies_when_enforcing() {
+    let mut cookies = ServerCookies::with_secret(Secret);
+    cookies.enforce = true;
+
+    let outcome = cookies.evaluate_at(Some(&edns(&ClientCookie)), client(), Timestamp);
+
+    assert!(matches!(
+      outcome,
+      CookieOutcome::Reject(ResponseCode::BadCookie, Some(_))
+    ));
+  }
+}
@@ -0,0 +1,49 @@
+//! DNSSEC algorithm numbers (RFC 8624) and DS digest types (RFC 4509, RFC 6605).
+
+pub struct SecurityAlgorithm;
+
+impl SecurityAlgorithm {
+  pub const RsaSha1: u8 = 5;
+  pub const RsaSha1Nsec3Sha1: u8 = 7;
+  pub const RsaSha256: u8 = 8;
+  pub const RsaSha512: u8 = 10;
+  pub const EcdsaP256Sha256: u8 = 13;
+  pub const EcdsaP384Sha384: u8 = 14;
+  pub const Ed25519: u8 = 15;
+
+  /// Parses a mnemonic such as `ECDSAP256SHA256`, or the algorithm number.
+  pub fn from_mnemonic(text: &str) -> Option<u8> {
+    match SecurityAlgorithmMnemonics
+      .iter()
+      .find(|(_, mnemonic)| mnemonic.eq_ignore_ascii_case(text))
+    {
+      Some((algorithm, _)) => Some(*algorithm),
+      None => text.parse().ok(),
+    }
+  }
+
+  pub fn mnemonic(algorithm: u8) -> String {
+    match SecurityAlgorithmMnemonics.iter().find(|(known, _)| *known == algorithm) {
+      Some((_, mnemonic)) => mnemonic.to_string(),
+      None => algorithm.to_string(),
+    }
+  }
+}
+
+const SecurityAlgorithmMnemonics: [(u8, &str); 7] = [
+  (SecurityAlgorithm::RsaSha1, "RSASHA1"),
+  (SecurityAlgorithm::RsaSha1Nsec3Sha1, "RSASHA1-NSEC3-SHA1"),
+  (SecurityAlgorithm::RsaSha256, "RSASHA256"),
+  (SecurityAlgorithm::RsaSha512, "RSASHA512"),
+  (SecurityAlgorithm::EcdsaP256Sha256, "ECDSAP256SHA256"),
+  (SecurityAlgorithm::EcdsaP384Sha384, "ECDSAP384SHA384"),
+  (SecurityAlgorithm::Ed25519, "ED25519"),
+];
+
+pub struct DigestType;
+
+impl DigestType {
+  pub const Sha1: u8 = 1;
+  pub const Sha256: u8 = 2;
+  pub const Sha384: u8 = 4;
+}
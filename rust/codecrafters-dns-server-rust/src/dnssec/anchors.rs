@@ -0,0 +1,573 @@
+//! Trust anchors (RFC 4033 section 2): the DS or DNSKEY records a validator trusts without proof,
+//! from which every chain of trust starts. Without configuration the root zone's key signing keys
+//! as published by IANA are used.
+//!
+//! Anchored key sets are followed through key rollovers as RFC 5011 describes. Whenever the DNSKEY
+//! set of an anchored zone is fetched and verified, new secure entry point keys are added pending,
+//! and trusted after the 30 day hold-down if they stay published. Keys gone from the set are
+//! missing but still trusted, and keys published with the REVOKE bit and signing the set are
+//! trusted no more. DS anchors give way to the keys they match the first time those are seen. An
+//! anchor file keeps these states in a comment after each record, one record per line:
+//!
+//! ```text
+//! . DNSKEY 257 3 8 AwEAAa... ; state=valid changed=1700000000
+//! ```
+//!
+//! Negative trust anchors (RFC 7646) turn validation off below a name until they expire.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use data_encoding::{BASE64, HEXUPPER};
+
+use crate::dnssec::{ds_digest, key_tag, verify_rrsig};
+use crate::messages::*;
+use crate::zones::parse_zone;
+
+/// The DS records of the root KSK-2017 and KSK-2024.
+const RootAnchors: &str = "
+. DS 20326 8 2 E06D44B80B8F1D39A95C0B0D7C65D08458E880409BBC683457104237C7F8EC8D
+. DS 38696 8 2 683D2D0ACB8C9B712A1948B27F741219298D0A450D612C483AF444A4C0FB2B16
+";
+
+/// The DNSKEY flag of secure entry points, the keys anchors are made of (RFC 4034 section 2.1.1).
+const SecureEntryPointFlag: u16 = 0x0001;
+/// The DNSKEY flag of revoked keys (RFC 5011 section 7).
+const RevokeFlag: u16 = 0x0080;
+/// How long a new key must stay published before it is trusted (RFC 5011 section 2.4.1).
+const AddHoldDown: u32 = 30 * 24 * 60 * 60;
+/// How long a revoked key is remembered (RFC 5011 section 2.4.2).
+const RemoveHoldDown: u32 = 30 * 24 * 60 * 60;
+
+/// The key states of RFC 5011 section 4.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyState {
+  /// Seen in the key set, waiting out the add hold-down.
+  AddPending,
+  /// Trusted and published.
+  Valid,
+  /// Trusted but gone from the key set.
+  Missing,
+  /// Published with the REVOKE bit by the zone, never trusted again.
+  Revoked,
+}
+
+impl KeyState {
+  const Names: [(KeyState, &str); 4] = [
+    (KeyState::AddPending, "add-pending"),
+    (KeyState::Valid, "valid"),
+    (KeyState::Missing, "missing"),
+    (KeyState::Revoked, "revoked"),
+  ];
+
+  fn name(self) -> &'static str {
+    Self::Names.iter().find(|(state, _)| *state == self).unwrap().1
+  }
+
+  fn from_name(text: &str) -> Option<KeyState> {
+    Self::Names
+      .iter()
+      .find(|(_, name)| *name == text)
+      .map(|(state, _)| *state)
+  }
+
+  fn is_trusted(self) -> bool {
+    matches!(self, KeyState::Valid | KeyState::Missing)
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrustAnchor {
+  /// A DS or DNSKEY record; DS anchors are always valid.
+  pub record: AnswerProperties,
+  pub state: KeyState,
+  /// When the state last changed, in seconds since the epoch.
+  pub changed: u32,
+}
+
+/// A name below which nothing is validated until `until`, in seconds since the epoch.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NegativeTrustAnchor {
+  pub name: DomainName,
+  pub until: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct TrustAnchors {
+  anchors: Vec<TrustAnchor>,
+  negative: Vec<NegativeTrustAnchor>,
+  /// The file key states are saved to as they change.
+  path: Option<PathBuf>,
+}
+
+impl Default for TrustAnchors {
+  fn default() -> Self {
+    Self::parse(RootAnchors).expect("Root trust anchors")
+  }
+}
+
+impl TrustAnchors {
+  /// Reads the anchors of a file and keeps it up to date with their states. A missing file is
+  /// created with the root anchors.
+  pub fn load(path: &Path) -> Result<Self, String> {
+    let mut anchors = match fs::read_to_string(path) {
+      Ok(text) => Self::parse(&text).map_err(|error| format!("{}: {}", path.display(), error))?,
+      Err(error) if error.kind() == io::ErrorKind::NotFound => Self::default(),
+      Err(error) => return Err(format!("{}: {}", path.display(), error)),
+    };
+
+    anchors.path = Some(path.to_path_buf());
+    anchors
+      .save()
+      .map_err(|error| format!("{}: {}", path.display(), error))?;
+    Ok(anchors)
+  }
+
+  /// Parses DS and DNSKEY records in master file format, one per line and names relative to the
+  /// root, each optionally followed by its key state.
+  pub fn parse(text: &str) -> Result<Self, String> {
+    let mut anchors = vec![];
+    for (number, line) in text.lines().enumerate().map(|(index, line)| (index + 1, line.trim())) {
+      let (record, comment) = line.split_once(';').unwrap_or((line, ""));
+      if record.trim().is_empty() {
+        continue;
+      }
+      let invalid = |message: String| format!("Line {}: {}", number, message);
+
+      let records =
+        parse_zone(&format!("$TTL 0\n{}", record), &DomainName::root()).map_err(|error| invalid(error.message))?;
+      let [record] = &records[..] else {
+        return Err(invalid("Expected a single record".to_string()));
+      };
+      if record.record_type != RecordType::DelegationSigner && record.record_type != RecordType::DnsKey {
+        return Err(invalid(format!(
+          "Trust anchor for {} is neither DS nor DNSKEY",
+          record.domain_name
+        )));
+      }
+
+      let (mut state, mut changed) = (KeyState::Valid, 0);
+      for field in comment.split_whitespace() {
+        match field.split_once('=') {
+          Some(("state", value)) => {
+            state = KeyState::from_name(value).ok_or_else(|| invalid(format!("Unknown key state '{}'", value)))?
+          }
+          Some(("changed", value)) => {
+            changed = value
+              .parse()
+              .map_err(|_| invalid(format!("Invalid time '{}'", value)))?
+          }
+          _ => {}
+        }
+      }
+
+      anchors.push(TrustAnchor {
+        record: record.clone(),
+        state,
+        changed,
+      });
+    }
+
+    Ok(Self {
+      anchors,
+      negative: vec![],
+      path: None,
+    })
+  }
+
+  /// The anchors in the file format `parse` reads.
+  pub fn to_text(&self) -> String {
+    let mut text = String::from("; Trust anchors, updated as their keys roll over (RFC 5011).\n");
+    for anchor in &self.anchors {
+      let owner = &anchor.record.domain_name;
+      match anchor.record.record_data() {
+        RecordData::DelegationSigner {
+          key_tag,
+          algorithm,
+          digest_type,
+          digest,
+        } => {
+          text += &format!(
+            "{} DS {} {} {} {}\n",
+            owner,
+            key_tag,
+            algorithm,
+            digest_type,
+            HEXUPPER.encode(&digest)
+          )
+        }
+        RecordData::DnsKey {
+          flags,
+          protocol,
+          algorithm,
+          public_key,
+        } => {
+          text += &format!(
+            "{} DNSKEY {} {} {} {} ; state={} changed={}\n",
+            owner,
+            flags,
+            protocol,
+            algorithm,
+            BASE64.encode(&public_key),
+            anchor.state.name(),
+            anchor.changed
+          )
+        }
+        _ => {}
+      }
+    }
+
+    text
+  }
+
+  /// Writes the anchors to their file, when they came from one.
+  pub fn save(&self) -> io::Result<()> {
+    let Some(path) = &self.path else {
+      return Ok(());
+    };
+
+    // Written aside and moved over, so a crash never leaves the anchors half written.
+    let partial = path.with_extension("tmp");
+    fs::write(&partial, self.to_text())?;
+    fs::rename(&partial, path)
+  }
+
+  pub fn anchors(&self) -> &[TrustAnchor] {
+    &self.anchors
+  }
+
+  /// The anchored name closest to `name`, i.e. the longest one `name` lies under.
+  pub fn closest(&self, name: &DomainName) -> Option<DomainName> {
+    self
+      .anchors
+      .iter()
+      .filter(|anchor| anchor.state.is_trusted())
+      .map(|anchor| &anchor.record.domain_name)
+      .filter(|owner| name.is_subdomain_of(owner))
+      .max_by_key(|owner| owner.label_count())
+      .cloned()
+  }
+
+  /// Whether the DNSKEY record `dnskey` is trusted, by being an anchor or by matching an anchor DS.
+  pub fn trusts(&self, dnskey: &AnswerProperties) -> bool {
+    self
+      .anchors
+      .iter()
+      .filter(|anchor| anchor.state.is_trusted() && anchor.record.domain_name.eq_ignore_case(&dnskey.domain_name))
+      .any(|anchor| match anchor.record.record_type {
+        RecordType::DnsKey => anchor.record.data == dnskey.data,
+        _ => ds_matches(&anchor.record, dnskey),
+      })
+  }
+
+  /// Follows the verified DNSKEY set of the anchored zone at `owner` with its `signatures`,
+  /// returning whether any key changed state.
+  pub fn track(
+    &mut self,
+    owner: &DomainName,
+    dnskeys: &[AnswerProperties],
+    signatures: &[AnswerProperties],
+    now: u32,
+  ) -> bool {
+    let before = self.anchors.clone();
+    let entry_points: Vec<&AnswerProperties> = dnskeys
+      .iter()
+      .filter(|dnskey| key_flags(dnskey) & SecureEntryPointFlag != 0)
+      .collect();
+
+    // Keys matching a DS anchor are trusted right away, and replace it.
+    let vouched: Vec<AnswerProperties> = entry_points
+      .iter()
+      .filter(|dnskey| key_flags(dnskey) & RevokeFlag == 0)
+      .filter(|dnskey| {
+        self
+          .anchors
+          .iter()
+          .any(|anchor| anchor.record.record_type == RecordType::DelegationSigner && ds_matches(&anchor.record, dnskey))
+      })
+      .map(|dnskey| (*dnskey).clone())
+      .collect();
+    if !vouched.is_empty() {
+      self.anchors.retain(|anchor| {
+        anchor.record.record_type != RecordType::DelegationSigner || !anchor.record.domain_name.eq_ignore_case(owner)
+      });
+      for dnskey in vouched {
+        self.set_state(dnskey, KeyState::Valid, now);
+      }
+    }
+
+    for dnskey in &entry_points {
+      let flags = key_flags(dnskey);
+      if flags & RevokeFlag != 0 {
+        // A revoked key only counts when it signed the set itself (RFC 5011 section 2.1).
+        let mut original = (*dnskey).clone();
+        original.data[..2].copy_from_slice(&(flags & !RevokeFlag).to_be_bytes());
+        let known = self
+          .find(&original)
+          .is_some_and(|anchor| anchor.state != KeyState::Revoked);
+        let self_signed = signatures.iter().any(|rrsig| verify_rrsig(rrsig, dnskeys, dnskey, now));
+        if known && self_signed {
+          self.set_state(original, KeyState::Revoked, now);
+        }
+        continue;
+      }
+
+      match self.find(dnskey).map(|anchor| (anchor.state, anchor.changed)) {
+        None => self.set_state((*dnskey).clone(), KeyState::AddPending, now),
+        Some((KeyState::AddPending, since)) if now.saturating_sub(since) >= AddHoldDown => {
+          self.set_state((*dnskey).clone(), KeyState::Valid, now)
+        }
+        Some((KeyState::Missing, _)) => self.set_state((*dnskey).clone(), KeyState::Valid, now),
+        Some(_) => {}
+      }
+    }
+
+    let published = |anchor: &TrustAnchor| {
+      dnskeys.iter().any(|dnskey| {
+        let mut data = dnskey.data.clone();
+        if data.len() >= 2 {
+          let flags = u16::from_be_bytes([data[0], data[1]]) & !RevokeFlag;
+          data[..2].copy_from_slice(&flags.to_be_bytes());
+        }
+        data == anchor.record.data
+      })
+    };
+    for anchor in self.anchors.iter_mut().filter(|anchor| {
+      anchor.record.record_type == RecordType::DnsKey && anchor.record.domain_name.eq_ignore_case(owner)
+    }) {
+      if anchor.state == KeyState::Valid && !published(anchor) {
+        (anchor.state, anchor.changed) = (KeyState::Missing, now);
+      }
+    }
+    self.anchors.retain(|anchor| {
+      let elsewhere =
+        anchor.record.record_type != RecordType::DnsKey || !anchor.record.domain_name.eq_ignore_case(owner);
+      elsewhere
+        || match anchor.state {
+          KeyState::AddPending => published(anchor),
+          KeyState::Revoked => now.saturating_sub(anchor.changed) < RemoveHoldDown,
+          _ => true,
+        }
+    });
+
+    self.anchors != before
+  }
+
+  fn find(&self, dnskey: &AnswerProperties) -> Option<&TrustAnchor> {
+    self.anchors.iter().find(|anchor| {
+      anchor.record.record_type == RecordType::DnsKey
+        && anchor.record.domain_name.eq_ignore_case(&dnskey.domain_name)
+        && anchor.record.data == dnskey.data
+    })
+  }
+
+  fn set_state(&mut self, dnskey: AnswerProperties, state: KeyState, now: u32) {
+    let anchor = TrustAnchor {
+      record: AnswerProperties {
+        time_to_live: 0,
+        ..dnskey
+      },
+      state,
+      changed: now,
+    };
+
+    match self.anchors.iter_mut().find(|existing| {
+      existing.record.record_type == RecordType::DnsKey
+        && existing.record.domain_name.eq_ignore_case(&anchor.record.domain_name)
+        && existing.record.data == anchor.record.data
+    }) {
+      Some(existing) => *existing = anchor,
+      None => self.anchors.push(anchor),
+    }
+  }
+
+  /// Turns validation off at and below `name` until `until`, in seconds since the epoch.
+  pub fn add_negative(&mut self, name: DomainName, until: u32) {
+    self.negative.retain(|anchor| !anchor.name.eq_ignore_case(&name));
+    self.negative.push(NegativeTrustAnchor { name, until });
+  }
+
+  pub fn negative(&self) -> &[NegativeTrustAnchor] {
+    &self.negative
+  }
+
+  /// Whether a negative trust anchor covers `name` at `now`; expired ones are dropped.
+  pub fn is_negative(&mut self, name: &DomainName, now: u32) -> bool {
+    self.negative.retain(|anchor| anchor.until > now);
+
+    self.negative.iter().any(|anchor| name.is_subdomain_of(&anchor.name))
+  }
+}
+
+fn key_flags(dnskey: &AnswerProperties) -> u16 {
+  match dnskey.data[..] {
+    [high, low, ..] => u16::from_be_bytes([high, low]),
+    _ => 0,
+  }
+}
+
+/// Whether the DS record `ds` refers to the DNSKEY record `dnskey` (RFC 4035 section 5.2).
+pub fn ds_matches(ds: &AnswerProperties, dnskey: &AnswerProperties) -> bool {
+  let RecordData::DelegationSigner {
+    key_tag: tag,
+    algorithm,
+    digest_type,
+    digest,
+  } = ds.record_data()
+  else {
+    return false;
+  };
+  let RecordData::DnsKey {
+    algorithm: key_algorithm,
+    ..
+  } = dnskey.record_data()
+  else {
+    return false;
+  };
+
+  ds.domain_name.eq_ignore_case(&dnskey.domain_name)
+    && algorithm == key_algorithm
+    && tag == key_tag(&dnskey.data)
+    && ds_digest(&dnskey.domain_name, &dnskey.data, digest_type) == Some(digest)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::dnssec::{DigestType, SecurityAlgorithm, SigningKey, sign_rrset};
+
+  fn ds_line(key: &SigningKey) -> String {
+    let digest = ds_digest(&key.owner, &key.dnskey(3600).data, DigestType::Sha256).unwrap();
+    format!(
+      "{} DS {} {} 2 {}",
+      key.owner,
+      key.key_tag(),
+      key.algorithm,
+      HEXUPPER.encode(&digest)
+    )
+  }
+
+  fn ksk(owner: &DomainName) -> SigningKey {
+    SigningKey::generate(owner.clone(), SecurityAlgorithm::Ed25519, SigningKey::KeySigningKey).unwrap()
+  }
+
+  /// The DNSKEY records of `keys`, revoking those in `revoked`, with signatures of every key.
+  fn key_set(keys: &[&SigningKey], revoked: &[&SigningKey]) -> (Vec<AnswerProperties>, Vec<AnswerProperties>) {
+    let signers: Vec<SigningKey> = keys
+      .iter()
+      .map(|key| {
+        let mut signer = (*key).clone();
+        if revoked.iter().any(|other| other.key_tag() == key.key_tag()) {
+          signer.flags |= RevokeFlag;
+        }
+        signer
+      })
+      .collect();
+    let dnskeys: Vec<AnswerProperties> = signers.iter().map(|key| key.dnskey(3600)).collect();
+    let signatures = signers
+      .iter()
+      .map(|key| sign_rrset(&dnskeys, key, 0, i32::MAX as u32))
+      .collect();
+
+    (dnskeys, signatures)
+  }
+
+  #[test]
+  fn it_trusts_keys_matching_anchors() {
+    let owner = DomainName::from("example");
+    let key = ksk(&owner);
+    let other = ksk(&owner);
+    let anchors = TrustAnchors::parse(&ds_line(&key)).unwrap();
+
+    assert_eq!(anchors.trusts(&key.dnskey(3600)), true);
+    assert_eq!(anchors.trusts(&other.dnskey(3600)), false);
+    assert_eq!(anchors.closest(&DomainName::from("www.example")), Some(owner));
+    assert_eq!(anchors.closest(&DomainName::from("www.test")), None);
+  }
+
+  #[test]
+  fn it_defaults_to_the_root_keys() {
+    let anchors = TrustAnchors::default();
+
+    assert_eq!(anchors.anchors().len(), 2);
+    assert_eq!(
+      anchors.closest(&DomainName::from("example.com")),
+      Some(DomainName::root())
+    );
+    assert_eq!(TrustAnchors::parse("example. A 192.0.2.1").is_err(), true);
+  }
+
+  #[test]
+  fn it_rolls_keys_over_after_the_hold_down() {
+    let owner = DomainName::from("example");
+    let (old, new) = (ksk(&owner), ksk(&owner));
+    let mut anchors = TrustAnchors::parse(&ds_line(&old)).unwrap();
+    let start = 1_000_000;
+
+    let (dnskeys, signatures) = key_set(&[&old], &[]);
+    assert_eq!(anchors.track(&owner, &dnskeys, &signatures, start), true);
+    assert_eq!(anchors.anchors()[0].record.record_type, RecordType::DnsKey);
+
+    let (dnskeys, signatures) = key_set(&[&old, &new], &[]);
+    anchors.track(&owner, &dnskeys, &signatures, start + 10);
+    assert_eq!(anchors.trusts(&new.dnskey(3600)), false);
+    anchors.track(&owner, &dnskeys, &signatures, start + 10 + AddHoldDown);
+    assert_eq!(anchors.trusts(&new.dnskey(3600)), true);
+
+    let (dnskeys, signatures) = key_set(&[&old, &new], &[&old]);
+    anchors.track(&owner, &dnskeys, &signatures, start + 20 + AddHoldDown);
+    assert_eq!(anchors.trusts(&old.dnskey(3600)), false);
+    assert_eq!(anchors.trusts(&new.dnskey(3600)), true);
+  }
+
+  #[test]
+  fn it_keeps_missing_keys_and_forgets_pending_ones() {
+    let owner = DomainName::from("example");
+    let (old, new) = (ksk(&owner), ksk(&owner));
+    let mut anchors = TrustAnchors::parse(&ds_line(&old)).unwrap();
+
+    let (dnskeys, signatures) = key_set(&[&old, &new], &[]);
+    anchors.track(&owner, &dnskeys, &signatures, 100);
+    let (dnskeys, signatures) = key_set(&[&old], &[]);
+    anchors.track(&owner, &dnskeys, &signatures, 200);
+    let forgotten: Vec<KeyState> = anchors.anchors().iter().map(|anchor| anchor.state).collect();
+    let (dnskeys, signatures) = key_set(&[&new], &[]);
+    anchors.track(&owner, &dnskeys, &signatures, 300);
+    let missing: Vec<KeyState> = anchors.anchors().iter().map(|anchor| anchor.state).collect();
+
+    assert_eq!(forgotten, [KeyState::Valid]);
+    assert_eq!(missing, [KeyState::Missing, KeyState::AddPending]);
+    assert_eq!(anchors.trusts(&old.dnskey(3600)), true);
+    assert_eq!(anchors.trusts(&new.dnskey(3600)), false);
+  }
+
+  #[test]
+  fn it_saves_key_states() {
+    let owner = DomainName(vec!["dotted.label".into(), "example".into()]);
+    let (old, new) = (ksk(&owner), ksk(&owner));
+    let mut anchors = TrustAnchors::parse(&ds_line(&old)).unwrap();
+    let (dnskeys, signatures) = key_set(&[&old, &new], &[]);
+    anchors.track(&owner, &dnskeys, &signatures, 100);
+
+    let reread = TrustAnchors::parse(&anchors.to_text()).unwrap();
+
+    assert_eq!(reread.anchors(), anchors.anchors());
+    assert_eq!(reread.anchors()[1].state, KeyState::AddPending);
+    assert_eq!(reread.anchors()[1].changed, 100);
+  }
+
+  #[test]
+  fn it_expires_negative_anchors() {
+    let mut anchors = TrustAnchors::default();
+    anchors.add_negative(DomainName::from("broken.example"), 1000);
+
+    assert_eq!(anchors.is_negative(&DomainName::from("www.broken.example"), 999), true);
+    assert_eq!(anchors.is_negative(&DomainName::from("example"), 999), false);
+    assert_eq!(
+      anchors.is_negative(&DomainName::from("www.broken.example"), 1000),
+      false
+    );
+    assert_eq!(anchors.negative().is_empty(), true);
+  }
+}
@@ -0,0 +1,203 @@
+//! Canonical form and order (RFC 4034 section 6): owner names and the names embedded in the data
+//! of the RFC 1035 era types are lowercased, names sort label by label from the right, and the
+//! records of an RRset sort by their data as unsigned octets. Key tags and DS digests are computed
+//! from the DNSKEY data (RFC 4034 appendix B and section 5.1.4).
+
+use std::cmp::Ordering;
+
+use ring::digest;
+
+use crate::dnssec::DigestType;
+use crate::messages::*;
+
+/// Orders names as RFC 4034 section 6.1 does: by their lowercased labels, rightmost first.
+pub fn canonical_cmp(first: &DomainName, second: &DomainName) -> Ordering {
+  let labels = |name: &DomainName| {
+    name
+      .0
+      .iter()
+      .rev()
+      .map(|label| label.to_ascii_lowercase().into_bytes())
+      .collect::<Vec<_>>()
+  };
+
+  labels(first).cmp(&labels(second))
+}
+
+/// The record data with embedded names lowercased (RFC 4034 section 6.2, RFC 6840 section 5.1).
+pub fn canonical_data(record: &AnswerProperties) -> Vec<u8> {
+  let data = match record.record_data() {
+    RecordData::NameServer(name) => RecordData::NameServer(name.to_lowercase()),
+    RecordData::CanonicalName(name) => RecordData::CanonicalName(name.to_lowercase()),
+    RecordData::Pointer(name) => RecordData::Pointer(name.to_lowercase()),
+    RecordData::DelegationName(name) => RecordData::DelegationName(name.to_lowercase()),
+    RecordData::StartOfAuthority {
+      primary,
+      mailbox,
+      serial,
+      refresh,
+      retry,
+      expire,
+      minimum,
+    } => RecordData::StartOfAuthority {
+      primary: primary.to_lowercase(),
+      mailbox: mailbox.to_lowercase(),
+      serial,
+      refresh,
+      retry,
+      expire,
+      minimum,
+    },
+    RecordData::MailExchange { preference, exchange } => RecordData::MailExchange {
+      preference,
+      exchange: exchange.to_lowercase(),
+    },
+    RecordData::Service {
+      priority,
+      weight,
+      port,
+      target,
+    } => RecordData::Service {
+      priority,
+      weight,
+      port,
+      target: target.to_lowercase(),
+    },
+    RecordData::NamingAuthority {
+      order,
+      preference,
+      flags,
+      services,
+      regexp,
+      replacement,
+    } => RecordData::NamingAuthority {
+      order,
+      preference,
+      flags,
+      services,
+      regexp,
+      replacement: replacement.to_lowercase(),
+    },
+    RecordData::Signature {
+      type_covered,
+      algorithm,
+      labels,
+      original_ttl,
+      expiration,
+      inception,
+      key_tag,
+      signer,
+      signature,
+    } => RecordData::Signature {
+      type_covered,
+      algorithm,
+      labels,
+      original_ttl,
+      expiration,
+      inception,
+      key_tag,
+      signer: signer.to_lowercase(),
+      signature,
+    },
+    _ => return record.data.clone(),
+  };
+
+  data.to_bytes()
+}
+
+/// The RRset in canonical form and order, without duplicate records (RFC 4034 section 6.3).
+pub fn canonical_rrset(rrset: &[AnswerProperties]) -> Vec<AnswerProperties> {
+  let mut canonical: Vec<AnswerProperties> = rrset
+    .iter()
+    .map(|record| AnswerProperties {
+      domain_name: record.domain_name.to_lowercase(),
+      data: canonical_data(record),
+      ..record.clone()
+    })
+    .collect();
+  canonical.sort_by(|first, second| first.data.cmp(&second.data));
+  canonical.dedup_by(|first, second| first.data == second.data);
+
+  canonical
+}
+
+/// The key tag of DNSKEY data (RFC 4034 appendix B).
+pub fn key_tag(dnskey: &[u8]) -> u16 {
+  let mut sum: u32 = 0;
+  for (index, byte) in dnskey.iter().enumerate() {
+    sum += match index % 2 {
+      0 => (*byte as u32) << 8,
+      _ => *byte as u32,
+    };
+  }
+  sum += (sum >> 16) & 0xffff;
+
+  (sum & 0xffff) as u16
+}
+
+/// The digest of a DS record for the DNSKEY data owned by `owner`, or `None` for unsupported types.
+pub fn ds_digest(owner: &DomainName, dnskey: &[u8], digest_type: u8) -> Option<Vec<u8>> {
+  let algorithm = match digest_type {
+    DigestType::Sha1 => &digest::SHA1_FOR_LEGACY_USE_ONLY,
+    DigestType::Sha256 => &digest::SHA256,
+    DigestType::Sha384 => &digest::SHA384,
+    _ => return None,
+  };
+
+  let mut data = owner.to_lowercase().to_bytes();
+  data.extend(dnskey);
+  Some(digest::digest(algorithm, &data).as_ref().to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::macros::*;
+  use data_encoding::BASE64;
+
+  #[test]
+  fn it_orders_names_canonically() {
+    let mut names: Vec<DomainName> = [
+      "z.example",
+      "a.example",
+      "yljkjljk.a.example",
+      "Z.a.example",
+      "example",
+      "*.z.example",
+    ]
+    .iter()
+    .map(|name| DomainName::from(*name))
+    .collect();
+
+    names.sort_by(canonical_cmp);
+
+    let names: Vec<String> = names.iter().map(|name| name.0.join(".")).collect();
+    assert_eq!(
+      names,
+      [
+        "example",
+        "a.example",
+        "yljkjljk.a.example",
+        "Z.a.example",
+        "z.example",
+        "*.z.example"
+      ]
+    );
+  }
+
+  #[test]
+  fn it_computes_key_tags_and_ds_digests() {
+    // dskey.example.com from RFC 4034 section 5.4.
+    let public_key = "AQOeiiR0GOMYkDshWoSKz9XzfwJr1AYtsmx3TGkJaNXVbfi/2pHm822aJ5iI9BMzNXxeYCmZDRD99WYwYqUSdjMmmAphXdvxegXd/M5+X7OrzKBaMbCVdFLUUh6DhweJBjEVv5f2wwjM9XzcnOf+EPbtG9DMBmADjFDc2w/rljwvFw==";
+    let mut dnskey = vec![1, 0, 3, 5];
+    dnskey.extend(BASE64.decode(public_key.as_bytes()).unwrap());
+    let owner = DomainName::from("dskey.example.com");
+
+    assert_eq!(key_tag(&dnskey), 60485);
+    assert_eq!(
+      ds_digest(&owner, &dnskey, DigestType::Sha1).unwrap(),
+      hex!["2bb183af5f22588179a53b0a98631fad1a292118"]
+    );
+    assert_eq!(ds_digest(&owner, &dnskey, 99), None);
+  }
+}
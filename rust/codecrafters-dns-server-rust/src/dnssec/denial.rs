@@ -0,0 +1,380 @@
+//! Authenticated denial of existence (RFC 4035 section 5.4, RFC 5155 section 8): whether verified
+//! NSEC or NSEC3 records prove that a name does not exist, that it has no data of a type, that no
+//! closer match ruled out a wildcard expansion, or that a delegation is unsigned.
+//!
+//! NSEC records found at delegation points or DNAME owners say nothing about the names below them,
+//! which belong to another zone or are redirected, so they never prove those names missing.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use crate::dnssec::{Nsec3OptOut, Nsec3Sha1, canonical_cmp, covers, nsec3_hash, owner_hash};
+use crate::messages::*;
+
+/// Whether `records` prove that `name` does not exist, wildcard included.
+pub fn proves_name_error(records: &[AnswerProperties], name: &DomainName) -> bool {
+  let nsec =
+    nsec_closest_encloser(records, name).is_some_and(|encloser| nsec_covering(records, &wildcard(&encloser)).is_some());
+  let nsec3 = nsec3_closest_encloser(records, name)
+    .is_some_and(|(encloser, _)| nsec3_covering(records, &wildcard(&encloser)).is_some());
+
+  nsec || nsec3
+}
+
+/// Whether `records` prove that `name` exists without records of `record_type`, either itself or
+/// through the wildcard that would have been expanded for it.
+pub fn proves_no_data(records: &[AnswerProperties], name: &DomainName, record_type: RecordType) -> bool {
+  let lacks = |types: &[RecordType]| {
+    !types.contains(&record_type)
+      && !types.contains(&RecordType::CanonicalName)
+      && match record_type {
+        // The parent side of a cut proves DS missing, the child apex proves the rest.
+        RecordType::DelegationSigner => !types.contains(&RecordType::StartOfAuthority),
+        _ => !is_cut(types),
+      }
+  };
+
+  if nsec_matching(records, name).is_some_and(|types| lacks(&types)) {
+    return true;
+  }
+  // An empty non-terminal has no NSEC of its own, only names below it following it.
+  let empty_non_terminal = records.iter().any(|record| match record.record_data() {
+    RecordData::NextSecure { next, .. } => {
+      nsec_spans(record, &next, name) && next.is_subdomain_of(name) && !next.eq_ignore_case(name)
+    }
+    _ => false,
+  });
+  if empty_non_terminal {
+    return true;
+  }
+  if nsec_closest_encloser(records, name).is_some_and(|encloser| {
+    let wildcard = wildcard(&encloser);
+    nsec_matching(records, &wildcard).is_some_and(|types| lacks(&types))
+  }) {
+    return true;
+  }
+
+  if nsec3_matching(records, name).is_some_and(|(_, types)| lacks(&types)) {
+    return true;
+  }
+  nsec3_closest_encloser(records, name).is_some_and(|(encloser, _)| {
+    let wildcard = wildcard(&encloser);
+    nsec3_matching(records, &wildcard).is_some_and(|(_, types)| lacks(&types))
+  })
+}
+
+/// Whether `records` prove that no name closer to `name` than the wildcard a signature with
+/// `labels` labels was expanded from exists (RFC 4035 section 5.3.4).
+pub fn proves_wildcard_expansion(records: &[AnswerProperties], name: &DomainName, labels: usize) -> bool {
+  if labels >= name.label_count() {
+    return false;
+  }
+
+  let next_closer = name.suffix(labels + 1);
+  nsec_covering(records, name).is_some() || nsec3_covering(records, &next_closer).is_some()
+}
+
+/// Whether `records` prove that the delegation at `name` has no DS records, either directly or by
+/// an opt-out span covering it (RFC 5155 section 8.9).
+pub fn proves_unsigned_delegation(records: &[AnswerProperties], name: &DomainName) -> bool {
+  let unsigned = |types: &[RecordType]| {
+    types.contains(&RecordType::NameServer)
+      && !types.contains(&RecordType::DelegationSigner)
+      && !types.contains(&RecordType::StartOfAuthority)
+  };
+
+  nsec_matching(records, name).is_some_and(|types| unsigned(&types))
+    || nsec3_matching(records, name).is_some_and(|(_, types)| unsigned(&types))
+    || nsec3_closest_encloser(records, name).is_some_and(|(_, flags)| flags & Nsec3OptOut != 0)
+}
+
+/// The records among `records` that match or cover `name`, one of its ancestors, or the wildcard
+/// directly below one of them: all a proof about `name` can be made of.
+pub fn proof_records(records: &[AnswerProperties], name: &DomainName) -> Vec<AnswerProperties> {
+  let candidates: Vec<DomainName> = (0..=name.label_count())
+    .flat_map(|count| [name.suffix(count), wildcard(&name.suffix(count))])
+    .collect();
+  let mut hashes: HashMap<(Vec<u8>, u16, DomainName), Vec<u8>> = HashMap::new();
+
+  records
+    .iter()
+    .filter(|record| match record.record_data() {
+      RecordData::NextSecure { next, .. } => candidates
+        .iter()
+        .any(|candidate| record.domain_name.eq_ignore_case(candidate) || nsec_spans(record, &next, candidate)),
+      RecordData::NextSecure3 {
+        algorithm: Nsec3Sha1,
+        iterations,
+        salt,
+        next_hashed,
+        ..
+      } => {
+        let Some(owner) = owner_hash(&record.domain_name) else {
+          return false;
+        };
+        candidates
+          .iter()
+          .filter(|candidate| in_nsec3_zone(record, candidate))
+          .any(|candidate| {
+            let hash = hashes
+              .entry((salt.clone(), iterations, candidate.clone()))
+              .or_insert_with(|| nsec3_hash(candidate, &salt, iterations));
+            *hash == owner || covers(&owner, &next_hashed, hash)
+          })
+      }
+      _ => false,
+    })
+    .cloned()
+    .collect()
+}
+
+fn wildcard(encloser: &DomainName) -> DomainName {
+  let mut labels = vec!["*".to_string()];
+  labels.extend(encloser.0.iter().cloned());
+
+  DomainName(labels)
+}
+
+/// A delegation point: NS records outside of the zone apex.
+fn is_cut(types: &[RecordType]) -> bool {
+  types.contains(&RecordType::NameServer) && !types.contains(&RecordType::StartOfAuthority)
+}
+
+/// Whether a record with `types` at an ancestor of a name hides that name from its zone.
+fn hides_descendants(types: &[RecordType]) -> bool {
+  is_cut(types) || types.contains(&RecordType::DelegationName)
+}
+
+fn nsec_matching(records: &[AnswerProperties], name: &DomainName) -> Option<Vec<RecordType>> {
+  records
+    .iter()
+    .filter(|record| record.domain_name.eq_ignore_case(name))
+    .find_map(|record| match record.record_data() {
+      RecordData::NextSecure { types, .. } => Some(types),
+      _ => None,
+    })
+}
+
+/// Whether the NSEC record at `record`, pointing at `next`, spans `name`. The last NSEC of a zone
+/// points back at its apex.
+fn nsec_spans(record: &AnswerProperties, next: &DomainName, name: &DomainName) -> bool {
+  let owner = &record.domain_name;
+  let after_owner = canonical_cmp(owner, name) == Ordering::Less;
+
+  match canonical_cmp(owner, next) {
+    Ordering::Less => after_owner && canonical_cmp(name, next) == Ordering::Less,
+    _ => after_owner && name.is_subdomain_of(next),
+  }
+}
+
+/// The NSEC record proving that `name` does not exist, as its owner and next name.
+fn nsec_covering(records: &[AnswerProperties], name: &DomainName) -> Option<(DomainName, DomainName)> {
+  records.iter().find_map(|record| match record.record_data() {
+    RecordData::NextSecure { next, types } => {
+      let hidden = hides_descendants(&types) && name.is_subdomain_of(&record.domain_name);
+      (!hidden && nsec_spans(record, &next, name)).then(|| (record.domain_name.clone(), next))
+    }
+    _ => None,
+  })
+}
+
+/// The closest encloser of a `name` that does not exist: the longest ancestor it shares with either
+/// end of the NSEC span covering it.
+fn nsec_closest_encloser(records: &[AnswerProperties], name: &DomainName) -> Option<DomainName> {
+  let (owner, next) = nsec_covering(records, name)?;
+
+  let shared = |other: &DomainName| {
+    (0..=name.label_count().min(other.label_count()))
+      .rev()
+      .find(|&count| name.suffix(count).eq_ignore_case(&other.suffix(count)))
+      .unwrap_or(0)
+  };
+  Some(name.suffix(shared(&owner).max(shared(&next))))
+}
+
+/// The NSEC3 record whose hash matches `name` in a zone `name` lies in, as its flags and types.
+fn nsec3_matching(records: &[AnswerProperties], name: &DomainName) -> Option<(u8, Vec<RecordType>)> {
+  records.iter().find_map(|record| match record.record_data() {
+    RecordData::NextSecure3 {
+      algorithm: Nsec3Sha1,
+      flags,
+      iterations,
+      salt,
+      types,
+      ..
+    } if in_nsec3_zone(record, name) => {
+      (owner_hash(&record.domain_name)? == nsec3_hash(name, &salt, iterations)).then_some((flags, types))
+    }
+    _ => None,
+  })
+}
+
+/// The flags of the NSEC3 record whose span covers the hash of `name`.
+fn nsec3_covering(records: &[AnswerProperties], name: &DomainName) -> Option<u8> {
+  records.iter().find_map(|record| match record.record_data() {
+    RecordData::NextSecure3 {
+      algorithm: Nsec3Sha1,
+      flags,
+      iterations,
+      salt,
+      next_hashed,
+      ..
+    } if in_nsec3_zone(record, name) => {
+      let owner = owner_hash(&record.domain_name)?;
+      covers(&owner, &next_hashed, &nsec3_hash(name, &salt, iterations)).then_some(flags)
+    }
+    _ => None,
+  })
+}
+
+fn in_nsec3_zone(record: &AnswerProperties, name: &DomainName) -> bool {
+  record
+    .domain_name
+    .parent()
+    .is_some_and(|zone| name.is_subdomain_of(&zone))
+}
+
+/// The closest encloser proof of RFC 5155 section 8.3 for a `name` that does not exist: an ancestor
+/// with a matching NSEC3 record and the next closer name covered, along with the covering flags.
+fn nsec3_closest_encloser(records: &[AnswerProperties], name: &DomainName) -> Option<(DomainName, u8)> {
+  for count in (0..=name.label_count()).rev() {
+    let ancestor = name.suffix(count);
+    let Some((_, types)) = nsec3_matching(records, &ancestor) else {
+      continue;
+    };
+    if count == name.label_count() || hides_descendants(&types) {
+      return None;
+    }
+
+    return nsec3_covering(records, &name.suffix(count + 1)).map(|flags| (ancestor, flags));
+  }
+
+  None
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::dnssec::hashed_owner;
+
+  fn nsec(owner: &str, next: &str, types: &[RecordType]) -> AnswerProperties {
+    AnswerProperties {
+      domain_name: DomainName::from(owner),
+      record_type: RecordType::NextSecure,
+      record_class: RecordClass::Internet,
+      time_to_live: 60,
+      data: RecordData::NextSecure {
+        next: DomainName::from(next),
+        types: types.to_vec(),
+      }
+      .to_bytes(),
+    }
+  }
+
+  /// The NSEC3 chain of a zone at `example` holding `names`, hashed with salt aabb and 2 iterations.
+  fn nsec3_chain(names: &[(&str, &[RecordType])], flags: u8) -> Vec<AnswerProperties> {
+    let origin = DomainName::from("example");
+    let mut hashed: Vec<(Vec<u8>, Vec<RecordType>)> = names
+      .iter()
+      .map(|(name, types)| (nsec3_hash(&DomainName::from(*name), &[0xaa, 0xbb], 2), types.to_vec()))
+      .collect();
+    hashed.sort();
+
+    (0..hashed.len())
+      .map(|index| AnswerProperties {
+        domain_name: hashed_owner(&hashed[index].0, &origin),
+        record_type: RecordType::NextSecure3,
+        record_class: RecordClass::Internet,
+        time_to_live: 60,
+        data: RecordData::NextSecure3 {
+          algorithm: Nsec3Sha1,
+          flags,
+          iterations: 2,
+          salt: vec![0xaa, 0xbb],
+          next_hashed: hashed[(index + 1) % hashed.len()].0.clone(),
+          types: hashed[index].1.clone(),
+        }
+        .to_bytes(),
+      })
+      .collect()
+  }
+
+  #[test]
+  fn it_proves_names_missing_with_nsec() {
+    let records = [
+      nsec("b.example", "d.example", &[RecordType::Host]),
+      nsec("example", "a.example", &[RecordType::StartOfAuthority]),
+    ];
+
+    assert_eq!(proves_name_error(&records, &DomainName::from("c.example")), true);
+    assert_eq!(proves_name_error(&records, &DomainName::from("a.example")), false);
+    assert_eq!(
+      proves_no_data(&records, &DomainName::from("b.example"), RecordType::Text),
+      true
+    );
+    assert_eq!(
+      proves_no_data(&records, &DomainName::from("b.example"), RecordType::Host),
+      false
+    );
+  }
+
+  #[test]
+  fn it_ignores_nsec_below_delegations() {
+    let records = [
+      nsec("sub.example", "z.example", &[RecordType::NameServer]),
+      nsec("example", "a.example", &[RecordType::StartOfAuthority]),
+    ];
+
+    assert_eq!(proves_name_error(&records, &DomainName::from("www.sub.example")), false);
+    assert_eq!(
+      proves_unsigned_delegation(&records, &DomainName::from("sub.example")),
+      true
+    );
+    assert_eq!(
+      proves_no_data(&records, &DomainName::from("sub.example"), RecordType::Host),
+      false
+    );
+  }
+
+  #[test]
+  fn it_proves_names_missing_with_nsec3() {
+    let records = nsec3_chain(
+      &[
+        ("example", &[RecordType::StartOfAuthority]),
+        ("a.example", &[RecordType::Host]),
+        ("b.example", &[RecordType::Host]),
+      ],
+      0,
+    );
+
+    assert_eq!(proves_name_error(&records, &DomainName::from("d.example")), true);
+    assert_eq!(proves_name_error(&records, &DomainName::from("a.example")), false);
+    assert_eq!(
+      proves_no_data(&records, &DomainName::from("a.example"), RecordType::Text),
+      true
+    );
+    assert_eq!(
+      proves_no_data(&records, &DomainName::from("a.example"), RecordType::Host),
+      false
+    );
+    assert_eq!(
+      proves_wildcard_expansion(&records, &DomainName::from("d.example"), 1),
+      true
+    );
+  }
+
+  #[test]
+  fn it_proves_unsigned_delegations_in_opt_out_spans() {
+    let names: [(&str, &[RecordType]); 2] = [
+      ("example", &[RecordType::StartOfAuthority]),
+      ("a.example", &[RecordType::Host]),
+    ];
+    let delegation = DomainName::from("insecure.example");
+
+    assert_eq!(
+      proves_unsigned_delegation(&nsec3_chain(&names, Nsec3OptOut), &delegation),
+      true
+    );
+    assert_eq!(proves_unsigned_delegation(&nsec3_chain(&names, 0), &delegation), false);
+  }
+}
@@ -0,0 +1,216 @@
+//! Zone signing keys, read from a key file holding one key per line:
+//! `<zone> <KSK|ZSK> <algorithm> <base64 PKCS#8 private key>`, e.g.
+//! `example.com. KSK ECDSAP256SHA256 MIGHAgEAMBMGByqGSM49...`.
+//! Keys signing the DNSKEY RRset carry the SEP flag (KSK); the others sign the rest of the zone.
+//! Blank lines and lines starting with `;` or `#` are skipped.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use data_encoding::BASE64;
+use ring::rand::SystemRandom;
+use ring::signature::{ECDSA_P256_SHA256_FIXED_SIGNING, EcdsaKeyPair, Ed25519KeyPair, KeyPair};
+
+use crate::dnssec::{SecurityAlgorithm, key_tag};
+use crate::messages::*;
+
+enum Pair {
+  Ecdsa(EcdsaKeyPair),
+  Ed25519(Ed25519KeyPair),
+}
+
+#[derive(Clone)]
+pub struct SigningKey {
+  pub owner: DomainName,
+  pub flags: u16,
+  pub algorithm: u8,
+  pkcs8: Vec<u8>,
+  pair: Arc<Pair>,
+}
+
+impl SigningKey {
+  pub const ZoneSigningKey: u16 = 256;
+  /// A zone key with the secure entry point flag (RFC 4034 section 2.1.1).
+  pub const KeySigningKey: u16 = 257;
+
+  /// A new key of an algorithm that can sign, ECDSA P-256 or Ed25519.
+  pub fn generate(owner: DomainName, algorithm: u8, flags: u16) -> Result<Self, String> {
+    let random = SystemRandom::new();
+    let pkcs8 = match algorithm {
+      SecurityAlgorithm::EcdsaP256Sha256 => EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &random),
+      SecurityAlgorithm::Ed25519 => Ed25519KeyPair::generate_pkcs8(&random),
+      _ => return Err(format!("Cannot sign with algorithm {}", algorithm)),
+    }
+    .map_err(|_| "Failed to generate a key".to_string())?;
+
+    Self::from_pkcs8(owner, algorithm, flags, pkcs8.as_ref())
+  }
+
+  pub fn from_pkcs8(owner: DomainName, algorithm: u8, flags: u16, pkcs8: &[u8]) -> Result<Self, String> {
+    let pair = match algorithm {
+      SecurityAlgorithm::EcdsaP256Sha256 => {
+        EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8, &SystemRandom::new()).map(Pair::Ecdsa)
+      }
+      SecurityAlgorithm::Ed25519 => Ed25519KeyPair::from_pkcs8_maybe_unchecked(pkcs8).map(Pair::Ed25519),
+      _ => return Err(format!("Cannot sign with algorithm {}", algorithm)),
+    }
+    .map_err(|error| format!("Invalid private key: {}", error))?;
+
+    Ok(Self {
+      owner,
+      flags,
+      algorithm,
+      pkcs8: pkcs8.to_vec(),
+      pair: Arc::new(pair),
+    })
+  }
+
+  pub fn is_key_signing(&self) -> bool {
+    self.flags & 1 != 0
+  }
+
+  /// The public key as DNSKEY carries it: the point without its uncompressed marker for ECDSA
+  /// (RFC 6605 section 4), the raw key for Ed25519 (RFC 8080 section 3).
+  pub fn public_key(&self) -> Vec<u8> {
+    match self.pair.as_ref() {
+      Pair::Ecdsa(pair) => pair.public_key().as_ref()[1..].to_vec(),
+      Pair::Ed25519(pair) => pair.public_key().as_ref().to_vec(),
+    }
+  }
+
+  pub fn dnskey(&self, time_to_live: u32) -> AnswerProperties {
+    AnswerProperties {
+      domain_name: self.owner.clone(),
+      record_type: RecordType::DnsKey,
+      record_class: RecordClass::Internet,
+      time_to_live,
+      data: RecordData::DnsKey {
+        flags: self.flags,
+        protocol: 3,
+        algorithm: self.algorithm,
+        public_key: self.public_key(),
+      }
+      .to_bytes(),
+    }
+  }
+
+  pub fn key_tag(&self) -> u16 {
+    key_tag(&self.dnskey(0).data)
+  }
+
+  pub fn sign(&self, data: &[u8]) -> Vec<u8> {
+    match self.pair.as_ref() {
+      Pair::Ecdsa(pair) => pair
+        .sign(&SystemRandom::new(), data)
+        .expect("The system random source works")
+        .as_ref()
+        .to_vec(),
+      Pair::Ed25519(pair) => pair.sign(data).as_ref().to_vec(),
+    }
+  }
+
+  /// The line of a key file holding this key.
+  pub fn to_line(&self) -> String {
+    format!(
+      "{} {} {} {}",
+      self.owner,
+      if self.is_key_signing() { "KSK" } else { "ZSK" },
+      SecurityAlgorithm::mnemonic(self.algorithm),
+      BASE64.encode(&self.pkcs8)
+    )
+  }
+}
+
+/// Leaves the private key out of logs.
+impl fmt::Debug for SigningKey {
+  fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+    formatter
+      .debug_struct("SigningKey")
+      .field("owner", &self.owner)
+      .field("flags", &self.flags)
+      .field("algorithm", &self.algorithm)
+      .field("key_tag", &self.key_tag())
+      .finish_non_exhaustive()
+  }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SigningKeys(Vec<SigningKey>);
+
+impl SigningKeys {
+  pub fn load(path: &Path) -> Result<Self, String> {
+    let text = fs::read_to_string(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+
+    Self::parse(&text).map_err(|error| format!("{}: {}", path.display(), error))
+  }
+
+  pub fn parse(text: &str) -> Result<Self, String> {
+    let mut keys = vec![];
+    for (number, line) in text.lines().enumerate().map(|(index, line)| (index + 1, line.trim())) {
+      if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+        continue;
+      }
+
+      let invalid = |reason: &str| format!("line {}: {}", number, reason);
+      let [owner, role, algorithm, private_key] = line.split_whitespace().collect::<Vec<_>>()[..] else {
+        return Err(invalid("expected <zone> <KSK|ZSK> <algorithm> <base64 private key>"));
+      };
+      let flags = match role.to_ascii_uppercase().as_str() {
+        "KSK" => SigningKey::KeySigningKey,
+        "ZSK" => SigningKey::ZoneSigningKey,
+        _ => return Err(invalid("role must be KSK or ZSK")),
+      };
+      let algorithm = SecurityAlgorithm::from_mnemonic(algorithm).ok_or_else(|| invalid("unknown algorithm"))?;
+      let private_key = BASE64
+        .decode(private_key.as_bytes())
+        .map_err(|_| invalid("private key is not base64"))?;
+
+      let owner = parse_name(owner, &DomainName::root()).map_err(|error| invalid(&error))?;
+      let key = SigningKey::from_pkcs8(owner, algorithm, flags, &private_key).map_err(|error| invalid(&error))?;
+      keys.push(key);
+    }
+
+    Ok(SigningKeys(keys))
+  }
+
+  /// The keys of the zone at `origin`.
+  pub fn for_zone(&self, origin: &DomainName) -> Vec<SigningKey> {
+    self
+      .0
+      .iter()
+      .filter(|key| key.owner.eq_ignore_case(origin))
+      .cloned()
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn it_writes_and_reads_key_files() {
+    let origin = DomainName::from("example.com");
+    let ksk = SigningKey::generate(
+      origin.clone(),
+      SecurityAlgorithm::EcdsaP256Sha256,
+      SigningKey::KeySigningKey,
+    )
+    .unwrap();
+    let zsk = SigningKey::generate(origin.clone(), SecurityAlgorithm::Ed25519, SigningKey::ZoneSigningKey).unwrap();
+
+    let keys = SigningKeys::parse(&format!("; example.com\n{}\n{}\n", ksk.to_line(), zsk.to_line())).unwrap();
+    let loaded = keys.for_zone(&DomainName::from("EXAMPLE.com"));
+
+    assert_eq!(loaded.len(), 2);
+    assert_eq!(loaded[0].is_key_signing(), true);
+    assert_eq!(loaded[0].public_key().len(), 64);
+    assert_eq!(loaded[0].key_tag(), ksk.key_tag());
+    assert_eq!(loaded[1].public_key(), zsk.public_key());
+    assert_eq!(loaded[1].sign(b"data").len(), 64);
+    assert!(keys.for_zone(&DomainName::from("example.net")).is_empty());
+    assert!(SigningKey::generate(origin, SecurityAlgorithm::RsaSha256, SigningKey::ZoneSigningKey).is_err());
+  }
+}
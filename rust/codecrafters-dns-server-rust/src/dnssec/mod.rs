@@ -0,0 +1,20 @@
+//! DNSSEC (RFC 4033, RFC 4034, RFC 4035): signing keys, the canonical form signatures are computed
+//! over, the NSEC3 hashing of owner names (RFC 5155), and the checks validation is built from.
+
+pub mod algorithm;
+pub mod anchors;
+pub mod canonical;
+pub mod denial;
+pub mod keys;
+pub mod nsec3;
+pub mod sign;
+pub mod verify;
+
+pub use algorithm::*;
+pub use anchors::*;
+pub use canonical::*;
+pub use denial::*;
+pub use keys::*;
+pub use nsec3::*;
+pub use sign::*;
+pub use verify::*;
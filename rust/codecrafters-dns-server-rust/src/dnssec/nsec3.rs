@@ -0,0 +1,97 @@
+//! NSEC3 owner name hashing (RFC 5155 section 5): SHA-1 over the lowercased wire name and the
+//! salt, iterated over its own output and the salt `iterations` more times. Hashed owner names are
+//! the Base32 (extended hex alphabet) hash as a single label under the zone apex.
+
+use std::cmp::Ordering;
+
+use data_encoding::BASE32_DNSSEC;
+use ring::digest;
+
+use crate::messages::*;
+
+/// The only hash algorithm defined for NSEC3 (RFC 5155 section 11).
+pub const Nsec3Sha1: u8 = 1;
+/// The NSEC3 flag marking spans that may hold unsigned delegations (RFC 5155 section 3.1.2.1).
+pub const Nsec3OptOut: u8 = 1;
+
+pub fn nsec3_hash(name: &DomainName, salt: &[u8], iterations: u16) -> Vec<u8> {
+  let round = |data: &[u8]| {
+    let mut input = data.to_vec();
+    input.extend(salt);
+    digest::digest(&digest::SHA1_FOR_LEGACY_USE_ONLY, &input)
+      .as_ref()
+      .to_vec()
+  };
+
+  let mut hash = round(&name.to_lowercase().to_bytes());
+  for _ in 0..iterations {
+    hash = round(&hash);
+  }
+
+  hash
+}
+
+/// The owner name of the NSEC3 record for `name` in the zone at `origin`.
+pub fn hashed_name(name: &DomainName, origin: &DomainName, salt: &[u8], iterations: u16) -> DomainName {
+  hashed_owner(&nsec3_hash(name, salt, iterations), origin)
+}
+
+/// The owner name of the NSEC3 record with `hash` in the zone at `origin`.
+pub fn hashed_owner(hash: &[u8], origin: &DomainName) -> DomainName {
+  let mut labels = vec![BASE32_DNSSEC.encode(hash)];
+  labels.extend(origin.0.iter().cloned());
+
+  DomainName(labels)
+}
+
+/// The hash carried in the first label of an NSEC3 owner name.
+pub fn owner_hash(owner: &DomainName) -> Option<Vec<u8>> {
+  BASE32_DNSSEC
+    .decode(owner.0.first()?.to_ascii_lowercase().as_bytes())
+    .ok()
+}
+
+/// Whether the NSEC3 span from `owner` to `next` strictly covers `hash`; the last record of the
+/// chain wraps around to the first.
+pub fn covers(owner: &[u8], next: &[u8], hash: &[u8]) -> bool {
+  match owner.cmp(next) {
+    Ordering::Less => owner < hash && hash < next,
+    _ => owner < hash || hash < next,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn it_hashes_owner_names() {
+    // The example zone of RFC 5155 appendix A, with salt aabbccdd and 12 iterations.
+    let origin = DomainName::from("example");
+    let salt = [0xaa, 0xbb, 0xcc, 0xdd];
+
+    assert_eq!(
+      hashed_name(&origin, &origin, &salt, 12),
+      DomainName::from("0p9mhaveqvm6t7vbl5lop2u3t2rp3tom.example")
+    );
+    assert_eq!(
+      hashed_name(&DomainName::from("A.example"), &origin, &salt, 12),
+      DomainName::from("35mthgpgcu1qg68fab165klnsnk3dpvl.example")
+    );
+    assert_eq!(
+      owner_hash(&DomainName::from("35MTHGPGCU1QG68FAB165KLNSNK3DPVL.example")),
+      Some(nsec3_hash(&DomainName::from("a.example"), &salt, 12))
+    );
+  }
+
+  #[test]
+  fn it_covers_hashes_between_owner_and_next() {
+    assert_eq!(covers(&[2], &[5], &[3]), true);
+    assert_eq!(covers(&[2], &[5], &[5]), false);
+    assert_eq!(covers(&[2], &[5], &[2]), false);
+    assert_eq!(covers(&[5], &[2], &[9]), true);
+    assert_eq!(covers(&[5], &[2], &[1]), true);
+    assert_eq!(covers(&[5], &[2], &[3]), false);
+    assert_eq!(covers(&[5], &[5], &[3]), true);
+  }
+}
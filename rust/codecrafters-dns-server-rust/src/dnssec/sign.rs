@@ -0,0 +1,185 @@
+//! RRSIG computation (RFC 4034 section 3.1.8.1): the signature covers the RRSIG data up to the
+//! signer's name, followed by every record of the RRset in canonical form and order, each with the
+//! original TTL and, for wildcard expansions, the wildcard owner the RRset was synthesized from.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::dnssec::{SigningKey, canonical_rrset};
+use crate::messages::*;
+
+/// Seconds since the epoch as signature validity periods count them (RFC 4034 section 3.1.5).
+pub fn timestamp_now() -> u32 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|duration| duration.as_secs() as u32)
+    .unwrap_or(0)
+}
+
+/// The labels field of a signature over records owned by `owner`: wildcard labels do not count.
+pub fn signature_labels(owner: &DomainName) -> u8 {
+  match owner.0.first() {
+    Some(label) if label == "*" => owner.label_count() as u8 - 1,
+    _ => owner.label_count() as u8,
+  }
+}
+
+/// The data a signature with the RRSIG fields of `signature` is computed over, or `None` when
+/// `signature` is not RRSIG data.
+pub fn signed_data(signature: &RecordData, rrset: &[AnswerProperties]) -> Option<Vec<u8>> {
+  let RecordData::Signature {
+    type_covered,
+    algorithm,
+    labels,
+    original_ttl,
+    expiration,
+    inception,
+    key_tag,
+    signer,
+    ..
+  } = signature
+  else {
+    return None;
+  };
+
+  let mut data = vec![];
+  data.extend(type_covered.0.to_be_bytes());
+  data.push(*algorithm);
+  data.push(*labels);
+  data.extend(original_ttl.to_be_bytes());
+  data.extend(expiration.to_be_bytes());
+  data.extend(inception.to_be_bytes());
+  data.extend(key_tag.to_be_bytes());
+  data.extend(signer.to_lowercase().to_bytes());
+
+  for record in canonical_rrset(rrset) {
+    let owner = match record.domain_name.label_count() > *labels as usize {
+      true => {
+        let mut owner = DomainName(vec!["*".to_string()]);
+        owner.0.extend(record.domain_name.suffix(*labels as usize).0);
+        owner
+      }
+      false => record.domain_name,
+    };
+
+    data.extend(owner.to_bytes());
+    data.extend(record.record_type.0.to_be_bytes());
+    data.extend(record.record_class.0.to_be_bytes());
+    data.extend(original_ttl.to_be_bytes());
+    data.extend((record.data.len() as u16).to_be_bytes());
+    data.extend(record.data);
+  }
+
+  Some(data)
+}
+
+/// The RRSIG record of `key` over `rrset`, valid from `inception` until `expiration`.
+pub fn sign_rrset(rrset: &[AnswerProperties], key: &SigningKey, inception: u32, expiration: u32) -> AnswerProperties {
+  let first = &rrset[0];
+  let mut signature = RecordData::Signature {
+    type_covered: first.record_type,
+    algorithm: key.algorithm,
+    labels: signature_labels(&first.domain_name),
+    original_ttl: first.time_to_live,
+    expiration,
+    inception,
+    key_tag: key.key_tag(),
+    signer: key.owner.clone(),
+    signature: vec![],
+  };
+  let data = signed_data(&signature, rrset).expect("Signature data");
+  if let RecordData::Signature { signature, .. } = &mut signature {
+    *signature = key.sign(&data);
+  }
+
+  AnswerProperties {
+    domain_name: first.domain_name.clone(),
+    record_type: RecordType::Signature,
+    record_class: first.record_class,
+    time_to_live: first.time_to_live,
+    data: signature.to_bytes(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::dnssec::SecurityAlgorithm;
+  use ring::signature::{ECDSA_P256_SHA256_FIXED, ED25519, UnparsedPublicKey, VerificationAlgorithm};
+
+  fn record(name: &str, address: [u8; 4]) -> AnswerProperties {
+    AnswerProperties {
+      domain_name: DomainName::from(name),
+      record_type: RecordType::Host,
+      record_class: RecordClass::Internet,
+      time_to_live: 300,
+      data: address.to_vec(),
+    }
+  }
+
+  #[test]
+  fn it_signs_rrsets_in_canonical_form() {
+    let origin = DomainName::from("example.com");
+    let rrset = [
+      record("WWW.example.com", [10, 0, 0, 2]),
+      record("www.example.com", [10, 0, 0, 1]),
+    ];
+
+    for (algorithm, verification) in [
+      (
+        SecurityAlgorithm::EcdsaP256Sha256,
+        &ECDSA_P256_SHA256_FIXED as &dyn VerificationAlgorithm,
+      ),
+      (SecurityAlgorithm::Ed25519, &ED25519),
+    ] {
+      let key = SigningKey::generate(origin.clone(), algorithm, SigningKey::ZoneSigningKey).unwrap();
+      let rrsig = sign_rrset(&rrset, &key, 1000, 2000);
+
+      let data = rrsig.record_data();
+      let RecordData::Signature {
+        labels,
+        key_tag,
+        signature,
+        ..
+      } = &data
+      else {
+        panic!("Expected a signature");
+      };
+      assert_eq!(*labels, 3);
+      assert_eq!(*key_tag, key.key_tag());
+
+      let mut public_key = key.public_key();
+      if algorithm == SecurityAlgorithm::EcdsaP256Sha256 {
+        public_key.insert(0, 4);
+      }
+      let verifier = UnparsedPublicKey::new(verification, public_key);
+      let reordered = [rrset[1].clone(), rrset[0].clone()];
+      assert!(
+        verifier
+          .verify(&signed_data(&data, &reordered).unwrap(), signature)
+          .is_ok()
+      );
+      assert!(
+        verifier
+          .verify(&signed_data(&data, &rrset[..1]).unwrap(), signature)
+          .is_err()
+      );
+    }
+  }
+
+  #[test]
+  fn it_signs_wildcards_over_their_source() {
+    let key = SigningKey::generate(
+      DomainName::from("example.com"),
+      SecurityAlgorithm::Ed25519,
+      SigningKey::ZoneSigningKey,
+    )
+    .unwrap();
+    let wildcard = [record("*.example.com", [10, 0, 0, 1])];
+    let rrsig = sign_rrset(&wildcard, &key, 1000, 2000);
+    let data = rrsig.record_data();
+
+    let expanded = [record("a.b.example.com", [10, 0, 0, 1])];
+    assert_eq!(signature_labels(&wildcard[0].domain_name), 2);
+    assert_eq!(signed_data(&data, &expanded), signed_data(&data, &wildcard));
+  }
+}
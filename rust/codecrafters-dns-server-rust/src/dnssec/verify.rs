@@ -0,0 +1,195 @@
+//! RRSIG verification (RFC 4035 section 5.3): the signature must name the zone key it was made with,
+//! cover the RRset's owner, type and class, be inside its validity period, and check out against
+//! the public key over the same data signing computes. RSA keys are kept in the RFC 3110 layout of
+//! exponent length, exponent and modulus; ECDSA keys as the bare point coordinates (RFC 6605).
+
+use ring::signature::{
+  ECDSA_P256_SHA256_FIXED, ECDSA_P384_SHA384_FIXED, ED25519, RSA_PKCS1_1024_8192_SHA1_FOR_LEGACY_USE_ONLY,
+  RSA_PKCS1_1024_8192_SHA256_FOR_LEGACY_USE_ONLY, RSA_PKCS1_1024_8192_SHA512_FOR_LEGACY_USE_ONLY,
+  RsaPublicKeyComponents, UnparsedPublicKey,
+};
+
+use crate::dnssec::{SecurityAlgorithm, key_tag, signature_labels, signed_data};
+use crate::messages::*;
+
+/// The DNSKEY flag of keys that may sign the zone's data (RFC 4034 section 2.1.1).
+const ZoneKeyFlag: u16 = 0x0100;
+/// The only protocol value a DNSKEY may carry (RFC 4034 section 2.1.2).
+const DnsKeyProtocol: u8 = 3;
+
+/// Whether signatures made with `algorithm` can be checked.
+pub fn is_supported_algorithm(algorithm: u8) -> bool {
+  matches!(
+    algorithm,
+    SecurityAlgorithm::RsaSha1
+      | SecurityAlgorithm::RsaSha1Nsec3Sha1
+      | SecurityAlgorithm::RsaSha256
+      | SecurityAlgorithm::RsaSha512
+      | SecurityAlgorithm::EcdsaP256Sha256
+      | SecurityAlgorithm::EcdsaP384Sha384
+      | SecurityAlgorithm::Ed25519
+  )
+}
+
+/// Whether the RRSIG record `rrsig` over `rrset` was made by the zone key `dnskey` and is valid at
+/// `now`, in seconds since the epoch.
+pub fn verify_rrsig(rrsig: &AnswerProperties, rrset: &[AnswerProperties], dnskey: &AnswerProperties, now: u32) -> bool {
+  let signature = rrsig.record_data();
+  let RecordData::Signature {
+    type_covered,
+    algorithm,
+    labels,
+    expiration,
+    inception,
+    key_tag: tag,
+    signer,
+    signature: signature_bytes,
+    ..
+  } = &signature
+  else {
+    return false;
+  };
+  let RecordData::DnsKey {
+    flags,
+    protocol,
+    algorithm: key_algorithm,
+    public_key,
+  } = dnskey.record_data()
+  else {
+    return false;
+  };
+  let Some(first) = rrset.first() else {
+    return false;
+  };
+
+  let matches_rrset = rrset.iter().all(|record| {
+    record.record_type == *type_covered
+      && record.record_class == rrsig.record_class
+      && record.domain_name.eq_ignore_case(&rrsig.domain_name)
+  });
+  let matches_key = flags & ZoneKeyFlag != 0
+    && protocol == DnsKeyProtocol
+    && key_algorithm == *algorithm
+    && key_tag(&dnskey.data) == *tag
+    && dnskey.domain_name.eq_ignore_case(signer);
+  // Serial number arithmetic keeps the validity period meaningful across 2106 (RFC 4034 section 3.1.5).
+  let current = now.wrapping_sub(*inception) as i32 >= 0 && expiration.wrapping_sub(now) as i32 >= 0;
+  if !matches_rrset
+    || !matches_key
+    || !current
+    || !first.domain_name.is_subdomain_of(signer)
+    || *labels > signature_labels(&first.domain_name)
+  {
+    return false;
+  }
+
+  match signed_data(&signature, rrset) {
+    Some(data) => verify_signature(*algorithm, &public_key, &data, signature_bytes),
+    None => false,
+  }
+}
+
+/// Whether `signature` over `data` checks out against the DNSKEY `public_key` of `algorithm`.
+pub fn verify_signature(algorithm: u8, public_key: &[u8], data: &[u8], signature: &[u8]) -> bool {
+  let rsa = |parameters| match rsa_components(public_key) {
+    Some(components) => components.verify(parameters, data, signature).is_ok(),
+    None => false,
+  };
+  let point = |parameters| {
+    let mut key = vec![0x04];
+    key.extend(public_key);
+    UnparsedPublicKey::new(parameters, key).verify(data, signature).is_ok()
+  };
+
+  match algorithm {
+    SecurityAlgorithm::RsaSha1 | SecurityAlgorithm::RsaSha1Nsec3Sha1 => {
+      rsa(&RSA_PKCS1_1024_8192_SHA1_FOR_LEGACY_USE_ONLY)
+    }
+    SecurityAlgorithm::RsaSha256 => rsa(&RSA_PKCS1_1024_8192_SHA256_FOR_LEGACY_USE_ONLY),
+    SecurityAlgorithm::RsaSha512 => rsa(&RSA_PKCS1_1024_8192_SHA512_FOR_LEGACY_USE_ONLY),
+    SecurityAlgorithm::EcdsaP256Sha256 => point(&ECDSA_P256_SHA256_FIXED),
+    SecurityAlgorithm::EcdsaP384Sha384 => point(&ECDSA_P384_SHA384_FIXED),
+    SecurityAlgorithm::Ed25519 => UnparsedPublicKey::new(&ED25519, public_key)
+      .verify(data, signature)
+      .is_ok(),
+    _ => false,
+  }
+}
+
+/// The modulus and exponent of an RSA key (RFC 3110 section 2): one length octet for the exponent,
+/// or a zero followed by two when it is longer than 255 octets.
+fn rsa_components(public_key: &[u8]) -> Option<RsaPublicKeyComponents<&[u8]>> {
+  let (length, rest) = match public_key.split_first()? {
+    (0, rest) => (
+      u16::from_be_bytes([*rest.first()?, *rest.get(1)?]) as usize,
+      rest.get(2..)?,
+    ),
+    (&length, rest) => (length as usize, rest),
+  };
+  if length == 0 || rest.len() <= length {
+    return None;
+  }
+
+  let (e, n) = rest.split_at(length);
+  Some(RsaPublicKeyComponents { n, e })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::dnssec::{SigningKey, sign_rrset};
+
+  fn record(name: &str, address: [u8; 4]) -> AnswerProperties {
+    AnswerProperties {
+      domain_name: DomainName::from(name),
+      record_type: RecordType::Host,
+      record_class: RecordClass::Internet,
+      time_to_live: 300,
+      data: address.to_vec(),
+    }
+  }
+
+  #[test]
+  fn it_verifies_signatures_of_zone_keys() {
+    let rrset = [record("www.example.com", [192, 0, 2, 1])];
+
+    for algorithm in [SecurityAlgorithm::EcdsaP256Sha256, SecurityAlgorithm::Ed25519] {
+      let key = SigningKey::generate(DomainName::from("example.com"), algorithm, SigningKey::ZoneSigningKey).unwrap();
+      let rrsig = sign_rrset(&rrset, &key, 1000, 2000);
+      let dnskey = key.dnskey(300);
+
+      assert_eq!(verify_rrsig(&rrsig, &rrset, &dnskey, 1500), true);
+      assert_eq!(verify_rrsig(&rrsig, &rrset, &dnskey, 2001), false);
+      assert_eq!(
+        verify_rrsig(&rrsig, &[record("www.example.com", [6, 6, 6, 6])], &dnskey, 1500),
+        false
+      );
+    }
+  }
+
+  #[test]
+  fn it_rejects_keys_the_signature_does_not_name() {
+    let rrset = [record("www.example.com", [192, 0, 2, 1])];
+    let origin = DomainName::from("example.com");
+    let key = SigningKey::generate(origin.clone(), SecurityAlgorithm::Ed25519, SigningKey::ZoneSigningKey).unwrap();
+    let other = SigningKey::generate(origin, SecurityAlgorithm::Ed25519, SigningKey::ZoneSigningKey).unwrap();
+    let rrsig = sign_rrset(&rrset, &key, 1000, 2000);
+
+    assert_eq!(verify_rrsig(&rrsig, &rrset, &other.dnskey(300), 1500), false);
+  }
+
+  #[test]
+  fn it_reads_rsa_exponent_and_modulus() {
+    let key = [&[3u8, 1, 0, 1][..], &[0xab; 128]].concat();
+    let long = [&[0u8, 1, 0][..], &[1; 256], &[0xcd; 128]].concat();
+
+    let short = rsa_components(&key).unwrap();
+    let wide = rsa_components(&long).unwrap();
+
+    assert_eq!(short.e, &[1, 0, 1]);
+    assert_eq!(short.n.len(), 128);
+    assert_eq!(wide.e.len(), 256);
+    assert_eq!(wide.n, &[0xcd; 128]);
+    assert_eq!(rsa_components(&[3, 1, 0, 1]).is_none(), true);
+  }
+}
@@ -0,0 +1,22 @@
+//! A DNS server and the pieces it is built from, usable on their own:
+//!
+//! - `messages`: the wire format codec, with presentation format, RFC 8427 JSON and a message
+//!   builder. Received bytes are read with `MessageProperties::try_from`, which reports a
+//!   `MessageError` for malformed messages instead of panicking.
+//! - `resolver`: UDP transport, forwarding, recursive resolution and DNSSEC validation.
+//! - `zones`, `dnssec`, `tsig`, `cookies`: master files, zone signing, transaction signatures and
+//!   DNS cookies.
+//! - `server`: the authoritative and resolving server answering over UDP and TCP.
+//! - `commands`: what the binaries run, the server, `signzone`, `checkzone` and `dig`.
+
+#![allow(non_upper_case_globals)]
+pub mod commands;
+pub mod cookies;
+pub mod dnssec;
+#[allow(unused)]
+pub mod macros;
+pub mod messages;
+pub mod resolver;
+pub mod server;
+pub mod tsig;
+pub mod zones;
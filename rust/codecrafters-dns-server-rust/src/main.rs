@@ -1,109 +1,41 @@
-#![allow(non_upper_case_globals)]
-#[allow(unused_imports)]
-pub mod macros;
-pub mod messages;
-use rand::random;
-use std::net::UdpSocket;
-
-use crate::messages::*;
-
-const Host: &str = "127.0.0.1";
-const Port: u16 = 2053;
+use codecrafters_dns_server::commands::*;
 
 fn main() {
-  let args = std::env::args().collect::<Vec<String>>();
-  let mut resolver = None;
-  if args.len() > 1 && args[1] == "--resolver" {
-    resolver = Some(&args[2]);
-  }
-
-  let socket = UdpSocket::bind(format!("{}:{}", Host, Port)).expect("Failed to bind to address");
-  let mut buffer = vec![0; 512];
-
-  loop {
-    match socket.recv_from(&mut buffer) {
-      Ok((size, source)) => {
-        println!("Received {} bytes from {}", size, source);
-
-        let request = MessageProperties::from(MessageBuffer(buffer[..size].to_vec()));
-
-        let header = request.header;
-        let id = header.id;
-        let opcode = header.operation_code();
-        let recursion_desired = header.is_recursion_desired();
-        let rcode = if opcode == 0 { 0 } else { 4 };
-        let flags = Flags::Response
-          | Flags::recursion_desired(recursion_desired)
-          | Flags::from_operation_code(opcode)
-          | Flags::from_response_code(rcode);
-
-        let questions: Vec<QuestionProperties> = (0..header.question_count as usize)
-          .map(|i| QuestionProperties {
-            domain_name: request.questions[i].domain_name.clone(),
-            record_type: RecordType::Host,
-            record_class: RecordClass::Internet,
-          })
-          .collect();
-
-        let mut answers = vec![];
-        if let Some(resolver) = &resolver {
-          for question in &request.questions {
-            let flags = Flags::Query
-              | Flags::recursion_desired(recursion_desired)
-              | Flags::from_operation_code(opcode)
-              | Flags::from_response_code(rcode);
-
-            let payload = MessageProperties {
-              header: HeaderProperties {
-                id: random::<u16>(),
-                flags,
-                question_count: 1,
-                answer_record_count: 0,
-                authority_record_count: 0,
-                additional_record_count: 0,
-              },
-              questions: vec![question.clone()],
-              answers: vec![],
-            };
-
-            let response: Vec<u8> = payload.into();
-            socket.send_to(&response, resolver).expect("Failed to send request");
-            let (size, _) = socket.recv_from(&mut buffer).expect("Failed to receive response");
-            let response = MessageProperties::from(MessageBuffer(buffer[..size].to_vec()));
-
-            answers.extend(response.answers);
-          }
-        } else {
-          for question in request.questions {
-            answers.push(AnswerProperties {
-              domain_name: question.domain_name.clone(),
-              record_type: RecordType::Host,
-              record_class: RecordClass::Internet,
-              time_to_live: 60,
-              data: vec![8, 8, 8, 8],
-            });
-          }
-        };
-
-        let message = MessageProperties {
-          header: HeaderProperties {
-            id,
-            flags,
-            question_count: questions.len() as u16,
-            answer_record_count: answers.len() as u16,
-            authority_record_count: 0,
-            additional_record_count: 0,
-          },
-          questions,
-          answers,
-        };
-
-        let response: Vec<u8> = message.into();
-        socket.send_to(&response, source).expect("Failed to send response");
+  let args = std::env::args().skip(1).collect::<Vec<String>>();
+
+  match args.first().map(String::as_str) {
+    Some("signzone") => match SignZoneOptions::parse(&args[1..]).and_then(|options| sign_zone_file(&options)) {
+      Ok(summary) => println!("{}", summary),
+      Err(error) => {
+        eprintln!("signzone: {}", error);
+        std::process::exit(1);
       }
-      Err(message) => {
-        eprintln!("Error receiving data: {}", message);
-        break;
+    },
+    Some("checkzone") => {
+      let options = CheckZoneOptions::parse(&args[1..]).unwrap_or_else(|error| {
+        eprintln!("checkzone: {}", error);
+        std::process::exit(2);
+      });
+      let problems = check_zone_file(&options);
+      for problem in &problems {
+        println!("{}", problem);
+      }
+      let errors = problems
+        .iter()
+        .filter(|problem| problem.severity == Severity::Error)
+        .count();
+      eprintln!(
+        "{}: {} errors, {} warnings",
+        options.origin,
+        errors,
+        problems.len() - errors
+      );
+      std::process::exit(if errors > 0 { 1 } else { 0 });
+    }
+    _ => {
+      if let Err(error) = serve(&args) {
+        eprintln!("{}", error);
+        std::process::exit(1);
       }
     }
   }
@@ -6,7 +6,7 @@
 //! Data length - 2 bytes - Length of the data
 //! Data - variable - The data
 
-use crate::messages::{DomainName, RecordClass, RecordType, SizeOf};
+use crate::messages::{DomainName, MessageError, RecordClass, RecordType, SizeOf};
 
 #[derive(Clone, Debug)]
 pub struct AnswerBuffer(pub Vec<u8>, pub usize);
@@ -17,39 +17,78 @@ impl From<AnswerBuffer> for Vec<u8> {
   }
 }
 
-impl From<AnswerBuffer> for AnswerProperties {
-  fn from(AnswerBuffer(buffer, offset): AnswerBuffer) -> Self {
-    let (domain_name, offset) = DomainName::from_bytes(&buffer, offset);
-    let record_type = RecordType::from(u16::from_be_bytes([buffer[offset], buffer[offset + 1]]));
-    let record_class = RecordClass::from(u16::from_be_bytes([buffer[offset + 2], buffer[offset + 3]]));
-
-    let time_to_live = u32::from_be_bytes([
-      buffer[offset + 4],
-      buffer[offset + 5],
-      buffer[offset + 6],
-      buffer[offset + 7],
-    ]);
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AnswerProperties {
+  pub domain_name: DomainName,
+  pub record_type: RecordType,
+  pub record_class: RecordClass,
+  pub time_to_live: u32,
+  pub data: Vec<u8>,
+}
 
-    let data_length = u16::from_be_bytes([buffer[offset + 8], buffer[offset + 9]]);
-    let data = buffer[offset + 10..offset + 10 + data_length as usize].to_vec();
+impl AnswerProperties {
+  /// Reads the record at `start` of the `section` named in errors.
+  pub fn try_from_bytes(buffer: &[u8], start: usize, section: &'static str) -> Result<(Self, usize), MessageError> {
+    let (domain_name, offset) =
+      DomainName::try_from_bytes(buffer, start).ok_or(MessageError::MalformedName { section, offset: start })?;
+    let truncated = MessageError::Truncated { section, offset: start };
+    let fields = buffer.get(offset..offset + 10).ok_or(truncated.clone())?;
+    let record_type = RecordType::from(u16::from_be_bytes([fields[0], fields[1]]));
+    let record_class = RecordClass::from(u16::from_be_bytes([fields[2], fields[3]]));
+    let time_to_live = u32::from_be_bytes([fields[4], fields[5], fields[6], fields[7]]);
+    let data_length = u16::from_be_bytes([fields[8], fields[9]]) as usize;
+
+    if offset + 10 + data_length > buffer.len() {
+      return Err(truncated);
+    }
+    let data = decompress_data(buffer, offset + 10, data_length, record_type).ok_or(MessageError::MalformedName {
+      section,
+      offset: offset + 10,
+    })?;
 
-    Self {
+    let properties = Self {
       domain_name,
       record_type,
       record_class,
       time_to_live,
       data,
-    }
+    };
+
+    Ok((properties, offset + 10 + data_length))
   }
 }
 
-#[derive(Clone, Debug)]
-pub struct AnswerProperties {
-  pub domain_name: DomainName,
-  pub record_type: RecordType,
-  pub record_class: RecordClass,
-  pub time_to_live: u32,
-  pub data: Vec<u8>,
+/// Expands compressed names inside the data of the RFC 1035 record types allowed to use compression,
+/// so the data stays meaningful once detached from the message it was read from.
+fn decompress_data(buffer: &[u8], offset: usize, length: usize, record_type: RecordType) -> Option<Vec<u8>> {
+  let end = offset + length;
+  // Empty data occurs in UPDATE messages, e.g. when deleting a whole RRset (RFC 2136 section 2.5.2).
+  if length == 0 {
+    return Some(vec![]);
+  }
+
+  let data = match record_type {
+    RecordType::NameServer | RecordType::CanonicalName | RecordType::Pointer => {
+      DomainName::try_from_bytes(buffer, offset)?.0.to_bytes()
+    }
+    RecordType::MailExchange => {
+      let mut data = buffer.get(offset..offset + 2)?.to_vec();
+      data.extend(DomainName::try_from_bytes(buffer, offset + 2)?.0.to_bytes());
+      data
+    }
+    RecordType::StartOfAuthority => {
+      let (primary, next) = DomainName::try_from_bytes(buffer, offset)?;
+      let (mailbox, next) = DomainName::try_from_bytes(buffer, next)?;
+
+      let mut data = primary.to_bytes();
+      data.extend(mailbox.to_bytes());
+      data.extend(buffer.get(next..end)?);
+      data
+    }
+    _ => buffer[offset..end].to_vec(),
+  };
+
+  Some(data)
 }
 
 impl From<AnswerProperties> for Vec<u8> {
@@ -67,18 +106,18 @@ impl From<AnswerProperties> for AnswerBuffer {
     let record_type = properties.record_type.0;
     let record_class = properties.record_class.0;
 
-    buffer.push(((record_type as u16) >> 8) as u8);
-    buffer.push((record_type as u16) as u8);
-    buffer.push(((record_class as u16) >> 8) as u8);
-    buffer.push((record_class as u16) as u8);
-    buffer.push(((properties.time_to_live as u32) >> 24) as u8);
-    buffer.push(((properties.time_to_live as u32) >> 16) as u8);
-    buffer.push(((properties.time_to_live as u32) >> 8) as u8);
-    buffer.push((properties.time_to_live as u32) as u8);
+    buffer.push((record_type >> 8) as u8);
+    buffer.push(record_type as u8);
+    buffer.push((record_class >> 8) as u8);
+    buffer.push(record_class as u8);
+    buffer.push((properties.time_to_live >> 24) as u8);
+    buffer.push((properties.time_to_live >> 16) as u8);
+    buffer.push((properties.time_to_live >> 8) as u8);
+    buffer.push(properties.time_to_live as u8);
 
     let data_length = properties.data.len() as u16;
-    buffer.push(((data_length as u16) >> 8) as u8);
-    buffer.push((data_length as u16) as u8);
+    buffer.push((data_length >> 8) as u8);
+    buffer.push(data_length as u8);
     buffer.extend(properties.data);
 
     AnswerBuffer(buffer, 0)
@@ -97,6 +136,7 @@ mod tests {
   use crate::macros::*;
 
   #[test]
+  #[allow(clippy::identity_op)]
   fn it_calculates_size_of() {
     let properties = AnswerProperties {
       domain_name: DomainName::from("abc.com"),
@@ -133,4 +173,37 @@ mod tests {
       hex!["0361626303636f6d0000010001000000120000"]
     );
   }
+
+  #[test]
+  fn it_returns_offset_after_compressed_name() {
+    // abc.com at 0, then an A record owned by a pointer to it
+    let buffer = hex!["0361626303636f6d00c000000100010000003c000408080808ff"];
+
+    let (answer, offset) = AnswerProperties::try_from_bytes(&buffer, 9, "answer").unwrap();
+
+    assert_eq!(answer.domain_name, DomainName::from("abc.com"));
+    assert_eq!(answer.data, vec![8, 8, 8, 8]);
+    assert_eq!(offset, buffer.len() - 1);
+  }
+
+  #[test]
+  fn it_decompresses_names_in_data() {
+    // abc.com at 0, then an NS record pointing at ns.<pointer to abc.com>
+    let buffer = hex!["0361626303636f6d00c000000200010000003c0005026e73c000"];
+
+    let (answer, _) = AnswerProperties::try_from_bytes(&buffer, 9, "answer").unwrap();
+
+    assert_eq!(answer.data, DomainName::from("ns.abc.com").to_bytes());
+  }
+
+  #[test]
+  fn it_keeps_empty_data_empty() {
+    // An NS record without data, followed by bytes that must not be read as its name
+    let buffer = hex!["0361626303636f6d0000020001000000000000c000"];
+
+    let (answer, offset) = AnswerProperties::try_from_bytes(&buffer, 0, "answer").unwrap();
+
+    assert!(answer.data.is_empty());
+    assert_eq!(offset, buffer.len() - 2);
+  }
 }
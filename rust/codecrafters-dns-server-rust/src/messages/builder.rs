@@ -0,0 +1,318 @@
+//! Fluent construction of messages, deriving what the header repeats from the sections:
+//!
+//! ```ignore
+//! let query = Message::query("example.com", RecordType::Ipv6Host).dnssec_ok().build()?;
+//! let response = Message::response_to(&query).answer(record).build()?;
+//! ```
+//!
+//! Queries get a random ID and the RD bit; responses copy the ID, operation code, RD bit and
+//! questions of the request. The counts are always those of the sections, and `build()` refuses
+//! what would not make a valid message: names over 255 octets, sections over 65535 records, an
+//! operation code over 15, an extended response code without EDNS to carry it, and OPT or TSIG
+//! records in the sections, which go in through `edns()` and by signing the built message. Each
+//! comes back as a `MessageError`.
+
+use rand::random;
+
+use crate::messages::*;
+
+/// The messages as the builder reads them, e.g. `Message::query(name, record_type)`.
+pub type Message = MessageProperties;
+
+#[derive(Clone, Debug)]
+pub struct MessageBuilder {
+  id: u16,
+  response: bool,
+  operation_code: u8,
+  recursion_desired: bool,
+  flags: Flags,
+  response_code: u8,
+  questions: Vec<QuestionProperties>,
+  answers: Vec<AnswerProperties>,
+  authorities: Vec<AnswerProperties>,
+  additionals: Vec<AnswerProperties>,
+  edns: Option<EdnsProperties>,
+}
+
+impl MessageProperties {
+  /// A recursive query for `record_type` records of `name` in class IN.
+  pub fn query(name: impl Into<DomainName>, record_type: RecordType) -> MessageBuilder {
+    MessageBuilder {
+      id: random::<u16>(),
+      response: false,
+      operation_code: OperationCode::Query,
+      recursion_desired: true,
+      flags: Flags::None,
+      response_code: ResponseCode::NoError,
+      questions: vec![],
+      answers: vec![],
+      authorities: vec![],
+      additionals: vec![],
+      edns: None,
+    }
+    .question(QuestionProperties {
+      domain_name: name.into(),
+      record_type,
+      record_class: RecordClass::Internet,
+    })
+  }
+
+  /// An empty response echoing the ID, operation code, RD bit and questions of `request`.
+  pub fn response_to(request: &MessageProperties) -> MessageBuilder {
+    let header = &request.header;
+
+    MessageBuilder {
+      id: header.id,
+      response: true,
+      operation_code: header.operation_code(),
+      recursion_desired: header.is_recursion_desired(),
+      flags: Flags::None,
+      response_code: ResponseCode::NoError,
+      questions: request.questions.clone(),
+      answers: vec![],
+      authorities: vec![],
+      additionals: vec![],
+      edns: None,
+    }
+  }
+}
+
+impl MessageBuilder {
+  pub fn id(mut self, id: u16) -> Self {
+    self.id = id;
+    self
+  }
+
+  pub fn operation_code(mut self, code: u8) -> Self {
+    self.operation_code = code;
+    self
+  }
+
+  pub fn recursion_desired(mut self, value: bool) -> Self {
+    self.recursion_desired = value;
+    self
+  }
+
+  /// Sets `flags` in addition to those already set, e.g. `Flags::Authoritative`. Only AA, TC, RD,
+  /// RA, AD and CD are taken; the others have their own methods.
+  pub fn flags(mut self, flags: Flags) -> Self {
+    self.flags = self.flags | flags.settable();
+    self
+  }
+
+  /// The full response code; codes above 15 need `edns()` for their upper bits.
+  pub fn response_code(mut self, code: u8) -> Self {
+    self.response_code = code;
+    self
+  }
+
+  /// Asks the questions in `class` rather than IN.
+  pub fn record_class(mut self, class: RecordClass) -> Self {
+    for question in &mut self.questions {
+      question.record_class = class;
+    }
+    self
+  }
+
+  pub fn question(mut self, question: QuestionProperties) -> Self {
+    self.questions.push(question);
+    self
+  }
+
+  pub fn answer(mut self, record: AnswerProperties) -> Self {
+    self.answers.push(record);
+    self
+  }
+
+  pub fn authority(mut self, record: AnswerProperties) -> Self {
+    self.authorities.push(record);
+    self
+  }
+
+  pub fn additional(mut self, record: AnswerProperties) -> Self {
+    self.additionals.push(record);
+    self
+  }
+
+  /// Adds the OPT pseudo-record, last in the additional section.
+  pub fn edns(mut self, edns: EdnsProperties) -> Self {
+    self.edns = Some(edns);
+    self
+  }
+
+  /// Adds the OPT pseudo-record with the DO bit set, keeping the options of one added before.
+  pub fn dnssec_ok(mut self) -> Self {
+    self.edns.get_or_insert_with(EdnsProperties::new).dnssec_ok = true;
+    self
+  }
+
+  pub fn build(self) -> Result<MessageProperties, MessageError> {
+    if self.operation_code > 0x0f {
+      return Err(MessageError::OperationCodeTooLarge(self.operation_code));
+    }
+    if self.response_code > 0x0f && self.edns.is_none() {
+      return Err(MessageError::ResponseCodeWithoutEdns(self.response_code));
+    }
+
+    let names = self
+      .questions
+      .iter()
+      .map(|question| &question.domain_name)
+      .chain(self.records().map(|record| &record.domain_name));
+    for name in names {
+      if name.to_bytes().len() > DomainName::MaxLength {
+        return Err(MessageError::NameTooLong(name.clone()));
+      }
+      if name.0.iter().any(|label| label.len() > DomainName::MaxLabelLength) {
+        return Err(MessageError::LabelTooLong(name.clone()));
+      }
+    }
+    if let Some(record) = self.records().find(|record| {
+      record.record_type == RecordType::Options || record.record_type == RecordType::TransactionSignature
+    }) {
+      return Err(MessageError::ReservedRecord(record.record_type));
+    }
+
+    let count = |section: &'static str, count: usize| {
+      u16::try_from(count).map_err(|_| MessageError::SectionTooLarge { section, count })
+    };
+    let header = HeaderProperties {
+      id: self.id,
+      flags: self.header_flags(),
+      question_count: count("question", self.questions.len())?,
+      answer_record_count: count("answer", self.answers.len())?,
+      authority_record_count: count("authority", self.authorities.len())?,
+      additional_record_count: count("additional", self.additionals.len() + self.edns.iter().count())?,
+    };
+
+    let mut message = MessageProperties {
+      header,
+      questions: self.questions,
+      answers: self.answers,
+      authorities: self.authorities,
+      additionals: self.additionals,
+    };
+    if let Some(edns) = self.edns {
+      message.set_edns(edns);
+    }
+    message.set_response_code(self.response_code);
+
+    Ok(message)
+  }
+
+  fn header_flags(&self) -> Flags {
+    let response = match self.response {
+      true => Flags::Response,
+      false => Flags::Query,
+    };
+
+    response
+      | Flags::from_operation_code(self.operation_code)
+      | Flags::recursion_desired(self.recursion_desired)
+      | self.flags
+  }
+
+  fn records(&self) -> impl Iterator<Item = &AnswerProperties> {
+    self.answers.iter().chain(&self.authorities).chain(&self.additionals)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn record(name: &str) -> AnswerProperties {
+    AnswerProperties {
+      domain_name: DomainName::from(name),
+      record_type: RecordType::Ipv6Host,
+      record_class: RecordClass::Internet,
+      time_to_live: 60,
+      data: "2001:db8::1".parse::<std::net::Ipv6Addr>().unwrap().octets().to_vec(),
+    }
+  }
+
+  #[test]
+  fn it_builds_queries_and_responses_with_derived_counts() {
+    let query = MessageProperties::query("example.com", RecordType::Ipv6Host)
+      .dnssec_ok()
+      .build()
+      .unwrap();
+    let response = MessageProperties::response_to(&query)
+      .flags(Flags::Authoritative)
+      .answer(record("example.com"))
+      .authority(record("ns.example.com"))
+      .edns(EdnsProperties::new())
+      .response_code(ResponseCode::BadCookie)
+      .build()
+      .unwrap();
+
+    assert_eq!(query.header.is_query(), true);
+    assert_eq!(query.header.is_recursion_desired(), true);
+    assert_eq!(query.header.question_count, 1);
+    assert_eq!(query.header.additional_record_count, 1);
+    assert_eq!(query.edns().unwrap().dnssec_ok, true);
+    assert_eq!(response.header.id, query.header.id);
+    assert_eq!(response.header.is_response(), true);
+    assert_eq!(response.header.is_authoritative(), true);
+    assert_eq!(response.header.is_recursion_desired(), true);
+    assert_eq!(response.questions[0].domain_name, DomainName::from("example.com"));
+    assert_eq!(
+      [
+        response.header.question_count,
+        response.header.answer_record_count,
+        response.header.authority_record_count,
+        response.header.additional_record_count
+      ],
+      [1, 1, 1, 1]
+    );
+    assert_eq!(response.response_code(), ResponseCode::BadCookie);
+  }
+
+  #[test]
+  fn it_rejects_inconsistent_messages() {
+    let query = || Message::query("example.com", RecordType::Host);
+    let long = vec!["a".repeat(63); 4].join(".");
+    let mut opt = record("example.com");
+    opt.record_type = RecordType::Options;
+
+    assert_eq!(
+      query().operation_code(16).build().unwrap_err(),
+      MessageError::OperationCodeTooLarge(16)
+    );
+    assert_eq!(
+      query().response_code(ResponseCode::BadCookie).build().unwrap_err(),
+      MessageError::ResponseCodeWithoutEdns(ResponseCode::BadCookie)
+    );
+    assert_eq!(
+      query().answer(record(&long)).build().unwrap_err(),
+      MessageError::NameTooLong(DomainName::from(long.as_str()))
+    );
+    assert_eq!(
+      query().answer(record(&"a".repeat(64))).build().unwrap_err(),
+      MessageError::LabelTooLong(DomainName::from("a".repeat(64).as_str()))
+    );
+    assert_eq!(
+      query()
+        .flags(Flags::Response | Flags::from_response_code(ResponseCode::Refused) | Flags::Truncated)
+        .build()
+        .map(|message| (
+          message.header.is_response(),
+          message.header.response_code(),
+          message.header.is_truncated()
+        )),
+      Ok((false, 0, true))
+    );
+    assert_eq!(
+      query().additional(opt).build().unwrap_err(),
+      MessageError::ReservedRecord(RecordType::Options)
+    );
+    assert_eq!(
+      query()
+        .operation_code(OperationCode::Notify)
+        .recursion_desired(false)
+        .build()
+        .map(|message| (message.header.operation_code(), message.header.is_recursion_desired())),
+      Ok((OperationCode::Notify, false))
+    );
+  }
+}
@@ -1,9 +1,21 @@
 use crate::messages::SizeOf;
 
-#[derive(Clone, Debug)]
+/// Upper bound on compression pointers followed while decoding a single name.
+const MaxPointerJumps: usize = 64;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct DomainName(pub Vec<String>);
 
 impl DomainName {
+  /// Longest name allowed in wire format, length octets included.
+  pub const MaxLength: usize = 255;
+  /// Longest label allowed, its length octet left out (RFC 1035 section 2.3.4).
+  pub const MaxLabelLength: usize = 63;
+
+  pub fn root() -> Self {
+    DomainName(vec![])
+  }
+
   pub fn to_bytes(&self) -> Vec<u8> {
     let mut buffer: Vec<u8> = Vec::with_capacity(self.size_of());
 
@@ -16,16 +28,70 @@ impl DomainName {
     buffer
   }
 
-  pub fn from_bytes(bytes: &[u8], offset: usize) -> (Self, usize) {
-    let (str, offset) = decode(bytes, offset);
+  /// Reads the name at `offset`, returning it with the offset past it, or `None` for truncated
+  /// names, compression pointer loops, label types other than plain labels and pointers, names
+  /// longer than `MaxLength`, and labels that are not UTF-8, which the labels could not hold
+  /// without changing their octets.
+  pub fn try_from_bytes(bytes: &[u8], offset: usize) -> Option<(Self, usize)> {
+    let (labels, offset) = decode(bytes, offset)?;
+
+    Some((DomainName(labels), offset))
+  }
+
+  pub fn is_root(&self) -> bool {
+    self.0.is_empty()
+  }
+
+  pub fn label_count(&self) -> usize {
+    self.0.len()
+  }
+
+  pub fn parent(&self) -> Option<DomainName> {
+    match self.is_root() {
+      true => None,
+      false => Some(DomainName(self.0[1..].to_vec())),
+    }
+  }
+
+  /// The ancestor made of the last `count` labels, e.g. `suffix(2)` of `a.b.example.com` is `example.com`.
+  pub fn suffix(&self, count: usize) -> DomainName {
+    let count = count.min(self.label_count());
+
+    DomainName(self.0[self.label_count() - count..].to_vec())
+  }
+
+  pub fn eq_ignore_case(&self, other: &DomainName) -> bool {
+    self.label_count() == other.label_count() && self.0.iter().zip(&other.0).all(|(a, b)| a.eq_ignore_ascii_case(b))
+  }
+
+  /// Whether this name equals `ancestor` or lies below it, compared case-insensitively.
+  pub fn is_subdomain_of(&self, ancestor: &DomainName) -> bool {
+    self.label_count() >= ancestor.label_count() && self.suffix(ancestor.label_count()).eq_ignore_case(ancestor)
+  }
+
+  /// Swaps the `suffix` this name lies under for `target`, e.g. `www.abc.com` with `abc.com`
+  /// replaced by `example.net` becomes `www.example.net`.
+  pub fn replace_suffix(&self, suffix: &DomainName, target: &DomainName) -> DomainName {
+    let mut labels = self.0[..self.label_count() - suffix.label_count()].to_vec();
+    labels.extend(target.0.iter().cloned());
 
-    (DomainName::from(str.as_str()), offset)
+    DomainName(labels)
+  }
+
+  pub fn to_lowercase(&self) -> DomainName {
+    DomainName(self.0.iter().map(|label| label.to_ascii_lowercase()).collect())
   }
 }
 
 impl From<&str> for DomainName {
   fn from(value: &str) -> Self {
-    DomainName(value.split(".").map(|s| s.to_string()).collect())
+    DomainName(
+      value
+        .split(".")
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string())
+        .collect(),
+    )
   }
 }
 
@@ -35,9 +101,57 @@ impl SizeOf for DomainName {
   }
 }
 
+fn decode(buffer: &[u8], offset: usize) -> Option<(Vec<String>, usize)> {
+  let mut labels: Vec<String> = Vec::new();
+  let mut offset = offset;
+  let mut end = None;
+  let mut jumps = 0;
+  let mut length = 1;
+
+  loop {
+    let value = *buffer.get(offset)?;
+    if value == 0 {
+      offset += 1;
+      break;
+    }
+
+    if is_pointer_byte(value) {
+      jumps += 1;
+      if jumps > MaxPointerJumps || offset + 1 >= buffer.len() {
+        return None;
+      }
+
+      end.get_or_insert(offset + 2);
+      offset = read_pointer_offset(buffer, offset);
+      continue;
+    }
+
+    length += value as usize + 1;
+    if value & 0xc0 != 0 || length > DomainName::MaxLength {
+      return None;
+    }
+
+    offset += 1;
+    let label = String::from_utf8(buffer.get(offset..offset + value as usize)?.to_vec()).ok()?;
+    labels.push(label);
+    offset += value as usize;
+  }
+
+  Some((labels, end.unwrap_or(offset)))
+}
+
+fn is_pointer_byte(byte: u8) -> bool {
+  (byte & 0xc0) == 0xc0
+}
+
+fn read_pointer_offset(buffer: &[u8], offset: usize) -> usize {
+  (u16::from_be_bytes([buffer[offset], buffer[offset + 1]]) & 0x3fff) as usize
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
+  use crate::macros::*;
 
   #[test]
   fn it_converts_from_str_to_domain_name() {
@@ -53,39 +167,49 @@ mod tests {
     // 3 abc + 3 abc + nullbyte
     assert_eq!(domain_name.size_of(), 6 + 2 + 1);
   }
-}
 
-fn decode(buffer: &[u8], offset: usize) -> (String, usize) {
-  let mut labels: Vec<String> = Vec::new();
-  let mut offset = offset;
+  #[test]
+  fn it_converts_root_to_single_null_byte() {
+    assert_eq!(DomainName::from("").to_bytes(), vec![0]);
+    assert_eq!(DomainName::from(".").to_bytes(), vec![0]);
+    assert_eq!(DomainName::from("abc.com."), DomainName::from("abc.com"));
+  }
 
-  loop {
-    let value = buffer[offset];
-    if value == 0 {
-      offset += 1;
-      break;
-    }
+  #[test]
+  fn it_returns_offset_after_pointer() {
+    // abc.com at 0, then "www" + pointer to 0
+    let buffer = hex!["0361626303636f6d0003777777c000"];
 
-    if is_pointer_byte(value) {
-      let (label, _) = decode(buffer, read_pointer_offset(buffer, offset));
-      labels.push(label);
-      offset += 2;
-      break;
-    }
+    let (name, offset) = DomainName::try_from_bytes(&buffer, 9).unwrap();
 
-    offset += 1;
-    let label = String::from_utf8(buffer[offset..offset + value as usize].to_vec()).unwrap();
-    labels.push(label);
-    offset += value as usize;
+    assert_eq!(name, DomainName::from("www.abc.com"));
+    assert_eq!(offset, buffer.len());
   }
 
-  (labels.join("."), offset)
-}
+  #[test]
+  fn it_rejects_pointer_loops() {
+    let buffer = hex!["c000"];
 
-fn is_pointer_byte(byte: u8) -> bool {
-  (byte & 0xc0) == 0xc0
-}
+    assert_eq!(DomainName::try_from_bytes(&buffer, 0), None);
+  }
 
-fn read_pointer_offset(buffer: &[u8], offset: usize) -> usize {
-  (u16::from_be_bytes([buffer[offset], buffer[offset + 1]]) & 0x3fff) as usize
+  #[test]
+  fn it_rejects_labels_that_are_not_utf8() {
+    assert_eq!(
+      DomainName::try_from_bytes(&hex!["02c3a900"], 0),
+      Some((DomainName::from("é"), 4))
+    );
+    assert_eq!(DomainName::try_from_bytes(&hex!["02ff4100"], 0), None);
+  }
+
+  #[test]
+  fn it_compares_ancestry_ignoring_case() {
+    let name = DomainName::from("a.B.Example.com");
+
+    assert!(name.is_subdomain_of(&DomainName::from("example.COM")));
+    assert!(name.is_subdomain_of(&DomainName::root()));
+    assert!(!name.is_subdomain_of(&DomainName::from("ample.com")));
+    assert_eq!(name.suffix(2), DomainName::from("Example.com"));
+    assert_eq!(name.parent(), Some(DomainName::from("B.Example.com")));
+  }
 }
@@ -0,0 +1,143 @@
+//! EDNS(0) OPT pseudo-record, carried in the additional section:
+//! Name - root
+//! Type - 2 bytes - 41
+//! Class - 2 bytes - Requestor's UDP payload size
+//! TTL - 4 bytes - Extended RCODE (8 bits) + Version (8 bits) + DNSSEC OK (1 bit) + Zero (15 bits)
+//! Data - variable - Options, each being a 2 byte code, a 2 byte length and the option data
+
+use crate::messages::{AnswerProperties, DomainName, RecordClass, RecordType};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EdnsOption {
+  pub code: u16,
+  pub data: Vec<u8>,
+}
+
+impl EdnsOption {
+  pub const Cookie: u16 = 10;
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EdnsProperties {
+  pub udp_payload_size: u16,
+  pub extended_response_code: u8,
+  pub version: u8,
+  pub dnssec_ok: bool,
+  pub options: Vec<EdnsOption>,
+}
+
+impl EdnsProperties {
+  pub const DefaultPayloadSize: u16 = 1232;
+
+  pub fn new() -> Self {
+    Self {
+      udp_payload_size: Self::DefaultPayloadSize,
+      extended_response_code: 0,
+      version: 0,
+      dnssec_ok: false,
+      options: vec![],
+    }
+  }
+
+  pub fn option(&self, code: u16) -> Option<&EdnsOption> {
+    self.options.iter().find(|option| option.code == code)
+  }
+
+  pub fn set_option(&mut self, option: EdnsOption) {
+    self.options.retain(|existing| existing.code != option.code);
+    self.options.push(option);
+  }
+}
+
+impl Default for EdnsProperties {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl From<&AnswerProperties> for EdnsProperties {
+  fn from(record: &AnswerProperties) -> Self {
+    let mut options = vec![];
+    let mut offset = 0;
+    while offset + 4 <= record.data.len() {
+      let code = u16::from_be_bytes([record.data[offset], record.data[offset + 1]]);
+      let length = u16::from_be_bytes([record.data[offset + 2], record.data[offset + 3]]) as usize;
+      let end = (offset + 4 + length).min(record.data.len());
+
+      options.push(EdnsOption {
+        code,
+        data: record.data[offset + 4..end].to_vec(),
+      });
+      offset = end;
+    }
+
+    Self {
+      udp_payload_size: record.record_class.0,
+      extended_response_code: (record.time_to_live >> 24) as u8,
+      version: (record.time_to_live >> 16) as u8,
+      dnssec_ok: record.time_to_live & 0x8000 != 0,
+      options,
+    }
+  }
+}
+
+impl From<EdnsProperties> for AnswerProperties {
+  fn from(properties: EdnsProperties) -> Self {
+    let mut data = vec![];
+    for option in properties.options {
+      data.extend(option.code.to_be_bytes());
+      data.extend((option.data.len() as u16).to_be_bytes());
+      data.extend(option.data);
+    }
+
+    Self {
+      domain_name: DomainName::root(),
+      record_type: RecordType::Options,
+      record_class: RecordClass(properties.udp_payload_size),
+      time_to_live: ((properties.extended_response_code as u32) << 24)
+        | ((properties.version as u32) << 16)
+        | ((properties.dnssec_ok as u32) << 15),
+      data,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::macros::*;
+  use crate::messages::AnswerBuffer;
+
+  #[test]
+  fn it_converts_from_properties_to_buffer() {
+    let mut properties = EdnsProperties::new();
+    properties.dnssec_ok = true;
+    properties.set_option(EdnsOption {
+      code: EdnsOption::Cookie,
+      data: hex!["0102030405060708"].to_vec(),
+    });
+
+    let buffer = AnswerBuffer::from(AnswerProperties::from(properties));
+
+    assert_eq!(
+      buffer.0,
+      // root + type + payload size + ttl with DO
+      // + data length + cookie option
+      hex!["00002904d000008000000c000a00080102030405060708"]
+    );
+  }
+
+  #[test]
+  fn it_converts_twoway() {
+    let mut properties = EdnsProperties::new();
+    properties.extended_response_code = 1;
+    properties.set_option(EdnsOption {
+      code: EdnsOption::Cookie,
+      data: vec![1; 24],
+    });
+
+    let parsed = EdnsProperties::from(&AnswerProperties::from(properties.clone()));
+
+    assert_eq!(parsed, properties);
+  }
+}
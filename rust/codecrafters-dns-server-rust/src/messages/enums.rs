@@ -1,21 +1,118 @@
-#[derive(Clone, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct RecordType(pub u16);
 
 impl RecordType {
   pub const Host: RecordType = RecordType(1);
+  pub const NameServer: RecordType = RecordType(2);
+  pub const CanonicalName: RecordType = RecordType(5);
+  pub const StartOfAuthority: RecordType = RecordType(6);
+  pub const Pointer: RecordType = RecordType(12);
+  pub const HostInfo: RecordType = RecordType(13);
+  pub const MailExchange: RecordType = RecordType(15);
+  pub const Text: RecordType = RecordType(16);
+  pub const Ipv6Host: RecordType = RecordType(28);
+  pub const Service: RecordType = RecordType(33);
+  pub const NamingAuthority: RecordType = RecordType(35);
+  pub const DelegationName: RecordType = RecordType(39);
+  pub const Options: RecordType = RecordType(41);
+  pub const DelegationSigner: RecordType = RecordType(43);
+  pub const SshFingerprint: RecordType = RecordType(44);
+  pub const Signature: RecordType = RecordType(46);
+  pub const NextSecure: RecordType = RecordType(47);
+  pub const DnsKey: RecordType = RecordType(48);
+  pub const NextSecure3: RecordType = RecordType(50);
+  pub const NextSecure3Parameters: RecordType = RecordType(51);
+  pub const TlsAssociation: RecordType = RecordType(52);
+  pub const TransactionSignature: RecordType = RecordType(250);
+  pub const IncrementalTransfer: RecordType = RecordType(251);
+  pub const ZoneTransfer: RecordType = RecordType(252);
+  pub const Any: RecordType = RecordType(255);
+  pub const CertificationAuthority: RecordType = RecordType(257);
+
+  /// Parses a type mnemonic such as `AAAA`, or the RFC 3597 generic `TYPE28` form.
+  pub fn from_mnemonic(text: &str) -> Option<RecordType> {
+    let text = text.to_ascii_uppercase();
+    if let Some((record_type, _)) = RecordTypeMnemonics.iter().find(|(_, mnemonic)| *mnemonic == text) {
+      return Some(*record_type);
+    }
+
+    text.strip_prefix("TYPE")?.parse().ok().map(RecordType)
+  }
+
+  pub fn mnemonic(&self) -> String {
+    match RecordTypeMnemonics.iter().find(|(record_type, _)| record_type == self) {
+      Some((_, mnemonic)) => mnemonic.to_string(),
+      None => format!("TYPE{}", self.0),
+    }
+  }
 }
 
+const RecordTypeMnemonics: [(RecordType, &str); 26] = [
+  (RecordType::Host, "A"),
+  (RecordType::NameServer, "NS"),
+  (RecordType::CanonicalName, "CNAME"),
+  (RecordType::StartOfAuthority, "SOA"),
+  (RecordType::Pointer, "PTR"),
+  (RecordType::HostInfo, "HINFO"),
+  (RecordType::MailExchange, "MX"),
+  (RecordType::Text, "TXT"),
+  (RecordType::Ipv6Host, "AAAA"),
+  (RecordType::Service, "SRV"),
+  (RecordType::NamingAuthority, "NAPTR"),
+  (RecordType::DelegationName, "DNAME"),
+  (RecordType::Options, "OPT"),
+  (RecordType::DelegationSigner, "DS"),
+  (RecordType::SshFingerprint, "SSHFP"),
+  (RecordType::Signature, "RRSIG"),
+  (RecordType::NextSecure, "NSEC"),
+  (RecordType::DnsKey, "DNSKEY"),
+  (RecordType::NextSecure3, "NSEC3"),
+  (RecordType::NextSecure3Parameters, "NSEC3PARAM"),
+  (RecordType::TlsAssociation, "TLSA"),
+  (RecordType::TransactionSignature, "TSIG"),
+  (RecordType::IncrementalTransfer, "IXFR"),
+  (RecordType::ZoneTransfer, "AXFR"),
+  (RecordType::Any, "ANY"),
+  (RecordType::CertificationAuthority, "CAA"),
+];
+
 impl From<u16> for RecordType {
   fn from(value: u16) -> Self {
     RecordType(value)
   }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct RecordClass(pub u16);
 
 impl RecordClass {
   pub const Internet: RecordClass = RecordClass(1);
+  pub const Chaos: RecordClass = RecordClass(3);
+  pub const Hesiod: RecordClass = RecordClass(4);
+  /// Meta classes of UPDATE messages (RFC 2136 section 1.3).
+  pub const None: RecordClass = RecordClass(254);
+  pub const Any: RecordClass = RecordClass(255);
+
+  /// Parses a class mnemonic such as `IN`, or the RFC 3597 generic `CLASS1` form.
+  pub fn from_mnemonic(text: &str) -> Option<RecordClass> {
+    match text.to_ascii_uppercase().as_str() {
+      "IN" => Some(RecordClass::Internet),
+      "CH" => Some(RecordClass::Chaos),
+      "HS" => Some(RecordClass::Hesiod),
+      text => text.strip_prefix("CLASS")?.parse().ok().map(RecordClass),
+    }
+  }
+
+  pub fn mnemonic(&self) -> String {
+    match *self {
+      RecordClass::Internet => "IN".to_string(),
+      RecordClass::Chaos => "CH".to_string(),
+      RecordClass::Hesiod => "HS".to_string(),
+      RecordClass::None => "NONE".to_string(),
+      RecordClass::Any => "ANY".to_string(),
+      RecordClass(value) => format!("CLASS{}", value),
+    }
+  }
 }
 
 impl From<u16> for RecordClass {
@@ -23,3 +120,87 @@ impl From<u16> for RecordClass {
     RecordClass(value)
   }
 }
+
+pub struct OperationCode;
+
+impl OperationCode {
+  pub const Query: u8 = 0;
+  pub const Notify: u8 = 4;
+  pub const Update: u8 = 5;
+
+  pub fn mnemonic(code: u8) -> String {
+    match code {
+      0 => "QUERY".to_string(),
+      1 => "IQUERY".to_string(),
+      2 => "STATUS".to_string(),
+      OperationCode::Notify => "NOTIFY".to_string(),
+      OperationCode::Update => "UPDATE".to_string(),
+      code => format!("OPCODE{}", code),
+    }
+  }
+}
+
+pub struct ResponseCode;
+
+impl ResponseCode {
+  pub const NoError: u8 = 0;
+  pub const FormatError: u8 = 1;
+  pub const ServerFailure: u8 = 2;
+  pub const NameError: u8 = 3;
+  pub const NotImplemented: u8 = 4;
+  pub const Refused: u8 = 5;
+  pub const NameExists: u8 = 6;
+  pub const RecordSetExists: u8 = 7;
+  pub const RecordSetMissing: u8 = 8;
+  pub const NotAuthoritative: u8 = 9;
+  pub const NotInZone: u8 = 10;
+  /// Errors carried in the TSIG record of a NOTAUTH response (RFC 8945 section 3).
+  pub const BadSignature: u8 = 16;
+  pub const BadKey: u8 = 17;
+  pub const BadTime: u8 = 18;
+  pub const BadCookie: u8 = 23;
+
+  pub fn mnemonic(code: u8) -> String {
+    match ResponseCodeMnemonics.iter().find(|(known, _)| *known == code) {
+      Some((_, mnemonic)) => mnemonic.to_string(),
+      None => format!("RCODE{}", code),
+    }
+  }
+}
+
+const ResponseCodeMnemonics: [(u8, &str); 15] = [
+  (ResponseCode::NoError, "NOERROR"),
+  (ResponseCode::FormatError, "FORMERR"),
+  (ResponseCode::ServerFailure, "SERVFAIL"),
+  (ResponseCode::NameError, "NXDOMAIN"),
+  (ResponseCode::NotImplemented, "NOTIMP"),
+  (ResponseCode::Refused, "REFUSED"),
+  (ResponseCode::NameExists, "YXDOMAIN"),
+  (ResponseCode::RecordSetExists, "YXRRSET"),
+  (ResponseCode::RecordSetMissing, "NXRRSET"),
+  (ResponseCode::NotAuthoritative, "NOTAUTH"),
+  (ResponseCode::NotInZone, "NOTZONE"),
+  (ResponseCode::BadSignature, "BADSIG"),
+  (ResponseCode::BadKey, "BADKEY"),
+  (ResponseCode::BadTime, "BADTIME"),
+  (ResponseCode::BadCookie, "BADCOOKIE"),
+];
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn it_converts_mnemonics_twoway() {
+    assert_eq!(RecordType::from_mnemonic("aaaa"), Some(RecordType::Ipv6Host));
+    assert_eq!(RecordType::from_mnemonic("TYPE65534"), Some(RecordType(65534)));
+    assert_eq!(RecordType::from_mnemonic("BOGUS"), None);
+    assert_eq!(RecordType::Signature.mnemonic(), "RRSIG");
+    assert_eq!(RecordType(65534).mnemonic(), "TYPE65534");
+    assert_eq!(RecordClass::from_mnemonic("in"), Some(RecordClass::Internet));
+    assert_eq!(RecordClass::from_mnemonic("CLASS7"), Some(RecordClass(7)));
+    assert_eq!(ResponseCode::mnemonic(ResponseCode::NameError), "NXDOMAIN");
+    assert_eq!(ResponseCode::mnemonic(12), "RCODE12");
+    assert_eq!(OperationCode::mnemonic(OperationCode::Update), "UPDATE");
+  }
+}
@@ -0,0 +1,38 @@
+//! What can be wrong with a message read off the wire or put together with the builder. Parsing
+//! with `try_from` reports these instead of panicking, so a server can answer FORMERR and a client
+//! can drop the response.
+
+use std::io;
+
+use thiserror::Error;
+
+use crate::messages::{DomainName, RecordType, ResponseCode};
+
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum MessageError {
+  #[error("Message of {0} octets is shorter than its header")]
+  ShortHeader(usize),
+  #[error("Malformed name at offset {offset} in the {section} section")]
+  MalformedName { section: &'static str, offset: usize },
+  #[error("The {section} section ends past the message at offset {offset}")]
+  Truncated { section: &'static str, offset: usize },
+  #[error("Operation code {0} does not fit in 4 bits")]
+  OperationCodeTooLarge(u8),
+  #[error("Response code {} needs EDNS to carry its upper bits", ResponseCode::mnemonic(*.0))]
+  ResponseCodeWithoutEdns(u8),
+  #[error("Name {0} is longer than {max} octets", max = DomainName::MaxLength)]
+  NameTooLong(DomainName),
+  #[error("Name {0} has a label longer than {max} octets", max = DomainName::MaxLabelLength)]
+  LabelTooLong(DomainName),
+  #[error("{0} records go in through edns() or by signing the built message")]
+  ReservedRecord(RecordType),
+  #[error("{count} records do not fit in the {section} section")]
+  SectionTooLarge { section: &'static str, count: usize },
+}
+
+/// Lets code exchanging messages over sockets report bad ones as `InvalidData`.
+impl From<MessageError> for io::Error {
+  fn from(error: MessageError) -> Self {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+  }
+}
@@ -6,13 +6,16 @@
 //! - Truncation (TC) - 1 bit - 1 if the message is larger than 512 bytes. Always 0 in UDP responses.
 //! - Recursion Desired (RD) - 1 bit - Sender sets this to 1 if the server should recursively resolve this query, 0 otherwise.
 //! - Recursion Available (RA) - 1 bit - Server sets this to 1 to indicate that recursion is available.
-//! - Reserved (Z) - 3 bits - Used by DNSSEC queries. At inception, it was reserved for future use.
+//! - Reserved (Z) - 1 bit - Reserved for future use, must be zero.
+//! - Authentic Data (AD) - 1 bit - Set by a validating resolver when all answer data is secure (RFC 4035).
+//! - Checking Disabled (CD) - 1 bit - Set by a client that does its own DNSSEC validation.
 //! - Response Code (RCODE) - 4 bits - Response code indicating the status of the response.
 //! - Question Count (QDCOUNT) - 16 bits - Number of questions in the Question section.
 //! - Answer Record Count (ANCOUNT) - 16 bits - Number of records in the Answer section.
 //! - Authority Record Count (NSCOUNT) - 16 bits - Number of records in the Authority section.
 //! - Additional Record Count (ARCOUNT) - 16 bits - Number of records in the Additional section.
 
+use crate::messages::MessageError;
 use crate::messages::traits::SizeOf;
 
 #[derive(Clone, Debug)]
@@ -24,16 +27,22 @@ impl From<HeaderBuffer> for Vec<u8> {
   }
 }
 
-impl From<HeaderBuffer> for HeaderProperties {
-  fn from(HeaderBuffer(buffer): HeaderBuffer) -> Self {
-    Self {
+impl TryFrom<&[u8]> for HeaderProperties {
+  type Error = MessageError;
+
+  fn try_from(buffer: &[u8]) -> Result<Self, MessageError> {
+    if buffer.len() < 12 {
+      return Err(MessageError::ShortHeader(buffer.len()));
+    }
+
+    Ok(Self {
       id: u16::from_be_bytes([buffer[0], buffer[1]]),
       flags: Flags(u16::from_be_bytes([buffer[2], buffer[3]])),
       question_count: u16::from_be_bytes([buffer[4], buffer[5]]),
       answer_record_count: u16::from_be_bytes([buffer[6], buffer[7]]),
       authority_record_count: u16::from_be_bytes([buffer[8], buffer[9]]),
       additional_record_count: u16::from_be_bytes([buffer[10], buffer[11]]),
-    }
+    })
   }
 }
 
@@ -72,6 +81,14 @@ impl HeaderProperties {
     self.flags.is_recursion_available()
   }
 
+  pub const fn is_authentic_data(&self) -> bool {
+    self.flags.is_authentic_data()
+  }
+
+  pub const fn is_checking_disabled(&self) -> bool {
+    self.flags.is_checking_disabled()
+  }
+
   pub const fn response_code(&self) -> u8 {
     self.flags.response_code()
   }
@@ -112,17 +129,19 @@ impl SizeOf for HeaderProperties {
   }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Flags(u16);
 
 impl Flags {
   pub const None: Flags = Flags(0b0000_0000_0000_0000);
   pub const Query: Flags = Flags(0b0000_0000_0000_0000);
   pub const Response: Flags = Flags(0b1000_0000_0000_0000);
-  pub const Authoritative: Flags = Flags(0b0000_0010_0000_0000);
-  pub const Truncated: Flags = Flags(0b0000_0100_0000_0000);
+  pub const Authoritative: Flags = Flags(0b0000_0100_0000_0000);
+  pub const Truncated: Flags = Flags(0b0000_0010_0000_0000);
   pub const RecursionDesired: Flags = Flags(0b0000_0001_0000_0000);
   pub const RecursionAvailable: Flags = Flags(0b0000_0000_1000_0000);
+  pub const AuthenticData: Flags = Flags(0b0000_0000_0010_0000);
+  pub const CheckingDisabled: Flags = Flags(0b0000_0000_0001_0000);
 
   pub const fn recursion_desired(value: bool) -> Flags {
     match value {
@@ -132,11 +151,16 @@ impl Flags {
   }
 
   pub const fn from_response_code(code: u8) -> Flags {
-    Flags((code as u16) as u16)
+    Flags(code as u16)
+  }
+
+  /// Replaces the 4 bit response code, keeping every other flag.
+  pub const fn with_response_code(self, code: u8) -> Flags {
+    Flags((self.0 & !(FlagBits::ResponseCode as u16)) | (code as u16 & FlagBits::ResponseCode as u16))
   }
 
   pub const fn from_operation_code(code: u8) -> Flags {
-    Flags(((code as u16) << 11) as u16)
+    Flags((code as u16) << 11)
   }
 
   pub const fn is_query(&self) -> bool {
@@ -163,6 +187,15 @@ impl Flags {
     self.is_set(FlagBits::RecursionAvailable)
   }
 
+  /// Whether the answer was validated with DNSSEC (RFC 4035 section 3.2.3).
+  pub const fn is_authentic_data(&self) -> bool {
+    self.is_set(FlagBits::AuthenticData)
+  }
+
+  pub const fn is_checking_disabled(&self) -> bool {
+    self.is_set(FlagBits::CheckingDisabled)
+  }
+
   pub const fn response_code(&self) -> u8 {
     (self.0 & FlagBits::ResponseCode as u16) as u8
   }
@@ -171,6 +204,19 @@ impl Flags {
     ((self.0 & FlagBits::OperationCode as u16) >> 11) as u8
   }
 
+  /// Only the flags a message may set on its own: AA, TC, RD, RA, AD and CD.
+  pub const fn settable(self) -> Flags {
+    Flags(
+      self.0
+        & (Flags::Authoritative.0
+          | Flags::Truncated.0
+          | Flags::RecursionDesired.0
+          | Flags::RecursionAvailable.0
+          | Flags::AuthenticData.0
+          | Flags::CheckingDisabled.0),
+    )
+  }
+
   const fn is_set(&self, flags: FlagBits) -> bool {
     let flags = flags as u16;
     self.0 & flags == flags
@@ -182,11 +228,13 @@ impl Flags {
 pub enum FlagBits {
   QueryResponse = 0b1000_0000_0000_0000,
   OperationCode = 0b0111_1000_0000_0000,
-  Truncated = 0b0000_0100_0000_0000,
-  Authoritative = 0b0000_0010_0000_0000,
+  Authoritative = 0b0000_0100_0000_0000,
+  Truncated = 0b0000_0010_0000_0000,
   RecursionDesired = 0b0000_0001_0000_0000,
   RecursionAvailable = 0b0000_0000_1000_0000,
-  Reserved = 0b0000_0000_0111_0000,
+  Reserved = 0b0000_0000_0100_0000,
+  AuthenticData = 0b0000_0000_0010_0000,
+  CheckingDisabled = 0b0000_0000_0001_0000,
   ResponseCode = 0b0000_0000_0000_1111,
 }
 
@@ -204,8 +252,8 @@ mod tests {
 
   #[test]
   fn it_converts_from_buffer_to_properties() {
-    let test_buffer: HeaderBuffer = HeaderBuffer(hex!["abcd00000000000000000000"].to_vec());
-    let header = HeaderProperties::from(test_buffer);
+    let test_buffer = hex!["abcd00000000000000000000"];
+    let header = HeaderProperties::try_from(&test_buffer[..]).unwrap();
     println!("{:?}", &header.id);
 
     assert_eq!(header.id, 0xabcd);
@@ -227,7 +275,7 @@ mod tests {
   fn it_converts_twoway() {
     let test_buffer: HeaderBuffer = HeaderBuffer(hex!["abcd00000000000000000000"].to_vec());
 
-    let properties = HeaderProperties::from(test_buffer.clone());
+    let properties = HeaderProperties::try_from(test_buffer.0.as_slice()).unwrap();
     let buffer = HeaderBuffer::from(properties);
 
     assert_eq!(buffer.0, test_buffer.0);
@@ -240,6 +288,23 @@ mod tests {
     assert_eq!(flags.operation_code(), 7);
   }
 
+  #[test]
+  fn it_places_authoritative_before_truncated() {
+    let header = HeaderProperties::try_from(&hex!["abcd84000000000000000000"][..]).unwrap();
+
+    assert_eq!(header.is_authoritative(), true);
+    assert_eq!(header.is_truncated(), false);
+    assert_eq!(Flags::Response | Flags::Truncated, Flags(0x8200));
+  }
+
+  #[test]
+  fn it_replaces_response_code() {
+    let flags = (Flags::Response | Flags::from_response_code(3)).with_response_code(5);
+
+    assert_eq!(flags.response_code(), 5);
+    assert_eq!(flags.is_response(), true);
+  }
+
   #[test]
   fn it_convert_response_code_to_flags() {
     let flags = Flags::from_response_code(7);
@@ -0,0 +1,562 @@
+//! JSON representation of DNS messages (RFC 8427), through serde so any format crate can carry it.
+//!
+//! Messages serialize fully decoded: the header fields under their RFC 8427 names (`ID`, `QR`,
+//! `Opcode`, `RCODE`, `QDCOUNT`, ...), a single question as `QNAME`, `QTYPE` and `QCLASS` or more
+//! as `questionRRs`, and the records as `answerRRs`, `authorityRRs` and `additionalRRs`. Every
+//! record keeps its data as `RDATAHEX`, and known types add it in presentation format under
+//! `rdata` and the type mnemonic, e.g. `"rdataA": "8.8.8.8"`.
+//!
+//! The compact form is the wire format as a single `messageOctetsHEX` member, written with the
+//! [`compact`] module. Deserializing a message accepts either form, the flags as booleans or as 0
+//! and 1, and names with or without the trailing dot. Missing counts are taken from the sections,
+//! counts given must match them, and record members other than those of RFC 8427 are ignored.
+
+use std::collections::BTreeMap;
+
+use data_encoding::{HEXLOWER_PERMISSIVE, HEXUPPER};
+use serde::de::{Error, IgnoredAny};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::messages::*;
+
+#[derive(Serialize, Deserialize, Default)]
+struct HeaderObject {
+  #[serde(rename = "ID", default)]
+  id: u16,
+  #[serde(rename = "QR", default, deserialize_with = "flag")]
+  response: bool,
+  #[serde(rename = "Opcode", default)]
+  operation_code: u8,
+  #[serde(rename = "AA", default, deserialize_with = "flag")]
+  authoritative: bool,
+  #[serde(rename = "TC", default, deserialize_with = "flag")]
+  truncated: bool,
+  #[serde(rename = "RD", default, deserialize_with = "flag")]
+  recursion_desired: bool,
+  #[serde(rename = "RA", default, deserialize_with = "flag")]
+  recursion_available: bool,
+  #[serde(rename = "AD", default, deserialize_with = "flag")]
+  authentic_data: bool,
+  #[serde(rename = "CD", default, deserialize_with = "flag")]
+  checking_disabled: bool,
+  #[serde(rename = "RCODE", default)]
+  response_code: u8,
+  #[serde(rename = "QDCOUNT", default, skip_serializing_if = "Option::is_none")]
+  question_count: Option<u16>,
+  #[serde(rename = "ANCOUNT", default, skip_serializing_if = "Option::is_none")]
+  answer_record_count: Option<u16>,
+  #[serde(rename = "NSCOUNT", default, skip_serializing_if = "Option::is_none")]
+  authority_record_count: Option<u16>,
+  #[serde(rename = "ARCOUNT", default, skip_serializing_if = "Option::is_none")]
+  additional_record_count: Option<u16>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct QuestionObject {
+  #[serde(rename = "NAME")]
+  name: String,
+  #[serde(rename = "TYPE")]
+  record_type: u16,
+  #[serde(rename = "TYPEname", default)]
+  type_name: String,
+  #[serde(rename = "CLASS", default = "internet")]
+  record_class: u16,
+  #[serde(rename = "CLASSname", default)]
+  class_name: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RecordObject {
+  #[serde(rename = "NAME")]
+  name: String,
+  #[serde(rename = "TYPE")]
+  record_type: u16,
+  #[serde(rename = "TYPEname", default)]
+  type_name: String,
+  #[serde(rename = "CLASS", default = "internet")]
+  record_class: u16,
+  #[serde(rename = "CLASSname", default)]
+  class_name: String,
+  #[serde(rename = "TTL", default)]
+  time_to_live: u32,
+  #[serde(rename = "RDLENGTH", default)]
+  data_length: usize,
+  #[serde(rename = "RDATAHEX", default, skip_serializing_if = "Option::is_none")]
+  data: Option<String>,
+  /// The `rdata<TYPE>` member, along with any other member not named above.
+  #[serde(flatten)]
+  presentation: BTreeMap<String, Member>,
+}
+
+/// A record member beyond those RFC 8427 defines: the `rdata<TYPE>` string or one to ignore.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Member {
+  Text(String),
+  #[serde(skip_serializing)]
+  Other(IgnoredAny),
+}
+
+#[derive(Serialize, Deserialize)]
+struct MessageObject {
+  #[serde(flatten)]
+  header: HeaderObject,
+  #[serde(rename = "QNAME", default, skip_serializing_if = "Option::is_none")]
+  question_name: Option<String>,
+  #[serde(rename = "QTYPE", default, skip_serializing_if = "Option::is_none")]
+  question_type: Option<u16>,
+  #[serde(rename = "QTYPEname", default, skip_serializing_if = "Option::is_none")]
+  question_type_name: Option<String>,
+  #[serde(rename = "QCLASS", default, skip_serializing_if = "Option::is_none")]
+  question_class: Option<u16>,
+  #[serde(rename = "QCLASSname", default, skip_serializing_if = "Option::is_none")]
+  question_class_name: Option<String>,
+  #[serde(rename = "questionRRs", default, skip_serializing_if = "Vec::is_empty")]
+  questions: Vec<QuestionObject>,
+  #[serde(rename = "answerRRs", default, skip_serializing_if = "Vec::is_empty")]
+  answers: Vec<RecordObject>,
+  #[serde(rename = "authorityRRs", default, skip_serializing_if = "Vec::is_empty")]
+  authorities: Vec<RecordObject>,
+  #[serde(rename = "additionalRRs", default, skip_serializing_if = "Vec::is_empty")]
+  additionals: Vec<RecordObject>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CompactObject {
+  #[serde(rename = "messageOctetsHEX")]
+  octets: String,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AnyMessageObject {
+  Compact(CompactObject),
+  Decoded(Box<MessageObject>),
+}
+
+impl From<&HeaderProperties> for HeaderObject {
+  fn from(header: &HeaderProperties) -> Self {
+    Self {
+      id: header.id,
+      response: header.is_response(),
+      operation_code: header.operation_code(),
+      authoritative: header.is_authoritative(),
+      truncated: header.is_truncated(),
+      recursion_desired: header.is_recursion_desired(),
+      recursion_available: header.is_recursion_available(),
+      authentic_data: header.is_authentic_data(),
+      checking_disabled: header.is_checking_disabled(),
+      response_code: header.response_code(),
+      question_count: Some(header.question_count),
+      answer_record_count: Some(header.answer_record_count),
+      authority_record_count: Some(header.authority_record_count),
+      additional_record_count: Some(header.additional_record_count),
+    }
+  }
+}
+
+impl HeaderObject {
+  fn to_header(&self) -> HeaderProperties {
+    let bits = [
+      (self.response, Flags::Response),
+      (self.authoritative, Flags::Authoritative),
+      (self.truncated, Flags::Truncated),
+      (self.recursion_desired, Flags::RecursionDesired),
+      (self.recursion_available, Flags::RecursionAvailable),
+      (self.authentic_data, Flags::AuthenticData),
+      (self.checking_disabled, Flags::CheckingDisabled),
+    ];
+    let flags = bits.into_iter().filter(|(set, _)| *set).fold(
+      Flags::from_operation_code(self.operation_code & 0x0f),
+      |flags, (_, bit)| flags | bit,
+    );
+
+    HeaderProperties {
+      id: self.id,
+      flags: flags.with_response_code(self.response_code),
+      question_count: self.question_count.unwrap_or_default(),
+      answer_record_count: self.answer_record_count.unwrap_or_default(),
+      authority_record_count: self.authority_record_count.unwrap_or_default(),
+      additional_record_count: self.additional_record_count.unwrap_or_default(),
+    }
+  }
+}
+
+impl From<&QuestionProperties> for QuestionObject {
+  fn from(question: &QuestionProperties) -> Self {
+    Self {
+      name: question.domain_name.to_string(),
+      record_type: question.record_type.0,
+      type_name: question.record_type.mnemonic(),
+      record_class: question.record_class.0,
+      class_name: question.record_class.mnemonic(),
+    }
+  }
+}
+
+impl TryFrom<QuestionObject> for QuestionProperties {
+  type Error = String;
+
+  fn try_from(question: QuestionObject) -> Result<Self, String> {
+    Ok(Self {
+      domain_name: parse_name(&question.name, &DomainName::root())?,
+      record_type: RecordType(question.record_type),
+      record_class: RecordClass(question.record_class),
+    })
+  }
+}
+
+impl From<&AnswerProperties> for RecordObject {
+  fn from(record: &AnswerProperties) -> Self {
+    let mut presentation = BTreeMap::new();
+    match record.record_data() {
+      RecordData::Unknown(_) => {}
+      data => {
+        presentation.insert(format!("rdata{}", record.record_type), Member::Text(data.to_string()));
+      }
+    }
+
+    Self {
+      name: record.domain_name.to_string(),
+      record_type: record.record_type.0,
+      type_name: record.record_type.mnemonic(),
+      record_class: record.record_class.0,
+      class_name: record.record_class.mnemonic(),
+      time_to_live: record.time_to_live,
+      data_length: record.data.len(),
+      data: Some(HEXUPPER.encode(&record.data)),
+      presentation,
+    }
+  }
+}
+
+impl TryFrom<RecordObject> for AnswerProperties {
+  type Error = String;
+
+  fn try_from(record: RecordObject) -> Result<Self, String> {
+    let domain_name = parse_name(&record.name, &DomainName::root())?;
+    let record_type = RecordType(record.record_type);
+    let text = match record.presentation.get(&format!("rdata{}", record_type)) {
+      Some(Member::Text(text)) => Some(text),
+      _ => None,
+    };
+    let data = match (record.data, text) {
+      (Some(data), _) => HEXLOWER_PERMISSIVE
+        .decode(data.as_bytes())
+        .map_err(|_| format!("Invalid RDATAHEX of {}", record.name))?,
+      (None, Some(text)) => {
+        let entries = tokenize(text).map_err(|(_, error)| error)?;
+        let tokens = entries.first().map(|entry| entry.tokens.as_slice()).unwrap_or_default();
+        parse_record_data(record_type, tokens, &DomainName::root())?
+      }
+      (None, None) => return Err(format!("No RDATAHEX for {}", record.name)),
+    };
+
+    Ok(Self {
+      domain_name,
+      record_type,
+      record_class: RecordClass(record.record_class),
+      time_to_live: record.time_to_live,
+      data,
+    })
+  }
+}
+
+impl From<&MessageProperties> for MessageObject {
+  fn from(message: &MessageProperties) -> Self {
+    let records = |records: &[AnswerProperties]| records.iter().map(RecordObject::from).collect();
+    let mut object = Self {
+      header: HeaderObject::from(&message.header),
+      question_name: None,
+      question_type: None,
+      question_type_name: None,
+      question_class: None,
+      question_class_name: None,
+      questions: vec![],
+      answers: records(&message.answers),
+      authorities: records(&message.authorities),
+      additionals: records(&message.additionals),
+    };
+
+    match message.questions.as_slice() {
+      [question] => {
+        object.question_name = Some(question.domain_name.to_string());
+        object.question_type = Some(question.record_type.0);
+        object.question_type_name = Some(question.record_type.mnemonic());
+        object.question_class = Some(question.record_class.0);
+        object.question_class_name = Some(question.record_class.mnemonic());
+      }
+      questions => object.questions = questions.iter().map(QuestionObject::from).collect(),
+    }
+
+    object
+  }
+}
+
+impl TryFrom<MessageObject> for MessageProperties {
+  type Error = String;
+
+  fn try_from(object: MessageObject) -> Result<Self, String> {
+    let records = |records: Vec<RecordObject>| {
+      records
+        .into_iter()
+        .map(AnswerProperties::try_from)
+        .collect::<Result<Vec<_>, String>>()
+    };
+    let mut questions = object
+      .questions
+      .into_iter()
+      .map(QuestionProperties::try_from)
+      .collect::<Result<Vec<_>, String>>()?;
+    if let Some(name) = object.question_name {
+      questions.insert(
+        0,
+        QuestionProperties {
+          domain_name: parse_name(&name, &DomainName::root())?,
+          record_type: RecordType(object.question_type.unwrap_or(RecordType::Host.0)),
+          record_class: RecordClass(object.question_class.unwrap_or(RecordClass::Internet.0)),
+        },
+      );
+    }
+
+    let mut message = Self {
+      header: object.header.to_header(),
+      questions,
+      answers: records(object.answers)?,
+      authorities: records(object.authorities)?,
+      additionals: records(object.additionals)?,
+    };
+    // Counts given must be those of the sections, which the wire format repeats.
+    let count = |member: &str, given: Option<u16>, length: usize| {
+      let length = u16::try_from(length).map_err(|_| format!("{} records do not fit in {}", length, member))?;
+      match given {
+        Some(given) if given != length => Err(format!("{} of {} does not match {} records", member, given, length)),
+        _ => Ok(length),
+      }
+    };
+    let header = &object.header;
+    message.header.question_count = count("QDCOUNT", header.question_count, message.questions.len())?;
+    message.header.answer_record_count = count("ANCOUNT", header.answer_record_count, message.answers.len())?;
+    message.header.authority_record_count = count("NSCOUNT", header.authority_record_count, message.authorities.len())?;
+    message.header.additional_record_count =
+      count("ARCOUNT", header.additional_record_count, message.additionals.len())?;
+
+    Ok(message)
+  }
+}
+
+impl Serialize for HeaderProperties {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    HeaderObject::from(self).serialize(serializer)
+  }
+}
+
+impl<'de> Deserialize<'de> for HeaderProperties {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    Ok(HeaderObject::deserialize(deserializer)?.to_header())
+  }
+}
+
+impl Serialize for QuestionProperties {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    QuestionObject::from(self).serialize(serializer)
+  }
+}
+
+impl<'de> Deserialize<'de> for QuestionProperties {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    QuestionObject::deserialize(deserializer)?
+      .try_into()
+      .map_err(D::Error::custom)
+  }
+}
+
+impl Serialize for AnswerProperties {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    RecordObject::from(self).serialize(serializer)
+  }
+}
+
+impl<'de> Deserialize<'de> for AnswerProperties {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    RecordObject::deserialize(deserializer)?
+      .try_into()
+      .map_err(D::Error::custom)
+  }
+}
+
+impl Serialize for MessageProperties {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    MessageObject::from(self).serialize(serializer)
+  }
+}
+
+impl<'de> Deserialize<'de> for MessageProperties {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    match AnyMessageObject::deserialize(deserializer)? {
+      AnyMessageObject::Compact(object) => from_octets(&object.octets).map_err(D::Error::custom),
+      AnyMessageObject::Decoded(object) => (*object).try_into().map_err(D::Error::custom),
+    }
+  }
+}
+
+/// The compact form, for `#[serde(with = "compact")]` on message fields or called directly.
+pub mod compact {
+  use super::*;
+
+  pub fn serialize<S: Serializer>(message: &MessageProperties, serializer: S) -> Result<S::Ok, S::Error> {
+    let octets: Vec<u8> = message.clone().into();
+
+    CompactObject {
+      octets: HEXUPPER.encode(&octets),
+    }
+    .serialize(serializer)
+  }
+
+  pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<MessageProperties, D::Error> {
+    from_octets(&CompactObject::deserialize(deserializer)?.octets).map_err(D::Error::custom)
+  }
+}
+
+fn from_octets(hex: &str) -> Result<MessageProperties, String> {
+  let octets = HEXLOWER_PERMISSIVE
+    .decode(hex.as_bytes())
+    .map_err(|_| "Invalid messageOctetsHEX".to_string())?;
+
+  MessageProperties::try_from(octets.as_slice()).map_err(|error| error.to_string())
+}
+
+/// RFC 8427 calls the flags booleans, yet its own examples write them as 0 and 1.
+fn flag<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+  #[derive(Deserialize)]
+  #[serde(untagged)]
+  enum Flag {
+    Boolean(bool),
+    Number(u8),
+  }
+
+  match Flag::deserialize(deserializer)? {
+    Flag::Boolean(value) => Ok(value),
+    Flag::Number(value) => Ok(value != 0),
+  }
+}
+
+fn internet() -> u16 {
+  RecordClass::Internet.0
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::{Value, json};
+
+  fn message() -> MessageProperties {
+    let mut message = MessageProperties {
+      header: HeaderProperties {
+        id: 19678,
+        flags: Flags::Response | Flags::RecursionDesired | Flags::RecursionAvailable,
+        question_count: 1,
+        answer_record_count: 1,
+        authority_record_count: 0,
+        additional_record_count: 0,
+      },
+      questions: vec![QuestionProperties {
+        domain_name: DomainName::from("example.com"),
+        record_type: RecordType::Host,
+        record_class: RecordClass::Internet,
+      }],
+      answers: vec![AnswerProperties {
+        domain_name: DomainName::from("example.com"),
+        record_type: RecordType::Host,
+        record_class: RecordClass::Internet,
+        time_to_live: 3600,
+        data: vec![192, 0, 2, 1],
+      }],
+      authorities: vec![],
+      additionals: vec![],
+    };
+    message.set_edns(EdnsProperties::new());
+    message
+  }
+
+  #[test]
+  fn it_serializes_messages_decoded() {
+    let value = serde_json::to_value(message()).unwrap();
+
+    assert_eq!(value["ID"], 19678);
+    assert_eq!(value["QR"], true);
+    assert_eq!(value["AA"], false);
+    assert_eq!(value["Opcode"], 0);
+    assert_eq!(value["ARCOUNT"], 1);
+    assert_eq!(value["QNAME"], "example.com.");
+    assert_eq!(value["QTYPEname"], "A");
+    assert_eq!(value.get("questionRRs"), None);
+    assert_eq!(
+      value["answerRRs"],
+      json!([{
+        "NAME": "example.com.",
+        "TYPE": 1,
+        "TYPEname": "A",
+        "CLASS": 1,
+        "CLASSname": "IN",
+        "TTL": 3600,
+        "RDLENGTH": 4,
+        "RDATAHEX": "C0000201",
+        "rdataA": "192.0.2.1"
+      }])
+    );
+    assert_eq!(value["additionalRRs"][0]["TYPEname"], "OPT");
+    assert_eq!(value["additionalRRs"][0]["CLASS"], 1232);
+  }
+
+  #[test]
+  fn it_round_trips_both_forms() {
+    let original: Vec<u8> = message().into();
+
+    let decoded: MessageProperties = serde_json::from_value(serde_json::to_value(message()).unwrap()).unwrap();
+    let mut compact = serde_json::Serializer::new(vec![]);
+    compact::serialize(&message(), &mut compact).unwrap();
+    let compact: Value = serde_json::from_slice(&compact.into_inner()).unwrap();
+    let parsed: MessageProperties = serde_json::from_value(compact.clone()).unwrap();
+
+    assert_eq!(Vec::<u8>::from(decoded), original);
+    assert_eq!(compact["messageOctetsHEX"], HEXUPPER.encode(&original));
+    assert_eq!(Vec::<u8>::from(parsed), original);
+  }
+
+  #[test]
+  fn it_reads_rfc_8427_examples() {
+    let query: MessageProperties = serde_json::from_value(json!({
+      "ID": 19678, "QR": 0, "Opcode": 0, "AA": 0, "TC": 0, "RD": 1, "RA": 0, "AD": 0, "CD": 0, "RCODE": 0,
+      "QDCOUNT": 1, "ANCOUNT": 0, "NSCOUNT": 0, "ARCOUNT": 0,
+      "QNAME": "example.com", "QTYPE": 1, "QCLASS": 1
+    }))
+    .unwrap();
+    let response: MessageProperties = serde_json::from_value(json!({
+      "ID": 32784, "QR": true, "RCODE": 3,
+      "QNAME": "example.com", "QTYPE": 28,
+      "answerRRs": [{
+        "NAME": "example.com", "TYPE": 15, "TTL": 300, "rdataMX": "10 mail.example.com.",
+        "comment": { "source": "cache" }, "hits": 3
+      }]
+    }))
+    .unwrap();
+    let miscounted = json!({ "ID": 1, "ANCOUNT": 2, "QNAME": "example.com", "QTYPE": 1 });
+
+    assert_eq!(query.header.is_recursion_desired(), true);
+    assert_eq!(query.questions[0].domain_name, DomainName::from("example.com"));
+    assert_eq!(query.questions[0].record_type, RecordType::Host);
+    assert_eq!(response.header.response_code(), 3);
+    assert_eq!(response.header.question_count, 1);
+    assert_eq!(response.header.answer_record_count, 1);
+    assert_eq!(
+      response.answers[0].to_string(),
+      "example.com. 300 IN MX 10 mail.example.com."
+    );
+    assert_eq!(
+      serde_json::from_value::<MessageProperties>(json!({ "messageOctetsHEX": "4CDE" })).is_err(),
+      true
+    );
+    assert_eq!(serde_json::from_value::<MessageProperties>(miscounted).is_err(), true);
+  }
+}
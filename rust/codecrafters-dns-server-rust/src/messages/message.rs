@@ -1,4 +1,7 @@
 use crate::messages::answer::*;
+use crate::messages::edns::*;
+use crate::messages::enums::*;
+use crate::messages::error::*;
 use crate::messages::header::*;
 use crate::messages::question::*;
 use crate::messages::traits::*;
@@ -12,33 +15,41 @@ impl From<MessageBuffer> for Vec<u8> {
   }
 }
 
-impl From<MessageBuffer> for MessageProperties {
-  fn from(MessageBuffer(buffer): MessageBuffer) -> Self {
-    let mut offset = 0;
-    let header = HeaderProperties::from(HeaderBuffer(buffer.clone()));
-    offset += 12;
+impl TryFrom<&[u8]> for MessageProperties {
+  type Error = MessageError;
+
+  fn try_from(buffer: &[u8]) -> Result<Self, MessageError> {
+    let header = HeaderProperties::try_from(buffer)?;
+    let mut offset = 12;
 
     let mut questions = vec![];
     for _ in 0..header.question_count {
-      let question = QuestionBuffer(buffer.clone(), offset);
-      let props = QuestionProperties::from(question);
-      offset += props.size_of();
-      questions.push(props);
+      let (question, next) = QuestionProperties::try_from_bytes(buffer, offset)?;
+      offset = next;
+      questions.push(question);
     }
 
-    let mut answers = vec![];
-    for _ in 0..header.answer_record_count {
-      let answer = AnswerBuffer(buffer.clone(), offset);
-      let props = AnswerProperties::from(answer);
-      offset += props.size_of();
-      answers.push(props);
-    }
+    let mut read_records = |count: u16, section: &'static str| {
+      let mut records = vec![];
+      for _ in 0..count {
+        let (record, next) = AnswerProperties::try_from_bytes(buffer, offset, section)?;
+        offset = next;
+        records.push(record);
+      }
+      Ok::<_, MessageError>(records)
+    };
 
-    Self {
+    let answers = read_records(header.answer_record_count, "answer")?;
+    let authorities = read_records(header.authority_record_count, "authority")?;
+    let additionals = read_records(header.additional_record_count, "additional")?;
+
+    Ok(Self {
       header,
       questions,
       answers,
-    }
+      authorities,
+      additionals,
+    })
   }
 }
 
@@ -47,6 +58,73 @@ pub struct MessageProperties {
   pub header: HeaderProperties,
   pub questions: Vec<QuestionProperties>,
   pub answers: Vec<AnswerProperties>,
+  pub authorities: Vec<AnswerProperties>,
+  pub additionals: Vec<AnswerProperties>,
+}
+
+impl MessageProperties {
+  /// Largest UDP message allowed without EDNS (RFC 1035 section 4.2.1).
+  pub const MaxUdpSize: usize = 512;
+
+  pub fn edns(&self) -> Option<EdnsProperties> {
+    self
+      .additionals
+      .iter()
+      .find(|record| record.record_type == RecordType::Options)
+      .map(EdnsProperties::from)
+  }
+
+  pub fn set_edns(&mut self, edns: EdnsProperties) {
+    self
+      .additionals
+      .retain(|record| record.record_type != RecordType::Options);
+    self.additionals.push(edns.into());
+    self.header.additional_record_count = self.additionals.len() as u16;
+  }
+
+  /// The full response code, including the upper bits carried in the OPT pseudo-record.
+  pub fn response_code(&self) -> u8 {
+    let extended = self.edns().map_or(0, |edns| edns.extended_response_code);
+
+    (extended << 4) | self.header.response_code()
+  }
+
+  /// Sets the full response code; codes above 15 need an OPT pseudo-record to carry their upper bits.
+  pub fn set_response_code(&mut self, code: u8) {
+    self.header.flags = self.header.flags.with_response_code(code & 0x0f);
+
+    if let Some(record) = self
+      .additionals
+      .iter_mut()
+      .find(|record| record.record_type == RecordType::Options)
+    {
+      record.time_to_live = (record.time_to_live & 0x00ff_ffff) | (((code >> 4) as u32) << 24);
+    }
+  }
+
+  /// Fits the message into `limit` bytes. Additional records are dropped first, last ones first,
+  /// keeping OPT; if the message still does not fit, all records are removed and the TC bit is set
+  /// so the client retries over TCP.
+  pub fn truncate(&mut self, limit: usize) {
+    while self.size_of() > limit {
+      let Some(index) = self
+        .additionals
+        .iter()
+        .rposition(|record| record.record_type != RecordType::Options)
+      else {
+        self.answers.clear();
+        self.authorities.clear();
+        self.header.flags = self.header.flags | Flags::Truncated;
+        break;
+      };
+
+      self.additionals.remove(index);
+    }
+
+    self.header.answer_record_count = self.answers.len() as u16;
+    self.header.authority_record_count = self.authorities.len() as u16;
+    self.header.additional_record_count = self.additionals.len() as u16;
+  }
 }
 
 impl SizeOf for MessageProperties {
@@ -54,6 +132,16 @@ impl SizeOf for MessageProperties {
     self.header.size_of()
       + self.questions.iter().map(|question| question.size_of()).sum::<usize>()
       + self.answers.iter().map(|answer| answer.size_of()).sum::<usize>()
+      + self
+        .authorities
+        .iter()
+        .map(|authority| authority.size_of())
+        .sum::<usize>()
+      + self
+        .additionals
+        .iter()
+        .map(|additional| additional.size_of())
+        .sum::<usize>()
   }
 }
 
@@ -76,12 +164,144 @@ impl From<MessageProperties> for MessageBuffer {
       buffer.extend(question_buffer);
     }
 
-    for answer in properties.answers {
-      let answer_buffer: Vec<u8> = AnswerBuffer::from(answer).into();
+    let records = properties
+      .answers
+      .into_iter()
+      .chain(properties.authorities)
+      .chain(properties.additionals);
+    for record in records {
+      let record_buffer: Vec<u8> = AnswerBuffer::from(record).into();
 
-      buffer.extend(answer_buffer);
+      buffer.extend(record_buffer);
     }
 
     MessageBuffer(buffer)
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::macros::*;
+  use crate::messages::*;
+
+  #[test]
+  fn it_reads_all_sections_with_compressed_names() {
+    let buffer = hex![
+      "abcd81800001000100010001\
+       03616263036e65740000010001\
+       c00c000100010000003c000401020304\
+       c00c000200010000003c0005026e73c00c\
+       c035000100010000003c000405060708"
+    ];
+
+    let message = MessageProperties::try_from(&buffer[..]).unwrap();
+
+    assert_eq!(message.questions[0].domain_name, DomainName::from("abc.net"));
+    assert_eq!(message.answers[0].data, vec![1, 2, 3, 4]);
+    assert_eq!(message.authorities[0].record_type, RecordType::NameServer);
+    assert_eq!(message.authorities[0].data, DomainName::from("ns.abc.net").to_bytes());
+    assert_eq!(message.additionals[0].domain_name, DomainName::from("ns.abc.net"));
+    assert_eq!(message.additionals[0].data, vec![5, 6, 7, 8]);
+  }
+
+  #[test]
+  fn it_reports_malformed_messages_instead_of_panicking() {
+    let parse = |buffer: &[u8]| MessageProperties::try_from(buffer).map(|message| message.questions.len());
+
+    assert_eq!(parse(&hex!["abcd8180"]), Err(MessageError::ShortHeader(4)));
+    assert_eq!(
+      parse(&hex!["abcd01000001000000000000c00c"]),
+      Err(MessageError::MalformedName {
+        section: "question",
+        offset: 12
+      })
+    );
+    assert_eq!(
+      parse(&hex!["abcd0100000100000000000003616263000001"]),
+      Err(MessageError::Truncated {
+        section: "question",
+        offset: 12
+      })
+    );
+    assert_eq!(
+      parse(&hex!["abcd8180000000000000000100002904d0000080000008000a0004"]),
+      Err(MessageError::Truncated {
+        section: "additional",
+        offset: 12
+      })
+    );
+    assert_eq!(parse(&hex!["abcd01000001000000000000000001000100"]), Ok(1));
+  }
+
+  #[test]
+  fn it_splits_extended_response_codes_into_edns() {
+    let mut message = MessageProperties::try_from(&hex!["abcd80000000000000000000"][..]).unwrap();
+    message.set_edns(EdnsProperties::new());
+
+    message.set_response_code(ResponseCode::BadCookie);
+
+    assert_eq!(message.header.response_code(), 7);
+    assert_eq!(message.edns().unwrap().extended_response_code, 1);
+    assert_eq!(message.response_code(), ResponseCode::BadCookie);
+  }
+
+  #[test]
+  fn it_truncates_additionals_before_answers() {
+    let record = |name: &str| AnswerProperties {
+      domain_name: DomainName::from(name),
+      record_type: RecordType::Host,
+      record_class: RecordClass::Internet,
+      time_to_live: 60,
+      data: vec![192, 0, 2, 1],
+    };
+    let mut message = MessageProperties::try_from(&hex!["abcd80000000000000000000"][..]).unwrap();
+    message.answers = vec![record("a.abc.net")];
+    message.additionals = vec![record("b.abc.net"), record("c.abc.net")];
+    message.set_edns(EdnsProperties::new());
+
+    let mut trimmed = message.clone();
+    trimmed.truncate(message.size_of() - 1);
+    let mut truncated = message.clone();
+    truncated.truncate(40);
+
+    assert_eq!(trimmed.additionals.len(), 2);
+    assert_eq!(trimmed.additionals[0].domain_name, DomainName::from("b.abc.net"));
+    assert_eq!(trimmed.header.additional_record_count, 2);
+    assert_eq!(trimmed.header.is_truncated(), false);
+    assert_eq!(truncated.answers.len(), 0);
+    assert_eq!(truncated.additionals[0].record_type, RecordType::Options);
+    assert_eq!(truncated.header.is_truncated(), true);
+  }
+
+  #[test]
+  fn it_converts_twoway() {
+    let message = MessageProperties {
+      header: HeaderProperties {
+        id: 7,
+        flags: Flags::Response,
+        question_count: 0,
+        answer_record_count: 0,
+        authority_record_count: 1,
+        additional_record_count: 0,
+      },
+      questions: vec![],
+      answers: vec![],
+      authorities: vec![AnswerProperties {
+        domain_name: DomainName::from("abc.net"),
+        record_type: RecordType::NameServer,
+        record_class: RecordClass::Internet,
+        time_to_live: 60,
+        data: DomainName::from("ns.abc.net").to_bytes(),
+      }],
+      additionals: vec![],
+    };
+
+    let buffer: Vec<u8> = message.clone().into();
+    let parsed = MessageProperties::try_from(buffer.as_slice()).unwrap();
+
+    assert_eq!(buffer.len(), message.size_of());
+    assert_eq!(parsed.authorities[0].domain_name, DomainName::from("abc.net"));
+    assert_eq!(parsed.authorities[0].data, message.authorities[0].data);
+  }
+}
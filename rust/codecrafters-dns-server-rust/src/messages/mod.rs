@@ -1,15 +1,30 @@
 pub mod answer;
+pub mod builder;
 pub mod domain_name;
+pub mod edns;
 pub mod enums;
+pub mod error;
 pub mod header;
+pub mod json;
 pub mod message;
+pub mod presentation;
 pub mod question;
+pub mod rdata;
+pub mod record_data;
+pub mod tokens;
 pub mod traits;
 
 pub use answer::*;
+pub use builder::*;
 pub use domain_name::*;
+pub use edns::*;
 pub use enums::*;
+pub use error::*;
 pub use header::*;
 pub use message::*;
+pub use presentation::*;
 pub use question::*;
+pub use rdata::*;
+pub use record_data::*;
+pub use tokens::*;
 pub use traits::*;
@@ -0,0 +1,570 @@
+//! Presentation format (RFC 1035 section 5.1), the text form of DNS data found in master files and
+//! dig output. Names are absolute with the octets that would end or split a label escaped, and
+//! character strings are quoted. Data the record model does not know is written in the RFC 3597
+//! generic `\# <length> <hex>` format, so every record reads back into the same wire data.
+//!
+//! A record reads `example.com. 60 IN A 8.8.8.8`, and a message is laid out as dig prints it:
+//! the header with its flags, the EDNS pseudo-section, then the question, answer, authority and
+//! additional sections.
+
+use std::fmt;
+
+use data_encoding::{BASE32_DNSSEC, BASE64, HEXUPPER};
+
+use crate::messages::*;
+
+impl fmt::Display for DomainName {
+  fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if self.is_root() {
+      return formatter.write_str(".");
+    }
+
+    for label in &self.0 {
+      for byte in label.bytes() {
+        match byte {
+          b'.' | b'\\' | b'"' | b';' | b'(' | b')' | b'@' | b'$' => write!(formatter, "\\{}", byte as char)?,
+          0x21..=0x7e => write!(formatter, "{}", byte as char)?,
+          _ => write!(formatter, "\\{:03}", byte)?,
+        }
+      }
+      formatter.write_str(".")?;
+    }
+
+    Ok(())
+  }
+}
+
+impl fmt::Display for RecordType {
+  fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+    formatter.write_str(&self.mnemonic())
+  }
+}
+
+impl fmt::Display for RecordClass {
+  fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+    formatter.write_str(&self.mnemonic())
+  }
+}
+
+/// A quoted `<character-string>`, escaping quotes, backslashes and unprintable octets.
+pub fn format_character_string(string: &[u8]) -> String {
+  let mut text = String::from("\"");
+  for &byte in string {
+    match byte {
+      b'"' | b'\\' => {
+        text.push('\\');
+        text.push(byte as char);
+      }
+      0x20..=0x7e => text.push(byte as char),
+      _ => text += &format!("\\{:03}", byte),
+    }
+  }
+  text.push('"');
+
+  text
+}
+
+/// An RRSIG timestamp as `YYYYMMDDHHmmSS` in UTC (RFC 4034 section 3.2).
+pub fn format_timestamp(timestamp: u32) -> String {
+  let seconds = timestamp as i64;
+  let (year, month, day) = civil_from_days(seconds.div_euclid(86400));
+  let time = seconds.rem_euclid(86400);
+
+  format!(
+    "{:04}{:02}{:02}{:02}{:02}{:02}",
+    year,
+    month,
+    day,
+    time / 3600,
+    time / 60 % 60,
+    time % 60
+  )
+}
+
+/// The data in the format of its type, e.g. `10 mail.example.com.` for MX.
+impl fmt::Display for RecordData {
+  fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let types = |types: &[RecordType]| {
+      types
+        .iter()
+        .map(|record_type| format!(" {}", record_type))
+        .collect::<String>()
+    };
+    let salt = |salt: &[u8]| match salt.is_empty() {
+      true => "-".to_string(),
+      false => HEXUPPER.encode(salt),
+    };
+
+    match self {
+      RecordData::Host(address) => write!(formatter, "{}", address),
+      RecordData::Ipv6Host(address) => write!(formatter, "{}", address),
+      RecordData::NameServer(name)
+      | RecordData::CanonicalName(name)
+      | RecordData::Pointer(name)
+      | RecordData::DelegationName(name) => write!(formatter, "{}", name),
+      RecordData::StartOfAuthority {
+        primary,
+        mailbox,
+        serial,
+        refresh,
+        retry,
+        expire,
+        minimum,
+      } => write!(
+        formatter,
+        "{} {} {} {} {} {} {}",
+        primary, mailbox, serial, refresh, retry, expire, minimum
+      ),
+      RecordData::HostInfo { cpu, os } => write!(
+        formatter,
+        "{} {}",
+        format_character_string(cpu),
+        format_character_string(os)
+      ),
+      RecordData::MailExchange { preference, exchange } => write!(formatter, "{} {}", preference, exchange),
+      RecordData::Text(strings) => {
+        let strings: Vec<String> = strings.iter().map(|string| format_character_string(string)).collect();
+        formatter.write_str(&strings.join(" "))
+      }
+      RecordData::Service {
+        priority,
+        weight,
+        port,
+        target,
+      } => write!(formatter, "{} {} {} {}", priority, weight, port, target),
+      RecordData::NamingAuthority {
+        order,
+        preference,
+        flags,
+        services,
+        regexp,
+        replacement,
+      } => write!(
+        formatter,
+        "{} {} {} {} {} {}",
+        order,
+        preference,
+        format_character_string(flags),
+        format_character_string(services),
+        format_character_string(regexp),
+        replacement
+      ),
+      RecordData::DelegationSigner {
+        key_tag,
+        algorithm,
+        digest_type,
+        digest,
+      } if !digest.is_empty() => write!(
+        formatter,
+        "{} {} {} {}",
+        key_tag,
+        algorithm,
+        digest_type,
+        HEXUPPER.encode(digest)
+      ),
+      RecordData::SshFingerprint {
+        algorithm,
+        fingerprint_type,
+        fingerprint,
+      } if !fingerprint.is_empty() => write!(
+        formatter,
+        "{} {} {}",
+        algorithm,
+        fingerprint_type,
+        HEXUPPER.encode(fingerprint)
+      ),
+      RecordData::Signature {
+        type_covered,
+        algorithm,
+        labels,
+        original_ttl,
+        expiration,
+        inception,
+        key_tag,
+        signer,
+        signature,
+      } if !signature.is_empty() => write!(
+        formatter,
+        "{} {} {} {} {} {} {} {} {}",
+        type_covered,
+        algorithm,
+        labels,
+        original_ttl,
+        format_timestamp(*expiration),
+        format_timestamp(*inception),
+        key_tag,
+        signer,
+        BASE64.encode(signature)
+      ),
+      RecordData::NextSecure { next, types: bitmap } => write!(formatter, "{}{}", next, types(bitmap)),
+      RecordData::DnsKey {
+        flags,
+        protocol,
+        algorithm,
+        public_key,
+      } if !public_key.is_empty() => write!(
+        formatter,
+        "{} {} {} {}",
+        flags,
+        protocol,
+        algorithm,
+        BASE64.encode(public_key)
+      ),
+      RecordData::NextSecure3 {
+        algorithm,
+        flags,
+        iterations,
+        salt: nsec3_salt,
+        next_hashed,
+        types: bitmap,
+      } => write!(
+        formatter,
+        "{} {} {} {} {}{}",
+        algorithm,
+        flags,
+        iterations,
+        salt(nsec3_salt),
+        BASE32_DNSSEC.encode(next_hashed).to_uppercase(),
+        types(bitmap)
+      ),
+      RecordData::NextSecure3Parameters {
+        algorithm,
+        flags,
+        iterations,
+        salt: nsec3_salt,
+      } => write!(formatter, "{} {} {} {}", algorithm, flags, iterations, salt(nsec3_salt)),
+      RecordData::TlsAssociation {
+        usage,
+        selector,
+        matching_type,
+        data,
+      } if !data.is_empty() => write!(
+        formatter,
+        "{} {} {} {}",
+        usage,
+        selector,
+        matching_type,
+        HEXUPPER.encode(data)
+      ),
+      RecordData::CertificationAuthority { flags, tag, value } => write!(
+        formatter,
+        "{} {} {}",
+        flags,
+        String::from_utf8_lossy(tag),
+        format_character_string(value)
+      ),
+      _ => {
+        let data = self.to_bytes();
+        match data.is_empty() {
+          true => write!(formatter, "\\# 0"),
+          false => write!(formatter, "\\# {} {}", data.len(), HEXUPPER.encode(&data)),
+        }
+      }
+    }
+  }
+}
+
+/// The question as `example.com. IN A`.
+impl fmt::Display for QuestionProperties {
+  fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      formatter,
+      "{} {} {}",
+      self.domain_name, self.record_class, self.record_type
+    )
+  }
+}
+
+/// The record as `example.com. 60 IN A 8.8.8.8`.
+impl fmt::Display for AnswerProperties {
+  fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      formatter,
+      "{} {} {} {} {}",
+      self.domain_name,
+      self.time_to_live,
+      self.record_class,
+      self.record_type,
+      self.record_data()
+    )
+  }
+}
+
+/// The header as dig prints it, over two lines.
+impl fmt::Display for HeaderProperties {
+  fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write_header(formatter, self, self.response_code())
+  }
+}
+
+/// The OPT pseudo-record as `; EDNS: version: 0, flags: do; udp: 1232`.
+impl fmt::Display for EdnsProperties {
+  fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      formatter,
+      "; EDNS: version: {}, flags:{}; udp: {}",
+      self.version,
+      if self.dnssec_ok { " do" } else { "" },
+      self.udp_payload_size
+    )?;
+    for option in &self.options {
+      write!(formatter, "\n; OPT={}: {}", option.code, HEXUPPER.encode(&option.data))?;
+    }
+
+    Ok(())
+  }
+}
+
+/// The whole message in dig's layout, sections headed by `;; ANSWER SECTION:` and the like.
+impl fmt::Display for MessageProperties {
+  fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write_header(formatter, &self.header, self.response_code())?;
+
+    if let Some(edns) = self.edns() {
+      write!(formatter, "\n\n;; OPT PSEUDOSECTION:\n{}", edns)?;
+    }
+    if !self.questions.is_empty() {
+      write!(formatter, "\n\n;; QUESTION SECTION:")?;
+      for question in &self.questions {
+        write!(formatter, "\n;{}", question)?;
+      }
+    }
+
+    let additionals: Vec<&AnswerProperties> = self
+      .additionals
+      .iter()
+      .filter(|record| record.record_type != RecordType::Options)
+      .collect();
+    for (title, records) in [
+      ("ANSWER", self.answers.iter().collect()),
+      ("AUTHORITY", self.authorities.iter().collect()),
+      ("ADDITIONAL", additionals),
+    ] {
+      if records.is_empty() {
+        continue;
+      }
+      write!(formatter, "\n\n;; {} SECTION:", title)?;
+      for record in records {
+        write!(formatter, "\n{}", record)?;
+      }
+    }
+
+    Ok(())
+  }
+}
+
+/// The header lines with `response_code`, which may carry upper bits from the OPT record.
+fn write_header(formatter: &mut fmt::Formatter<'_>, header: &HeaderProperties, response_code: u8) -> fmt::Result {
+  let flags: Vec<&str> = [
+    (header.is_response(), "qr"),
+    (header.is_authoritative(), "aa"),
+    (header.is_truncated(), "tc"),
+    (header.is_recursion_desired(), "rd"),
+    (header.is_recursion_available(), "ra"),
+    (header.is_authentic_data(), "ad"),
+    (header.is_checking_disabled(), "cd"),
+  ]
+  .into_iter()
+  .filter(|(set, _)| *set)
+  .map(|(_, name)| name)
+  .collect();
+
+  write!(
+    formatter,
+    ";; ->>HEADER<<- opcode: {}, status: {}, id: {}\n;; flags: {}; QUERY: {}, ANSWER: {}, AUTHORITY: {}, ADDITIONAL: {}",
+    OperationCode::mnemonic(header.operation_code()),
+    ResponseCode::mnemonic(response_code),
+    header.id,
+    flags.join(" "),
+    header.question_count,
+    header.answer_record_count,
+    header.authority_record_count,
+    header.additional_record_count
+  )
+}
+
+/// The proleptic Gregorian date of a count of days since 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+  let days = days + 719468;
+  let era = days.div_euclid(146097);
+  let day_of_era = days - era * 146097;
+  let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+  let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+  let month = (5 * day_of_year + 2) / 153;
+  let day = day_of_year - (153 * month + 2) / 5 + 1;
+  let month = if month < 10 { month + 3 } else { month - 9 };
+  let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+  (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn record(name: &str, record_type: RecordType, data: RecordData) -> AnswerProperties {
+    AnswerProperties {
+      domain_name: DomainName::from(name),
+      record_type,
+      record_class: RecordClass::Internet,
+      time_to_live: 60,
+      data: data.to_bytes(),
+    }
+  }
+
+  #[test]
+  fn it_escapes_names_and_strings() {
+    let name = DomainName(vec!["a.b".to_string(), "semi;colon\\".to_string(), "com".to_string()]);
+
+    assert_eq!(name.to_string(), "a\\.b.semi\\;colon\\\\.com.");
+    assert_eq!(DomainName(vec!["tab\there".to_string()]).to_string(), "tab\\009here.");
+    assert_eq!(DomainName::root().to_string(), ".");
+    assert_eq!(format_character_string(b"say \"hi\"\x07"), "\"say \\\"hi\\\"\\007\"");
+  }
+
+  #[test]
+  fn it_formats_timestamps() {
+    assert_eq!(format_timestamp(0), "19700101000000");
+    assert_eq!(format_timestamp(1_700_000_000), "20231114221320");
+  }
+
+  #[test]
+  fn it_formats_records_of_every_type() {
+    let name = || DomainName::from("mail.example.com");
+    let records = [
+      (
+        RecordType::Host,
+        RecordData::Host("8.8.8.8".parse().unwrap()),
+        "8.8.8.8",
+      ),
+      (
+        RecordType::Ipv6Host,
+        RecordData::Ipv6Host("2001:db8::1".parse().unwrap()),
+        "2001:db8::1",
+      ),
+      (
+        RecordType::CanonicalName,
+        RecordData::CanonicalName(name()),
+        "mail.example.com.",
+      ),
+      (
+        RecordType::MailExchange,
+        RecordData::MailExchange {
+          preference: 10,
+          exchange: name(),
+        },
+        "10 mail.example.com.",
+      ),
+      (
+        RecordType::Text,
+        RecordData::Text(vec![b"v=spf1".to_vec(), b"a b".to_vec()]),
+        "\"v=spf1\" \"a b\"",
+      ),
+      (
+        RecordType::Service,
+        RecordData::Service {
+          priority: 0,
+          weight: 5,
+          port: 5060,
+          target: name(),
+        },
+        "0 5 5060 mail.example.com.",
+      ),
+      (
+        RecordType::DelegationSigner,
+        RecordData::DelegationSigner {
+          key_tag: 12345,
+          algorithm: 13,
+          digest_type: 2,
+          digest: vec![0xab, 0xcd],
+        },
+        "12345 13 2 ABCD",
+      ),
+      (
+        RecordType::NextSecure,
+        RecordData::NextSecure {
+          next: name(),
+          types: vec![RecordType::Host, RecordType::Signature, RecordType::NextSecure],
+        },
+        "mail.example.com. A RRSIG NSEC",
+      ),
+      (
+        RecordType::NextSecure3Parameters,
+        RecordData::NextSecure3Parameters {
+          algorithm: 1,
+          flags: 0,
+          iterations: 0,
+          salt: vec![],
+        },
+        "1 0 0 -",
+      ),
+      (
+        RecordType::CertificationAuthority,
+        RecordData::CertificationAuthority {
+          flags: 0,
+          tag: b"issue".to_vec(),
+          value: b"ca.example".to_vec(),
+        },
+        "0 issue \"ca.example\"",
+      ),
+      (RecordType(65280), RecordData::Unknown(vec![1, 2, 3]), "\\# 3 010203"),
+    ];
+
+    for (record_type, data, text) in records {
+      assert_eq!(
+        record("example.com", record_type, data).to_string(),
+        format!("example.com. 60 IN {} {}", record_type, text)
+      );
+    }
+  }
+
+  #[test]
+  fn it_formats_messages_like_dig() {
+    let mut message = MessageProperties {
+      header: HeaderProperties {
+        id: 4242,
+        flags: Flags::Response | Flags::RecursionDesired | Flags::RecursionAvailable,
+        question_count: 1,
+        answer_record_count: 1,
+        authority_record_count: 0,
+        additional_record_count: 0,
+      },
+      questions: vec![QuestionProperties {
+        domain_name: DomainName::from("example.com"),
+        record_type: RecordType::Host,
+        record_class: RecordClass::Internet,
+      }],
+      answers: vec![record(
+        "example.com",
+        RecordType::Host,
+        RecordData::Host("8.8.8.8".parse().unwrap()),
+      )],
+      authorities: vec![],
+      additionals: vec![],
+    };
+    message.set_edns(EdnsProperties {
+      dnssec_ok: true,
+      ..EdnsProperties::new()
+    });
+
+    assert_eq!(
+      message.header.to_string(),
+      ";; ->>HEADER<<- opcode: QUERY, status: NOERROR, id: 4242\n\
+       ;; flags: qr rd ra; QUERY: 1, ANSWER: 1, AUTHORITY: 0, ADDITIONAL: 1"
+    );
+    assert_eq!(
+      message.to_string(),
+      ";; ->>HEADER<<- opcode: QUERY, status: NOERROR, id: 4242\n\
+       ;; flags: qr rd ra; QUERY: 1, ANSWER: 1, AUTHORITY: 0, ADDITIONAL: 1\n\
+       \n\
+       ;; OPT PSEUDOSECTION:\n\
+       ; EDNS: version: 0, flags: do; udp: 1232\n\
+       \n\
+       ;; QUESTION SECTION:\n\
+       ;example.com. IN A\n\
+       \n\
+       ;; ANSWER SECTION:\n\
+       example.com. 60 IN A 8.8.8.8"
+    );
+  }
+}
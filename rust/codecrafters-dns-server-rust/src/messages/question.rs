@@ -3,7 +3,7 @@
 //! Type - 2 bytes - Record type
 //! Class - 2 bytes - Record class
 
-use crate::messages::{DomainName, RecordClass, RecordType, SizeOf};
+use crate::messages::{DomainName, MessageError, RecordClass, RecordType, SizeOf};
 
 #[derive(Clone, Debug)]
 pub struct QuestionBuffer(pub Vec<u8>, pub usize);
@@ -14,20 +14,6 @@ impl From<QuestionBuffer> for Vec<u8> {
   }
 }
 
-impl From<QuestionBuffer> for QuestionProperties {
-  fn from(QuestionBuffer(buffer, offset): QuestionBuffer) -> Self {
-    let (domain_name, offset) = DomainName::from_bytes(&buffer, offset);
-    let record_type = RecordType::from(u16::from_be_bytes([buffer[offset], buffer[offset + 1]]));
-    let record_class = RecordClass::from(u16::from_be_bytes([buffer[offset + 2], buffer[offset + 3]]));
-
-    Self {
-      domain_name,
-      record_type,
-      record_class,
-    }
-  }
-}
-
 #[derive(Clone, Debug)]
 pub struct QuestionProperties {
   pub domain_name: DomainName,
@@ -35,6 +21,27 @@ pub struct QuestionProperties {
   pub record_class: RecordClass,
 }
 
+impl QuestionProperties {
+  pub fn try_from_bytes(buffer: &[u8], start: usize) -> Result<(Self, usize), MessageError> {
+    let (domain_name, offset) = DomainName::try_from_bytes(buffer, start).ok_or(MessageError::MalformedName {
+      section: "question",
+      offset: start,
+    })?;
+    let fields = buffer.get(offset..offset + 4).ok_or(MessageError::Truncated {
+      section: "question",
+      offset: start,
+    })?;
+
+    let properties = Self {
+      domain_name,
+      record_type: RecordType::from(u16::from_be_bytes([fields[0], fields[1]])),
+      record_class: RecordClass::from(u16::from_be_bytes([fields[2], fields[3]])),
+    };
+
+    Ok((properties, offset + 4))
+  }
+}
+
 impl From<QuestionProperties> for Vec<u8> {
   fn from(properties: QuestionProperties) -> Self {
     QuestionBuffer::from(properties).into()
@@ -52,10 +59,10 @@ impl From<QuestionProperties> for QuestionBuffer {
     let record_type = properties.record_type.0;
     let record_class = properties.record_class.0;
 
-    buffer.push(((record_type as u16) >> 8) as u8);
-    buffer.push((record_type as u16) as u8);
-    buffer.push(((record_class as u16) >> 8) as u8);
-    buffer.push((record_class as u16) as u8);
+    buffer.push((record_type >> 8) as u8);
+    buffer.push(record_type as u8);
+    buffer.push((record_class >> 8) as u8);
+    buffer.push(record_class as u8);
 
     QuestionBuffer(buffer, 0)
   }
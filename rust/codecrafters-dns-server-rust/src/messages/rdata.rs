@@ -0,0 +1,500 @@
+//! Presentation format of names, TTLs and record data as they appear in master files and in the
+//! RFC 8427 JSON of messages, read back into wire format. The `presentation` module writes it.
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use data_encoding::{BASE32_DNSSEC, BASE64, HEXLOWER_PERMISSIVE};
+
+use crate::messages::*;
+
+const MaxCharacterStringLength: usize = 255;
+
+/// Parses an absolute (`example.com.`) or origin-relative (`www`, `@`) name, resolving `\.` and
+/// `\DDD` escapes inside labels. Labels take the octets names decode from the wire with: any that
+/// together make UTF-8.
+pub fn parse_name(text: &str, origin: &DomainName) -> Result<DomainName, String> {
+  if text == "@" {
+    return Ok(origin.clone());
+  }
+  if text == "." {
+    return Ok(DomainName::root());
+  }
+
+  let mut labels = vec![];
+  let mut label = vec![];
+  let mut absolute = false;
+  let mut bytes = text.bytes().peekable();
+
+  while let Some(byte) = bytes.next() {
+    match byte {
+      b'.' if label.is_empty() => return Err(format!("Empty label in name '{}'", text)),
+      b'.' => {
+        labels.push(std::mem::take(&mut label));
+        absolute = bytes.peek().is_none();
+      }
+      b'\\' => label.push(unescape(&mut bytes, text)?),
+      _ => label.push(byte),
+    }
+  }
+  if !label.is_empty() {
+    labels.push(label);
+  }
+
+  let mut name = vec![];
+  for label in labels {
+    if label.len() > DomainName::MaxLabelLength {
+      return Err(format!(
+        "Label longer than {} octets in name '{}'",
+        DomainName::MaxLabelLength,
+        text
+      ));
+    }
+    let label = String::from_utf8(label).map_err(|_| format!("Label that is not UTF-8 in name '{}'", text))?;
+    name.push(label);
+  }
+
+  if !absolute {
+    name.extend(origin.0.iter().cloned());
+  }
+
+  Ok(DomainName(name))
+}
+
+/// Parses a `<character-string>`, quoted or not, resolving `\X` and `\DDD` escapes.
+pub fn parse_character_string(text: &str) -> Result<Vec<u8>, String> {
+  let mut string = vec![];
+  let mut bytes = text.bytes().peekable();
+
+  while let Some(byte) = bytes.next() {
+    match byte {
+      b'\\' => string.push(unescape(&mut bytes, text)?),
+      _ => string.push(byte),
+    }
+  }
+
+  match string.len() > MaxCharacterStringLength {
+    true => Err(format!(
+      "Character string longer than {} octets",
+      MaxCharacterStringLength
+    )),
+    false => Ok(string),
+  }
+}
+
+fn unescape(bytes: &mut std::iter::Peekable<std::str::Bytes>, text: &str) -> Result<u8, String> {
+  let Some(first) = bytes.next() else {
+    return Err(format!("Dangling escape in '{}'", text));
+  };
+  if !first.is_ascii_digit() {
+    return Ok(first);
+  }
+
+  let mut value = (first - b'0') as u32;
+  for _ in 0..2 {
+    match bytes.next() {
+      Some(digit) if digit.is_ascii_digit() => value = value * 10 + (digit - b'0') as u32,
+      _ => return Err(format!("Escape must have three decimal digits in '{}'", text)),
+    }
+  }
+
+  u8::try_from(value).map_err(|_| format!("Escape \\{} is out of range in '{}'", value, text))
+}
+
+/// Parses a TTL in seconds, either plain (`3600`) or with BIND units (`1h30m`, `2w`).
+pub fn parse_ttl(text: &str) -> Result<u32, String> {
+  let invalid = || format!("Invalid TTL '{}'", text);
+
+  if let Ok(seconds) = text.parse::<u32>() {
+    return Ok(seconds);
+  }
+
+  let mut total: u64 = 0;
+  let mut value: Option<u64> = None;
+  for char in text.chars() {
+    if let Some(digit) = char.to_digit(10) {
+      value = Some(value.unwrap_or(0) * 10 + digit as u64);
+      if value > Some(u32::MAX as u64) {
+        return Err(invalid());
+      }
+      continue;
+    }
+
+    let unit = match char.to_ascii_lowercase() {
+      's' => 1,
+      'm' => 60,
+      'h' => 3600,
+      'd' => 86400,
+      'w' => 604800,
+      _ => return Err(invalid()),
+    };
+    total += value.take().ok_or_else(invalid)? * unit;
+  }
+  if value.is_some() || total > u32::MAX as u64 {
+    return Err(invalid());
+  }
+
+  Ok(total as u32)
+}
+
+/// Parses the data of a record following its type, either in the type specific format or in the
+/// RFC 3597 generic `\# <length> <hex>` format.
+pub fn parse_record_data(record_type: RecordType, tokens: &[Token], origin: &DomainName) -> Result<Vec<u8>, String> {
+  let mut fields = Fields { tokens, index: 0 };
+
+  if tokens.first().is_some_and(|token| token.text == "\\#" && !token.quoted) {
+    fields.index = 1;
+    let length: usize = fields.number("data length")?;
+    let data = HEXLOWER_PERMISSIVE
+      .decode(fields.rest().as_bytes())
+      .map_err(|_| "Invalid hex data".to_string())?;
+
+    return match data.len() == length {
+      true => Ok(data),
+      false => Err(format!(
+        "Data length {} does not match the {} octets given",
+        length,
+        data.len()
+      )),
+    };
+  }
+
+  let record_data = match record_type {
+    RecordType::Host => RecordData::Host(fields.parse::<Ipv4Addr>("IPv4 address")?),
+    RecordType::NameServer => RecordData::NameServer(fields.name(origin)?),
+    RecordType::CanonicalName => RecordData::CanonicalName(fields.name(origin)?),
+    RecordType::StartOfAuthority => RecordData::StartOfAuthority {
+      primary: fields.name(origin)?,
+      mailbox: fields.name(origin)?,
+      serial: fields.number("serial")?,
+      refresh: fields.ttl()?,
+      retry: fields.ttl()?,
+      expire: fields.ttl()?,
+      minimum: fields.ttl()?,
+    },
+    RecordType::Pointer => RecordData::Pointer(fields.name(origin)?),
+    RecordType::HostInfo => RecordData::HostInfo {
+      cpu: fields.character_string()?,
+      os: fields.character_string()?,
+    },
+    RecordType::MailExchange => RecordData::MailExchange {
+      preference: fields.number("preference")?,
+      exchange: fields.name(origin)?,
+    },
+    RecordType::Text => {
+      let mut strings = vec![fields.character_string()?];
+      while fields.index < tokens.len() {
+        strings.push(fields.character_string()?);
+      }
+      RecordData::Text(strings)
+    }
+    RecordType::Ipv6Host => RecordData::Ipv6Host(fields.parse::<Ipv6Addr>("IPv6 address")?),
+    RecordType::Service => RecordData::Service {
+      priority: fields.number("priority")?,
+      weight: fields.number("weight")?,
+      port: fields.number("port")?,
+      target: fields.name(origin)?,
+    },
+    RecordType::NamingAuthority => RecordData::NamingAuthority {
+      order: fields.number("order")?,
+      preference: fields.number("preference")?,
+      flags: fields.character_string()?,
+      services: fields.character_string()?,
+      regexp: fields.character_string()?,
+      replacement: fields.name(origin)?,
+    },
+    RecordType::DelegationName => RecordData::DelegationName(fields.name(origin)?),
+    RecordType::DelegationSigner => RecordData::DelegationSigner {
+      key_tag: fields.number("key tag")?,
+      algorithm: fields.number("algorithm")?,
+      digest_type: fields.number("digest type")?,
+      digest: fields.hex_rest()?,
+    },
+    RecordType::SshFingerprint => RecordData::SshFingerprint {
+      algorithm: fields.number("algorithm")?,
+      fingerprint_type: fields.number("fingerprint type")?,
+      fingerprint: fields.hex_rest()?,
+    },
+    RecordType::Signature => RecordData::Signature {
+      type_covered: fields.record_type()?,
+      algorithm: fields.number("algorithm")?,
+      labels: fields.number("labels")?,
+      original_ttl: fields.ttl()?,
+      expiration: fields.timestamp()?,
+      inception: fields.timestamp()?,
+      key_tag: fields.number("key tag")?,
+      signer: fields.name(origin)?,
+      signature: fields.base64_rest()?,
+    },
+    RecordType::NextSecure => RecordData::NextSecure {
+      next: fields.name(origin)?,
+      types: fields.record_types()?,
+    },
+    RecordType::DnsKey => RecordData::DnsKey {
+      flags: fields.number("flags")?,
+      protocol: fields.number("protocol")?,
+      algorithm: fields.number("algorithm")?,
+      public_key: fields.base64_rest()?,
+    },
+    RecordType::NextSecure3 => RecordData::NextSecure3 {
+      algorithm: fields.number("hash algorithm")?,
+      flags: fields.number("flags")?,
+      iterations: fields.number("iterations")?,
+      salt: fields.salt()?,
+      next_hashed: BASE32_DNSSEC
+        .decode(fields.next("next hashed owner")?.as_bytes())
+        .map_err(|_| "Invalid base32 next hashed owner".to_string())?,
+      types: fields.record_types()?,
+    },
+    RecordType::NextSecure3Parameters => RecordData::NextSecure3Parameters {
+      algorithm: fields.number("hash algorithm")?,
+      flags: fields.number("flags")?,
+      iterations: fields.number("iterations")?,
+      salt: fields.salt()?,
+    },
+    RecordType::TlsAssociation => RecordData::TlsAssociation {
+      usage: fields.number("certificate usage")?,
+      selector: fields.number("selector")?,
+      matching_type: fields.number("matching type")?,
+      data: fields.hex_rest()?,
+    },
+    RecordType::CertificationAuthority => RecordData::CertificationAuthority {
+      flags: fields.number("flags")?,
+      tag: fields.character_string()?,
+      value: fields.character_string()?,
+    },
+    _ => {
+      return Err(format!(
+        "Type {} needs its data in the generic \\# format",
+        record_type.mnemonic()
+      ));
+    }
+  };
+
+  if fields.index < tokens.len() {
+    return Err(format!("Unexpected data '{}'", tokens[fields.index].text));
+  }
+
+  Ok(record_data.to_bytes())
+}
+
+/// Parses an RRSIG timestamp, either `YYYYMMDDHHmmSS` in UTC or seconds since the epoch.
+pub fn parse_timestamp(text: &str) -> Result<u32, String> {
+  let invalid = || format!("Invalid timestamp '{}'", text);
+
+  if text.len() != 14 {
+    return text.parse().map_err(|_| invalid());
+  }
+  if !text.bytes().all(|byte| byte.is_ascii_digit()) {
+    return Err(invalid());
+  }
+
+  let field = |range: std::ops::Range<usize>| text[range].parse::<i64>().unwrap();
+  let (year, month, day) = (field(0..4), field(4..6), field(6..8));
+  let (hour, minute, second) = (field(8..10), field(10..12), field(12..14));
+  if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 59 {
+    return Err(invalid());
+  }
+
+  let seconds = days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second;
+
+  // Timestamps use serial number arithmetic, so dates past 2106 wrap around (RFC 4034 section 3.1.5).
+  Ok(seconds.rem_euclid(1 << 32) as u32)
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+  let year = if month <= 2 { year - 1 } else { year };
+  let era = year.div_euclid(400);
+  let year_of_era = year - era * 400;
+  let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+  let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+  era * 146097 + day_of_era - 719468
+}
+
+struct Fields<'a> {
+  tokens: &'a [Token],
+  index: usize,
+}
+
+impl Fields<'_> {
+  fn next(&mut self, field: &str) -> Result<&str, String> {
+    let token = self
+      .tokens
+      .get(self.index)
+      .ok_or_else(|| format!("Missing {}", field))?;
+    self.index += 1;
+
+    Ok(&token.text)
+  }
+
+  fn parse<T: std::str::FromStr>(&mut self, field: &str) -> Result<T, String> {
+    let text = self.next(field)?;
+
+    text.parse().map_err(|_| format!("Invalid {} '{}'", field, text))
+  }
+
+  fn number<T: std::str::FromStr>(&mut self, field: &str) -> Result<T, String> {
+    self.parse(field)
+  }
+
+  fn ttl(&mut self) -> Result<u32, String> {
+    parse_ttl(self.next("TTL")?)
+  }
+
+  fn timestamp(&mut self) -> Result<u32, String> {
+    parse_timestamp(self.next("timestamp")?)
+  }
+
+  fn name(&mut self, origin: &DomainName) -> Result<DomainName, String> {
+    parse_name(self.next("domain name")?, origin)
+  }
+
+  fn character_string(&mut self) -> Result<Vec<u8>, String> {
+    parse_character_string(self.next("character string")?)
+  }
+
+  fn record_type(&mut self) -> Result<RecordType, String> {
+    let text = self.next("record type")?;
+
+    RecordType::from_mnemonic(text).ok_or_else(|| format!("Unknown record type '{}'", text))
+  }
+
+  fn record_types(&mut self) -> Result<Vec<RecordType>, String> {
+    let mut types = vec![];
+    while self.index < self.tokens.len() {
+      types.push(self.record_type()?);
+    }
+
+    Ok(types)
+  }
+
+  fn salt(&mut self) -> Result<Vec<u8>, String> {
+    match self.next("salt")? {
+      "-" => Ok(vec![]),
+      salt => HEXLOWER_PERMISSIVE
+        .decode(salt.as_bytes())
+        .map_err(|_| format!("Invalid salt '{}'", salt)),
+    }
+  }
+
+  /// Remaining tokens joined together, as base64 and hex data may be split by whitespace.
+  fn rest(&mut self) -> String {
+    let rest = self.tokens[self.index.min(self.tokens.len())..]
+      .iter()
+      .map(|token| token.text.as_str())
+      .collect();
+    self.index = self.tokens.len();
+
+    rest
+  }
+
+  fn hex_rest(&mut self) -> Result<Vec<u8>, String> {
+    HEXLOWER_PERMISSIVE
+      .decode(self.rest().as_bytes())
+      .map_err(|_| "Invalid hex data".to_string())
+  }
+
+  fn base64_rest(&mut self) -> Result<Vec<u8>, String> {
+    BASE64
+      .decode(self.rest().as_bytes())
+      .map_err(|_| "Invalid base64 data".to_string())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn tokens(texts: &[&str]) -> Vec<Token> {
+    texts
+      .iter()
+      .map(|text| Token {
+        text: text.to_string(),
+        quoted: false,
+        line: 1,
+      })
+      .collect()
+  }
+
+  #[test]
+  fn it_parses_relative_absolute_and_escaped_names() {
+    let origin = DomainName::from("example.com");
+
+    assert_eq!(parse_name("@", &origin).unwrap(), origin);
+    assert_eq!(parse_name("www", &origin).unwrap(), DomainName::from("www.example.com"));
+    assert_eq!(
+      parse_name("www.abc.com.", &origin).unwrap(),
+      DomainName::from("www.abc.com")
+    );
+    assert_eq!(
+      parse_name("first\\.last.\\065b", &origin).unwrap(),
+      DomainName(vec!["first.last".into(), "Ab".into(), "example".into(), "com".into()])
+    );
+    assert!(parse_name("a..b", &origin).is_err());
+    assert!(parse_name(&"a".repeat(64), &origin).is_err());
+    assert!(parse_name("\\256", &origin).is_err());
+  }
+
+  #[test]
+  fn it_reads_back_the_names_that_decode_from_the_wire() {
+    let wire = |label: &[u8]| {
+      let mut bytes = vec![label.len() as u8];
+      bytes.extend(label);
+      bytes.extend(DomainName::from("example").to_bytes());
+      DomainName::try_from_bytes(&bytes, 0).map(|(name, _)| name)
+    };
+    let decoded = wire("é".as_bytes()).unwrap();
+
+    assert_eq!(decoded.to_string(), "\\195\\169.example.");
+    assert_eq!(parse_name(&decoded.to_string(), &DomainName::root()), Ok(decoded));
+    assert_eq!(wire(&[200]), None);
+    assert!(parse_name("\\200.example.", &DomainName::root()).is_err());
+  }
+
+  #[test]
+  fn it_parses_ttls_with_units() {
+    assert_eq!(parse_ttl("3600"), Ok(3600));
+    assert_eq!(parse_ttl("1h30M"), Ok(5400));
+    assert_eq!(parse_ttl("1w1d"), Ok(691200));
+    assert!(parse_ttl("1x").is_err());
+    assert!(parse_ttl("h").is_err());
+  }
+
+  #[test]
+  fn it_parses_signature_timestamps() {
+    assert_eq!(parse_timestamp("19700101000000"), Ok(0));
+    assert_eq!(parse_timestamp("20240229120000"), Ok(1709208000));
+    assert_eq!(parse_timestamp("1709208000"), Ok(1709208000));
+    assert!(parse_timestamp("20241301000000").is_err());
+  }
+
+  #[test]
+  fn it_parses_typed_and_generic_record_data() {
+    let origin = DomainName::from("example.com");
+
+    let mx = parse_record_data(RecordType::MailExchange, &tokens(&["10", "mail"]), &origin).unwrap();
+    let generic = parse_record_data(RecordType::Host, &tokens(&["\\#", "4", "0a00", "0001"]), &origin).unwrap();
+    let nsec3 = parse_record_data(
+      RecordType::NextSecure3,
+      &tokens(&["1", "0", "10", "AABB", "2T7B4G4VSA5SMI47K61MV5BV1A22BOJR", "A", "RRSIG"]),
+      &origin,
+    )
+    .unwrap();
+
+    assert_eq!(
+      RecordData::decode(RecordType::MailExchange, &mx),
+      RecordData::MailExchange {
+        preference: 10,
+        exchange: DomainName::from("mail.example.com"),
+      }
+    );
+    assert_eq!(generic, vec![10, 0, 0, 1]);
+    assert!(matches!(
+      RecordData::decode(RecordType::NextSecure3, &nsec3),
+      RecordData::NextSecure3 { iterations: 10, ref salt, ref next_hashed, .. } if *salt == [0xaa, 0xbb] && next_hashed.len() == 20
+    ));
+    assert!(parse_record_data(RecordType::Host, &tokens(&["\\#", "3", "0a00"]), &origin).is_err());
+    assert!(parse_record_data(RecordType::Host, &tokens(&["1.2.3.4", "5"]), &origin).is_err());
+  }
+}
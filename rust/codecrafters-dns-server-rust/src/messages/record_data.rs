@@ -0,0 +1,549 @@
+//! Typed view of record data. Answers keep their data as uncompressed wire bytes; `RecordData`
+//! decodes them per record type and encodes them back. Data that does not match the layout of
+//! its type, and types without a variant, are kept as `Unknown` bytes (RFC 3597).
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use crate::messages::{AnswerProperties, DomainName, RecordType};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RecordData {
+  Host(Ipv4Addr),
+  NameServer(DomainName),
+  CanonicalName(DomainName),
+  StartOfAuthority {
+    primary: DomainName,
+    mailbox: DomainName,
+    serial: u32,
+    refresh: u32,
+    retry: u32,
+    expire: u32,
+    minimum: u32,
+  },
+  Pointer(DomainName),
+  HostInfo {
+    cpu: Vec<u8>,
+    os: Vec<u8>,
+  },
+  MailExchange {
+    preference: u16,
+    exchange: DomainName,
+  },
+  Text(Vec<Vec<u8>>),
+  Ipv6Host(Ipv6Addr),
+  Service {
+    priority: u16,
+    weight: u16,
+    port: u16,
+    target: DomainName,
+  },
+  NamingAuthority {
+    order: u16,
+    preference: u16,
+    flags: Vec<u8>,
+    services: Vec<u8>,
+    regexp: Vec<u8>,
+    replacement: DomainName,
+  },
+  DelegationName(DomainName),
+  DelegationSigner {
+    key_tag: u16,
+    algorithm: u8,
+    digest_type: u8,
+    digest: Vec<u8>,
+  },
+  SshFingerprint {
+    algorithm: u8,
+    fingerprint_type: u8,
+    fingerprint: Vec<u8>,
+  },
+  Signature {
+    type_covered: RecordType,
+    algorithm: u8,
+    labels: u8,
+    original_ttl: u32,
+    expiration: u32,
+    inception: u32,
+    key_tag: u16,
+    signer: DomainName,
+    signature: Vec<u8>,
+  },
+  NextSecure {
+    next: DomainName,
+    types: Vec<RecordType>,
+  },
+  DnsKey {
+    flags: u16,
+    protocol: u8,
+    algorithm: u8,
+    public_key: Vec<u8>,
+  },
+  NextSecure3 {
+    algorithm: u8,
+    flags: u8,
+    iterations: u16,
+    salt: Vec<u8>,
+    next_hashed: Vec<u8>,
+    types: Vec<RecordType>,
+  },
+  NextSecure3Parameters {
+    algorithm: u8,
+    flags: u8,
+    iterations: u16,
+    salt: Vec<u8>,
+  },
+  TlsAssociation {
+    usage: u8,
+    selector: u8,
+    matching_type: u8,
+    data: Vec<u8>,
+  },
+  CertificationAuthority {
+    flags: u8,
+    tag: Vec<u8>,
+    value: Vec<u8>,
+  },
+  Unknown(Vec<u8>),
+}
+
+impl RecordData {
+  pub fn decode(record_type: RecordType, data: &[u8]) -> RecordData {
+    let mut reader = Reader { data, offset: 0 };
+
+    match reader.record_data(record_type) {
+      Some(record_data) if reader.offset == data.len() => record_data,
+      _ => RecordData::Unknown(data.to_vec()),
+    }
+  }
+
+  pub fn to_bytes(&self) -> Vec<u8> {
+    let mut buffer = vec![];
+
+    match self {
+      RecordData::Host(address) => buffer.extend(address.octets()),
+      RecordData::NameServer(name)
+      | RecordData::CanonicalName(name)
+      | RecordData::Pointer(name)
+      | RecordData::DelegationName(name) => buffer.extend(name.to_bytes()),
+      RecordData::StartOfAuthority {
+        primary,
+        mailbox,
+        serial,
+        refresh,
+        retry,
+        expire,
+        minimum,
+      } => {
+        buffer.extend(primary.to_bytes());
+        buffer.extend(mailbox.to_bytes());
+        for value in [serial, refresh, retry, expire, minimum] {
+          buffer.extend(value.to_be_bytes());
+        }
+      }
+      RecordData::HostInfo { cpu, os } => {
+        push_character_string(&mut buffer, cpu);
+        push_character_string(&mut buffer, os);
+      }
+      RecordData::MailExchange { preference, exchange } => {
+        buffer.extend(preference.to_be_bytes());
+        buffer.extend(exchange.to_bytes());
+      }
+      RecordData::Text(strings) => {
+        for string in strings {
+          push_character_string(&mut buffer, string);
+        }
+      }
+      RecordData::Ipv6Host(address) => buffer.extend(address.octets()),
+      RecordData::Service {
+        priority,
+        weight,
+        port,
+        target,
+      } => {
+        buffer.extend(priority.to_be_bytes());
+        buffer.extend(weight.to_be_bytes());
+        buffer.extend(port.to_be_bytes());
+        buffer.extend(target.to_bytes());
+      }
+      RecordData::NamingAuthority {
+        order,
+        preference,
+        flags,
+        services,
+        regexp,
+        replacement,
+      } => {
+        buffer.extend(order.to_be_bytes());
+        buffer.extend(preference.to_be_bytes());
+        push_character_string(&mut buffer, flags);
+        push_character_string(&mut buffer, services);
+        push_character_string(&mut buffer, regexp);
+        buffer.extend(replacement.to_bytes());
+      }
+      RecordData::DelegationSigner {
+        key_tag,
+        algorithm,
+        digest_type,
+        digest,
+      } => {
+        buffer.extend(key_tag.to_be_bytes());
+        buffer.extend([*algorithm, *digest_type]);
+        buffer.extend(digest);
+      }
+      RecordData::SshFingerprint {
+        algorithm,
+        fingerprint_type,
+        fingerprint,
+      } => {
+        buffer.extend([*algorithm, *fingerprint_type]);
+        buffer.extend(fingerprint);
+      }
+      RecordData::Signature {
+        type_covered,
+        algorithm,
+        labels,
+        original_ttl,
+        expiration,
+        inception,
+        key_tag,
+        signer,
+        signature,
+      } => {
+        buffer.extend(type_covered.0.to_be_bytes());
+        buffer.extend([*algorithm, *labels]);
+        buffer.extend(original_ttl.to_be_bytes());
+        buffer.extend(expiration.to_be_bytes());
+        buffer.extend(inception.to_be_bytes());
+        buffer.extend(key_tag.to_be_bytes());
+        buffer.extend(signer.to_bytes());
+        buffer.extend(signature);
+      }
+      RecordData::NextSecure { next, types } => {
+        buffer.extend(next.to_bytes());
+        buffer.extend(encode_type_bitmap(types));
+      }
+      RecordData::DnsKey {
+        flags,
+        protocol,
+        algorithm,
+        public_key,
+      } => {
+        buffer.extend(flags.to_be_bytes());
+        buffer.extend([*protocol, *algorithm]);
+        buffer.extend(public_key);
+      }
+      RecordData::NextSecure3 {
+        algorithm,
+        flags,
+        iterations,
+        salt,
+        next_hashed,
+        types,
+      } => {
+        buffer.extend([*algorithm, *flags]);
+        buffer.extend(iterations.to_be_bytes());
+        push_character_string(&mut buffer, salt);
+        push_character_string(&mut buffer, next_hashed);
+        buffer.extend(encode_type_bitmap(types));
+      }
+      RecordData::NextSecure3Parameters {
+        algorithm,
+        flags,
+        iterations,
+        salt,
+      } => {
+        buffer.extend([*algorithm, *flags]);
+        buffer.extend(iterations.to_be_bytes());
+        push_character_string(&mut buffer, salt);
+      }
+      RecordData::TlsAssociation {
+        usage,
+        selector,
+        matching_type,
+        data,
+      } => {
+        buffer.extend([*usage, *selector, *matching_type]);
+        buffer.extend(data);
+      }
+      RecordData::CertificationAuthority { flags, tag, value } => {
+        buffer.push(*flags);
+        push_character_string(&mut buffer, tag);
+        buffer.extend(value);
+      }
+      RecordData::Unknown(data) => buffer.extend(data),
+    }
+
+    buffer
+  }
+}
+
+impl AnswerProperties {
+  pub fn record_data(&self) -> RecordData {
+    RecordData::decode(self.record_type, &self.data)
+  }
+}
+
+/// Encodes the window blocks of the NSEC and NSEC3 type bitmaps (RFC 4034 section 4.1.2).
+pub fn encode_type_bitmap(types: &[RecordType]) -> Vec<u8> {
+  let mut types = types.to_vec();
+  types.sort();
+  types.dedup();
+
+  let mut buffer = vec![];
+  for window in 0..=255u8 {
+    let mut bitmap = [0u8; 32];
+    let mut length = 0;
+    for record_type in types.iter().filter(|record_type| (record_type.0 >> 8) as u8 == window) {
+      let bit = (record_type.0 & 0xff) as usize;
+      bitmap[bit / 8] |= 0x80 >> (bit % 8);
+      length = bit / 8 + 1;
+    }
+
+    if length > 0 {
+      buffer.extend([window, length as u8]);
+      buffer.extend(&bitmap[..length]);
+    }
+  }
+
+  buffer
+}
+
+pub fn decode_type_bitmap(data: &[u8]) -> Option<Vec<RecordType>> {
+  let mut types = vec![];
+  let mut offset = 0;
+
+  while offset < data.len() {
+    let window = *data.get(offset)? as u16;
+    let length = *data.get(offset + 1)? as usize;
+    let bitmap = data.get(offset + 2..offset + 2 + length)?;
+
+    for (index, byte) in bitmap.iter().enumerate() {
+      for bit in 0..8 {
+        if byte & (0x80 >> bit) != 0 {
+          types.push(RecordType((window << 8) | (index * 8 + bit) as u16));
+        }
+      }
+    }
+    offset += 2 + length;
+  }
+
+  Some(types)
+}
+
+fn push_character_string(buffer: &mut Vec<u8>, string: &[u8]) {
+  buffer.push(string.len() as u8);
+  buffer.extend(string);
+}
+
+struct Reader<'a> {
+  data: &'a [u8],
+  offset: usize,
+}
+
+impl Reader<'_> {
+  fn record_data(&mut self, record_type: RecordType) -> Option<RecordData> {
+    let record_data = match record_type {
+      RecordType::Host => RecordData::Host(Ipv4Addr::from(<[u8; 4]>::try_from(self.bytes(4)?).ok()?)),
+      RecordType::NameServer => RecordData::NameServer(self.name()?),
+      RecordType::CanonicalName => RecordData::CanonicalName(self.name()?),
+      RecordType::StartOfAuthority => RecordData::StartOfAuthority {
+        primary: self.name()?,
+        mailbox: self.name()?,
+        serial: self.u32()?,
+        refresh: self.u32()?,
+        retry: self.u32()?,
+        expire: self.u32()?,
+        minimum: self.u32()?,
+      },
+      RecordType::Pointer => RecordData::Pointer(self.name()?),
+      RecordType::HostInfo => RecordData::HostInfo {
+        cpu: self.character_string()?,
+        os: self.character_string()?,
+      },
+      RecordType::MailExchange => RecordData::MailExchange {
+        preference: self.u16()?,
+        exchange: self.name()?,
+      },
+      RecordType::Text => {
+        let mut strings = vec![];
+        while self.offset < self.data.len() {
+          strings.push(self.character_string()?);
+        }
+        RecordData::Text(strings)
+      }
+      RecordType::Ipv6Host => RecordData::Ipv6Host(Ipv6Addr::from(<[u8; 16]>::try_from(self.bytes(16)?).ok()?)),
+      RecordType::Service => RecordData::Service {
+        priority: self.u16()?,
+        weight: self.u16()?,
+        port: self.u16()?,
+        target: self.name()?,
+      },
+      RecordType::NamingAuthority => RecordData::NamingAuthority {
+        order: self.u16()?,
+        preference: self.u16()?,
+        flags: self.character_string()?,
+        services: self.character_string()?,
+        regexp: self.character_string()?,
+        replacement: self.name()?,
+      },
+      RecordType::DelegationName => RecordData::DelegationName(self.name()?),
+      RecordType::DelegationSigner => RecordData::DelegationSigner {
+        key_tag: self.u16()?,
+        algorithm: self.u8()?,
+        digest_type: self.u8()?,
+        digest: self.rest(),
+      },
+      RecordType::SshFingerprint => RecordData::SshFingerprint {
+        algorithm: self.u8()?,
+        fingerprint_type: self.u8()?,
+        fingerprint: self.rest(),
+      },
+      RecordType::Signature => RecordData::Signature {
+        type_covered: RecordType(self.u16()?),
+        algorithm: self.u8()?,
+        labels: self.u8()?,
+        original_ttl: self.u32()?,
+        expiration: self.u32()?,
+        inception: self.u32()?,
+        key_tag: self.u16()?,
+        signer: self.name()?,
+        signature: self.rest(),
+      },
+      RecordType::NextSecure => RecordData::NextSecure {
+        next: self.name()?,
+        types: decode_type_bitmap(&self.rest())?,
+      },
+      RecordType::DnsKey => RecordData::DnsKey {
+        flags: self.u16()?,
+        protocol: self.u8()?,
+        algorithm: self.u8()?,
+        public_key: self.rest(),
+      },
+      RecordType::NextSecure3 => RecordData::NextSecure3 {
+        algorithm: self.u8()?,
+        flags: self.u8()?,
+        iterations: self.u16()?,
+        salt: self.character_string()?,
+        next_hashed: self.character_string()?,
+        types: decode_type_bitmap(&self.rest())?,
+      },
+      RecordType::NextSecure3Parameters => RecordData::NextSecure3Parameters {
+        algorithm: self.u8()?,
+        flags: self.u8()?,
+        iterations: self.u16()?,
+        salt: self.character_string()?,
+      },
+      RecordType::TlsAssociation => RecordData::TlsAssociation {
+        usage: self.u8()?,
+        selector: self.u8()?,
+        matching_type: self.u8()?,
+        data: self.rest(),
+      },
+      RecordType::CertificationAuthority => RecordData::CertificationAuthority {
+        flags: self.u8()?,
+        tag: self.character_string()?,
+        value: self.rest(),
+      },
+      _ => RecordData::Unknown(self.rest()),
+    };
+
+    Some(record_data)
+  }
+
+  fn bytes(&mut self, length: usize) -> Option<&[u8]> {
+    let bytes = self.data.get(self.offset..self.offset + length)?;
+    self.offset += length;
+    Some(bytes)
+  }
+
+  fn u8(&mut self) -> Option<u8> {
+    Some(self.bytes(1)?[0])
+  }
+
+  fn u16(&mut self) -> Option<u16> {
+    Some(u16::from_be_bytes(self.bytes(2)?.try_into().ok()?))
+  }
+
+  fn u32(&mut self) -> Option<u32> {
+    Some(u32::from_be_bytes(self.bytes(4)?.try_into().ok()?))
+  }
+
+  fn name(&mut self) -> Option<DomainName> {
+    let (name, offset) = DomainName::try_from_bytes(self.data, self.offset)?;
+    self.offset = offset;
+    Some(name)
+  }
+
+  fn character_string(&mut self) -> Option<Vec<u8>> {
+    let length = self.u8()? as usize;
+    Some(self.bytes(length)?.to_vec())
+  }
+
+  fn rest(&mut self) -> Vec<u8> {
+    let rest = self.data[self.offset..].to_vec();
+    self.offset = self.data.len();
+    rest
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::macros::*;
+
+  #[test]
+  fn it_converts_twoway() {
+    let records = [
+      (RecordType::Host, RecordData::Host(Ipv4Addr::new(8, 8, 8, 8))),
+      (
+        RecordType::StartOfAuthority,
+        RecordData::StartOfAuthority {
+          primary: DomainName::from("ns.abc.com"),
+          mailbox: DomainName::from("admin.abc.com"),
+          serial: 2024010101,
+          refresh: 3600,
+          retry: 600,
+          expire: 86400,
+          minimum: 300,
+        },
+      ),
+      (
+        RecordType::Text,
+        RecordData::Text(vec![b"hello world".to_vec(), b"".to_vec()]),
+      ),
+      (
+        RecordType::NextSecure,
+        RecordData::NextSecure {
+          next: DomainName::from("b.abc.com"),
+          types: vec![
+            RecordType::Host,
+            RecordType::Signature,
+            RecordType::NextSecure,
+            RecordType::CertificationAuthority,
+          ],
+        },
+      ),
+    ];
+
+    for (record_type, record_data) in records {
+      assert_eq!(RecordData::decode(record_type, &record_data.to_bytes()), record_data);
+    }
+  }
+
+  #[test]
+  fn it_encodes_type_bitmaps_per_window() {
+    let types = [RecordType::Host, RecordType::Text, RecordType::CertificationAuthority];
+
+    // window 0: A (bit 1) and TXT (bit 16), window 1: CAA (bit 1)
+    assert_eq!(encode_type_bitmap(&types), hex!["0003400080010140"].to_vec());
+  }
+
+  #[test]
+  fn it_keeps_malformed_data_as_unknown() {
+    assert_eq!(
+      RecordData::decode(RecordType::Host, &[1, 2, 3]),
+      RecordData::Unknown(vec![1, 2, 3])
+    );
+    assert_eq!(RecordData::decode(RecordType(999), &[1]), RecordData::Unknown(vec![1]));
+  }
+}
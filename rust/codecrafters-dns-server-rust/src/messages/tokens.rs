@@ -0,0 +1,166 @@
+//! Splits presentation format text, such as a master file, into entries of whitespace separated
+//! tokens (RFC 1035 section 5.1). Comments run from `;` to the end of the line, parentheses continue
+//! an entry across lines and quotes group whitespace into a single token. Escapes are kept verbatim in the token text since
+//! names and character strings interpret them differently.
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Token {
+  pub text: String,
+  pub quoted: bool,
+  pub line: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+  /// Entries starting with a blank inherit the owner of the previous record.
+  pub blank_owner: bool,
+  pub line: usize,
+  pub tokens: Vec<Token>,
+}
+
+pub fn tokenize(text: &str) -> Result<Vec<Entry>, (usize, String)> {
+  let mut entries = vec![];
+  let mut entry: Option<Entry> = None;
+  let mut token: Option<Token> = None;
+  let mut line = 1;
+  let mut parentheses = 0;
+  let mut at_line_start = true;
+  let mut chars = text.chars().peekable();
+
+  while let Some(char) = chars.next() {
+    let current = entry.get_or_insert_with(|| Entry {
+      blank_owner: at_line_start && (char == ' ' || char == '\t'),
+      line,
+      tokens: vec![],
+    });
+    at_line_start = false;
+
+    if token.as_ref().is_some_and(|token| token.quoted) {
+      match char {
+        '"' => current.tokens.extend(token.take()),
+        '\n' => return Err((line, "Unterminated quoted string".to_string())),
+        _ => {
+          let quoted = token.as_mut().unwrap();
+          quoted.text.push(char);
+          if char == '\\'
+            && let Some(escaped) = chars.next()
+          {
+            quoted.text.push(escaped);
+          }
+        }
+      }
+      continue;
+    }
+
+    match char {
+      ' ' | '\t' | '\r' | '\n' | ';' | '(' | ')' | '"' => {
+        if let Some(token) = token.take() {
+          current.tokens.push(token);
+        }
+      }
+      _ => {}
+    }
+
+    match char {
+      ' ' | '\t' | '\r' => {}
+      ';' => {
+        while chars.peek().is_some_and(|&next| next != '\n') {
+          chars.next();
+        }
+      }
+      '(' => parentheses += 1,
+      ')' => {
+        if parentheses == 0 {
+          return Err((line, "Unbalanced closing parenthesis".to_string()));
+        }
+        parentheses -= 1;
+      }
+      '"' => {
+        token = Some(Token {
+          text: String::new(),
+          quoted: true,
+          line,
+        })
+      }
+      '\n' => {
+        line += 1;
+        if parentheses == 0 {
+          at_line_start = true;
+          if let Some(entry) = entry.take().filter(|entry| !entry.tokens.is_empty()) {
+            entries.push(entry);
+          }
+        }
+      }
+      _ => {
+        let token = token.get_or_insert_with(|| Token {
+          text: String::new(),
+          quoted: false,
+          line,
+        });
+        token.text.push(char);
+        if char == '\\'
+          && let Some(escaped) = chars.next()
+        {
+          token.text.push(escaped);
+        }
+      }
+    }
+  }
+
+  if token.as_ref().is_some_and(|token| token.quoted) {
+    return Err((line, "Unterminated quoted string".to_string()));
+  }
+  if parentheses > 0 {
+    return Err((line, "Unbalanced opening parenthesis".to_string()));
+  }
+
+  if let Some(mut entry) = entry {
+    entry.tokens.extend(token);
+    if !entry.tokens.is_empty() {
+      entries.push(entry);
+    }
+  }
+
+  Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn texts(entry: &Entry) -> Vec<&str> {
+    entry.tokens.iter().map(|token| token.text.as_str()).collect()
+  }
+
+  #[test]
+  fn it_joins_parenthesised_lines_and_skips_comments() {
+    let entries = tokenize("@ IN SOA ns admin ( ; primary\n  1 2 3\n  4 5 )\n\n  A 1.2.3.4 ; blank owner\n").unwrap();
+
+    assert_eq!(entries.len(), 2);
+    assert_eq!(
+      texts(&entries[0]),
+      ["@", "IN", "SOA", "ns", "admin", "1", "2", "3", "4", "5"]
+    );
+    assert_eq!(entries[0].blank_owner, false);
+    assert_eq!(entries[1].blank_owner, true);
+    assert_eq!(entries[1].line, 5);
+  }
+
+  #[test]
+  fn it_keeps_quoted_strings_and_escapes_together() {
+    let entries = tokenize("txt TXT \"a ; b\" \"say \\\"hi\\\"\" a\\ b\\;c").unwrap();
+
+    assert_eq!(
+      texts(&entries[0]),
+      ["txt", "TXT", "a ; b", "say \\\"hi\\\"", "a\\ b\\;c"]
+    );
+    assert_eq!(entries[0].tokens[2].quoted, true);
+    assert_eq!(entries[0].tokens[4].quoted, false);
+  }
+
+  #[test]
+  fn it_reports_unbalanced_input_with_line_numbers() {
+    assert_eq!(tokenize("a A (\n1.2.3.4\n").unwrap_err().0, 3);
+    assert_eq!(tokenize("a TXT \"open\n").unwrap_err().0, 1);
+  }
+}
@@ -0,0 +1,178 @@
+//! Aggressive use of the DNSSEC-validated cache (RFC 8198).
+//!
+//! The NSEC and NSEC3 records of secure negative answers are kept with their signatures and the
+//! zone's SOA set for their TTL. A later question whose name or type falls into a span already
+//! proven empty is answered from them with NXDOMAIN or NODATA without asking upstream. NSEC3
+//! records with opt-out are not kept, since their spans may hide unsigned delegations.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::dnssec::*;
+use crate::messages::*;
+use crate::resolver::Resolution;
+
+/// Longest time a denial is used, whatever its TTL.
+const MaxDenialTtl: u32 = 3 * 60 * 60;
+/// Most denial RRsets kept at once; new ones are dropped while the cache is full.
+const MaxCachedDenials: usize = 10_000;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AggressiveStats {
+  pub synthesized_nxdomain: u64,
+  pub synthesized_nodata: u64,
+}
+
+impl AggressiveStats {
+  pub fn synthesized(&self) -> u64 {
+    self.synthesized_nxdomain + self.synthesized_nodata
+  }
+}
+
+#[derive(Clone, Debug)]
+struct Cached {
+  /// The records of the RRset followed by their signatures.
+  records: Vec<AnswerProperties>,
+  until: Instant,
+}
+
+/// Validated denial records of one signed zone.
+#[derive(Clone, Debug, Default)]
+struct ZoneDenials {
+  soa: Option<Cached>,
+  /// By lowercased owner and type.
+  denials: HashMap<(DomainName, RecordType), Cached>,
+}
+
+#[derive(Debug, Default)]
+pub struct DenialCache {
+  zones: HashMap<DomainName, ZoneDenials>,
+  stats: AggressiveStats,
+}
+
+impl DenialCache {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn stats(&self) -> &AggressiveStats {
+    &self.stats
+  }
+
+  pub fn len(&self) -> usize {
+    self.zones.values().map(|zone| zone.denials.len()).sum()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  /// Keeps the validated `soa` set and `denials` of the signed zone at `apex`, each RRset followed
+  /// by its signatures as found in `authorities`.
+  pub fn insert(
+    &mut self,
+    apex: &DomainName,
+    soa: &[AnswerProperties],
+    denials: &[AnswerProperties],
+    authorities: &[AnswerProperties],
+  ) {
+    let now = Instant::now();
+    if self.len() >= MaxCachedDenials {
+      self.expire(now);
+    }
+
+    let signed = |records: &[AnswerProperties]| {
+      let mut signed = records.to_vec();
+      signed.extend(authorities.iter().filter(|record| {
+        record.record_type == RecordType::Signature
+          && record.domain_name.eq_ignore_case(&records[0].domain_name)
+          && matches!(record.record_data(), RecordData::Signature { type_covered, .. } if type_covered == records[0].record_type)
+      }).cloned());
+      let ttl = records.iter().map(|record| record.time_to_live).min().unwrap_or(0);
+
+      Cached {
+        records: signed,
+        until: now + Duration::from_secs(ttl.min(MaxDenialTtl) as u64),
+      }
+    };
+
+    let full = self.len() >= MaxCachedDenials;
+    let zone = self.zones.entry(apex.to_lowercase()).or_default();
+    if !soa.is_empty() {
+      zone.soa = Some(signed(soa));
+    }
+    for record in denials {
+      if full || is_opt_out(record) {
+        continue;
+      }
+
+      let key = (record.domain_name.to_lowercase(), record.record_type);
+      zone.denials.insert(key, signed(std::slice::from_ref(record)));
+    }
+  }
+
+  /// The negative answer to `question` proven by cached denials of the closest zone, if any.
+  pub fn synthesize(&mut self, question: &QuestionProperties) -> Option<Resolution> {
+    let now = Instant::now();
+    let name = &question.domain_name;
+    let zone = self
+      .zones
+      .iter()
+      .filter(|(apex, _)| name.is_subdomain_of(apex))
+      .max_by_key(|(apex, _)| apex.label_count())
+      .map(|(_, zone)| zone)?;
+    let soa = zone.soa.as_ref().filter(|soa| soa.until > now)?;
+
+    let current: Vec<&Cached> = zone.denials.values().filter(|cached| cached.until > now).collect();
+    let records: Vec<AnswerProperties> = current.iter().map(|cached| cached.records[0].clone()).collect();
+    let proof = proof_records(&records, name);
+    let response_code = if proves_name_error(&proof, name) {
+      ResponseCode::NameError
+    } else if proves_no_data(&proof, name, question.record_type) {
+      ResponseCode::NoError
+    } else {
+      return None;
+    };
+
+    let mut authorities = remaining(soa, now);
+    for cached in current.iter().filter(|cached| proof.contains(&cached.records[0])) {
+      authorities.extend(remaining(cached, now));
+    }
+
+    match response_code {
+      ResponseCode::NameError => self.stats.synthesized_nxdomain += 1,
+      _ => self.stats.synthesized_nodata += 1,
+    }
+    Some(Resolution {
+      response_code,
+      answers: vec![],
+      authorities,
+    })
+  }
+
+  fn expire(&mut self, now: Instant) {
+    for zone in self.zones.values_mut() {
+      zone.denials.retain(|_, cached| cached.until > now);
+      zone.soa = zone.soa.take().filter(|soa| soa.until > now);
+    }
+    self.zones.retain(|_, zone| !zone.denials.is_empty());
+  }
+}
+
+fn is_opt_out(record: &AnswerProperties) -> bool {
+  matches!(record.record_data(), RecordData::NextSecure3 { flags, .. } if flags & Nsec3OptOut != 0)
+}
+
+/// The cached records with their TTL counted down to what is left of it.
+fn remaining(cached: &Cached, now: Instant) -> Vec<AnswerProperties> {
+  let left = cached.until.saturating_duration_since(now).as_secs() as u32;
+
+  cached
+    .records
+    .iter()
+    .map(|record| AnswerProperties {
+      time_to_live: record.time_to_live.min(left),
+      ..record.clone()
+    })
+    .collect()
+}
@@ -0,0 +1,72 @@
+//! Following aliases in answers: CNAME records owned by a name, and DNAME records owned by one of
+//! its ancestors redirecting the whole subtree (RFC 6672).
+
+use crate::messages::*;
+
+/// The record redirecting `name` together with the name it points to.
+pub fn find_alias<'a>(
+  answers: &'a [AnswerProperties],
+  name: &DomainName,
+) -> Option<(&'a AnswerProperties, DomainName)> {
+  let canonical = answers
+    .iter()
+    .find(|answer| answer.record_type == RecordType::CanonicalName && answer.domain_name.eq_ignore_case(name))
+    .and_then(|answer| match answer.record_data() {
+      RecordData::CanonicalName(target) => Some((answer, target)),
+      _ => None,
+    });
+  if canonical.is_some() {
+    return canonical;
+  }
+
+  answers
+    .iter()
+    .filter(|answer| answer.record_type == RecordType::DelegationName)
+    .filter(|answer| name.is_subdomain_of(&answer.domain_name) && !name.eq_ignore_case(&answer.domain_name))
+    .find_map(|answer| match answer.record_data() {
+      RecordData::DelegationName(target) => {
+        let target = name.replace_suffix(&answer.domain_name, &target);
+        (target.size_of() <= DomainName::MaxLength).then_some((answer, target))
+      }
+      _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn record(name: &str, record_data: RecordData, record_type: RecordType) -> AnswerProperties {
+    AnswerProperties {
+      domain_name: DomainName::from(name),
+      record_type,
+      record_class: RecordClass::Internet,
+      time_to_live: 60,
+      data: record_data.to_bytes(),
+    }
+  }
+
+  #[test]
+  fn it_follows_canonical_and_delegation_names() {
+    let answers = [
+      record(
+        "abc.com",
+        RecordData::DelegationName(DomainName::from("example.net")),
+        RecordType::DelegationName,
+      ),
+      record(
+        "www.example.com",
+        RecordData::CanonicalName(DomainName::from("web.example.com")),
+        RecordType::CanonicalName,
+      ),
+    ];
+
+    let canonical = find_alias(&answers, &DomainName::from("WWW.example.com")).unwrap();
+    let delegated = find_alias(&answers, &DomainName::from("a.b.abc.com")).unwrap();
+
+    assert_eq!(canonical.1, DomainName::from("web.example.com"));
+    assert_eq!(delegated.1, DomainName::from("a.b.example.net"));
+    assert_eq!(delegated.0.record_type, RecordType::DelegationName);
+    assert!(find_alias(&answers, &DomainName::from("abc.com")).is_none());
+  }
+}
@@ -0,0 +1,240 @@
+//! Strict bailiwick filtering of upstream responses.
+//!
+//! A server is only trusted for names inside the zone it was asked about. Answers must also
+//! belong to the alias chain starting at the query name, either owned by a name on it or being
+//! a DNAME used to extend it, and authority records must own an
+//! ancestor of it, so unrelated records riding along with a legitimate answer are dropped.
+//! NSEC and NSEC3 records and their signatures are kept anywhere in the zone, since denial proofs
+//! are owned by the names next to the one asked about; validation decides whether they prove anything.
+
+use crate::messages::*;
+use crate::resolver::aliases::find_alias;
+use crate::resolver::recursive::MaxCanonicalNames;
+
+pub fn retain_in_bailiwick(response: &mut MessageProperties, zone: &DomainName, name: &DomainName) {
+  let chain = alias_chain(&response.answers, zone, name);
+
+  response.answers.retain(|record| {
+    let on_chain = match record.record_type {
+      RecordType::DelegationName => chain
+        .iter()
+        .any(|alias| alias.is_subdomain_of(&record.domain_name) && !alias.eq_ignore_case(&record.domain_name)),
+      _ => chain.iter().any(|alias| alias.eq_ignore_case(&record.domain_name)),
+    };
+
+    record.domain_name.is_subdomain_of(zone) && on_chain
+  });
+  response.authorities.retain(|record| {
+    let denial = matches!(
+      record.record_type,
+      RecordType::NextSecure | RecordType::NextSecure3 | RecordType::Signature
+    );
+
+    record.domain_name.is_subdomain_of(zone) && (denial || name.is_subdomain_of(&record.domain_name))
+  });
+  response
+    .additionals
+    .retain(|record| record.record_type == RecordType::Options || record.domain_name.is_subdomain_of(zone));
+
+  response.header.answer_record_count = response.answers.len() as u16;
+  response.header.authority_record_count = response.authorities.len() as u16;
+  response.header.additional_record_count = response.additionals.len() as u16;
+}
+
+fn alias_chain(answers: &[AnswerProperties], zone: &DomainName, name: &DomainName) -> Vec<DomainName> {
+  let mut chain = vec![name.clone()];
+
+  for _ in 0..MaxCanonicalNames {
+    let last = chain.last().unwrap();
+    if !last.is_subdomain_of(zone) {
+      break;
+    }
+
+    match find_alias(answers, last) {
+      Some((_, target)) => chain.push(target),
+      None => break,
+    }
+  }
+
+  chain
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn record(name: &str, record_type: RecordType, data: Vec<u8>) -> AnswerProperties {
+    AnswerProperties {
+      domain_name: DomainName::from(name),
+      record_type,
+      record_class: RecordClass::Internet,
+      time_to_live: 60,
+      data,
+    }
+  }
+
+  fn response(
+    answers: Vec<AnswerProperties>,
+    authorities: Vec<AnswerProperties>,
+    additionals: Vec<AnswerProperties>,
+  ) -> MessageProperties {
+    MessageProperties {
+      header: HeaderProperties {
+        id: 1,
+        flags: Flags::Response,
+        question_count: 0,
+        answer_record_count: answers.len() as u16,
+        authority_record_count: authorities.len() as u16,
+        additional_record_count: additionals.len() as u16,
+      },
+      questions: vec![],
+      answers,
+      authorities,
+      additionals,
+    }
+  }
+
+  #[test]
+  fn it_keeps_answers_along_the_alias_chain() {
+    let mut message = response(
+      vec![
+        record(
+          "www.example.com",
+          RecordType::CanonicalName,
+          DomainName::from("web.example.com").to_bytes(),
+        ),
+        record("web.example.com", RecordType::Host, vec![1, 2, 3, 4]),
+        record("mail.example.com", RecordType::Host, vec![6, 6, 6, 6]),
+      ],
+      vec![],
+      vec![],
+    );
+
+    retain_in_bailiwick(
+      &mut message,
+      &DomainName::from("example.com"),
+      &DomainName::from("www.example.com"),
+    );
+
+    assert_eq!(message.answers.len(), 2);
+    assert_eq!(message.header.answer_record_count, 2);
+    assert_eq!(message.answers[1].data, vec![1, 2, 3, 4]);
+  }
+
+  #[test]
+  fn it_keeps_delegation_names_extending_the_chain() {
+    let mut message = response(
+      vec![
+        record(
+          "old.example.com",
+          RecordType::DelegationName,
+          DomainName::from("new.example.com").to_bytes(),
+        ),
+        record(
+          "www.old.example.com",
+          RecordType::CanonicalName,
+          DomainName::from("www.new.example.com").to_bytes(),
+        ),
+        record("www.new.example.com", RecordType::Host, vec![1, 2, 3, 4]),
+        record(
+          "other.example.com",
+          RecordType::DelegationName,
+          DomainName::from("evil.example.com").to_bytes(),
+        ),
+      ],
+      vec![],
+      vec![],
+    );
+
+    retain_in_bailiwick(
+      &mut message,
+      &DomainName::from("example.com"),
+      &DomainName::from("www.old.example.com"),
+    );
+
+    assert_eq!(message.answers.len(), 3);
+    assert_eq!(message.answers[0].record_type, RecordType::DelegationName);
+  }
+
+  #[test]
+  fn it_drops_alias_targets_outside_the_zone() {
+    let mut message = response(
+      vec![
+        record(
+          "www.example.com",
+          RecordType::CanonicalName,
+          DomainName::from("cdn.other.net").to_bytes(),
+        ),
+        record("cdn.other.net", RecordType::Host, vec![6, 6, 6, 6]),
+      ],
+      vec![],
+      vec![],
+    );
+
+    retain_in_bailiwick(
+      &mut message,
+      &DomainName::from("example.com"),
+      &DomainName::from("www.example.com"),
+    );
+
+    assert_eq!(message.answers.len(), 1);
+    assert_eq!(message.answers[0].record_type, RecordType::CanonicalName);
+  }
+
+  #[test]
+  fn it_drops_out_of_bailiwick_referrals_and_glue() {
+    let mut message = response(
+      vec![],
+      vec![
+        record(
+          "example.com",
+          RecordType::NameServer,
+          DomainName::from("ns.example.com").to_bytes(),
+        ),
+        record(
+          "victim.com",
+          RecordType::NameServer,
+          DomainName::from("ns.attacker.net").to_bytes(),
+        ),
+      ],
+      vec![
+        record("ns.example.com", RecordType::Host, vec![192, 0, 2, 1]),
+        record("ns.attacker.net", RecordType::Host, vec![6, 6, 6, 6]),
+      ],
+    );
+
+    retain_in_bailiwick(
+      &mut message,
+      &DomainName::from("com"),
+      &DomainName::from("www.example.com"),
+    );
+
+    assert_eq!(message.authorities.len(), 1);
+    assert_eq!(message.authorities[0].domain_name, DomainName::from("example.com"));
+    assert_eq!(message.additionals.len(), 1);
+    assert_eq!(message.additionals[0].domain_name, DomainName::from("ns.example.com"));
+  }
+
+  #[test]
+  fn it_keeps_denial_proofs_inside_the_zone() {
+    let mut message = response(
+      vec![],
+      vec![
+        record("a.example.com", RecordType::NextSecure, vec![]),
+        record("a.example.com", RecordType::Signature, vec![]),
+        record("a.example.net", RecordType::NextSecure, vec![]),
+        record("mail.example.com", RecordType::Host, vec![6, 6, 6, 6]),
+      ],
+      vec![],
+    );
+
+    retain_in_bailiwick(
+      &mut message,
+      &DomainName::from("example.com"),
+      &DomainName::from("b.example.com"),
+    );
+
+    let kept: Vec<RecordType> = message.authorities.iter().map(|record| record.record_type).collect();
+    assert_eq!(kept, [RecordType::NextSecure, RecordType::Signature]);
+  }
+}
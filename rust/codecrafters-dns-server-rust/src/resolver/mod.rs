@@ -0,0 +1,15 @@
+pub mod aggressive;
+pub mod aliases;
+pub mod bailiwick;
+pub mod recursive;
+pub mod root_hints;
+pub mod transport;
+pub mod validator;
+
+pub use aggressive::*;
+pub use aliases::*;
+pub use bailiwick::*;
+pub use recursive::*;
+pub use root_hints::*;
+pub use transport::*;
+pub use validator::*;
@@ -0,0 +1,538 @@
+//! Iterative resolution walking the delegation tree down from the root servers.
+//!
+//! Outgoing queries follow RFC 9156 QNAME minimisation: an authority is only asked about the
+//! next label below its zone (with QTYPE A) until the full name is reached. The resolver runs
+//! in the relaxed mode, so when a minimised query gets NXDOMAIN, an error response code or no
+//! response at all, it falls back to the full name for the rest of that zone.
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+use crate::messages::*;
+use crate::resolver::{Transport, find_alias, retain_in_bailiwick};
+
+/// RFC 9156 section 2.3 limits on how many minimised queries a single zone may cost.
+const MaxMinimiseCount: usize = 10;
+const MinimiseOneLabel: usize = 4;
+const MaxIterations: usize = 32;
+pub(crate) const MaxCanonicalNames: usize = 8;
+const MaxDepth: usize = 3;
+
+#[derive(Clone, Debug)]
+pub struct Resolution {
+  pub response_code: u8,
+  pub answers: Vec<AnswerProperties>,
+  pub authorities: Vec<AnswerProperties>,
+}
+
+impl Resolution {
+  pub fn failure() -> Self {
+    Self {
+      response_code: ResponseCode::ServerFailure,
+      answers: vec![],
+      authorities: vec![],
+    }
+  }
+}
+
+impl From<MessageProperties> for Resolution {
+  fn from(message: MessageProperties) -> Self {
+    Self {
+      response_code: message.header.response_code(),
+      answers: message.answers,
+      authorities: message.authorities,
+    }
+  }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MinimisationStats {
+  pub minimised_queries: u64,
+  pub full_queries: u64,
+  pub nxdomain_fallbacks: u64,
+  pub error_fallbacks: u64,
+  pub timeout_fallbacks: u64,
+}
+
+impl MinimisationStats {
+  pub fn fallbacks(&self) -> u64 {
+    self.nxdomain_fallbacks + self.error_fallbacks + self.timeout_fallbacks
+  }
+}
+
+pub struct RecursiveResolver<T: Transport> {
+  transport: T,
+  root_servers: Vec<SocketAddr>,
+  stats: MinimisationStats,
+  /// Asks for signatures and denial proofs along with the answers, for validating them.
+  pub dnssec_ok: bool,
+}
+
+impl<T: Transport> RecursiveResolver<T> {
+  pub fn new(transport: T, root_servers: Vec<SocketAddr>) -> Self {
+    Self {
+      transport,
+      root_servers,
+      stats: MinimisationStats::default(),
+      dnssec_ok: false,
+    }
+  }
+
+  pub fn stats(&self) -> &MinimisationStats {
+    &self.stats
+  }
+
+  pub fn resolve(&mut self, question: &QuestionProperties) -> Resolution {
+    self.resolve_at_depth(question, 0)
+  }
+
+  fn resolve_at_depth(&mut self, question: &QuestionProperties, depth: usize) -> Resolution {
+    let mut answers = vec![];
+    let mut name = question.domain_name.clone();
+
+    for _ in 0..MaxCanonicalNames {
+      let resolution = self.iterate(&name, question.record_type, depth);
+      answers.extend(resolution.answers);
+
+      if resolution.response_code != ResponseCode::NoError {
+        return Resolution {
+          response_code: resolution.response_code,
+          answers,
+          authorities: resolution.authorities,
+        };
+      }
+
+      let target = follow_aliases(&answers, &name);
+      let answered = answers
+        .iter()
+        .any(|answer| answer.record_type == question.record_type && answer.domain_name.eq_ignore_case(&target));
+      if answered || question.record_type == RecordType::CanonicalName || target.eq_ignore_case(&name) {
+        return Resolution {
+          response_code: ResponseCode::NoError,
+          answers,
+          authorities: resolution.authorities,
+        };
+      }
+
+      name = target;
+    }
+
+    Resolution::failure()
+  }
+
+  fn iterate(&mut self, name: &DomainName, record_type: RecordType, depth: usize) -> Resolution {
+    let mut zone = DomainName::root();
+    let mut servers = self.root_servers.clone();
+    let mut child = zone.clone();
+    let mut minimise = true;
+    let mut minimise_count = 0;
+
+    for _ in 0..MaxIterations {
+      let labels = match minimise {
+        true => minimised_label_count(child.label_count(), name.label_count(), minimise_count),
+        false => None,
+      };
+
+      let Some(labels) = labels else {
+        self.stats.full_queries += 1;
+        let Ok(response) = self.query(&servers, &zone, name, record_type) else {
+          return Resolution::failure();
+        };
+
+        let Some((cut, names)) = find_referral(&response, &zone, name) else {
+          return Resolution::from(response);
+        };
+
+        servers = self.servers_for(&response, &names, depth);
+        if servers.is_empty() {
+          return Resolution::failure();
+        }
+
+        zone = cut;
+        child = zone.clone();
+        minimise = true;
+        minimise_count = 0;
+        continue;
+      };
+
+      let candidate = name.suffix(labels);
+      minimise_count += 1;
+      self.stats.minimised_queries += 1;
+
+      let Ok(response) = self.query(&servers, &zone, &candidate, RecordType::Host) else {
+        self.stats.timeout_fallbacks += 1;
+        minimise = false;
+        continue;
+      };
+
+      match response.header.response_code() {
+        ResponseCode::NoError => match find_referral(&response, &zone, name) {
+          Some((cut, names)) => {
+            let next = self.servers_for(&response, &names, depth);
+            if next.is_empty() {
+              return Resolution::failure();
+            }
+
+            servers = next;
+            zone = cut;
+            child = zone.clone();
+            minimise_count = 0;
+          }
+          None => child = candidate,
+        },
+        ResponseCode::NameError => {
+          self.stats.nxdomain_fallbacks += 1;
+          minimise = false;
+        }
+        _ => {
+          self.stats.error_fallbacks += 1;
+          minimise = false;
+        }
+      }
+    }
+
+    Resolution::failure()
+  }
+
+  fn servers_for(&mut self, response: &MessageProperties, names: &[DomainName], depth: usize) -> Vec<SocketAddr> {
+    let mut servers: Vec<SocketAddr> = response
+      .additionals
+      .iter()
+      .filter(|record| names.iter().any(|name| name.eq_ignore_case(&record.domain_name)))
+      .filter_map(to_server_address)
+      .collect();
+
+    if servers.is_empty() && depth < MaxDepth {
+      for name in names {
+        let question = QuestionProperties {
+          domain_name: name.clone(),
+          record_type: RecordType::Host,
+          record_class: RecordClass::Internet,
+        };
+
+        let resolution = self.resolve_at_depth(&question, depth + 1);
+        servers.extend(resolution.answers.iter().filter_map(to_server_address));
+        if !servers.is_empty() {
+          break;
+        }
+      }
+    }
+
+    servers
+  }
+
+  fn query(
+    &mut self,
+    servers: &[SocketAddr],
+    zone: &DomainName,
+    name: &DomainName,
+    record_type: RecordType,
+  ) -> io::Result<MessageProperties> {
+    let mut query = MessageProperties::query(name.clone(), record_type).recursion_desired(false);
+    if self.dnssec_ok {
+      query = query.dnssec_ok();
+    }
+    let query = query
+      .build()
+      .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+
+    let mut error = io::Error::new(io::ErrorKind::NotFound, "No name servers to query");
+    for &server in servers {
+      match self.transport.exchange(server, &query) {
+        Ok(mut response) => {
+          retain_in_bailiwick(&mut response, zone, name);
+          return Ok(response);
+        }
+        Err(reason) => error = reason,
+      }
+    }
+
+    Err(error)
+  }
+}
+
+/// Number of labels of the next minimised query name, or `None` once the full name should be sent.
+/// The first `MinimiseOneLabel` queries add a single label, the rest spread the remaining labels
+/// over what is left of the `MaxMinimiseCount` budget.
+fn minimised_label_count(known: usize, total: usize, minimise_count: usize) -> Option<usize> {
+  if minimise_count >= MaxMinimiseCount || known + 1 >= total {
+    return None;
+  }
+
+  let remaining = total - known;
+  let step = match minimise_count < MinimiseOneLabel {
+    true => 1,
+    false => (remaining / (MaxMinimiseCount - minimise_count)).max(1),
+  };
+
+  Some(known + step).filter(|&labels| labels < total)
+}
+
+/// A referral hands out NS records for a cut strictly below `zone` that still encloses `name`.
+fn find_referral(
+  response: &MessageProperties,
+  zone: &DomainName,
+  name: &DomainName,
+) -> Option<(DomainName, Vec<DomainName>)> {
+  if response.header.response_code() != ResponseCode::NoError || !response.answers.is_empty() {
+    return None;
+  }
+
+  let cut = response
+    .authorities
+    .iter()
+    .find(|record| record.record_type == RecordType::NameServer)?
+    .domain_name
+    .clone();
+  if cut.label_count() <= zone.label_count() || !cut.is_subdomain_of(zone) || !name.is_subdomain_of(&cut) {
+    return None;
+  }
+
+  let names = response
+    .authorities
+    .iter()
+    .filter(|record| record.record_type == RecordType::NameServer && record.domain_name.eq_ignore_case(&cut))
+    .filter_map(|record| DomainName::try_from_bytes(&record.data, 0))
+    .map(|(name, _)| name)
+    .collect();
+
+  Some((cut, names))
+}
+
+fn follow_aliases(answers: &[AnswerProperties], name: &DomainName) -> DomainName {
+  let mut target = name.clone();
+
+  for _ in 0..MaxCanonicalNames {
+    match find_alias(answers, &target) {
+      Some((_, next)) => target = next,
+      None => break,
+    }
+  }
+
+  target
+}
+
+fn to_server_address(record: &AnswerProperties) -> Option<SocketAddr> {
+  match (record.record_type, record.data.as_slice()) {
+    (RecordType::Host, &[a, b, c, d]) => Some(SocketAddr::new(IpAddr::V4(Ipv4Addr::new(a, b, c, d)), 53)),
+    _ => None,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::collections::HashMap;
+
+  fn record(name: &str, record_type: RecordType, data: Vec<u8>) -> AnswerProperties {
+    AnswerProperties {
+      domain_name: DomainName::from(name),
+      record_type,
+      record_class: RecordClass::Internet,
+      time_to_live: 60,
+      data,
+    }
+  }
+
+  fn server(last: u8) -> SocketAddr {
+    SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 0, 2, last)), 53)
+  }
+
+  #[derive(Clone, Copy, PartialEq)]
+  enum Behaviour {
+    Correct,
+    NxDomainForEmptyNonTerminals,
+    DropsEmptyNonTerminalQueries,
+  }
+
+  /// Authorities answering from a flat record list: referrals for NS records below their origin,
+  /// answers for exact matches, NODATA for existing names and NXDOMAIN otherwise.
+  struct FakeAuthorities {
+    zones: HashMap<SocketAddr, (DomainName, Vec<AnswerProperties>, Behaviour)>,
+    log: Vec<(SocketAddr, DomainName, RecordType)>,
+  }
+
+  impl FakeAuthorities {
+    fn new(behaviour: Behaviour) -> Self {
+      let mut zones = HashMap::new();
+      zones.insert(
+        server(1),
+        (
+          DomainName::root(),
+          vec![
+            record("com", RecordType::NameServer, DomainName::from("ns.com").to_bytes()),
+            record("ns.com", RecordType::Host, vec![192, 0, 2, 2]),
+          ],
+          Behaviour::Correct,
+        ),
+      );
+      zones.insert(
+        server(2),
+        (
+          DomainName::from("com"),
+          vec![
+            record(
+              "example.com",
+              RecordType::NameServer,
+              DomainName::from("ns.example.com").to_bytes(),
+            ),
+            record("ns.example.com", RecordType::Host, vec![192, 0, 2, 3]),
+          ],
+          Behaviour::Correct,
+        ),
+      );
+      zones.insert(
+        server(3),
+        (
+          DomainName::from("example.com"),
+          vec![
+            record("a.b.example.com", RecordType::Host, vec![1, 2, 3, 4]),
+            record(
+              "www.example.com",
+              RecordType::CanonicalName,
+              DomainName::from("a.b.example.com").to_bytes(),
+            ),
+          ],
+          behaviour,
+        ),
+      );
+
+      Self { zones, log: vec![] }
+    }
+  }
+
+  impl Transport for FakeAuthorities {
+    fn exchange(&mut self, server: SocketAddr, query: &MessageProperties) -> io::Result<MessageProperties> {
+      let question = &query.questions[0];
+      let name = &question.domain_name;
+      self.log.push((server, name.clone(), question.record_type));
+
+      let (origin, records, behaviour) = &self.zones[&server];
+      let terminal = records.iter().any(|record| record.domain_name.eq_ignore_case(name));
+      if *behaviour == Behaviour::DropsEmptyNonTerminalQueries && !terminal {
+        return Err(io::Error::new(io::ErrorKind::TimedOut, "dropped"));
+      }
+
+      let mut response = query.clone();
+      response.header.flags = Flags::Response;
+
+      let cut = records.iter().find(|record| {
+        record.record_type == RecordType::NameServer
+          && record.domain_name.label_count() > origin.label_count()
+          && name.is_subdomain_of(&record.domain_name)
+      });
+
+      if let Some(cut) = cut {
+        let target = DomainName::try_from_bytes(&cut.data, 0).unwrap().0;
+        response.authorities.push(cut.clone());
+        response
+          .additionals
+          .extend(records.iter().filter(|record| record.domain_name == target).cloned());
+      } else {
+        response.answers.extend(
+          records
+            .iter()
+            .filter(|record| record.domain_name.eq_ignore_case(name))
+            .filter(|record| {
+              record.record_type == question.record_type || record.record_type == RecordType::CanonicalName
+            })
+            .cloned(),
+        );
+
+        let exists = records.iter().any(|record| record.domain_name.is_subdomain_of(name));
+        let broken = *behaviour == Behaviour::NxDomainForEmptyNonTerminals && !terminal;
+        if !exists || broken {
+          response.header.flags = Flags::Response | Flags::from_response_code(ResponseCode::NameError);
+        }
+      }
+
+      response.header.answer_record_count = response.answers.len() as u16;
+      response.header.authority_record_count = response.authorities.len() as u16;
+      response.header.additional_record_count = response.additionals.len() as u16;
+      Ok(response)
+    }
+  }
+
+  fn question(name: &str) -> QuestionProperties {
+    QuestionProperties {
+      domain_name: DomainName::from(name),
+      record_type: RecordType::Host,
+      record_class: RecordClass::Internet,
+    }
+  }
+
+  #[test]
+  fn it_sends_only_the_next_label_to_each_authority() {
+    let mut resolver = RecursiveResolver::new(FakeAuthorities::new(Behaviour::Correct), vec![server(1)]);
+
+    let resolution = resolver.resolve(&question("a.b.example.com"));
+
+    assert_eq!(resolution.response_code, ResponseCode::NoError);
+    assert_eq!(resolution.answers[0].data, vec![1, 2, 3, 4]);
+    assert_eq!(
+      resolver.transport.log,
+      vec![
+        (server(1), DomainName::from("com"), RecordType::Host),
+        (server(2), DomainName::from("example.com"), RecordType::Host),
+        (server(3), DomainName::from("b.example.com"), RecordType::Host),
+        (server(3), DomainName::from("a.b.example.com"), RecordType::Host),
+      ]
+    );
+    assert_eq!(resolver.stats().minimised_queries, 3);
+    assert_eq!(resolver.stats().full_queries, 1);
+    assert_eq!(resolver.stats().fallbacks(), 0);
+  }
+
+  #[test]
+  fn it_falls_back_to_the_full_name_on_nxdomain_for_empty_non_terminals() {
+    let authorities = FakeAuthorities::new(Behaviour::NxDomainForEmptyNonTerminals);
+    let mut resolver = RecursiveResolver::new(authorities, vec![server(1)]);
+
+    let resolution = resolver.resolve(&question("a.b.example.com"));
+
+    assert_eq!(resolution.response_code, ResponseCode::NoError);
+    assert_eq!(resolution.answers[0].data, vec![1, 2, 3, 4]);
+    assert_eq!(resolver.stats().nxdomain_fallbacks, 1);
+    assert_eq!(resolver.stats().fallbacks(), 1);
+  }
+
+  #[test]
+  fn it_falls_back_to_the_full_name_when_minimised_queries_time_out() {
+    let authorities = FakeAuthorities::new(Behaviour::DropsEmptyNonTerminalQueries);
+    let mut resolver = RecursiveResolver::new(authorities, vec![server(1)]);
+
+    let resolution = resolver.resolve(&question("a.b.example.com"));
+
+    assert_eq!(resolution.answers[0].data, vec![1, 2, 3, 4]);
+    assert_eq!(resolver.stats().timeout_fallbacks, 1);
+  }
+
+  #[test]
+  fn it_reports_nxdomain_for_missing_names() {
+    let mut resolver = RecursiveResolver::new(FakeAuthorities::new(Behaviour::Correct), vec![server(1)]);
+
+    let resolution = resolver.resolve(&question("missing.example.com"));
+
+    assert_eq!(resolution.response_code, ResponseCode::NameError);
+    assert_eq!(resolver.stats().fallbacks(), 0);
+  }
+
+  #[test]
+  fn it_follows_canonical_names() {
+    let mut resolver = RecursiveResolver::new(FakeAuthorities::new(Behaviour::Correct), vec![server(1)]);
+
+    let resolution = resolver.resolve(&question("www.example.com"));
+
+    assert_eq!(resolution.answers.len(), 2);
+    assert_eq!(resolution.answers[0].record_type, RecordType::CanonicalName);
+    assert_eq!(resolution.answers[1].data, vec![1, 2, 3, 4]);
+  }
+
+  #[test]
+  fn it_spreads_labels_after_the_single_label_phase() {
+    assert_eq!(minimised_label_count(0, 3, 0), Some(1));
+    assert_eq!(minimised_label_count(2, 3, 0), None);
+    assert_eq!(minimised_label_count(4, 24, 4), Some(7));
+    assert_eq!(minimised_label_count(4, 24, MaxMinimiseCount), None);
+  }
+}
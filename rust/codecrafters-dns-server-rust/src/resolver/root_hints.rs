@@ -0,0 +1,25 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+/// IPv4 addresses of a.root-servers.net through m.root-servers.net.
+const RootServers: [[u8; 4]; 13] = [
+  [198, 41, 0, 4],
+  [170, 247, 170, 2],
+  [192, 33, 4, 12],
+  [199, 7, 91, 13],
+  [192, 203, 230, 10],
+  [192, 5, 5, 241],
+  [192, 112, 36, 4],
+  [198, 97, 190, 53],
+  [192, 36, 148, 17],
+  [192, 58, 128, 30],
+  [193, 0, 14, 129],
+  [199, 7, 83, 42],
+  [202, 12, 27, 33],
+];
+
+pub fn root_hints() -> Vec<SocketAddr> {
+  RootServers
+    .iter()
+    .map(|&[a, b, c, d]| SocketAddr::new(IpAddr::V4(Ipv4Addr::new(a, b, c, d)), 53))
+    .collect()
+}
@@ -0,0 +1,255 @@
+//! Outgoing queries are hardened against off-path spoofing: every query is sent from a fresh socket
+//! bound to a random source port, and the query name goes out with DNS 0x20 random letter casing.
+//! A response is only accepted when it comes from the queried server, carries the query ID and
+//! echoes the question with exactly the same casing.
+
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+use rand::{random, random_range};
+
+use crate::messages::*;
+use crate::tsig::*;
+
+const MaxBindAttempts: usize = 16;
+
+pub trait Transport {
+  fn exchange(&mut self, server: SocketAddr, query: &MessageProperties) -> io::Result<MessageProperties>;
+}
+
+#[derive(Clone, Debug)]
+pub struct UdpTransport {
+  pub timeout: Duration,
+}
+
+impl Default for UdpTransport {
+  fn default() -> Self {
+    Self {
+      timeout: Duration::from_secs(2),
+    }
+  }
+}
+
+impl Transport for UdpTransport {
+  fn exchange(&mut self, server: SocketAddr, query: &MessageProperties) -> io::Result<MessageProperties> {
+    let socket = bind_random_port(server)?;
+
+    let mut randomised = query.clone();
+    for question in &mut randomised.questions {
+      question.domain_name = randomise_case(&question.domain_name);
+    }
+
+    let request: Vec<u8> = randomised.clone().into();
+    let (mut response, _) = self.send(&socket, server, &request, |response| {
+      response.header.id == query.header.id && echoes_question(&randomised, response)
+    })?;
+
+    restore_case(&mut response, &randomised, query);
+    Ok(response)
+  }
+}
+
+impl UdpTransport {
+  /// Sends the query signed with `key` and returns the response once its signature checks out.
+  /// The name keeps its casing, as the signature covers it and authenticates the server instead.
+  pub fn exchange_signed(
+    &mut self,
+    server: SocketAddr,
+    query: &MessageProperties,
+    key: &TsigKey,
+  ) -> io::Result<MessageProperties> {
+    let socket = bind_random_port(server)?;
+
+    let mut signed = query.clone();
+    let mut signer = TsigSigner::new(key.clone());
+    signer.sign(&mut signed, now());
+    let request: Vec<u8> = signed.into();
+    let (response, bytes) = self.send(&socket, server, &request, |response| {
+      response.header.id == query.header.id && echoes_question(query, response)
+    })?;
+
+    TsigVerifier::new(key.clone(), signer.mac())
+      .verify(&bytes, now())
+      .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error.to_string()))?;
+    Ok(response)
+  }
+
+  /// Sends the request and waits for a response from `server` that passes `accept`, returned along
+  /// with its octets. Datagrams that do not parse are dropped like those that do not match, so a
+  /// spoofed or truncated packet can neither end the wait nor fail the exchange.
+  fn send(
+    &self,
+    socket: &UdpSocket,
+    server: SocketAddr,
+    request: &[u8],
+    accept: impl Fn(&MessageProperties) -> bool,
+  ) -> io::Result<(MessageProperties, Vec<u8>)> {
+    socket.send_to(request, server)?;
+
+    let deadline = Instant::now() + self.timeout;
+    let mut buffer = vec![0; EdnsProperties::DefaultPayloadSize as usize];
+    loop {
+      let remaining = deadline.saturating_duration_since(Instant::now());
+      if remaining.is_zero() {
+        return Err(io::Error::new(io::ErrorKind::TimedOut, "No matching response"));
+      }
+      socket.set_read_timeout(Some(remaining))?;
+
+      let (size, source) = socket.recv_from(&mut buffer)?;
+      if source != server {
+        continue;
+      }
+
+      if let Ok(response) = MessageProperties::try_from(&buffer[..size])
+        && accept(&response)
+      {
+        return Ok((response, buffer[..size].to_vec()));
+      }
+    }
+  }
+}
+
+fn bind_random_port(server: SocketAddr) -> io::Result<UdpSocket> {
+  let host = match server {
+    SocketAddr::V4(_) => "0.0.0.0",
+    SocketAddr::V6(_) => "::",
+  };
+
+  let mut error = io::Error::new(io::ErrorKind::AddrInUse, "No free source port");
+  for _ in 0..MaxBindAttempts {
+    match UdpSocket::bind((host, random_range(1024..=u16::MAX))) {
+      Ok(socket) => return Ok(socket),
+      Err(reason) => error = reason,
+    }
+  }
+
+  Err(error)
+}
+
+/// Flips every letter of the name to a random case, as described by the DNS 0x20 draft.
+pub fn randomise_case(name: &DomainName) -> DomainName {
+  let labels = name.0.iter().map(|label| {
+    label
+      .chars()
+      .map(|char| match random::<bool>() {
+        true => char.to_ascii_uppercase(),
+        false => char.to_ascii_lowercase(),
+      })
+      .collect()
+  });
+
+  DomainName(labels.collect())
+}
+
+/// Puts the original casing back on the echoed question and on records owned by the query name.
+fn restore_case(response: &mut MessageProperties, randomised: &MessageProperties, query: &MessageProperties) {
+  for (sent, original) in randomised.questions.iter().zip(&query.questions) {
+    let records = response
+      .answers
+      .iter_mut()
+      .chain(&mut response.authorities)
+      .chain(&mut response.additionals);
+    for record in records.filter(|record| record.domain_name == sent.domain_name) {
+      record.domain_name = original.domain_name.clone();
+    }
+  }
+
+  response.questions = query.questions.clone();
+}
+
+fn echoes_question(query: &MessageProperties, response: &MessageProperties) -> bool {
+  response.questions.len() == query.questions.len()
+    && query.questions.iter().zip(&response.questions).all(|(sent, echoed)| {
+      sent.domain_name == echoed.domain_name
+        && sent.record_type == echoed.record_type
+        && sent.record_class == echoed.record_class
+    })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::thread;
+
+  fn query(name: &str) -> MessageProperties {
+    MessageProperties {
+      header: HeaderProperties {
+        id: 0x1234,
+        flags: Flags::Query,
+        question_count: 1,
+        answer_record_count: 0,
+        authority_record_count: 0,
+        additional_record_count: 0,
+      },
+      questions: vec![QuestionProperties {
+        domain_name: DomainName::from(name),
+        record_type: RecordType::Host,
+        record_class: RecordClass::Internet,
+      }],
+      answers: vec![],
+      authorities: vec![],
+      additionals: vec![],
+    }
+  }
+
+  #[test]
+  fn it_randomises_only_letter_case() {
+    let name = DomainName::from("a-very-long-label-with-many-letters.example.com");
+
+    let randomised = randomise_case(&name);
+
+    assert!(randomised.eq_ignore_case(&name));
+    assert_ne!(randomised, name);
+  }
+
+  #[test]
+  fn it_ignores_malformed_responses_and_different_question_casing() {
+    let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let address = server.local_addr().unwrap();
+
+    let upstream = thread::spawn(move || {
+      let mut buffer = vec![0; 512];
+      let mut sources = vec![];
+      for _ in 0..2 {
+        let (size, source) = server.recv_from(&mut buffer).unwrap();
+        let request = MessageProperties::try_from(&buffer[..size]).unwrap();
+        sources.push(source.port());
+
+        let truncated: Vec<u8> = request.clone().into();
+        server.send_to(&truncated[..truncated.len() - 3], source).unwrap();
+        server.send_to(&[0xff; 3], source).unwrap();
+
+        let mut spoofed = request.clone();
+        spoofed.header.flags = Flags::Response;
+        spoofed.questions[0].domain_name = spoofed.questions[0].domain_name.to_lowercase();
+        spoofed.header.answer_record_count = 1;
+        spoofed.answers.push(AnswerProperties {
+          domain_name: DomainName::from("abc.com"),
+          record_type: RecordType::Host,
+          record_class: RecordClass::Internet,
+          time_to_live: 60,
+          data: vec![6, 6, 6, 6],
+        });
+        let bytes: Vec<u8> = spoofed.into();
+        server.send_to(&bytes, source).unwrap();
+
+        let mut genuine = request.clone();
+        genuine.header.flags = Flags::Response;
+        let bytes: Vec<u8> = genuine.into();
+        server.send_to(&bytes, source).unwrap();
+      }
+      sources
+    });
+
+    let mut transport = UdpTransport::default();
+    let first = transport.exchange(address, &query("abcdefghijklmnop.com")).unwrap();
+    let second = transport.exchange(address, &query("abcdefghijklmnop.com")).unwrap();
+    let sources = upstream.join().unwrap();
+
+    assert!(first.answers.is_empty());
+    assert!(second.answers.is_empty());
+    assert_eq!(first.questions[0].domain_name, DomainName::from("abcdefghijklmnop.com"));
+    assert_ne!(sources[0], sources[1]);
+  }
+}
@@ -0,0 +1,792 @@
+//! DNSSEC validation of resolved answers (RFC 4035 section 5).
+//!
+//! The chain of trust is built top-down from the closest trust anchor: the anchor's DNSKEY set must
+//! be signed by an anchored key, and for every name below it on the way to the signer of the data
+//! the DS set is fetched. A verified DS set leads to the child's DNSKEY set, a verified proof of a
+//! delegation without DS makes everything below it insecure, and anything else leaves the name in
+//! its parent zone. Falling back to the parent never loosens validation, since data from a zone it
+//! missed is signed by keys the parent zone does not hold.
+//!
+//! Answers are secure when every RRset verifies with the keys of its signer, insecure when they lie
+//! below an unsigned delegation, and bogus otherwise. Negative answers need verified NSEC or NSEC3
+//! records proving the name or type missing. Verified DNSKEY sets and unsigned delegations are
+//! remembered for their TTL, and the denial records of secure negative answers go to the
+//! aggressive cache. Names below a negative trust anchor are insecure without any checks.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::dnssec::*;
+use crate::messages::*;
+use crate::resolver::{DenialCache, MaxCanonicalNames, Resolution, find_alias};
+
+/// Longest time a link of the chain of trust is remembered, whatever its TTL.
+const MaxTrustTtl: u32 = 24 * 60 * 60;
+
+/// The validation outcome of RFC 4033 section 5, ordered from best to worst so that the outcome of
+/// several RRsets is the worst of theirs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Security {
+  /// Every RRset is signed along a chain of trust from an anchor.
+  Secure,
+  /// The data lies below a delegation proven to be unsigned.
+  Insecure,
+  /// No trust anchor covers the data.
+  Indeterminate,
+  /// The data should be signed but signatures or proofs are missing or wrong.
+  Bogus,
+}
+
+/// Where a name stands in the chain of trust.
+#[derive(Clone, Debug)]
+enum Trust {
+  /// The name lies in the signed zone at `apex`, with its verified DNSKEY set.
+  Signed {
+    apex: DomainName,
+    keys: Vec<AnswerProperties>,
+  },
+  Unsigned(Security),
+}
+
+/// What the DS lookup for a name says about it.
+enum Link {
+  /// A signed zone starts here, with its verified DNSKEY set.
+  Signed(Vec<AnswerProperties>),
+  /// An unsigned zone starts here.
+  Unsigned,
+  /// The name stays in the zone above.
+  Within,
+}
+
+pub struct Validator {
+  pub anchors: TrustAnchors,
+  /// Verified DNSKEY sets by zone apex.
+  keys: HashMap<DomainName, (Vec<AnswerProperties>, Instant)>,
+  /// Delegations proven to be unsigned.
+  unsigned: HashMap<DomainName, Instant>,
+  /// Denials of existence validated so far, answering for the names they cover.
+  pub denials: DenialCache,
+}
+
+impl Validator {
+  pub fn new(anchors: TrustAnchors) -> Self {
+    Self {
+      anchors,
+      keys: HashMap::new(),
+      unsigned: HashMap::new(),
+      denials: DenialCache::new(),
+    }
+  }
+
+  /// The negative answer to `question` from denials validated earlier, unless a negative trust
+  /// anchor covers it.
+  pub fn synthesize(&mut self, question: &QuestionProperties) -> Option<Resolution> {
+    if self.anchors.is_negative(&question.domain_name, timestamp_now()) {
+      return None;
+    }
+
+    self.denials.synthesize(question)
+  }
+
+  /// Validates the answer to `question`, fetching the DS and DNSKEY sets the chain of trust needs
+  /// through `upstream`.
+  pub fn validate(
+    &mut self,
+    question: &QuestionProperties,
+    resolution: &Resolution,
+    upstream: &mut dyn FnMut(&QuestionProperties) -> Resolution,
+  ) -> Security {
+    let name = &question.domain_name;
+    if self.anchors.is_negative(name, timestamp_now()) {
+      return Security::Insecure;
+    }
+    if self.anchors.closest(name).is_none() {
+      return Security::Indeterminate;
+    }
+    let negative = match resolution.response_code {
+      ResponseCode::NoError => false,
+      ResponseCode::NameError => true,
+      _ => return Security::Indeterminate,
+    };
+
+    let mut security = Security::Secure;
+    for rrset in rrsets(&resolution.answers) {
+      security = security.max(self.validate_rrset(&rrset, resolution, upstream));
+    }
+
+    let mut target = name.clone();
+    for _ in 0..MaxCanonicalNames {
+      match find_alias(&resolution.answers, &target) {
+        Some((_, next)) => target = next,
+        None => break,
+      }
+    }
+    let answered = resolution.answers.iter().any(|answer| {
+      answer.domain_name.eq_ignore_case(&target)
+        && (answer.record_type == question.record_type || question.record_type == RecordType::Any)
+    });
+    if negative || !(answered || question.record_type == RecordType::CanonicalName) {
+      security = security.max(self.validate_denial(&target, question.record_type, negative, resolution, upstream));
+    }
+
+    security
+  }
+
+  fn validate_rrset(
+    &mut self,
+    rrset: &[AnswerProperties],
+    resolution: &Resolution,
+    upstream: &mut dyn FnMut(&QuestionProperties) -> Resolution,
+  ) -> Security {
+    let owner = &rrset[0].domain_name;
+    let signatures = signatures(&resolution.answers, owner, rrset[0].record_type);
+
+    let Some(signer) = signatures.iter().find_map(|rrsig| match rrsig.record_data() {
+      RecordData::Signature { signer, .. } => Some(signer),
+      _ => None,
+    }) else {
+      // CNAME records synthesized from a DNAME come unsigned (RFC 6672 section 5.3.1).
+      if rrset[0].record_type == RecordType::CanonicalName && is_synthesized(&rrset[0], &resolution.answers) {
+        return Security::Secure;
+      }
+
+      return match self.trust(owner, upstream) {
+        Trust::Signed { .. } => Security::Bogus,
+        Trust::Unsigned(security) => security,
+      };
+    };
+    if !owner.is_subdomain_of(&signer) {
+      return Security::Bogus;
+    }
+
+    match self.trust(&signer, upstream) {
+      Trust::Signed { apex, keys } if apex.eq_ignore_case(&signer) => match verify(rrset, &signatures, &keys) {
+        Some(labels) if (labels as usize) < owner.label_count() => {
+          let proofs = verified_denials(&resolution.authorities, &keys);
+          match proves_wildcard_expansion(&proofs, owner, labels as usize) {
+            true => Security::Secure,
+            false => Security::Bogus,
+          }
+        }
+        Some(_) => Security::Secure,
+        None => Security::Bogus,
+      },
+      Trust::Signed { .. } => Security::Bogus,
+      Trust::Unsigned(security) => security,
+    }
+  }
+
+  fn validate_denial(
+    &mut self,
+    name: &DomainName,
+    record_type: RecordType,
+    negative: bool,
+    resolution: &Resolution,
+    upstream: &mut dyn FnMut(&QuestionProperties) -> Resolution,
+  ) -> Security {
+    // DS records are denied by the zone above the cut.
+    let zone_name = match record_type {
+      RecordType::DelegationSigner => name.parent().unwrap_or_else(DomainName::root),
+      _ => name.clone(),
+    };
+    let (apex, keys) = match self.trust(&zone_name, upstream) {
+      Trust::Signed { apex, keys } => (apex, keys),
+      Trust::Unsigned(security) => return security,
+    };
+
+    let proofs = verified_denials(&resolution.authorities, &keys);
+    let proven = match negative {
+      true => proves_name_error(&proofs, name),
+      false => proves_no_data(&proofs, name, record_type),
+    };
+    match proven {
+      true => {
+        let soa = rrset(&resolution.authorities, &apex, RecordType::StartOfAuthority);
+        let soa_signatures = signatures(&resolution.authorities, &apex, RecordType::StartOfAuthority);
+        if verify(&soa, &soa_signatures, &keys).is_some() {
+          self.denials.insert(&apex, &soa, &proofs, &resolution.authorities);
+        }
+        Security::Secure
+      }
+      false if record_type == RecordType::DelegationSigner && proves_unsigned_delegation(&proofs, name) => {
+        Security::Insecure
+      }
+      false => Security::Bogus,
+    }
+  }
+
+  /// Follows the chain of trust from the closest anchor down to `name`.
+  fn trust(&mut self, name: &DomainName, upstream: &mut dyn FnMut(&QuestionProperties) -> Resolution) -> Trust {
+    if self.anchors.is_negative(name, timestamp_now()) {
+      return Trust::Unsigned(Security::Insecure);
+    }
+    let Some(anchor) = self.anchors.closest(name) else {
+      return Trust::Unsigned(Security::Indeterminate);
+    };
+    let mut keys = match self.cached_keys(&anchor) {
+      Some(keys) => keys,
+      None => match self.anchor_keys(&anchor, upstream) {
+        Some(keys) => keys,
+        None => return Trust::Unsigned(Security::Bogus),
+      },
+    };
+    let mut apex = anchor.clone();
+
+    for count in anchor.label_count() + 1..=name.label_count() {
+      let child = name.suffix(count);
+      if self.is_unsigned(&child) {
+        return Trust::Unsigned(Security::Insecure);
+      }
+      if let Some(cached) = self.cached_keys(&child) {
+        (apex, keys) = (child, cached);
+        continue;
+      }
+
+      match self.link(&keys, &child, upstream) {
+        Ok(Link::Signed(child_keys)) => (apex, keys) = (child, child_keys),
+        Ok(Link::Unsigned) => return Trust::Unsigned(Security::Insecure),
+        Ok(Link::Within) => {}
+        Err(security) => return Trust::Unsigned(security),
+      }
+    }
+
+    Trust::Signed { apex, keys }
+  }
+
+  /// The DNSKEY set of an anchored zone, when signed by a key the anchors trust. The anchors then
+  /// follow the key set through rollovers.
+  fn anchor_keys(
+    &mut self,
+    anchor: &DomainName,
+    upstream: &mut dyn FnMut(&QuestionProperties) -> Resolution,
+  ) -> Option<Vec<AnswerProperties>> {
+    let resolution = upstream(&question(anchor, RecordType::DnsKey));
+    let dnskeys = rrset(&resolution.answers, anchor, RecordType::DnsKey);
+    let signatures = signatures(&resolution.answers, anchor, RecordType::DnsKey);
+    let trusted: Vec<AnswerProperties> = dnskeys
+      .iter()
+      .filter(|dnskey| self.anchors.trusts(dnskey))
+      .cloned()
+      .collect();
+
+    verify(&dnskeys, &signatures, &trusted)?;
+    if self.anchors.track(anchor, &dnskeys, &signatures, timestamp_now())
+      && let Err(error) = self.anchors.save()
+    {
+      eprintln!("Failed to save trust anchors: {}", error);
+    }
+    self.remember_keys(anchor, &dnskeys);
+    Some(dnskeys)
+  }
+
+  /// Looks up the DS set of `child`, verified with the `keys` of the zone above it.
+  fn link(
+    &mut self,
+    keys: &[AnswerProperties],
+    child: &DomainName,
+    upstream: &mut dyn FnMut(&QuestionProperties) -> Resolution,
+  ) -> Result<Link, Security> {
+    let resolution = upstream(&question(child, RecordType::DelegationSigner));
+    let ds_set = rrset(&resolution.answers, child, RecordType::DelegationSigner);
+
+    if ds_set.is_empty() {
+      let proofs = verified_denials(&resolution.authorities, keys);
+      if !proves_unsigned_delegation(&proofs, child) {
+        return Ok(Link::Within);
+      }
+
+      let ttl = proofs.iter().map(|record| record.time_to_live).min().unwrap_or(0);
+      self.unsigned.insert(child.to_lowercase(), expiry(ttl));
+      return Ok(Link::Unsigned);
+    }
+
+    let ds_signatures = signatures(&resolution.answers, child, RecordType::DelegationSigner);
+    verify(&ds_set, &ds_signatures, keys).ok_or(Security::Bogus)?;
+    // A zone signed only with algorithms or digests not understood here counts as unsigned.
+    let usable: Vec<&AnswerProperties> = ds_set
+      .iter()
+      .filter(|ds| match ds.record_data() {
+        RecordData::DelegationSigner {
+          algorithm, digest_type, ..
+        } => {
+          is_supported_algorithm(algorithm)
+            && matches!(digest_type, DigestType::Sha1 | DigestType::Sha256 | DigestType::Sha384)
+        }
+        _ => false,
+      })
+      .collect();
+    if usable.is_empty() {
+      self
+        .unsigned
+        .insert(child.to_lowercase(), expiry(ds_set[0].time_to_live));
+      return Ok(Link::Unsigned);
+    }
+
+    let resolution = upstream(&question(child, RecordType::DnsKey));
+    let dnskeys = rrset(&resolution.answers, child, RecordType::DnsKey);
+    let key_signatures = signatures(&resolution.answers, child, RecordType::DnsKey);
+    let trusted: Vec<AnswerProperties> = dnskeys
+      .iter()
+      .filter(|dnskey| usable.iter().any(|ds| ds_matches(ds, dnskey)))
+      .cloned()
+      .collect();
+
+    verify(&dnskeys, &key_signatures, &trusted).ok_or(Security::Bogus)?;
+    self.remember_keys(child, &dnskeys);
+    Ok(Link::Signed(dnskeys))
+  }
+
+  fn cached_keys(&mut self, apex: &DomainName) -> Option<Vec<AnswerProperties>> {
+    let apex = apex.to_lowercase();
+    match self.keys.get(&apex) {
+      Some((keys, until)) if *until > Instant::now() => Some(keys.clone()),
+      Some(_) => {
+        self.keys.remove(&apex);
+        None
+      }
+      None => None,
+    }
+  }
+
+  fn remember_keys(&mut self, apex: &DomainName, keys: &[AnswerProperties]) {
+    let ttl = keys.iter().map(|key| key.time_to_live).min().unwrap_or(0);
+    self.keys.insert(apex.to_lowercase(), (keys.to_vec(), expiry(ttl)));
+  }
+
+  fn is_unsigned(&mut self, name: &DomainName) -> bool {
+    let name = name.to_lowercase();
+    match self.unsigned.get(&name) {
+      Some(until) if *until > Instant::now() => true,
+      Some(_) => {
+        self.unsigned.remove(&name);
+        false
+      }
+      None => false,
+    }
+  }
+}
+
+fn expiry(ttl: u32) -> Instant {
+  Instant::now() + Duration::from_secs(ttl.min(MaxTrustTtl) as u64)
+}
+
+fn question(name: &DomainName, record_type: RecordType) -> QuestionProperties {
+  QuestionProperties {
+    domain_name: name.clone(),
+    record_type,
+    record_class: RecordClass::Internet,
+  }
+}
+
+/// The records of `records` grouped by owner and type, signatures aside.
+fn rrsets(records: &[AnswerProperties]) -> Vec<Vec<AnswerProperties>> {
+  let mut rrsets: Vec<Vec<AnswerProperties>> = vec![];
+  for record in records
+    .iter()
+    .filter(|record| record.record_type != RecordType::Signature)
+  {
+    match rrsets.iter_mut().find(|rrset| {
+      rrset[0].record_type == record.record_type && rrset[0].domain_name.eq_ignore_case(&record.domain_name)
+    }) {
+      Some(rrset) => rrset.push(record.clone()),
+      None => rrsets.push(vec![record.clone()]),
+    }
+  }
+
+  rrsets
+}
+
+fn rrset(records: &[AnswerProperties], owner: &DomainName, record_type: RecordType) -> Vec<AnswerProperties> {
+  records
+    .iter()
+    .filter(|record| record.record_type == record_type && record.domain_name.eq_ignore_case(owner))
+    .cloned()
+    .collect()
+}
+
+/// The RRSIG records in `records` covering the `record_type` RRset at `owner`.
+fn signatures(records: &[AnswerProperties], owner: &DomainName, record_type: RecordType) -> Vec<AnswerProperties> {
+  records
+    .iter()
+    .filter(|record| record.record_type == RecordType::Signature && record.domain_name.eq_ignore_case(owner))
+    .filter(|record| matches!(record.record_data(), RecordData::Signature { type_covered, .. } if type_covered == record_type))
+    .cloned()
+    .collect()
+}
+
+/// The labels field of a signature over `rrset` made by one of `keys`, when there is one.
+fn verify(rrset: &[AnswerProperties], signatures: &[AnswerProperties], keys: &[AnswerProperties]) -> Option<u8> {
+  let now = timestamp_now();
+
+  signatures.iter().find_map(|rrsig| {
+    let RecordData::Signature { labels, .. } = rrsig.record_data() else {
+      return None;
+    };
+    keys
+      .iter()
+      .any(|key| verify_rrsig(rrsig, rrset, key, now))
+      .then_some(labels)
+  })
+}
+
+/// The NSEC and NSEC3 records in `authorities` signed by one of `keys`.
+fn verified_denials(authorities: &[AnswerProperties], keys: &[AnswerProperties]) -> Vec<AnswerProperties> {
+  rrsets(authorities)
+    .into_iter()
+    .filter(|rrset| matches!(rrset[0].record_type, RecordType::NextSecure | RecordType::NextSecure3))
+    .filter(|rrset| {
+      let signatures = signatures(authorities, &rrset[0].domain_name, rrset[0].record_type);
+      verify(rrset, &signatures, keys).is_some()
+    })
+    .flatten()
+    .collect()
+}
+
+/// Whether the CNAME record `cname` follows from a DNAME record among `answers`.
+fn is_synthesized(cname: &AnswerProperties, answers: &[AnswerProperties]) -> bool {
+  let RecordData::CanonicalName(target) = cname.record_data() else {
+    return false;
+  };
+
+  answers
+    .iter()
+    .filter(|answer| answer.record_type == RecordType::DelegationName)
+    .any(|dname| match dname.record_data() {
+      RecordData::DelegationName(redirect) => {
+        cname.domain_name.is_subdomain_of(&dname.domain_name)
+          && !cname.domain_name.eq_ignore_case(&dname.domain_name)
+          && cname
+            .domain_name
+            .replace_suffix(&dname.domain_name, &redirect)
+            .eq_ignore_case(&target)
+      }
+      _ => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::zones::{Catalog, Denial, SigningPolicy, Zone, parse_zone, sign_zone};
+
+  const Parent: &str = "\
+$TTL 3600
+@            SOA    ns hostmaster 1 7200 900 1209600 300
+             NS     ns
+ns           A      192.0.2.1
+www          A      192.0.2.2
+             TXT    \"parent\"
+*.wild       A      192.0.2.3
+secure       NS     ns.secure
+ns.secure    A      192.0.2.53
+insecure     NS     ns.insecure
+ns.insecure  A      192.0.2.54
+";
+
+  const Child: &str = "\
+$TTL 3600
+@            SOA    ns hostmaster 1 7200 900 1209600 300
+             NS     ns
+ns           A      192.0.2.53
+www          A      192.0.2.80
+";
+
+  /// Signed `example` with NSEC and its child `secure.example` with NSEC3, next to an unsigned
+  /// `insecure.example`, together with a trust anchor for `example`.
+  fn signed_zones() -> (Catalog, TrustAnchors) {
+    let parent = DomainName::from("example");
+    let child = DomainName::from("secure.example");
+    let keys = |origin: &DomainName| {
+      vec![
+        SigningKey::generate(
+          origin.clone(),
+          SecurityAlgorithm::EcdsaP256Sha256,
+          SigningKey::KeySigningKey,
+        )
+        .unwrap(),
+        SigningKey::generate(origin.clone(), SecurityAlgorithm::Ed25519, SigningKey::ZoneSigningKey).unwrap(),
+      ]
+    };
+    let ds = |key: &SigningKey| {
+      let digest = ds_digest(&key.owner, &key.dnskey(3600).data, DigestType::Sha256).unwrap();
+      format!("{} 13 2 {}", key.key_tag(), data_encoding::HEXUPPER.encode(&digest))
+    };
+
+    let parent_keys = keys(&parent);
+    let child_keys = keys(&child);
+    let parent_text = format!("{}secure DS {}\n", Parent, ds(&child_keys[0]));
+    let sign = |origin: &DomainName, text: &str, keys: Vec<SigningKey>, denial: &str| {
+      let zone = Zone::new(origin.clone(), parse_zone(text, origin).unwrap()).unwrap();
+      let policy = SigningPolicy::new(keys, Denial::parse(denial).unwrap());
+      sign_zone(&zone, &policy, timestamp_now()).unwrap()
+    };
+
+    let mut catalog = Catalog::new();
+    catalog.insert(sign(&parent, &parent_text, parent_keys.clone(), "nsec"));
+    catalog.insert(sign(&child, Child, child_keys, "nsec3:1:abcd"));
+    let insecure = DomainName::from("insecure.example");
+    catalog.insert(Zone::new(insecure.clone(), parse_zone(Child, &insecure).unwrap()).unwrap());
+
+    let anchors = TrustAnchors::parse(&format!("example. DS {}", ds(&parent_keys[0]))).unwrap();
+    (catalog, anchors)
+  }
+
+  fn question(name: &str, record_type: RecordType) -> QuestionProperties {
+    QuestionProperties {
+      domain_name: DomainName::from(name),
+      record_type,
+      record_class: RecordClass::Internet,
+    }
+  }
+
+  fn resolve(catalog: &Catalog, question: &QuestionProperties) -> Resolution {
+    let lookup = catalog.lookup(question, true);
+
+    Resolution {
+      response_code: lookup.response_code,
+      answers: lookup.answers,
+      authorities: lookup.authorities,
+    }
+  }
+
+  /// Validates the answer to `name` from the catalog, the way it was served.
+  fn validate(validator: &mut Validator, catalog: &Catalog, name: &str, record_type: RecordType) -> Security {
+    validate_with(validator, catalog, name, record_type, |_| {})
+  }
+
+  /// Validates the answer to `name` from the catalog after `tamper` changed it.
+  fn validate_with(
+    validator: &mut Validator,
+    catalog: &Catalog,
+    name: &str,
+    record_type: RecordType,
+    tamper: impl Fn(&mut Resolution),
+  ) -> Security {
+    let question = question(name, record_type);
+    let mut resolution = resolve(catalog, &question);
+    tamper(&mut resolution);
+
+    validator.validate(&question, &resolution, &mut |question| resolve(catalog, question))
+  }
+
+  #[test]
+  fn it_validates_answers_down_the_chain_of_trust() {
+    let (catalog, anchors) = signed_zones();
+    let mut validator = Validator::new(anchors);
+    let mut fetches = 0;
+    let question = question("www.secure.example", RecordType::Host);
+    let resolution = resolve(&catalog, &question);
+
+    let security = validator.validate(&question, &resolution, &mut |question| {
+      fetches += 1;
+      resolve(&catalog, question)
+    });
+    let first = fetches;
+    validator.validate(&question, &resolution, &mut |question| {
+      fetches += 1;
+      resolve(&catalog, question)
+    });
+
+    assert_eq!(security, Security::Secure);
+    // The DNSKEY set of the anchor, then the DS and DNSKEY sets of the child, then nothing.
+    assert_eq!(first, 3);
+    assert_eq!(fetches, first);
+    assert_eq!(
+      validate(&mut validator, &catalog, "www.example", RecordType::Host),
+      Security::Secure
+    );
+    assert_eq!(
+      validate(&mut validator, &catalog, "example", RecordType::DnsKey),
+      Security::Secure
+    );
+    assert_eq!(
+      validate(&mut validator, &catalog, "secure.example", RecordType::DelegationSigner),
+      Security::Secure
+    );
+  }
+
+  #[test]
+  fn it_marks_answers_below_unsigned_delegations_insecure() {
+    let (catalog, anchors) = signed_zones();
+    let mut validator = Validator::new(anchors);
+
+    assert_eq!(
+      validate(&mut validator, &catalog, "www.insecure.example", RecordType::Host),
+      Security::Insecure
+    );
+    assert_eq!(
+      validate(
+        &mut validator,
+        &catalog,
+        "insecure.example",
+        RecordType::DelegationSigner
+      ),
+      Security::Secure
+    );
+  }
+
+  #[test]
+  fn it_finds_tampered_and_stripped_answers_bogus() {
+    let (catalog, anchors) = signed_zones();
+    let mut validator = Validator::new(anchors);
+
+    let forged = validate_with(
+      &mut validator,
+      &catalog,
+      "www.secure.example",
+      RecordType::Host,
+      |resolution| {
+        resolution.answers[0].data = vec![6, 6, 6, 6];
+      },
+    );
+    let stripped = validate_with(
+      &mut validator,
+      &catalog,
+      "www.example",
+      RecordType::Host,
+      |resolution| {
+        resolution
+          .answers
+          .retain(|record| record.record_type != RecordType::Signature);
+      },
+    );
+
+    assert_eq!(forged, Security::Bogus);
+    assert_eq!(stripped, Security::Bogus);
+  }
+
+  #[test]
+  fn it_skips_validation_below_negative_trust_anchors() {
+    let (catalog, anchors) = signed_zones();
+    let mut validator = Validator::new(anchors);
+    validator
+      .anchors
+      .add_negative(DomainName::from("secure.example"), timestamp_now() + 60);
+
+    let forged = validate_with(
+      &mut validator,
+      &catalog,
+      "www.secure.example",
+      RecordType::Host,
+      |resolution| {
+        resolution.answers[0].data = vec![6, 6, 6, 6];
+      },
+    );
+
+    assert_eq!(forged, Security::Insecure);
+    assert_eq!(
+      validate(&mut validator, &catalog, "www.example", RecordType::Host),
+      Security::Secure
+    );
+  }
+
+  #[test]
+  fn it_validates_denials_of_existence() {
+    let (catalog, anchors) = signed_zones();
+    let mut validator = Validator::new(anchors);
+    let strip = |resolution: &mut Resolution| {
+      resolution
+        .authorities
+        .retain(|record| !matches!(record.record_type, RecordType::NextSecure | RecordType::NextSecure3));
+    };
+
+    assert_eq!(
+      validate(&mut validator, &catalog, "nope.example", RecordType::Host),
+      Security::Secure
+    );
+    assert_eq!(
+      validate(&mut validator, &catalog, "www.example", RecordType::MailExchange),
+      Security::Secure
+    );
+    assert_eq!(
+      validate(&mut validator, &catalog, "nope.secure.example", RecordType::Host),
+      Security::Secure
+    );
+    assert_eq!(
+      validate(&mut validator, &catalog, "www.secure.example", RecordType::Text),
+      Security::Secure
+    );
+    assert_eq!(
+      validate_with(&mut validator, &catalog, "nope.example", RecordType::Host, strip),
+      Security::Bogus
+    );
+    assert_eq!(
+      validate_with(&mut validator, &catalog, "nope.secure.example", RecordType::Host, strip),
+      Security::Bogus
+    );
+  }
+
+  #[test]
+  fn it_validates_wildcard_expansions() {
+    let (catalog, anchors) = signed_zones();
+    let mut validator = Validator::new(anchors);
+
+    let expanded = validate(&mut validator, &catalog, "host.wild.example", RecordType::Host);
+    let unproven = validate_with(
+      &mut validator,
+      &catalog,
+      "host.wild.example",
+      RecordType::Host,
+      |resolution| {
+        resolution.authorities.clear();
+      },
+    );
+
+    assert_eq!(expanded, Security::Secure);
+    assert_eq!(unproven, Security::Bogus);
+  }
+
+  #[test]
+  fn it_answers_covered_names_from_validated_denials() {
+    let (catalog, anchors) = signed_zones();
+    let mut validator = Validator::new(anchors);
+    let synthesize = |validator: &mut Validator, name: &str, record_type: RecordType| {
+      validator
+        .denials
+        .synthesize(&question(name, record_type))
+        .map(|resolution| resolution.response_code)
+    };
+
+    assert_eq!(synthesize(&mut validator, "nope.example", RecordType::Host), None);
+    validate(&mut validator, &catalog, "nope.example", RecordType::Host);
+    validate(&mut validator, &catalog, "www.example", RecordType::MailExchange);
+    validate(&mut validator, &catalog, "nope.secure.example", RecordType::Host);
+
+    let jam = synthesize(&mut validator, "jam.example", RecordType::Host);
+    let service = synthesize(&mut validator, "www.example", RecordType::Service);
+    let hashed = synthesize(&mut validator, "nope.secure.example", RecordType::Text);
+    let delegated = synthesize(&mut validator, "www.insecure.example", RecordType::Host);
+    let uncovered = synthesize(&mut validator, "ta.example", RecordType::Host);
+
+    assert_eq!(jam, Some(ResponseCode::NameError));
+    assert_eq!(service, Some(ResponseCode::NoError));
+    assert_eq!(hashed, Some(ResponseCode::NameError));
+    assert_eq!(delegated, None);
+    assert_eq!(uncovered, None);
+    assert_eq!(validator.denials.stats().synthesized_nxdomain, 2);
+    assert_eq!(validator.denials.stats().synthesized_nodata, 1);
+
+    let question = question("jam.example", RecordType::Host);
+    let resolution = validator.denials.synthesize(&question).unwrap();
+    assert_eq!(
+      validator.validate(&question, &resolution, &mut |question| resolve(&catalog, question)),
+      Security::Secure
+    );
+  }
+
+  #[test]
+  fn it_needs_an_anchor_trusting_the_zone_keys() {
+    let (catalog, _) = signed_zones();
+    let (_, other) = signed_zones();
+    let mut validator = Validator::new(other);
+
+    assert_eq!(
+      validate(&mut validator, &catalog, "www.example", RecordType::Host),
+      Security::Bogus
+    );
+    assert_eq!(
+      validate(&mut validator, &catalog, "www.test", RecordType::Host),
+      Security::Indeterminate
+    );
+  }
+}
@@ -0,0 +1,734 @@
+//! Turns a request into its responses, the same way for UDP and TCP. Only zone transfers answer
+//! with more than one message, and only over TCP. Requests signed with TSIG get signed responses.
+//! Questions for other servers are resolved under a lock of their own, so that waiting on them
+//! holds up neither the zones nor the clients they answer.
+
+use std::io;
+use std::net::SocketAddr;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+
+use crate::cookies::*;
+use crate::dnssec::timestamp_now;
+use crate::messages::*;
+use crate::resolver::*;
+use crate::server::{Secondary, announce};
+use crate::tsig::*;
+use crate::zones::*;
+
+pub enum Mode {
+  /// Answers from the zones in the catalog, refusing names outside of them.
+  Authoritative,
+  /// Passes questions outside the zones in the catalog on to an upstream resolver.
+  Forwarding(SocketAddr, CookieTransport<UdpTransport>),
+  /// Resolves questions outside the zones in the catalog iteratively from the root servers.
+  Recursive(RecursiveResolver<CookieTransport<UdpTransport>>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Protocol {
+  Udp,
+  Tcp,
+}
+
+pub struct Server {
+  pub catalog: Catalog,
+  pub cookies: ServerCookies,
+  /// Zones copied from a primary, which may announce changes with NOTIFY.
+  pub secondaries: Vec<Secondary>,
+  /// Hands the origins of notified secondary zones to the refresh loop.
+  pub notifications: Option<Sender<DomainName>>,
+  /// Keys accepted on signed requests.
+  pub keys: TsigKeys,
+  /// Answers the questions outside the catalog when forwarding or resolving recursively.
+  pub upstream: Option<Arc<Mutex<Upstream>>>,
+}
+
+/// What answers questions from other servers, along with what it keeps between them.
+pub struct Upstream {
+  pub mode: Mode,
+  /// Checks resolved answers with DNSSEC when set.
+  pub validator: Option<Validator>,
+}
+
+/// A response to send, or a query still to be answered from other servers.
+pub enum Prepared {
+  Done(Vec<MessageProperties>),
+  Upstream(Box<Pending>),
+}
+
+/// What finishing the response to a query takes once its questions are answered upstream.
+pub struct Pending {
+  request: MessageProperties,
+  response: MessageProperties,
+  edns: Option<EdnsProperties>,
+  cookie: Option<Vec<u8>>,
+  protocol: Protocol,
+  signer: Option<TsigSigner>,
+  upstream: Arc<Mutex<Upstream>>,
+}
+
+impl Server {
+  pub fn new(mode: Mode, catalog: Catalog, cookies: ServerCookies) -> Self {
+    let upstream = match mode {
+      Mode::Authoritative => None,
+      mode => Some(Arc::new(Mutex::new(Upstream { mode, validator: None }))),
+    };
+
+    Self {
+      catalog,
+      cookies,
+      secondaries: vec![],
+      notifications: None,
+      keys: TsigKeys::default(),
+      upstream,
+    }
+  }
+
+  /// Answers the request in `buffer`, which is kept as received for checking its TSIG record.
+  pub fn respond(&mut self, buffer: &[u8], source: SocketAddr, protocol: Protocol) -> Vec<MessageProperties> {
+    self.prepare(buffer, source, protocol).finish()
+  }
+
+  /// Answers what the server can by itself, leaving queries for the upstream pending, to be finished
+  /// once the server is no longer locked.
+  pub fn prepare(&mut self, buffer: &[u8], source: SocketAddr, protocol: Protocol) -> Prepared {
+    let request = &match MessageProperties::try_from(buffer) {
+      Ok(request) => request,
+      Err(error) => {
+        println!("Malformed request from {}: {}", source, error);
+        return Prepared::Done(format_error(buffer).into_iter().collect());
+      }
+    };
+    let edns = request.edns();
+    let opcode = request.header.operation_code();
+
+    let mut response = response_to(request);
+    let mut signer = match verify_request(&self.keys, buffer, now()) {
+      TsigOutcome::Unsigned => None,
+      TsigOutcome::Verified(signer) => Some(signer),
+      TsigOutcome::Reject(code, mut signer) => {
+        println!("Rejected signed request from {} with rcode {}", source, code);
+        return Prepared::Done(vec![finish(response, code, edns, None, protocol, signer.as_mut())]);
+      }
+    };
+    let cookie = match self.cookies.evaluate(edns.as_ref(), source.ip()) {
+      CookieOutcome::Proceed(cookie) => cookie,
+      CookieOutcome::Reject(code, cookie) => {
+        return Prepared::Done(vec![finish(response, code, edns, cookie, protocol, signer.as_mut())]);
+      }
+    };
+
+    let key = signer.as_ref().and_then(TsigSigner::key_name);
+    let transfer = request.questions.iter().any(|question| {
+      question.record_type == RecordType::ZoneTransfer || question.record_type == RecordType::IncrementalTransfer
+    });
+    let rcode = match opcode {
+      OperationCode::Query if transfer => {
+        let mut messages = self.transfer(request, source, key, protocol);
+        if let Some(signer) = &mut signer {
+          for message in &mut messages {
+            signer.sign(message, now());
+          }
+        }
+        return Prepared::Done(messages);
+      }
+      // Names in the zones served here are answered from them, only the others go upstream.
+      OperationCode::Query => match &self.upstream {
+        Some(upstream) if !self.serves(request) => {
+          return Prepared::Upstream(Box::new(Pending {
+            request: request.clone(),
+            response,
+            edns,
+            cookie,
+            protocol,
+            signer,
+            upstream: upstream.clone(),
+          }));
+        }
+        _ => self.answer(request, &mut response),
+      },
+      OperationCode::Notify => self.notified(request, source, key, &mut response),
+      OperationCode::Update => self.update(request, source, key),
+      _ => ResponseCode::NotImplemented,
+    };
+
+    Prepared::Done(vec![finish(response, rcode, edns, cookie, protocol, signer.as_mut())])
+  }
+
+  /// Fills in the records the zones hold for the questions, returning the response code.
+  /// Whether a question of the request falls in one of the zones served here.
+  fn serves(&self, request: &MessageProperties) -> bool {
+    request
+      .questions
+      .iter()
+      .any(|question| self.catalog.find(&question.domain_name).is_some())
+  }
+
+  fn answer(&self, request: &MessageProperties, response: &mut MessageProperties) -> u8 {
+    let mut rcode = ResponseCode::NoError;
+
+    let dnssec_ok = request.edns().is_some_and(|edns| edns.dnssec_ok);
+    let mut authoritative = !request.questions.is_empty();
+    for question in &request.questions {
+      let lookup = self.catalog.lookup(question, dnssec_ok);
+      if lookup.response_code != ResponseCode::NoError {
+        rcode = lookup.response_code;
+      }
+
+      authoritative &= lookup.authoritative;
+      response.answers.extend(lookup.answers);
+      response.authorities.extend(lookup.authorities);
+      response.additionals.extend(lookup.additionals);
+    }
+
+    if authoritative {
+      response.header.flags = response.header.flags | Flags::Authoritative;
+    }
+
+    rcode
+  }
+
+  /// Applies an RFC 2136 UPDATE from a client on the zone's update access list, by address or by
+  /// the TSIG key the request was signed with. Signed zones are signed again. The change is
+  /// journaled to disk before it is served, and announced to the secondaries.
+  fn update(&mut self, request: &MessageProperties, source: SocketAddr, key: Option<&DomainName>) -> u8 {
+    let [zone_section] = &request.questions[..] else {
+      return ResponseCode::FormatError;
+    };
+    if zone_section.record_type != RecordType::StartOfAuthority {
+      return ResponseCode::FormatError;
+    }
+    let Some(zone) = self.catalog.zone(&zone_section.domain_name) else {
+      return ResponseCode::NotAuthoritative;
+    };
+    let name = zone.origin.to_string();
+    let secondary = self
+      .secondaries
+      .iter()
+      .any(|secondary| secondary.origin.eq_ignore_case(&zone.origin));
+    if secondary || !zone.settings.allow_update.allows(source.ip(), key) {
+      println!("Refused update of {} from {}", name, source);
+      return ResponseCode::Refused;
+    }
+
+    let mut updated = match apply_update(zone, request) {
+      Ok(Some(updated)) => updated,
+      Ok(None) => return ResponseCode::NoError,
+      Err(rcode) => {
+        println!("Rejected update of {} from {} with rcode {}", name, source, rcode);
+        return rcode;
+      }
+    };
+
+    // The journal keeps what the master file would hold, without the records signing adds.
+    let change = match &zone.settings.signing {
+      None => ZoneChange::between(zone, &updated),
+      Some(policy) => {
+        let signed = match sign_zone(&updated, policy, timestamp_now()) {
+          Ok(signed) => signed,
+          Err(error) => {
+            eprintln!("Failed to sign update of {}: {}", name, error);
+            return ResponseCode::ServerFailure;
+          }
+        };
+        let change = ZoneChange::between(&unsigned(zone, policy), &unsigned(&signed, policy));
+        updated = signed;
+        change
+      }
+    };
+
+    if let Some(path) = &zone.settings.journal_file
+      && let Err(error) = append_journal(path, &change)
+    {
+      eprintln!("Failed to journal update of {}: {}", name, error);
+      return ResponseCode::ServerFailure;
+    }
+
+    println!("Updated {} to serial {} from {}", name, updated.serial(), source);
+    self.catalog.insert(updated.clone());
+    announce(&self.catalog, &updated);
+    ResponseCode::NoError
+  }
+
+  /// Acknowledges a NOTIFY from the primary of a secondary zone and schedules an immediate check
+  /// of its serial (RFC 1996 section 4.7). Announcements from other hosts, or not signed with the
+  /// zone's key when it has one, are refused.
+  fn notified(
+    &self,
+    request: &MessageProperties,
+    source: SocketAddr,
+    key: Option<&DomainName>,
+    response: &mut MessageProperties,
+  ) -> u8 {
+    let [question] = &request.questions[..] else {
+      return ResponseCode::FormatError;
+    };
+
+    let secondary = self
+      .secondaries
+      .iter()
+      .find(|secondary| secondary.origin.eq_ignore_case(&question.domain_name));
+    match secondary {
+      None => ResponseCode::NotAuthoritative,
+      Some(secondary)
+        if secondary.primary.ip() != source.ip()
+          || secondary
+            .settings
+            .key
+            .as_ref()
+            .is_some_and(|expected| !key.is_some_and(|key| key.eq_ignore_case(&expected.name))) =>
+      {
+        println!("Ignored NOTIFY for {} from {}", secondary.origin, source);
+        ResponseCode::Refused
+      }
+      Some(secondary) => {
+        println!("Received NOTIFY for {} from {}", secondary.origin, source);
+        if let Some(notifications) = &self.notifications {
+          let _ = notifications.send(secondary.origin.clone());
+        }
+        response.header.flags = response.header.flags | Flags::Authoritative;
+        ResponseCode::NoError
+      }
+    }
+  }
+
+  /// Streams a zone to a client on its transfer access list. Over UDP the client is only told to
+  /// retry over TCP.
+  fn transfer(
+    &self,
+    request: &MessageProperties,
+    source: SocketAddr,
+    key: Option<&DomainName>,
+    protocol: Protocol,
+  ) -> Vec<MessageProperties> {
+    let mut response = response_to(request);
+    let zone = match &request.questions[..] {
+      [question] => Ok(self.catalog.zone(&question.domain_name)),
+      _ => Err(ResponseCode::FormatError),
+    };
+
+    let rcode = match zone {
+      Err(rcode) => rcode,
+      Ok(None) => ResponseCode::NotAuthoritative,
+      Ok(Some(zone)) if !zone.settings.allow_transfer.allows(source.ip(), key) => {
+        println!("Refused transfer of {} to {}", zone.origin, source);
+        ResponseCode::Refused
+      }
+      Ok(Some(_)) if protocol == Protocol::Udp => {
+        response.header.flags = response.header.flags | Flags::Truncated;
+        ResponseCode::NoError
+      }
+      Ok(Some(zone)) => {
+        let messages = transfer(zone, request);
+        println!(
+          "Transferring {} serial {} to {} in {} messages",
+          zone.origin,
+          zone.serial(),
+          source,
+          messages.len()
+        );
+        return messages;
+      }
+    };
+
+    response.header.flags = response.header.flags.with_response_code(rcode);
+    vec![response]
+  }
+}
+
+/// Answers the request in `buffer` with `server` locked only for as long as it is needed, which
+/// leaves out waiting on other servers.
+pub fn respond_shared(
+  server: &Mutex<Server>,
+  buffer: &[u8],
+  source: SocketAddr,
+  protocol: Protocol,
+) -> Vec<MessageProperties> {
+  let prepared = server.lock().unwrap().prepare(buffer, source, protocol);
+  prepared.finish()
+}
+
+impl Prepared {
+  /// The responses, answering the questions upstream first when they are pending.
+  pub fn finish(self) -> Vec<MessageProperties> {
+    match self {
+      Prepared::Done(messages) => messages,
+      Prepared::Upstream(mut pending) => {
+        let rcode = pending
+          .upstream
+          .lock()
+          .unwrap()
+          .answer(&pending.request, &mut pending.response);
+        vec![finish(
+          pending.response,
+          rcode,
+          pending.edns,
+          pending.cookie,
+          pending.protocol,
+          pending.signer.as_mut(),
+        )]
+      }
+    }
+  }
+}
+
+impl Upstream {
+  /// How the recursive resolver has been minimising query names, when resolving recursively.
+  pub fn minimisation_stats(&self) -> Option<&MinimisationStats> {
+    match &self.mode {
+      Mode::Recursive(recursor) => Some(recursor.stats()),
+      _ => None,
+    }
+  }
+
+  /// How many negative answers have been synthesized from validated denials, when validating.
+  pub fn denial_stats(&self) -> Option<&AggressiveStats> {
+    self.validator.as_ref().map(|validator| validator.denials.stats())
+  }
+
+  /// Fills in the records resolved for the questions, returning the response code.
+  fn answer(&mut self, request: &MessageProperties, response: &mut MessageProperties) -> u8 {
+    let mut rcode = ResponseCode::NoError;
+
+    let dnssec_ok = request.edns().is_some_and(|edns| edns.dnssec_ok);
+    let upstream_dnssec_ok = dnssec_ok || self.validator.is_some();
+    // Clients setting CD validate for themselves and get the data unchecked (RFC 4035 section 3.2.2).
+    let mut validator = match request.header.is_checking_disabled() {
+      true => None,
+      false => self.validator.as_mut(),
+    };
+    let mut secure = validator.is_some() && !request.questions.is_empty();
+
+    match &mut self.mode {
+      Mode::Forwarding(resolver, transport) => {
+        let recursion_desired = request.header.is_recursion_desired();
+        for question in &request.questions {
+          let (resolution, security) = match synthesize(validator.as_deref_mut(), question) {
+            Some(resolution) => (resolution, Some(Security::Secure)),
+            None => {
+              let resolution = match forward(transport, *resolver, question, recursion_desired, upstream_dnssec_ok) {
+                Ok(upstream) => Resolution::from(upstream),
+                Err(error) => {
+                  eprintln!("Failed to forward query to {}: {}", resolver, error);
+                  rcode = ResponseCode::ServerFailure;
+                  secure = false;
+                  continue;
+                }
+              };
+
+              let security = validator.as_deref_mut().map(|validator| {
+                let mut upstream =
+                  |question: &QuestionProperties| match forward(transport, *resolver, question, true, true) {
+                    Ok(upstream) => Resolution::from(upstream),
+                    Err(_) => Resolution::failure(),
+                  };
+                validator.validate(question, &resolution, &mut upstream)
+              });
+              (resolution, security)
+            }
+          };
+
+          let code = add_resolution(response, question, resolution, security, dnssec_ok);
+          secure &= security == Some(Security::Secure);
+          if code != ResponseCode::NoError {
+            rcode = code;
+          }
+        }
+      }
+      Mode::Recursive(recursor) => {
+        response.header.flags = response.header.flags | Flags::RecursionAvailable;
+        for question in &request.questions {
+          let (resolution, security) = match synthesize(validator.as_deref_mut(), question) {
+            Some(resolution) => (resolution, Some(Security::Secure)),
+            None => {
+              let resolution = recursor.resolve(question);
+              let security = validator.as_deref_mut().map(|validator| {
+                let mut upstream = |question: &QuestionProperties| recursor.resolve(question);
+                validator.validate(question, &resolution, &mut upstream)
+              });
+              (resolution, security)
+            }
+          };
+
+          let code = add_resolution(response, question, resolution, security, dnssec_ok);
+          secure &= security == Some(Security::Secure);
+          if code != ResponseCode::NoError {
+            rcode = code;
+          }
+        }
+      }
+      // Servers without anything upstream answer from their zones alone.
+      Mode::Authoritative => return ResponseCode::Refused,
+    }
+
+    // Only clients showing they understand it get the AD bit (RFC 6840 section 5.8).
+    if secure && (dnssec_ok || request.header.is_authentic_data()) {
+      response.header.flags = response.header.flags | Flags::AuthenticData;
+    }
+
+    rcode
+  }
+}
+
+/// Passes `question` on to the upstream `resolver`, keeping only what is in bailiwick.
+fn forward(
+  transport: &mut CookieTransport<UdpTransport>,
+  resolver: SocketAddr,
+  question: &QuestionProperties,
+  recursion_desired: bool,
+  dnssec_ok: bool,
+) -> io::Result<MessageProperties> {
+  let mut payload = MessageProperties::query(question.domain_name.clone(), question.record_type)
+    .record_class(question.record_class)
+    .recursion_desired(recursion_desired);
+  if dnssec_ok {
+    payload = payload.dnssec_ok();
+  }
+  let payload = payload
+    .build()
+    .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+
+  let mut upstream = transport.exchange(resolver, &payload)?;
+  retain_in_bailiwick(&mut upstream, &DomainName::root(), &question.domain_name);
+  Ok(upstream)
+}
+
+/// The negative answer to `question` from the denials the validator has already checked, if any.
+fn synthesize(validator: Option<&mut Validator>, question: &QuestionProperties) -> Option<Resolution> {
+  validator?.synthesize(question)
+}
+
+/// Adds the records resolved for `question` to the response, returning their response code. Bogus
+/// data is withheld behind SERVFAIL (RFC 4035 section 5.5), and DNSSEC records only go to clients
+/// that set DO, apart from those asked for. The SOA of a negative answer goes to every client, which
+/// caches the answer by it (RFC 2308 section 5).
+fn add_resolution(
+  response: &mut MessageProperties,
+  question: &QuestionProperties,
+  resolution: Resolution,
+  security: Option<Security>,
+  dnssec_ok: bool,
+) -> u8 {
+  if security == Some(Security::Bogus) {
+    println!("Bogus answer for {}", question.domain_name);
+    return ResponseCode::ServerFailure;
+  }
+
+  let wanted = |record: &AnswerProperties| {
+    dnssec_ok
+      || record.record_type == question.record_type
+      || !matches!(
+        record.record_type,
+        RecordType::Signature | RecordType::NextSecure | RecordType::NextSecure3
+      )
+  };
+  response.answers.extend(resolution.answers.into_iter().filter(&wanted));
+  response
+    .authorities
+    .extend(resolution.authorities.into_iter().filter(&wanted));
+
+  resolution.response_code
+}
+
+/// An empty response echoing the ID, operation code, RD bit and questions of the request, or just
+/// the header fields when a question is too long to echo.
+fn response_to(request: &MessageProperties) -> MessageProperties {
+  MessageProperties::response_to(request).build().unwrap_or_else(|_| {
+    let header = MessageProperties {
+      header: request.header.clone(),
+      questions: vec![],
+      answers: vec![],
+      authorities: vec![],
+      additionals: vec![],
+    };
+    MessageProperties::response_to(&header)
+      .build()
+      .expect("Responses without questions are valid")
+  })
+}
+
+/// The FORMERR answer to a request that does not parse, when at least its header does and it is
+/// not itself a response (RFC 1035 section 4.1.1).
+fn format_error(buffer: &[u8]) -> Option<MessageProperties> {
+  let header = HeaderProperties::try_from(buffer)
+    .ok()
+    .filter(HeaderProperties::is_query)?;
+  let request = MessageProperties {
+    header,
+    questions: vec![],
+    answers: vec![],
+    authorities: vec![],
+    additionals: vec![],
+  };
+
+  MessageProperties::response_to(&request)
+    .response_code(ResponseCode::FormatError)
+    .build()
+    .ok()
+}
+
+/// Adds the OPT record, echoing the DO bit, when the request had one and fits the response into
+/// what the transport carries: the advertised EDNS payload size over UDP, clamped to 512..=1232,
+/// or 512 without EDNS. The TSIG record goes last, after room has been left for it.
+fn finish(
+  mut response: MessageProperties,
+  rcode: u8,
+  edns: Option<EdnsProperties>,
+  cookie: Option<Vec<u8>>,
+  protocol: Protocol,
+  signer: Option<&mut TsigSigner>,
+) -> MessageProperties {
+  if let Some(request) = &edns {
+    let mut options = EdnsProperties::new();
+    options.dnssec_ok = request.dnssec_ok;
+    if let Some(cookie) = cookie {
+      options.set_option(EdnsOption {
+        code: EdnsOption::Cookie,
+        data: cookie,
+      });
+    }
+    response.set_edns(options);
+  }
+  response.set_response_code(rcode);
+
+  let limit = match (protocol, edns) {
+    (Protocol::Tcp, _) => u16::MAX as usize,
+    (Protocol::Udp, Some(edns)) => (edns.udp_payload_size as usize).clamp(
+      MessageProperties::MaxUdpSize,
+      EdnsProperties::DefaultPayloadSize as usize,
+    ),
+    (Protocol::Udp, None) => MessageProperties::MaxUdpSize,
+  };
+  match signer {
+    Some(signer) => {
+      response.truncate(limit - signer.size_of());
+      signer.sign(&mut response, now());
+    }
+    None => response.truncate(limit),
+  }
+  response
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::macros::*;
+
+  #[test]
+  fn it_answers_malformed_requests_with_format_error() {
+    let mut server = Server::new(Mode::Authoritative, Catalog::new(), ServerCookies::with_secret([0; 16]));
+    let source = "192.0.2.1:53".parse().unwrap();
+
+    let responses = server.respond(&hex!["abcd010000010000000000000361626300"], source, Protocol::Udp);
+    let ignored = server.respond(&hex!["abcd8100000100000000000003616263"], source, Protocol::Udp);
+
+    assert_eq!(responses.len(), 1);
+    assert_eq!(responses[0].header.id, 0xabcd);
+    assert_eq!(responses[0].header.is_response(), true);
+    assert_eq!(responses[0].response_code(), ResponseCode::FormatError);
+    assert_eq!(responses[0].questions.len(), 0);
+    assert_eq!(ignored.len(), 0);
+    assert_eq!(server.respond(&hex!["abcd"], source, Protocol::Udp).len(), 0);
+  }
+
+  #[test]
+  fn it_keeps_negative_answers_cacheable_without_dnssec_records() {
+    let question = QuestionProperties {
+      domain_name: DomainName::from("missing.example.com"),
+      record_type: RecordType::Host,
+      record_class: RecordClass::Internet,
+    };
+    let record = |record_type, data: Vec<u8>| AnswerProperties {
+      domain_name: DomainName::from("example.com"),
+      record_type,
+      record_class: RecordClass::Internet,
+      time_to_live: 300,
+      data,
+    };
+    let resolution = Resolution {
+      response_code: ResponseCode::NameError,
+      answers: vec![],
+      authorities: vec![
+        record(RecordType::StartOfAuthority, vec![0; 22]),
+        record(RecordType::Signature, vec![0; 18]),
+        record(RecordType::NextSecure, vec![0, 0]),
+      ],
+    };
+    let added = |dnssec_ok| {
+      let mut response = response_to(
+        &MessageProperties::query("missing.example.com", RecordType::Host)
+          .build()
+          .unwrap(),
+      );
+      let rcode = add_resolution(&mut response, &question, resolution.clone(), None, dnssec_ok);
+      let types: Vec<RecordType> = response.authorities.iter().map(|record| record.record_type).collect();
+      (rcode, types)
+    };
+
+    assert_eq!(
+      added(false),
+      (ResponseCode::NameError, vec![RecordType::StartOfAuthority])
+    );
+    assert_eq!(added(true).1.len(), 3);
+  }
+
+  #[test]
+  fn it_answers_names_in_its_zones_next_to_the_upstream() {
+    let resolver = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    let mode = Mode::Forwarding(
+      resolver.local_addr().unwrap(),
+      CookieTransport::new(UdpTransport::default()),
+    );
+    let origin = DomainName::from("example.com");
+    let records = parse_zone("$TTL 60\n@ SOA ns h 1 1 1 1 1\nwww A 192.0.2.1\n", &origin).unwrap();
+    let mut catalog = Catalog::new();
+    catalog.insert(Zone::new(origin, records).unwrap());
+    let mut server = Server::new(mode, catalog, ServerCookies::with_secret([0; 16]));
+    let source = "192.0.2.1:53".parse().unwrap();
+    let query = |name| -> Vec<u8> { MessageProperties::query(name, RecordType::Host).build().unwrap().into() };
+
+    let local = server.prepare(&query("www.example.com"), source, Protocol::Udp);
+    let missing = server.prepare(&query("missing.example.com"), source, Protocol::Udp);
+    let outside = server.prepare(&query("example.net"), source, Protocol::Udp);
+
+    let Prepared::Done(local) = local else {
+      panic!("www.example.com went upstream")
+    };
+    assert_eq!(local[0].header.is_authoritative(), true);
+    assert_eq!(local[0].answers.len(), 1);
+    assert!(matches!(missing, Prepared::Done(responses) if responses[0].response_code() == ResponseCode::NameError));
+    assert!(matches!(outside, Prepared::Upstream(_)));
+  }
+
+  #[test]
+  fn it_releases_the_server_while_waiting_on_the_upstream() {
+    let resolver = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    let mode = Mode::Forwarding(
+      resolver.local_addr().unwrap(),
+      CookieTransport::new(UdpTransport::default()),
+    );
+    let server = Arc::new(Mutex::new(Server::new(
+      mode,
+      Catalog::new(),
+      ServerCookies::with_secret([0; 16]),
+    )));
+    let query: Vec<u8> = MessageProperties::query("example.com", RecordType::Host)
+      .build()
+      .unwrap()
+      .into();
+
+    let client = server.clone();
+    let responses =
+      std::thread::spawn(move || respond_shared(&client, &query, "192.0.2.1:53".parse().unwrap(), Protocol::Udp));
+    let mut buffer = [0; 512];
+    let (size, source) = resolver.recv_from(&mut buffer).unwrap();
+    let locked = server.try_lock().is_err();
+    let mut forwarded = MessageProperties::try_from(&buffer[..size]).unwrap();
+    forwarded.header.flags = forwarded.header.flags | Flags::Response;
+    resolver.send_to(&Vec::<u8>::from(forwarded), source).unwrap();
+
+    assert_eq!(locked, false);
+    assert_eq!(responses.join().unwrap()[0].response_code(), ResponseCode::NoError);
+  }
+}
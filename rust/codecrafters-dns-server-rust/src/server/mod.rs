@@ -0,0 +1,17 @@
+pub mod handler;
+pub mod notify;
+pub mod reload;
+pub mod resign;
+pub mod secondary;
+pub mod stats;
+pub mod tcp;
+pub mod udp;
+
+pub use handler::*;
+pub use notify::*;
+pub use reload::*;
+pub use resign::*;
+pub use secondary::*;
+pub use stats::*;
+pub use tcp::*;
+pub use udp::*;
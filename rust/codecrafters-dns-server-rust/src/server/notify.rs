@@ -0,0 +1,183 @@
+//! Outgoing NOTIFY (RFC 1996) announcing a new serial of a zone to its secondaries: the configured
+//! ones and the hosts named by the apex NS records, except the primary named in the SOA. Each
+//! secondary is notified from its own thread and asked again with a growing timeout until it
+//! acknowledges the announcement. Zones with a TSIG key send signed announcements.
+
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::thread;
+use std::time::Duration;
+
+use rand::random;
+
+use crate::messages::*;
+use crate::resolver::*;
+use crate::tsig::TsigKey;
+use crate::zones::*;
+
+const MaxNotifyAttempts: usize = 5;
+const InitialNotifyTimeout: Duration = Duration::from_secs(1);
+const NameServerPort: u16 = 53;
+
+/// Notifies every secondary of `zone` in the background.
+pub fn announce(catalog: &Catalog, zone: &Zone) {
+  let message = match notify_message(zone) {
+    Ok(message) => message,
+    Err(error) => {
+      eprintln!("NOTIFY for {} not sent: {}", zone.origin, error);
+      return;
+    }
+  };
+
+  for target in notify_targets(catalog, zone) {
+    let message = message.clone();
+    let name = zone.origin.to_string();
+    let key = zone.settings.key.clone();
+    thread::spawn(
+      move || match notify(target, &message, key.as_ref(), InitialNotifyTimeout) {
+        Ok(ResponseCode::NoError) => println!("NOTIFY for {} acknowledged by {}", name, target),
+        Ok(code) => println!("NOTIFY for {} answered by {} with rcode {}", name, target, code),
+        Err(error) => eprintln!("NOTIFY for {} not acknowledged by {}: {}", name, target, error),
+      },
+    );
+  }
+}
+
+/// The configured secondaries of the zone followed by the addresses of its NS hosts, as far as
+/// those are found in the zones served here.
+pub fn notify_targets(catalog: &Catalog, zone: &Zone) -> Vec<SocketAddr> {
+  let mut targets = zone.settings.also_notify.clone();
+
+  let primary = match zone.soa().record_data() {
+    RecordData::StartOfAuthority { primary, .. } => Some(primary),
+    _ => None,
+  };
+  for server in zone.rrset(&zone.origin, RecordType::NameServer) {
+    let RecordData::NameServer(host) = server.record_data() else {
+      continue;
+    };
+    if primary.as_ref().is_some_and(|primary| primary.eq_ignore_case(&host)) {
+      continue;
+    }
+
+    let Some(hosting) = catalog.find(&host) else {
+      continue;
+    };
+    let addresses = hosting
+      .rrset(&host, RecordType::Host)
+      .into_iter()
+      .chain(hosting.rrset(&host, RecordType::Ipv6Host))
+      .filter_map(|record| match record.record_data() {
+        RecordData::Host(address) => Some(IpAddr::V4(address)),
+        RecordData::Ipv6Host(address) => Some(IpAddr::V6(address)),
+        _ => None,
+      });
+    for address in addresses {
+      let target = SocketAddr::new(address, NameServerPort);
+      if !targets.contains(&target) {
+        targets.push(target);
+      }
+    }
+  }
+
+  targets
+}
+
+/// Sends a NOTIFY until `target` answers, doubling the timeout after every attempt. Returns the
+/// response code of the answer.
+pub fn notify(
+  target: SocketAddr,
+  message: &MessageProperties,
+  key: Option<&TsigKey>,
+  timeout: Duration,
+) -> io::Result<u8> {
+  let mut transport = UdpTransport { timeout };
+  let mut error = io::Error::new(io::ErrorKind::TimedOut, "No attempts made");
+
+  for _ in 0..MaxNotifyAttempts {
+    let mut message = message.clone();
+    message.header.id = random::<u16>();
+
+    let response = match key {
+      Some(key) => transport.exchange_signed(target, &message, key),
+      None => transport.exchange(target, &message),
+    };
+    match response {
+      Ok(response) if response.header.operation_code() == OperationCode::Notify => {
+        return Ok(response.response_code());
+      }
+      Ok(_) => error = io::Error::new(io::ErrorKind::InvalidData, "Response is not a NOTIFY"),
+      Err(reason) => error = reason,
+    }
+    transport.timeout *= 2;
+  }
+
+  Err(error)
+}
+
+/// A NOTIFY for the zone carrying its current SOA (RFC 1996 section 3.7).
+fn notify_message(zone: &Zone) -> Result<MessageProperties, MessageError> {
+  MessageProperties::query(zone.origin.clone(), RecordType::StartOfAuthority)
+    .operation_code(OperationCode::Notify)
+    .recursion_desired(false)
+    .flags(Flags::Authoritative)
+    .answer(zone.soa().clone())
+    .build()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::cookies::ServerCookies;
+  use crate::server::{Mode, Secondary, Server, serve_udp};
+  use std::net::UdpSocket;
+  use std::sync::{Arc, Mutex, mpsc};
+
+  fn zone(origin: &str, text: &str) -> Zone {
+    let origin = DomainName::from(origin);
+
+    Zone::new(origin.clone(), parse_zone(text, &origin).unwrap()).unwrap()
+  }
+
+  #[test]
+  fn it_notifies_configured_secondaries_and_name_servers() {
+    let mut primary = zone(
+      "example.com",
+      "$TTL 60\n@ SOA ns1 h 1 1 1 1 1\n@ NS ns1\n@ NS ns2\n@ NS ns.example.net.\n@ NS ns.abc.com.\nns1 A 192.0.2.1\nns2 A 192.0.2.2\nns2 AAAA 2001:db8::2\n",
+    );
+    primary.settings.also_notify = vec!["192.0.2.2:53".parse().unwrap(), "192.0.2.9:5353".parse().unwrap()];
+    let mut catalog = Catalog::new();
+    catalog.insert(primary.clone());
+    catalog.insert(zone("example.net", "$TTL 60\n@ SOA ns h 1 1 1 1 1\nns A 192.0.2.3\n"));
+
+    let targets = notify_targets(&catalog, &primary);
+
+    let expected: Vec<SocketAddr> = ["192.0.2.2:53", "192.0.2.9:5353", "[2001:db8::2]:53", "192.0.2.3:53"]
+      .iter()
+      .map(|target| target.parse().unwrap())
+      .collect();
+    assert_eq!(targets, expected);
+  }
+
+  #[test]
+  fn it_reports_acknowledgements() {
+    let zone = zone("example.com", "$TTL 60\n@ SOA ns h 1 1 1 1 1\n");
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let address = socket.local_addr().unwrap();
+    let mut secondary = Server::new(Mode::Authoritative, Catalog::new(), ServerCookies::with_secret([0; 16]));
+    secondary.secondaries = vec![Secondary {
+      origin: DomainName::from("example.com"),
+      primary: "127.0.0.1:53".parse().unwrap(),
+      settings: ZoneSettings::default(),
+    }];
+    let (sender, receiver) = mpsc::channel();
+    secondary.notifications = Some(sender);
+    let secondary = Arc::new(Mutex::new(secondary));
+    thread::spawn(move || serve_udp(socket, secondary));
+
+    let acknowledged = notify(address, &notify_message(&zone).unwrap(), None, InitialNotifyTimeout).unwrap();
+
+    assert_eq!(acknowledged, ResponseCode::NoError);
+    assert_eq!(receiver.recv().unwrap(), DomainName::from("example.com"));
+  }
+}
@@ -0,0 +1,178 @@
+//! Primary zones are reloaded when their master file changes on disk. A new version only replaces
+//! the served one when its serial is newer (RFC 1982), after which the secondaries are notified.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::dnssec::timestamp_now;
+use crate::messages::*;
+use crate::server::{Server, announce};
+use crate::zones::*;
+
+/// How often master files are checked for changes.
+const ReloadInterval: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Debug)]
+pub struct ZoneSource {
+  pub origin: DomainName,
+  pub path: PathBuf,
+  pub settings: ZoneSettings,
+}
+
+impl ZoneSource {
+  /// Loads the master file, replays the updates journaled since its serial and signs the result
+  /// when the zone is signed online. A journal that cannot be read fails the load, since serving
+  /// the master file alone would drop the updates in it and journal new ones after the bad entry.
+  pub fn load(&self) -> Result<Zone, ZoneError> {
+    let error = |file: &Path, message| ZoneError {
+      file: file.display().to_string(),
+      line: 0,
+      message,
+    };
+
+    let records = load_zone_file(&self.path, &self.origin)?;
+    let mut zone = Zone::new(self.origin.clone(), records).map_err(|message| error(&self.path, message))?;
+    zone.settings = self.settings.clone();
+    let journal = self.journal_file();
+    zone.settings.journal_file = Some(journal.clone());
+
+    let changes = read_journal(&journal).map_err(|reason| error(&journal, reason.to_string()))?;
+    for change in changes {
+      if change.from_serial == zone.serial() {
+        zone = change.apply(&zone).map_err(|message| error(&journal, message))?;
+      }
+    }
+
+    if let Some(policy) = &self.settings.signing {
+      zone = sign_zone(&zone, policy, timestamp_now()).map_err(|message| error(&self.path, message))?;
+    }
+
+    Ok(zone)
+  }
+
+  /// The master file path with `.jnl` appended.
+  pub fn journal_file(&self) -> PathBuf {
+    let mut path = self.path.clone().into_os_string();
+    path.push(".jnl");
+    PathBuf::from(path)
+  }
+
+  fn modified(&self) -> Option<SystemTime> {
+    fs::metadata(&self.path).and_then(|metadata| metadata.modified()).ok()
+  }
+}
+
+/// Watches the master files of the zones forever.
+pub fn run_reloads(server: Arc<Mutex<Server>>, sources: Vec<ZoneSource>) {
+  let mut modified: Vec<Option<SystemTime>> = sources.iter().map(ZoneSource::modified).collect();
+
+  loop {
+    thread::sleep(ReloadInterval);
+
+    for (source, seen) in sources.iter().zip(&mut modified) {
+      let current = source.modified();
+      if current != *seen {
+        *seen = current;
+        reload(&server, source);
+      }
+    }
+  }
+}
+
+/// Loads the zone again, serving and announcing it when its serial moved forward. Returns whether
+/// the served zone was replaced.
+pub fn reload(server: &Mutex<Server>, source: &ZoneSource) -> bool {
+  let name = source.origin.to_string();
+  let zone = match source.load() {
+    Ok(zone) => zone,
+    Err(error) => {
+      eprintln!("Failed to reload zone {}: {}", name, error);
+      return false;
+    }
+  };
+
+  let mut server = server.lock().unwrap();
+  if let Some(served) = server.catalog.zone(&source.origin)
+    && !serial_newer(zone.serial(), served.serial())
+  {
+    println!(
+      "Kept zone {} at serial {}: reloaded serial {} is not newer",
+      name,
+      served.serial(),
+      zone.serial()
+    );
+    return false;
+  }
+
+  println!("Reloaded zone {} with serial {}", name, zone.serial());
+  server.catalog.insert(zone.clone());
+  announce(&server.catalog, &zone);
+  true
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::cookies::ServerCookies;
+  use crate::server::Mode;
+
+  #[test]
+  fn it_replaces_zones_only_with_newer_serials() {
+    let path = std::env::temp_dir().join(format!("reload-{}.zone", std::process::id()));
+    let source = ZoneSource {
+      origin: DomainName::from("example.com"),
+      path: path.clone(),
+      settings: ZoneSettings::default(),
+    };
+    let server = Mutex::new(Server::new(
+      Mode::Authoritative,
+      Catalog::new(),
+      ServerCookies::with_secret([0; 16]),
+    ));
+    let reload_serial = |serial: u32| {
+      fs::write(&path, format!("$TTL 60\n@ SOA ns h {} 1 1 1 1\n", serial)).unwrap();
+      reload(&server, &source)
+    };
+
+    assert_eq!(reload_serial(5), true);
+    assert_eq!(reload_serial(4), false);
+    assert_eq!(reload_serial(6), true);
+    fs::remove_file(&path).unwrap();
+    assert_eq!(reload(&server, &source), false);
+
+    let served = server.lock().unwrap().catalog.zone(&source.origin).map(Zone::serial);
+    assert_eq!(served, Some(6));
+  }
+
+  #[test]
+  fn it_keeps_the_served_zone_when_the_journal_is_corrupt() {
+    let path = std::env::temp_dir().join(format!("reload-journal-{}.zone", std::process::id()));
+    let source = ZoneSource {
+      origin: DomainName::from("example.com"),
+      path: path.clone(),
+      settings: ZoneSettings::default(),
+    };
+    let server = Mutex::new(Server::new(
+      Mode::Authoritative,
+      Catalog::new(),
+      ServerCookies::with_secret([0; 16]),
+    ));
+    fs::write(&path, "$TTL 60\n@ SOA ns h 5 1 1 1 1\n").unwrap();
+    assert_eq!(reload(&server, &source), true);
+
+    fs::write(&path, "$TTL 60\n@ SOA ns h 6 1 1 1 1\n").unwrap();
+    fs::write(source.journal_file(), [0, 0, 0, 2, 1, 2]).unwrap();
+    let reloaded = reload(&server, &source);
+    let error = source.load().unwrap_err();
+    fs::remove_file(source.journal_file()).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(reloaded, false);
+    assert_eq!(error.file, source.journal_file().display().to_string());
+    let served = server.lock().unwrap().catalog.zone(&source.origin).map(Zone::serial);
+    assert_eq!(served, Some(5));
+  }
+}
@@ -0,0 +1,76 @@
+//! Zones signed online are signed again before their signatures expire. Each new signing moves the
+//! serial forward, so the new signatures reach the secondaries through the usual NOTIFY and IXFR,
+//! and the serial change is journaled so the zone keeps its serial across restarts.
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::dnssec::timestamp_now;
+use crate::server::{Server, announce};
+use crate::zones::*;
+
+/// How often signature expirations are checked.
+const ResignInterval: Duration = Duration::from_secs(60);
+
+/// Watches the signatures of the zones served forever.
+pub fn run_resigning(server: Arc<Mutex<Server>>) {
+  loop {
+    thread::sleep(ResignInterval);
+    resign_expiring(&server, timestamp_now());
+  }
+}
+
+/// Signs again every primary zone whose signatures expire soon, returning how many were.
+pub fn resign_expiring(server: &Mutex<Server>, now: u32) -> usize {
+  let mut server = server.lock().unwrap();
+  let expiring: Vec<Zone> = server
+    .catalog
+    .zones()
+    .iter()
+    .filter(|zone| {
+      !server
+        .secondaries
+        .iter()
+        .any(|secondary| secondary.origin.eq_ignore_case(&zone.origin))
+    })
+    .filter(|zone| {
+      zone
+        .settings
+        .signing
+        .as_ref()
+        .is_some_and(|policy| needs_resigning(zone, policy, now))
+    })
+    .cloned()
+    .collect();
+
+  let mut count = 0;
+  for zone in expiring {
+    let name = zone.origin.to_string();
+    let Some(policy) = &zone.settings.signing else {
+      continue;
+    };
+    let signed = match resign(&zone, policy, now) {
+      Ok(signed) => signed,
+      Err(error) => {
+        eprintln!("Failed to sign zone {} again: {}", name, error);
+        continue;
+      }
+    };
+
+    if let Some(path) = &zone.settings.journal_file {
+      let change = ZoneChange::between(&unsigned(&zone, policy), &unsigned(&signed, policy));
+      if let Err(error) = append_journal(path, &change) {
+        eprintln!("Failed to journal signing of {}: {}", name, error);
+        continue;
+      }
+    }
+
+    println!("Signed zone {} again with serial {}", name, signed.serial());
+    server.catalog.insert(signed.clone());
+    announce(&server.catalog, &signed);
+    count += 1;
+  }
+
+  count
+}
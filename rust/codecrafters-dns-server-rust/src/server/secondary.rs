@@ -0,0 +1,468 @@
+//! Secondary zones, copied from a primary server and kept current following RFC 1034 section 4.3.5.
+//! The primary's SOA serial is polled every refresh interval, and a newer serial (RFC 1982) is
+//! pulled with IXFR, which the primary may answer with the whole zone. Failed checks are repeated
+//! every retry interval, and a zone that could not be refreshed for the expire interval is no
+//! longer served. A NOTIFY from the primary (RFC 1996) triggers the check right away. With a TSIG
+//! key configured for the zone, the requests to the primary are signed and so must its answers be.
+
+use std::io;
+use std::net::{SocketAddr, TcpStream};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::messages::*;
+use crate::resolver::*;
+use crate::server::{Server, announce, read_message, write_message};
+use crate::tsig::*;
+use crate::zones::*;
+
+/// How long to wait before trying again when no copy of the zone has been loaded yet.
+const InitialRetry: Duration = Duration::from_secs(10);
+const TransferTimeout: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Debug)]
+pub struct Secondary {
+  pub origin: DomainName,
+  pub primary: SocketAddr,
+  pub settings: ZoneSettings,
+}
+
+struct Timers {
+  refresh_at: Instant,
+  expire_at: Option<Instant>,
+}
+
+/// Keeps the secondary zones of `server` current until the notification channel closes. The
+/// channel carries the origins of zones the primary sent a NOTIFY for.
+pub fn run_secondaries(server: Arc<Mutex<Server>>, secondaries: Vec<Secondary>, notifications: Receiver<DomainName>) {
+  let mut timers: Vec<Timers> = secondaries
+    .iter()
+    .map(|_| Timers {
+      refresh_at: Instant::now(),
+      expire_at: None,
+    })
+    .collect();
+
+  loop {
+    let Some(next) = timers.iter().map(|timer| timer.refresh_at).min() else {
+      return;
+    };
+
+    match notifications.recv_timeout(next.saturating_duration_since(Instant::now())) {
+      Ok(origin) => {
+        for (secondary, timer) in secondaries.iter().zip(&mut timers) {
+          if secondary.origin.eq_ignore_case(&origin) {
+            timer.refresh_at = Instant::now();
+          }
+        }
+      }
+      Err(RecvTimeoutError::Timeout) => {}
+      Err(RecvTimeoutError::Disconnected) => return,
+    }
+
+    for (secondary, timer) in secondaries.iter().zip(&mut timers) {
+      if timer.refresh_at <= Instant::now() {
+        refresh_secondary(&server, secondary, timer);
+      }
+    }
+  }
+}
+
+fn refresh_secondary(server: &Mutex<Server>, secondary: &Secondary, timers: &mut Timers) {
+  let name = secondary.origin.to_string();
+  let current = server.lock().unwrap().catalog.zone(&secondary.origin).cloned();
+
+  let key = secondary.settings.key.as_ref();
+  let zone = match refresh(secondary.primary, &secondary.origin, current.as_ref(), key) {
+    Ok(Some(mut zone)) => {
+      println!(
+        "Transferred zone {} serial {} from {}",
+        name,
+        zone.serial(),
+        secondary.primary
+      );
+      zone.settings = secondary.settings.clone();
+      let mut server = server.lock().unwrap();
+      server.catalog.insert(zone.clone());
+      announce(&server.catalog, &zone);
+      zone
+    }
+    Ok(None) => match current {
+      Some(zone) => zone,
+      None => return,
+    },
+    Err(error) => {
+      eprintln!("Failed to refresh zone {} from {}: {}", name, secondary.primary, error);
+
+      let now = Instant::now();
+      timers.refresh_at = now + current.as_ref().map_or(InitialRetry, |zone| soa_timers(zone).1);
+      if timers.expire_at.is_some_and(|expire_at| expire_at <= now) {
+        println!("Zone {} expired", name);
+        server.lock().unwrap().catalog.remove(&secondary.origin);
+        timers.expire_at = None;
+      }
+      return;
+    }
+  };
+
+  let (refresh, _, expire) = soa_timers(&zone);
+  let now = Instant::now();
+  timers.refresh_at = now + refresh;
+  timers.expire_at = Some(now + expire);
+}
+
+/// The refresh, retry and expire intervals of the zone's SOA.
+fn soa_timers(zone: &Zone) -> (Duration, Duration, Duration) {
+  match zone.soa().record_data() {
+    RecordData::StartOfAuthority {
+      refresh, retry, expire, ..
+    } => (
+      Duration::from_secs(refresh as u64),
+      Duration::from_secs(retry as u64),
+      Duration::from_secs(expire as u64),
+    ),
+    _ => (InitialRetry, InitialRetry, Duration::MAX),
+  }
+}
+
+/// Fetches a newer copy of the zone from `primary`, or `None` when `current` is up to date.
+pub fn refresh(
+  primary: SocketAddr,
+  origin: &DomainName,
+  current: Option<&Zone>,
+  key: Option<&TsigKey>,
+) -> io::Result<Option<Zone>> {
+  if let Some(current) = current {
+    let request = query(origin, RecordType::StartOfAuthority, vec![])?;
+    let response = match key {
+      Some(key) => UdpTransport::default().exchange_signed(primary, &request, key)?,
+      None => UdpTransport::default().exchange(primary, &request)?,
+    };
+    let serial = response.answers.iter().find_map(|record| match record.record_data() {
+      RecordData::StartOfAuthority { serial, .. } if record.domain_name.eq_ignore_case(origin) => Some(serial),
+      _ => None,
+    });
+    let Some(serial) = serial else {
+      return Err(invalid(format!(
+        "No SOA in response, rcode {}",
+        response.response_code()
+      )));
+    };
+
+    if !serial_newer(serial, current.serial()) {
+      return Ok(None);
+    }
+  }
+
+  let request = match current {
+    Some(current) => query(origin, RecordType::IncrementalTransfer, vec![current.soa().clone()])?,
+    None => query(origin, RecordType::ZoneTransfer, vec![])?,
+  };
+  let records = transfer_in(primary, &request, key)?;
+
+  apply_transfer(origin, current, records).map_err(invalid)
+}
+
+/// Sends a transfer request over TCP and collects the records of all response messages. A signed
+/// transfer must also end on a signed message.
+fn transfer_in(
+  primary: SocketAddr,
+  request: &MessageProperties,
+  key: Option<&TsigKey>,
+) -> io::Result<Vec<AnswerProperties>> {
+  let mut stream = TcpStream::connect_timeout(&primary, TransferTimeout)?;
+  stream.set_read_timeout(Some(TransferTimeout))?;
+  let mut signed = request.clone();
+  let mut verifier = key.map(|key| {
+    let mut signer = TsigSigner::new(key.clone());
+    signer.sign(&mut signed, now());
+    TsigVerifier::new(key.clone(), signer.mac())
+  });
+  let bytes: Vec<u8> = signed.into();
+  write_message(&mut stream, &bytes)?;
+
+  let incremental = request.questions[0].record_type == RecordType::IncrementalTransfer;
+  let mut records = vec![];
+  loop {
+    let Some(response) = read_message(&mut stream)? else {
+      return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Transfer ended early"));
+    };
+    if let Some(verifier) = &mut verifier {
+      verifier
+        .verify(&response, now())
+        .map_err(|error| invalid(error.to_string()))?;
+    }
+    let response = MessageProperties::try_from(response.as_slice())?;
+    if response.header.id != request.header.id {
+      return Err(invalid("Response ID does not match the request".to_string()));
+    }
+    if response.response_code() != ResponseCode::NoError {
+      return Err(invalid(format!(
+        "Transfer failed with rcode {}",
+        response.response_code()
+      )));
+    }
+
+    records.extend(response.answers);
+    if transfer_complete(&records, incremental) {
+      if verifier.is_some_and(|verifier| !verifier.is_complete()) {
+        return Err(invalid("Transfer ends on an unsigned message".to_string()));
+      }
+      return Ok(records);
+    }
+  }
+}
+
+/// Whether the records received so far end the transfer: a lone SOA answering an up to date IXFR,
+/// or the closing copy of the first SOA. Incremental answers repeat that SOA once more, as the
+/// start of the last batch of additions.
+fn transfer_complete(records: &[AnswerProperties], incremental: bool) -> bool {
+  let Some(RecordData::StartOfAuthority { serial, .. }) = records.first().map(AnswerProperties::record_data) else {
+    return !records.is_empty();
+  };
+  if records.len() == 1 {
+    return incremental;
+  }
+
+  let is_latest = |record: &AnswerProperties| matches!(record.record_data(), RecordData::StartOfAuthority { serial: latest, .. } if latest == serial);
+  let differences = incremental && records[1].record_type == RecordType::StartOfAuthority && !is_latest(&records[1]);
+  let closing = records[1..].iter().filter(|record| is_latest(record)).count();
+
+  closing >= if differences { 2 } else { 1 }
+}
+
+/// Builds the new version of the zone from transferred records, which are either the whole zone
+/// between two copies of the SOA or the RFC 1995 differences to `current`.
+fn apply_transfer(
+  origin: &DomainName,
+  current: Option<&Zone>,
+  records: Vec<AnswerProperties>,
+) -> Result<Option<Zone>, String> {
+  let Some(soa) = records
+    .first()
+    .filter(|record| record.record_type == RecordType::StartOfAuthority)
+  else {
+    return Err("Transfer does not start with an SOA record".to_string());
+  };
+  if records.len() == 1 {
+    return Ok(None);
+  }
+
+  let differences = records[1].record_type == RecordType::StartOfAuthority && records[1] != *soa;
+  let records = match current {
+    Some(current) if differences => {
+      let mut zone: Vec<AnswerProperties> = current
+        .records()
+        .filter(|record| record.record_type != RecordType::StartOfAuthority)
+        .cloned()
+        .collect();
+
+      let mut deleting = false;
+      for record in &records[1..records.len() - 1] {
+        if record.record_type == RecordType::StartOfAuthority {
+          deleting = !deleting;
+          continue;
+        }
+
+        match deleting {
+          true => {
+            let position = zone.iter().position(|existing| {
+              existing.domain_name.eq_ignore_case(&record.domain_name)
+                && existing.record_type == record.record_type
+                && existing.record_class == record.record_class
+                && existing.data == record.data
+            });
+            zone.remove(position.ok_or("Transfer deletes a record missing from the zone")?);
+          }
+          false => zone.push(record.clone()),
+        }
+      }
+
+      zone.push(soa.clone());
+      zone
+    }
+    _ => {
+      let mut zone = records;
+      zone.pop();
+      zone
+    }
+  };
+
+  Zone::new(origin.clone(), records).map(Some)
+}
+
+fn query(
+  origin: &DomainName,
+  record_type: RecordType,
+  authorities: Vec<AnswerProperties>,
+) -> io::Result<MessageProperties> {
+  let query = MessageProperties::query(origin.clone(), record_type).recursion_desired(false);
+
+  authorities
+    .into_iter()
+    .fold(query, MessageBuilder::authority)
+    .build()
+    .map_err(io::Error::from)
+}
+
+fn invalid(message: String) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::cookies::ServerCookies;
+  use crate::server::{Mode, Protocol, serve_tcp, serve_udp};
+  use std::net::{TcpListener, UdpSocket};
+  use std::sync::mpsc;
+  use std::thread;
+
+  fn zone(serial: u32, body: &str) -> Zone {
+    let origin = DomainName::from("example.com");
+    let text = format!("$TTL 60\n@ SOA ns h {} 3600 600 86400 60\n@ NS ns\n{}", serial, body);
+    let mut zone = Zone::new(origin.clone(), parse_zone(&text, &origin).unwrap()).unwrap();
+    zone.settings.allow_transfer = AccessList::any();
+    zone
+  }
+
+  /// Runs a server on UDP and TCP on the same local port.
+  fn start(server: Server) -> (Arc<Mutex<Server>>, SocketAddr) {
+    let server = Arc::new(Mutex::new(server));
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let address = socket.local_addr().unwrap();
+    let listener = TcpListener::bind(address).unwrap();
+
+    let udp_server = server.clone();
+    thread::spawn(move || serve_udp(socket, udp_server));
+    let tcp_server = server.clone();
+    thread::spawn(move || serve_tcp(listener, tcp_server));
+
+    (server, address)
+  }
+
+  fn server(catalog: Catalog) -> Server {
+    Server::new(Mode::Authoritative, catalog, ServerCookies::with_secret([0; 16]))
+  }
+
+  fn serial_of(server: &Mutex<Server>) -> Option<u32> {
+    let server = server.lock().unwrap();
+    server.catalog.zone(&DomainName::from("example.com")).map(Zone::serial)
+  }
+
+  #[test]
+  fn it_pulls_full_and_incremental_copies() {
+    let mut catalog = Catalog::new();
+    catalog.insert(zone(1, "www A 192.0.2.1\nold A 192.0.2.9\n"));
+    let (primary, address) = start(server(catalog));
+    let origin = DomainName::from("example.com");
+
+    let first = refresh(address, &origin, None, None).unwrap().unwrap();
+    let unchanged = refresh(address, &origin, Some(&first), None).unwrap();
+    primary
+      .lock()
+      .unwrap()
+      .catalog
+      .insert(zone(2, "www A 192.0.2.1\nnew A 192.0.2.10\n"));
+    let second = refresh(address, &origin, Some(&first), None).unwrap().unwrap();
+
+    assert_eq!(first.serial(), 1);
+    assert!(unchanged.is_none());
+    assert_eq!(second.serial(), 2);
+    assert_eq!(
+      second
+        .rrset(&DomainName::from("new.example.com"), RecordType::Host)
+        .len(),
+      1
+    );
+    assert!(second.records_at(&DomainName::from("old.example.com")).is_empty());
+    assert_eq!(second.records().count(), 4);
+  }
+
+  #[test]
+  fn it_streams_signed_transfers_to_clients_with_an_allowed_key() {
+    let keys = TsigKeys::parse("transfer hmac-sha256 c2VjcmV0\nother hmac-sha256 c2VjcmV0\n").unwrap();
+    let hosts: String = (0..1000).map(|host| format!("host{} A 192.0.2.1\n", host)).collect();
+    let mut signed_only = zone(1, &hosts);
+    signed_only.settings.allow_transfer = AccessList::parse("key:transfer").unwrap();
+    let mut catalog = Catalog::new();
+    catalog.insert(signed_only);
+    let mut primary = server(catalog);
+    primary.keys = keys.clone();
+    let (_, address) = start(primary);
+    let origin = DomainName::from("example.com");
+    let forged = TsigKey::new(DomainName::from("transfer"), Algorithm::HmacSha256, b"forged");
+
+    let signed = refresh(address, &origin, None, keys.find(&DomainName::from("transfer")));
+    let unsigned = refresh(address, &origin, None, None);
+    let other = refresh(address, &origin, None, keys.find(&DomainName::from("other")));
+    let bad_signature = refresh(address, &origin, None, Some(&forged));
+
+    assert_eq!(signed.unwrap().unwrap().records().count(), 1002);
+    assert!(unsigned.unwrap_err().to_string().contains("rcode 5"));
+    assert!(other.unwrap_err().to_string().contains("rcode 5"));
+    assert_eq!(
+      bad_signature.unwrap_err().to_string(),
+      TsigError::Rejected(16).to_string()
+    );
+  }
+
+  #[test]
+  fn it_refreshes_right_away_when_notified() {
+    let mut catalog = Catalog::new();
+    catalog.insert(zone(1, ""));
+    let (primary, primary_address) = start(server(catalog));
+
+    let (sender, receiver) = mpsc::channel();
+    let secondaries = vec![Secondary {
+      origin: DomainName::from("example.com"),
+      primary: primary_address,
+      settings: ZoneSettings::default(),
+    }];
+    let mut instance = server(Catalog::new());
+    instance.secondaries = secondaries.clone();
+    instance.notifications = Some(sender);
+    let (secondary, _) = start(instance);
+    let refresher = secondary.clone();
+    thread::spawn(move || run_secondaries(refresher, secondaries, receiver));
+
+    let wait_for = |serial: u32| {
+      let deadline = Instant::now() + Duration::from_secs(5);
+      while serial_of(&secondary) != Some(serial) && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(10));
+      }
+      serial_of(&secondary)
+    };
+    assert_eq!(wait_for(1), Some(1));
+
+    primary.lock().unwrap().catalog.insert(zone(2, "www A 192.0.2.1\n"));
+    let mut notify = query(&DomainName::from("example.com"), RecordType::StartOfAuthority, vec![]).unwrap();
+    notify.header.flags = Flags::from_operation_code(OperationCode::Notify);
+    let stranger = "192.0.2.1:53".parse().unwrap();
+    let notify: Vec<u8> = notify.into();
+    let ignored = secondary.lock().unwrap().respond(&notify, stranger, Protocol::Udp);
+    let acknowledged = secondary
+      .lock()
+      .unwrap()
+      .respond(&notify, primary_address, Protocol::Udp);
+
+    assert_eq!(ignored[0].header.response_code(), ResponseCode::Refused);
+    assert_eq!(acknowledged[0].header.response_code(), ResponseCode::NoError);
+    assert_eq!(acknowledged[0].header.operation_code(), OperationCode::Notify);
+    assert_eq!(wait_for(2), Some(2));
+  }
+
+  #[test]
+  fn it_recognises_the_end_of_a_transfer() {
+    let soa = |serial| zone(serial, "").soa().clone();
+    let host = zone(1, "www A 192.0.2.1\n").rrset(&DomainName::from("www.example.com"), RecordType::Host)[0].clone();
+
+    assert_eq!(transfer_complete(&[soa(2)], true), true);
+    assert_eq!(transfer_complete(&[soa(2)], false), false);
+    assert_eq!(transfer_complete(&[soa(2), host.clone()], false), false);
+    assert_eq!(transfer_complete(&[soa(2), host.clone(), soa(2)], false), true);
+    assert_eq!(transfer_complete(&[soa(2), soa(1), host.clone(), soa(2)], true), false);
+    assert_eq!(transfer_complete(&[soa(2), soa(1), soa(2), host, soa(2)], true), true);
+  }
+}
@@ -0,0 +1,82 @@
+//! The counters kept while resolving upstream, logged every few minutes so that how queries are
+//! minimised (RFC 9156) and how many negative answers come from validated denials (RFC 8198) can be
+//! followed on a running server.
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::server::Upstream;
+
+/// How often the counters are logged.
+const StatisticsInterval: Duration = Duration::from_secs(5 * 60);
+
+/// Logs the counters of `upstream` forever.
+pub fn run_statistics(upstream: Arc<Mutex<Upstream>>) {
+  loop {
+    thread::sleep(StatisticsInterval);
+    if let Some(line) = statistics(&upstream.lock().unwrap()) {
+      println!("{}", line);
+    }
+  }
+}
+
+/// The counters as one log line, when the mode keeps any.
+pub fn statistics(upstream: &Upstream) -> Option<String> {
+  let mut parts = vec![];
+  if let Some(stats) = upstream.minimisation_stats() {
+    parts.push(format!(
+      "QNAME minimisation: {} minimised, {} full, {} fallbacks ({} NXDOMAIN, {} errors, {} timeouts)",
+      stats.minimised_queries,
+      stats.full_queries,
+      stats.fallbacks(),
+      stats.nxdomain_fallbacks,
+      stats.error_fallbacks,
+      stats.timeout_fallbacks
+    ));
+  }
+  if let Some(stats) = upstream.denial_stats() {
+    parts.push(format!(
+      "Aggressive negative caching: {} NXDOMAIN and {} NODATA answers synthesized",
+      stats.synthesized_nxdomain, stats.synthesized_nodata
+    ));
+  }
+
+  (!parts.is_empty()).then(|| parts.join("; "))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::cookies::CookieTransport;
+  use crate::dnssec::TrustAnchors;
+  use crate::resolver::*;
+  use crate::server::Mode;
+
+  #[test]
+  fn it_summarises_the_counters_of_the_mode() {
+    let transport = || CookieTransport::new(UdpTransport::default());
+    let recursive = Upstream {
+      mode: Mode::Recursive(RecursiveResolver::new(transport(), vec![])),
+      validator: None,
+    };
+    let forwarding = Upstream {
+      mode: Mode::Forwarding("127.0.0.1:53".parse().unwrap(), transport()),
+      validator: None,
+    };
+    let validating = Upstream {
+      mode: Mode::Forwarding("127.0.0.1:53".parse().unwrap(), transport()),
+      validator: Some(Validator::new(TrustAnchors::default())),
+    };
+
+    assert_eq!(
+      statistics(&recursive),
+      Some("QNAME minimisation: 0 minimised, 0 full, 0 fallbacks (0 NXDOMAIN, 0 errors, 0 timeouts)".to_string())
+    );
+    assert_eq!(statistics(&forwarding), None);
+    assert_eq!(
+      statistics(&validating),
+      Some("Aggressive negative caching: 0 NXDOMAIN and 0 NODATA answers synthesized".to_string())
+    );
+  }
+}
@@ -0,0 +1,148 @@
+//! DNS over TCP, where every message is preceded by its length as 2 bytes (RFC 1035 section 4.2.2).
+//! A connection may carry several queries in a row and is closed after sitting idle.
+
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::server::{Protocol, Server, respond_shared};
+
+const IdleTimeout: Duration = Duration::from_secs(10);
+
+/// Reads one length prefixed message, or `None` when the peer closed the connection in between.
+pub fn read_message(stream: &mut impl Read) -> io::Result<Option<Vec<u8>>> {
+  let mut length = [0; 2];
+  match stream.read_exact(&mut length) {
+    Ok(()) => {}
+    Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+    Err(error) => return Err(error),
+  }
+
+  let mut message = vec![0; u16::from_be_bytes(length) as usize];
+  stream.read_exact(&mut message)?;
+  Ok(Some(message))
+}
+
+pub fn write_message(stream: &mut impl Write, message: &[u8]) -> io::Result<()> {
+  let length =
+    u16::try_from(message.len()).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Message too long"))?;
+
+  let mut framed = Vec::with_capacity(message.len() + 2);
+  framed.extend(length.to_be_bytes());
+  framed.extend(message);
+  stream.write_all(&framed)
+}
+
+/// Accepts connections forever, serving each on its own thread.
+pub fn serve_tcp(listener: TcpListener, server: Arc<Mutex<Server>>) {
+  for stream in listener.incoming() {
+    match stream {
+      Ok(stream) => {
+        let server = server.clone();
+        thread::spawn(move || {
+          if let Err(error) = serve_connection(stream, &server) {
+            eprintln!("TCP connection failed: {}", error);
+          }
+        });
+      }
+      Err(error) => eprintln!("Error accepting connection: {}", error),
+    }
+  }
+}
+
+fn serve_connection(mut stream: TcpStream, server: &Mutex<Server>) -> io::Result<()> {
+  let source: SocketAddr = stream.peer_addr()?;
+  stream.set_read_timeout(Some(IdleTimeout))?;
+
+  while let Some(request) = read_message(&mut stream)? {
+    println!("Received {} bytes from {} over TCP", request.len(), source);
+
+    let responses = respond_shared(server, &request, source, Protocol::Tcp);
+    for response in responses {
+      let response: Vec<u8> = response.into();
+      write_message(&mut stream, &response)?;
+    }
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::cookies::ServerCookies;
+  use crate::messages::*;
+  use crate::server::Mode;
+  use crate::zones::*;
+
+  #[test]
+  fn it_frames_messages_with_their_length() {
+    let mut buffer = vec![];
+    write_message(&mut buffer, &[1, 2, 3]).unwrap();
+
+    assert_eq!(buffer, [0, 3, 1, 2, 3]);
+    assert_eq!(read_message(&mut &buffer[..]).unwrap(), Some(vec![1, 2, 3]));
+    assert_eq!(read_message(&mut &[][..]).unwrap(), None);
+  }
+
+  #[test]
+  fn it_transfers_zones_only_to_allowed_clients() {
+    let origin = DomainName::from("example.com");
+    let records = parse_zone("$TTL 60\n@ SOA ns h 1 1 1 1 1\n@ NS ns\nns A 192.0.2.1\n", &origin).unwrap();
+    let mut allowed = Zone::new(origin.clone(), records).unwrap();
+    allowed.settings.allow_transfer = AccessList::parse("127.0.0.1").unwrap();
+    let mut denied = allowed.clone();
+    denied.origin = DomainName::from("example.net");
+    denied.settings.allow_transfer = AccessList::none();
+
+    let mut catalog = Catalog::new();
+    catalog.insert(allowed);
+    catalog.insert(denied);
+    let server = Server::new(Mode::Authoritative, catalog, ServerCookies::with_secret([0; 16]));
+    let server = Arc::new(Mutex::new(server));
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    thread::spawn(move || serve_tcp(listener, server));
+
+    let mut stream = TcpStream::connect(address).unwrap();
+    let mut transfer = |name: &str| {
+      let query = MessageProperties {
+        header: HeaderProperties {
+          id: 9,
+          flags: Flags::Query,
+          question_count: 1,
+          answer_record_count: 0,
+          authority_record_count: 0,
+          additional_record_count: 0,
+        },
+        questions: vec![QuestionProperties {
+          domain_name: DomainName::from(name),
+          record_type: RecordType::ZoneTransfer,
+          record_class: RecordClass::Internet,
+        }],
+        answers: vec![],
+        authorities: vec![],
+        additionals: vec![],
+      };
+      let query: Vec<u8> = query.into();
+      write_message(&mut stream, &query).unwrap();
+
+      let response = read_message(&mut stream).unwrap().unwrap();
+      MessageProperties::try_from(response.as_slice()).unwrap()
+    };
+
+    let transferred = transfer("example.com");
+    let refused = transfer("example.net");
+    let unknown = transfer("example.org");
+
+    let types: Vec<RecordType> = transferred.answers.iter().map(|record| record.record_type).collect();
+    assert_eq!(types.len(), 4);
+    assert_eq!(types[0], RecordType::StartOfAuthority);
+    assert_eq!(types[3], RecordType::StartOfAuthority);
+    assert_eq!(refused.header.response_code(), ResponseCode::Refused);
+    assert_eq!(unknown.header.response_code(), ResponseCode::NotAuthoritative);
+  }
+}
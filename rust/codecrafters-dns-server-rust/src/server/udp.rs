@@ -0,0 +1,80 @@
+//! DNS over UDP, one datagram per message. Queries waiting on other servers are finished on a thread
+//! of their own, so the next datagrams are answered meanwhile.
+
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::messages::*;
+use crate::server::{Prepared, Protocol, Server};
+
+/// Answers datagrams until the socket fails.
+pub fn serve_udp(socket: UdpSocket, server: Arc<Mutex<Server>>) {
+  let socket = Arc::new(socket);
+  let mut buffer = vec![0; EdnsProperties::DefaultPayloadSize as usize];
+
+  loop {
+    match socket.recv_from(&mut buffer) {
+      Ok((size, source)) => {
+        println!("Received {} bytes from {}", size, source);
+
+        let prepared = server.lock().unwrap().prepare(&buffer[..size], source, Protocol::Udp);
+        match prepared {
+          Prepared::Done(responses) => send(&socket, source, responses),
+          pending => {
+            let socket = socket.clone();
+            thread::spawn(move || send(&socket, source, pending.finish()));
+          }
+        }
+      }
+      Err(message) => {
+        eprintln!("Error receiving data: {}", message);
+        break;
+      }
+    }
+  }
+}
+
+fn send(socket: &UdpSocket, target: SocketAddr, responses: Vec<MessageProperties>) {
+  for response in responses {
+    let response: Vec<u8> = response.into();
+    if let Err(error) = socket.send_to(&response, target) {
+      eprintln!("Failed to send response to {}: {}", target, error);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::cookies::*;
+  use crate::resolver::UdpTransport;
+  use crate::server::Mode;
+  use crate::zones::Catalog;
+  use std::time::Duration;
+
+  #[test]
+  fn it_answers_other_datagrams_while_a_query_waits_upstream() {
+    let silent = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let mode = Mode::Forwarding(
+      silent.local_addr().unwrap(),
+      CookieTransport::new(UdpTransport::default()),
+    );
+    let server = Server::new(mode, Catalog::new(), ServerCookies::with_secret([0; 16]));
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let address = socket.local_addr().unwrap();
+    thread::spawn(move || serve_udp(socket, Arc::new(Mutex::new(server))));
+
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.set_read_timeout(Some(Duration::from_millis(500))).unwrap();
+    let query: Vec<u8> = Message::query("example.com", RecordType::Host).build().unwrap().into();
+    client.send_to(&query, address).unwrap();
+    silent.recv_from(&mut [0; 512]).unwrap();
+    client.send_to(&query[..query.len() - 2], address).unwrap();
+
+    let mut buffer = [0; 512];
+    let (size, _) = client.recv_from(&mut buffer).unwrap();
+    let response = MessageProperties::try_from(&buffer[..size]).unwrap();
+    assert_eq!(response.response_code(), ResponseCode::FormatError);
+  }
+}
@@ -0,0 +1,142 @@
+//! TSIG keys shared with other servers, read from a key file holding one key per line:
+//! `<name> <algorithm> <base64 secret>`, e.g. `transfer.example.com. hmac-sha256 c2VjcmV0`.
+//! Blank lines and lines starting with `;` or `#` are skipped.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use data_encoding::BASE64;
+use ring::hmac;
+
+use crate::messages::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+  HmacSha256,
+  HmacSha384,
+  HmacSha512,
+}
+
+impl Algorithm {
+  /// The algorithm named by an RFC 8945 section 6 algorithm name, e.g. `hmac-sha256.`.
+  pub fn from_name(name: &DomainName) -> Option<Self> {
+    match name.to_lowercase().0.join(".").as_str() {
+      "hmac-sha256" => Some(Algorithm::HmacSha256),
+      "hmac-sha384" => Some(Algorithm::HmacSha384),
+      "hmac-sha512" => Some(Algorithm::HmacSha512),
+      _ => None,
+    }
+  }
+
+  pub fn name(&self) -> DomainName {
+    DomainName::from(match self {
+      Algorithm::HmacSha256 => "hmac-sha256",
+      Algorithm::HmacSha384 => "hmac-sha384",
+      Algorithm::HmacSha512 => "hmac-sha512",
+    })
+  }
+
+  fn hmac(&self) -> hmac::Algorithm {
+    match self {
+      Algorithm::HmacSha256 => hmac::HMAC_SHA256,
+      Algorithm::HmacSha384 => hmac::HMAC_SHA384,
+      Algorithm::HmacSha512 => hmac::HMAC_SHA512,
+    }
+  }
+}
+
+#[derive(Clone)]
+pub struct TsigKey {
+  pub name: DomainName,
+  pub algorithm: Algorithm,
+  key: hmac::Key,
+}
+
+impl TsigKey {
+  pub fn new(name: DomainName, algorithm: Algorithm, secret: &[u8]) -> Self {
+    Self {
+      name,
+      algorithm,
+      key: hmac::Key::new(algorithm.hmac(), secret),
+    }
+  }
+
+  pub fn mac(&self, data: &[u8]) -> Vec<u8> {
+    hmac::sign(&self.key, data).as_ref().to_vec()
+  }
+
+  /// Checks a full length MAC in constant time.
+  pub fn verify(&self, data: &[u8], mac: &[u8]) -> bool {
+    hmac::verify(&self.key, data, mac).is_ok()
+  }
+}
+
+/// Leaves the secret out of logs.
+impl fmt::Debug for TsigKey {
+  fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+    formatter
+      .debug_struct("TsigKey")
+      .field("name", &self.name)
+      .field("algorithm", &self.algorithm)
+      .finish_non_exhaustive()
+  }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TsigKeys(Vec<TsigKey>);
+
+impl TsigKeys {
+  pub fn load(path: &Path) -> Result<Self, String> {
+    let text = fs::read_to_string(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+
+    Self::parse(&text).map_err(|error| format!("{}: {}", path.display(), error))
+  }
+
+  pub fn parse(text: &str) -> Result<Self, String> {
+    let mut keys = vec![];
+    for (number, line) in text.lines().enumerate().map(|(index, line)| (index + 1, line.trim())) {
+      if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+        continue;
+      }
+
+      let invalid = |reason: &str| format!("line {}: {}", number, reason);
+      let [name, algorithm, secret] = line.split_whitespace().collect::<Vec<_>>()[..] else {
+        return Err(invalid("expected <name> <algorithm> <base64 secret>"));
+      };
+      let algorithm = Algorithm::from_name(&DomainName::from(algorithm)).ok_or_else(|| invalid("unknown algorithm"))?;
+      let secret = BASE64
+        .decode(secret.as_bytes())
+        .map_err(|_| invalid("secret is not base64"))?;
+
+      keys.push(TsigKey::new(DomainName::from(name), algorithm, &secret));
+    }
+
+    Ok(TsigKeys(keys))
+  }
+
+  pub fn find(&self, name: &DomainName) -> Option<&TsigKey> {
+    self.0.iter().find(|key| key.name.eq_ignore_case(name))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn it_parses_key_files() {
+    let keys =
+      TsigKeys::parse("# transfers\ntransfer.example.com. HMAC-SHA512 c2VjcmV0\n\nupdate hmac-sha256 a2V5\n").unwrap();
+
+    let transfer = keys.find(&DomainName::from("Transfer.Example.com")).unwrap();
+    assert_eq!(transfer.algorithm, Algorithm::HmacSha512);
+    assert_eq!(transfer.mac(b"data").len(), 64);
+    assert_eq!(
+      keys.find(&DomainName::from("update")).unwrap().algorithm,
+      Algorithm::HmacSha256
+    );
+    assert!(TsigKeys::parse("key hmac-md5 c2VjcmV0").is_err());
+    assert!(TsigKeys::parse("key hmac-sha256 !!").is_err());
+  }
+}
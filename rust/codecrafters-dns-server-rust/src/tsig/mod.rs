@@ -0,0 +1,10 @@
+//! Transaction signatures (RFC 8945), authenticating requests and responses between servers
+//! sharing a secret key: zone transfers, UPDATE and NOTIFY.
+
+pub mod key;
+pub mod record;
+pub mod signer;
+
+pub use key::*;
+pub use record::*;
+pub use signer::*;
@@ -0,0 +1,147 @@
+//! TSIG pseudo-record (RFC 8945 section 4.2), the last record of the additional section:
+//! Name - the key name
+//! Type - 2 bytes - 250
+//! Class - 2 bytes - ANY
+//! TTL - 4 bytes - 0
+//! Data:
+//! Algorithm - uncompressed domain name
+//! Time signed - 6 bytes - Seconds since the epoch
+//! Fudge - 2 bytes - Seconds of clock skew allowed
+//! MAC size - 2 bytes
+//! MAC - variable
+//! Original ID - 2 bytes - The message ID when signed
+//! Error - 2 bytes - Extended response code
+//! Other length - 2 bytes
+//! Other data - variable - The server's time in BADTIME responses
+
+use crate::messages::*;
+use crate::tsig::TsigError;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TsigProperties {
+  pub key_name: DomainName,
+  pub algorithm: DomainName,
+  pub time_signed: u64,
+  pub fudge: u16,
+  pub mac: Vec<u8>,
+  pub original_id: u16,
+  pub error: u16,
+  pub other: Vec<u8>,
+}
+
+impl TsigProperties {
+  pub fn from_record(record: &AnswerProperties) -> Option<Self> {
+    let data = &record.data;
+    let (algorithm, offset) = DomainName::try_from_bytes(data, 0)?;
+    let read_u16 = |offset: usize| Some(u16::from_be_bytes(data.get(offset..offset + 2)?.try_into().ok()?));
+
+    let time_signed = data
+      .get(offset..offset + 6)?
+      .iter()
+      .fold(0, |time, byte| (time << 8) | *byte as u64);
+    let fudge = read_u16(offset + 6)?;
+    let mac_size = read_u16(offset + 8)? as usize;
+    let mac = data.get(offset + 10..offset + 10 + mac_size)?.to_vec();
+    let offset = offset + 10 + mac_size;
+    let original_id = read_u16(offset)?;
+    let error = read_u16(offset + 2)?;
+    let other_size = read_u16(offset + 4)? as usize;
+    let other = data.get(offset + 6..offset + 6 + other_size)?.to_vec();
+    if offset + 6 + other_size != data.len() {
+      return None;
+    }
+
+    Some(Self {
+      key_name: record.domain_name.clone(),
+      algorithm,
+      time_signed,
+      fudge,
+      mac,
+      original_id,
+      error,
+      other,
+    })
+  }
+
+  pub fn to_record(&self) -> AnswerProperties {
+    let mut data = self.algorithm.to_bytes();
+    data.extend(&self.time_signed.to_be_bytes()[2..]);
+    data.extend(self.fudge.to_be_bytes());
+    data.extend((self.mac.len() as u16).to_be_bytes());
+    data.extend(&self.mac);
+    data.extend(self.original_id.to_be_bytes());
+    data.extend(self.error.to_be_bytes());
+    data.extend((self.other.len() as u16).to_be_bytes());
+    data.extend(&self.other);
+
+    AnswerProperties {
+      domain_name: self.key_name.clone(),
+      record_type: RecordType::TransactionSignature,
+      record_class: RecordClass::Any,
+      time_to_live: 0,
+      data,
+    }
+  }
+}
+
+/// Splits a received message into its TSIG record and the bytes the MAC covers: the message
+/// without the record, with the additional count lowered and the original ID put back. Returns
+/// `Ok(None)` for unsigned messages, and fails when a TSIG record is malformed or not last.
+pub fn split_signature(buffer: &[u8]) -> Result<Option<(TsigProperties, Vec<u8>)>, TsigError> {
+  let message = MessageProperties::try_from(buffer).map_err(|_| TsigError::Malformed)?;
+  let signatures = message
+    .answers
+    .iter()
+    .chain(&message.authorities)
+    .chain(&message.additionals)
+    .filter(|record| record.record_type == RecordType::TransactionSignature)
+    .count();
+  match (signatures, message.additionals.last()) {
+    (0, _) => return Ok(None),
+    (1, Some(last)) if last.record_type == RecordType::TransactionSignature => {}
+    _ => return Err(TsigError::Malformed),
+  }
+  let tsig = TsigProperties::from_record(message.additionals.last().unwrap()).ok_or(TsigError::Malformed)?;
+
+  let mut offset = 12;
+  for _ in &message.questions {
+    offset = QuestionProperties::try_from_bytes(buffer, offset)
+      .map_err(|_| TsigError::Malformed)?
+      .1;
+  }
+  let records = message.answers.len() + message.authorities.len() + message.additionals.len();
+  for _ in 1..records {
+    offset = AnswerProperties::try_from_bytes(buffer, offset, "record")
+      .map_err(|_| TsigError::Malformed)?
+      .1;
+  }
+
+  let mut unsigned = buffer[..offset].to_vec();
+  unsigned[..2].copy_from_slice(&tsig.original_id.to_be_bytes());
+  unsigned[10..12].copy_from_slice(&(message.additionals.len() as u16 - 1).to_be_bytes());
+  Ok(Some((tsig, unsigned)))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn it_converts_twoway() {
+    let tsig = TsigProperties {
+      key_name: DomainName::from("key.example.com"),
+      algorithm: DomainName::from("hmac-sha256"),
+      time_signed: 0x0102_0304_0506,
+      fudge: 300,
+      mac: vec![7; 32],
+      original_id: 0xabcd,
+      error: ResponseCode::BadTime as u16,
+      other: vec![0, 0, 0, 0, 0, 1],
+    };
+
+    let record = tsig.to_record();
+
+    assert_eq!(record.data[13..19], [1, 2, 3, 4, 5, 6]);
+    assert_eq!(TsigProperties::from_record(&record), Some(tsig));
+  }
+}
@@ -0,0 +1,398 @@
+//! Signing and verifying messages following RFC 8945 sections 4.3 and 5. The MAC of a request
+//! covers the message and the TSIG variables; a response also covers the request MAC first. In a
+//! stream of messages, such as a zone transfer, every later message covers the previous MAC, the
+//! messages since then and only the time signed and fudge.
+
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::messages::*;
+use crate::tsig::{Algorithm, TsigKey, TsigKeys, TsigProperties, split_signature};
+
+/// Seconds the clocks of the signer and the verifier may differ by.
+pub const DefaultFudge: u16 = 300;
+/// How many unsigned messages may follow each other in a signed stream (RFC 8945 section 5.3.1).
+const MaxUnsignedMessages: usize = 99;
+
+/// Seconds since the epoch.
+pub fn now() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map_or(0, |duration| duration.as_secs())
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TsigError {
+  Malformed,
+  /// The message should have been signed but was not.
+  Unsigned,
+  /// The TSIG record names a key or algorithm that is not configured.
+  BadKey,
+  BadSignature,
+  BadTime,
+  /// The other side rejected our signature with this TSIG error.
+  Rejected(u16),
+}
+
+impl fmt::Display for TsigError {
+  fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      TsigError::Malformed => write!(formatter, "Malformed TSIG record"),
+      TsigError::Unsigned => write!(formatter, "Message is not signed"),
+      TsigError::BadKey => write!(formatter, "Unknown TSIG key"),
+      TsigError::BadSignature => write!(formatter, "TSIG signature does not match"),
+      TsigError::BadTime => write!(formatter, "TSIG time is outside the fudge"),
+      TsigError::Rejected(error) => write!(formatter, "Signature rejected with TSIG error {}", error),
+    }
+  }
+}
+
+/// Attaches TSIG records to a message or a stream of messages. For requests the signer starts
+/// without a previous MAC; for responses it starts from the MAC of the request.
+#[derive(Clone, Debug)]
+pub struct TsigSigner {
+  key_name: DomainName,
+  algorithm: DomainName,
+  /// Missing when answering BADKEY or BADSIG, which go out unsigned.
+  key: Option<TsigKey>,
+  fudge: u16,
+  error: u16,
+  other: Vec<u8>,
+  /// Repeats the time of a request answered with BADTIME instead of the current time.
+  time_signed: Option<u64>,
+  previous_mac: Vec<u8>,
+  first: bool,
+}
+
+impl TsigSigner {
+  pub fn new(key: TsigKey) -> Self {
+    Self {
+      key_name: key.name.clone(),
+      algorithm: key.algorithm.name(),
+      key: Some(key),
+      fudge: DefaultFudge,
+      error: 0,
+      other: vec![],
+      time_signed: None,
+      previous_mac: vec![],
+      first: true,
+    }
+  }
+
+  /// The signer of responses to a request verified with `key`.
+  fn responding(key: TsigKey, request: &TsigProperties) -> Self {
+    Self {
+      previous_mac: request.mac.clone(),
+      ..Self::new(key)
+    }
+  }
+
+  /// An unsigned TSIG record carrying `error`, for requests whose signature cannot be checked.
+  fn unsigned(request: &TsigProperties, error: u8) -> Self {
+    Self {
+      key_name: request.key_name.clone(),
+      algorithm: request.algorithm.clone(),
+      key: None,
+      fudge: request.fudge,
+      error: error as u16,
+      other: vec![],
+      time_signed: None,
+      previous_mac: vec![],
+      first: true,
+    }
+  }
+
+  /// The key the request was verified with, when it was.
+  pub fn key_name(&self) -> Option<&DomainName> {
+    match (&self.key, self.error) {
+      (Some(key), 0) => Some(&key.name),
+      _ => None,
+    }
+  }
+
+  /// The MAC of the last signed message.
+  pub fn mac(&self) -> &[u8] {
+    &self.previous_mac
+  }
+
+  /// Bytes the TSIG record adds to a message.
+  pub fn size_of(&self) -> usize {
+    let mac = self.key.as_ref().map_or(0, |key| key.mac(&[]).len());
+
+    self.key_name.size_of() + 10 + self.algorithm.size_of() + 16 + mac + self.other.len()
+  }
+
+  /// Appends the TSIG record to the message, which must not change afterwards.
+  pub fn sign(&mut self, message: &mut MessageProperties, now: u64) {
+    message.header.answer_record_count = message.answers.len() as u16;
+    message.header.authority_record_count = message.authorities.len() as u16;
+    message.header.additional_record_count = message.additionals.len() as u16;
+
+    let time_signed = self.time_signed.unwrap_or(now);
+    let mut tsig = TsigProperties {
+      key_name: self.key_name.clone(),
+      algorithm: self.algorithm.clone(),
+      time_signed,
+      fudge: self.fudge,
+      mac: vec![],
+      original_id: message.header.id,
+      error: self.error,
+      other: self.other.clone(),
+    };
+
+    if let Some(key) = &self.key {
+      let bytes: Vec<u8> = message.clone().into();
+      let digest = digest(&self.previous_mac, &bytes, &tsig, self.first);
+      tsig.mac = key.mac(&digest);
+      self.previous_mac = tsig.mac.clone();
+      self.first = false;
+    }
+
+    message.additionals.push(tsig.to_record());
+    message.header.additional_record_count += 1;
+  }
+}
+
+/// Checks the TSIG records of a message or a stream of messages signed with `key`.
+#[derive(Clone, Debug)]
+pub struct TsigVerifier {
+  key: TsigKey,
+  previous_mac: Vec<u8>,
+  first: bool,
+  /// Messages received since the last signed one, which the next MAC covers.
+  unsigned: Vec<u8>,
+  unsigned_count: usize,
+}
+
+impl TsigVerifier {
+  /// Verifies responses to a request sent with `request_mac`, or requests when it is empty.
+  pub fn new(key: TsigKey, request_mac: &[u8]) -> Self {
+    Self {
+      key,
+      previous_mac: request_mac.to_vec(),
+      first: true,
+      unsigned: vec![],
+      unsigned_count: 0,
+    }
+  }
+
+  /// Checks the next message, returning its TSIG record. Only the first message of a stream has to
+  /// be signed, as long as no more than 99 unsigned messages follow each other; those are accepted
+  /// as `None` and covered by the next MAC.
+  pub fn verify(&mut self, buffer: &[u8], now: u64) -> Result<Option<TsigProperties>, TsigError> {
+    let Some((tsig, message)) = split_signature(buffer)? else {
+      if self.first || self.unsigned_count == MaxUnsignedMessages {
+        return Err(TsigError::Unsigned);
+      }
+      self.unsigned.extend(buffer);
+      self.unsigned_count += 1;
+      return Ok(None);
+    };
+
+    if !tsig.key_name.eq_ignore_case(&self.key.name)
+      || Algorithm::from_name(&tsig.algorithm) != Some(self.key.algorithm)
+    {
+      return Err(TsigError::BadKey);
+    }
+    if tsig.error != 0 {
+      return Err(TsigError::Rejected(tsig.error));
+    }
+
+    let mut signed = std::mem::take(&mut self.unsigned);
+    signed.extend(message);
+    if !self
+      .key
+      .verify(&digest(&self.previous_mac, &signed, &tsig, self.first), &tsig.mac)
+    {
+      return Err(TsigError::BadSignature);
+    }
+    self.previous_mac = tsig.mac.clone();
+    self.first = false;
+    self.unsigned_count = 0;
+
+    if now.abs_diff(tsig.time_signed) > tsig.fudge as u64 {
+      return Err(TsigError::BadTime);
+    }
+    Ok(Some(tsig))
+  }
+
+  /// Whether the stream ended on a signed message.
+  pub fn is_complete(&self) -> bool {
+    !self.first && self.unsigned_count == 0
+  }
+}
+
+#[derive(Clone, Debug)]
+pub enum TsigOutcome {
+  /// The request carries no TSIG record.
+  Unsigned,
+  /// Answer normally, signing every response message.
+  Verified(TsigSigner),
+  /// Answer with only the response code, attaching the TSIG error when there is a signer.
+  Reject(u8, Option<TsigSigner>),
+}
+
+/// Handles the TSIG record of a request following RFC 8945 section 5.2: unknown keys are answered
+/// with an unsigned BADKEY and wrong MACs with an unsigned BADSIG, while a request outside the fudge
+/// gets a signed BADTIME carrying the server's time.
+pub fn verify_request(keys: &TsigKeys, buffer: &[u8], now: u64) -> TsigOutcome {
+  let tsig = match split_signature(buffer) {
+    Ok(Some((tsig, _))) => tsig,
+    Ok(None) => return TsigOutcome::Unsigned,
+    Err(_) => return TsigOutcome::Reject(ResponseCode::FormatError, None),
+  };
+
+  let reject = |signer| TsigOutcome::Reject(ResponseCode::NotAuthoritative, Some(signer));
+  let key = keys
+    .find(&tsig.key_name)
+    .filter(|key| Algorithm::from_name(&tsig.algorithm) == Some(key.algorithm));
+  let Some(key) = key else {
+    return reject(TsigSigner::unsigned(&tsig, ResponseCode::BadKey));
+  };
+
+  match TsigVerifier::new(key.clone(), &[]).verify(buffer, now) {
+    Ok(Some(tsig)) => TsigOutcome::Verified(TsigSigner::responding(key.clone(), &tsig)),
+    Err(TsigError::BadTime) => reject(TsigSigner {
+      error: ResponseCode::BadTime as u16,
+      other: now.to_be_bytes()[2..].to_vec(),
+      time_signed: Some(tsig.time_signed),
+      ..TsigSigner::responding(key.clone(), &tsig)
+    }),
+    Err(TsigError::Malformed) => TsigOutcome::Reject(ResponseCode::FormatError, None),
+    _ => reject(TsigSigner::unsigned(&tsig, ResponseCode::BadSignature)),
+  }
+}
+
+/// The data a MAC is computed over: the previous MAC prefixed with its size, the message, and the
+/// TSIG variables in canonical form, or only the timers after the first message of a stream.
+fn digest(previous_mac: &[u8], message: &[u8], tsig: &TsigProperties, first: bool) -> Vec<u8> {
+  let mut digest = vec![];
+  if !previous_mac.is_empty() {
+    digest.extend((previous_mac.len() as u16).to_be_bytes());
+    digest.extend(previous_mac);
+  }
+  digest.extend(message);
+
+  if first {
+    digest.extend(tsig.key_name.to_lowercase().to_bytes());
+    digest.extend(RecordClass::Any.0.to_be_bytes());
+    digest.extend(0u32.to_be_bytes());
+    digest.extend(tsig.algorithm.to_lowercase().to_bytes());
+  }
+  digest.extend(&tsig.time_signed.to_be_bytes()[2..]);
+  digest.extend(tsig.fudge.to_be_bytes());
+  if first {
+    digest.extend(tsig.error.to_be_bytes());
+    digest.extend((tsig.other.len() as u16).to_be_bytes());
+    digest.extend(&tsig.other);
+  }
+
+  digest
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const Now: u64 = 1_700_000_000;
+
+  fn key(secret: &[u8]) -> TsigKey {
+    TsigKey::new(DomainName::from("key.example.com"), Algorithm::HmacSha256, secret)
+  }
+
+  fn message(id: u16) -> MessageProperties {
+    MessageProperties {
+      header: HeaderProperties {
+        id,
+        flags: Flags::Query,
+        question_count: 1,
+        answer_record_count: 0,
+        authority_record_count: 0,
+        additional_record_count: 0,
+      },
+      questions: vec![QuestionProperties {
+        domain_name: DomainName::from("example.com"),
+        record_type: RecordType::ZoneTransfer,
+        record_class: RecordClass::Internet,
+      }],
+      answers: vec![],
+      authorities: vec![],
+      additionals: vec![],
+    }
+  }
+
+  fn signed_request(key: TsigKey, now: u64) -> (Vec<u8>, Vec<u8>) {
+    let mut signer = TsigSigner::new(key);
+    let mut request = message(7);
+    signer.sign(&mut request, now);
+
+    (request.into(), signer.mac().to_vec())
+  }
+
+  fn keys() -> TsigKeys {
+    TsigKeys::parse("key.example.com hmac-sha256 c2VjcmV0").unwrap()
+  }
+
+  fn error_of(response: &MessageProperties) -> u16 {
+    TsigProperties::from_record(response.additionals.last().unwrap())
+      .unwrap()
+      .error
+  }
+
+  #[test]
+  fn it_verifies_requests_and_signs_response_streams() {
+    let (request, request_mac) = signed_request(key(b"secret"), Now);
+
+    let TsigOutcome::Verified(mut signer) = verify_request(&keys(), &request, Now + 10) else {
+      panic!("Request not verified");
+    };
+    let mut responses = vec![message(7), message(7), message(7)];
+    for response in &mut responses {
+      signer.sign(response, Now + 10);
+    }
+
+    let mut verifier = TsigVerifier::new(key(b"secret"), &request_mac);
+    let results: Vec<bool> = responses
+      .into_iter()
+      .map(|response| verifier.verify(&Vec::<u8>::from(response), Now + 12).unwrap().is_some())
+      .collect();
+    assert_eq!(signer.key_name(), Some(&DomainName::from("key.example.com")));
+    assert_eq!(results, [true, true, true]);
+    assert_eq!(verifier.is_complete(), true);
+    let unsigned = TsigVerifier::new(key(b"secret"), &request_mac).verify(&Vec::<u8>::from(message(7)), Now);
+    assert_eq!(unsigned, Err(TsigError::Unsigned));
+  }
+
+  #[test]
+  fn it_rejects_unknown_keys_wrong_macs_and_stale_times() {
+    let (forged, _) = signed_request(key(b"forged"), Now);
+    let (stale, _) = signed_request(key(b"secret"), Now - 301);
+    let unknown = TsigKey::new(DomainName::from("other"), Algorithm::HmacSha256, b"secret");
+    let (unknown, _) = signed_request(unknown, Now);
+
+    let rejected = |request: &[u8]| {
+      let TsigOutcome::Reject(rcode, Some(mut signer)) = verify_request(&keys(), request, Now) else {
+        panic!("Request not rejected");
+      };
+      let mut response = message(7);
+      signer.sign(&mut response, Now);
+      (rcode, response)
+    };
+    let (forged_rcode, forged) = rejected(&forged);
+    let (_, unknown) = rejected(&unknown);
+    let (_, stale) = rejected(&stale);
+
+    assert_eq!(forged_rcode, ResponseCode::NotAuthoritative);
+    assert_eq!(error_of(&forged), ResponseCode::BadSignature as u16);
+    assert_eq!(error_of(&unknown), ResponseCode::BadKey as u16);
+    assert_eq!(error_of(&stale), ResponseCode::BadTime as u16);
+    let stale_tsig = TsigProperties::from_record(stale.additionals.last().unwrap()).unwrap();
+    assert_eq!(stale_tsig.time_signed, Now - 301);
+    assert_eq!(stale_tsig.other, Now.to_be_bytes()[2..]);
+    assert_eq!(stale_tsig.mac.len(), 32);
+    assert!(matches!(
+      verify_request(&keys(), &Vec::<u8>::from(message(7)), Now),
+      TsigOutcome::Unsigned
+    ));
+  }
+}
@@ -0,0 +1,111 @@
+//! Access lists, written as comma separated addresses, `address/prefix` networks and `key:<name>`
+//! entries admitting requests signed with that TSIG key, or `any` and `none`.
+
+use std::net::IpAddr;
+
+use crate::messages::DomainName;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AccessList {
+  networks: Vec<(IpAddr, u8)>,
+  keys: Vec<DomainName>,
+}
+
+impl AccessList {
+  pub fn none() -> Self {
+    Self::default()
+  }
+
+  pub fn any() -> Self {
+    AccessList {
+      networks: vec![("0.0.0.0".parse().unwrap(), 0), ("::".parse().unwrap(), 0)],
+      keys: vec![],
+    }
+  }
+
+  pub fn parse(text: &str) -> Result<Self, String> {
+    match text {
+      "any" => return Ok(Self::any()),
+      "none" => return Ok(Self::none()),
+      _ => {}
+    }
+
+    let mut list = Self::none();
+    for entry in text.split(',').map(str::trim) {
+      if let Some(key) = entry.strip_prefix("key:") {
+        list.keys.push(DomainName::from(key));
+        continue;
+      }
+
+      let invalid = || format!("Invalid address or network '{}'", entry);
+      let (address, prefix) = entry.split_once('/').unwrap_or((entry, ""));
+      let address: IpAddr = address.parse().map_err(|_| invalid())?;
+      let bits = match address {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+      };
+      let prefix = match prefix {
+        "" => bits,
+        prefix => prefix
+          .parse()
+          .ok()
+          .filter(|prefix| *prefix <= bits)
+          .ok_or_else(invalid)?,
+      };
+
+      list.networks.push((address, prefix));
+    }
+
+    Ok(list)
+  }
+
+  /// Whether a request from `address`, verified with the TSIG key `key` if signed, is admitted.
+  pub fn allows(&self, address: IpAddr, key: Option<&DomainName>) -> bool {
+    let signed = key.is_some_and(|key| self.keys.iter().any(|allowed| allowed.eq_ignore_case(key)));
+
+    signed
+      || self
+        .networks
+        .iter()
+        .any(|(network, prefix)| contains(*network, *prefix, address))
+  }
+}
+
+fn contains(network: IpAddr, prefix: u8, address: IpAddr) -> bool {
+  let (network, address, bits) = match (network, address.to_canonical()) {
+    (IpAddr::V4(network), IpAddr::V4(address)) => (network.to_bits() as u128, address.to_bits() as u128, 32),
+    (IpAddr::V6(network), IpAddr::V6(address)) => (network.to_bits(), address.to_bits(), 128),
+    _ => return false,
+  };
+  let mask = match prefix {
+    0 => 0,
+    prefix => u128::MAX << (bits - prefix as u32),
+  };
+
+  network & mask == address & mask
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn it_matches_addresses_and_networks() {
+    let list = AccessList::parse("192.0.2.1, 10.0.0.0/8,2001:db8::/32,key:transfer.example.com").unwrap();
+    let key = DomainName::from("Transfer.example.com");
+
+    assert_eq!(list.allows("192.0.2.1".parse().unwrap(), None), true);
+    assert_eq!(list.allows("192.0.2.2".parse().unwrap(), None), false);
+    assert_eq!(list.allows("10.20.30.40".parse().unwrap(), None), true);
+    assert_eq!(list.allows("::ffff:10.1.1.1".parse().unwrap(), None), true);
+    assert_eq!(list.allows("2001:db8:1::1".parse().unwrap(), None), true);
+    assert_eq!(AccessList::none().allows("127.0.0.1".parse().unwrap(), None), false);
+    assert_eq!(AccessList::any().allows("::1".parse().unwrap(), None), true);
+    assert_eq!(list.allows("192.0.2.9".parse().unwrap(), Some(&key)), true);
+    assert_eq!(
+      list.allows("192.0.2.9".parse().unwrap(), Some(&DomainName::from("other"))),
+      false
+    );
+    assert!(AccessList::parse("10.0.0.0/33").is_err());
+  }
+}
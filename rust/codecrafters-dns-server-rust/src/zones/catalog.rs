@@ -0,0 +1,228 @@
+//! The set of zones served authoritatively; queries go to the zone with the longest matching
+//! origin and are refused when no zone contains the name.
+
+use crate::messages::*;
+use crate::zones::*;
+
+#[derive(Clone, Debug, Default)]
+pub struct Catalog {
+  zones: Vec<Zone>,
+}
+
+impl Catalog {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Adds a zone, replacing any zone with the same origin. A replaced zone hands its journal on,
+  /// extended by the changes to the new version when the serial moved.
+  pub fn insert(&mut self, mut zone: Zone) {
+    if let Some(index) = self
+      .zones
+      .iter()
+      .position(|existing| existing.origin.eq_ignore_case(&zone.origin))
+    {
+      let existing = self.zones.remove(index);
+      zone.journal = existing.journal.clone();
+      if existing.serial() != zone.serial() {
+        zone.journal.record(ZoneChange::between(&existing, &zone));
+      }
+    }
+
+    self.zones.push(zone);
+  }
+
+  pub fn remove(&mut self, origin: &DomainName) -> Option<Zone> {
+    let index = self.zones.iter().position(|zone| zone.origin.eq_ignore_case(origin))?;
+
+    Some(self.zones.remove(index))
+  }
+
+  /// The zone with exactly the given origin.
+  pub fn zone(&self, origin: &DomainName) -> Option<&Zone> {
+    self.zones.iter().find(|zone| zone.origin.eq_ignore_case(origin))
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.zones.is_empty()
+  }
+
+  pub fn zones(&self) -> &[Zone] {
+    &self.zones
+  }
+
+  /// The zone closest to `name`, i.e. with the longest origin that `name` lies under.
+  pub fn find(&self, name: &DomainName) -> Option<&Zone> {
+    self
+      .zones
+      .iter()
+      .filter(|zone| name.is_subdomain_of(&zone.origin))
+      .max_by_key(|zone| zone.origin.label_count())
+  }
+
+  /// Answers the question, with signatures and denial proofs from signed zones for `dnssec_ok`.
+  pub fn lookup(&self, question: &QuestionProperties, dnssec_ok: bool) -> Lookup {
+    let name = &question.domain_name;
+    let zone = match question.record_type {
+      // The DS set of a child apex belongs to the parent zone when both are served here.
+      RecordType::DelegationSigner => match (self.find(name), name.parent()) {
+        (Some(child), Some(parent)) if child.origin.eq_ignore_case(name) => self.find(&parent).or(Some(child)),
+        (zone, _) => zone,
+      },
+      _ => self.find(name),
+    };
+
+    let Some(zone) = zone else {
+      return Lookup::refused();
+    };
+
+    let mut lookup = zone.lookup(name, question.record_type);
+    self.add_target_addresses(&mut lookup);
+    if dnssec_ok && zone.is_signed() {
+      zone.add_dnssec(name, question.record_type, &mut lookup);
+    }
+    lookup
+  }
+
+  /// Adds the addresses of hosts named by MX, SRV and NS answers to the additional section, as far
+  /// as they are found in the zones served here (RFC 1035 section 3.3, RFC 2782).
+  fn add_target_addresses(&self, lookup: &mut Lookup) {
+    let targets: Vec<DomainName> = lookup
+      .answers
+      .iter()
+      .filter_map(|answer| match answer.record_data() {
+        RecordData::MailExchange { exchange, .. } => Some(exchange),
+        RecordData::Service { target, .. } if !target.is_root() => Some(target),
+        RecordData::NameServer(target) => Some(target),
+        _ => None,
+      })
+      .collect();
+
+    for target in targets {
+      let Some(zone) = self.find(&target) else {
+        continue;
+      };
+
+      for record_type in [RecordType::Host, RecordType::Ipv6Host] {
+        for record in zone.rrset(&target, record_type) {
+          let known = lookup.additionals.iter().any(|additional| {
+            additional.record_type == record.record_type
+              && additional.domain_name.eq_ignore_case(&record.domain_name)
+              && additional.data == record.data
+          });
+          if !known {
+            lookup.additionals.push(record);
+          }
+        }
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn zone(origin: &str, text: &str) -> Zone {
+    let origin = DomainName::from(origin);
+
+    Zone::new(origin.clone(), parse_zone(text, &origin).unwrap()).unwrap()
+  }
+
+  fn question(name: &str) -> QuestionProperties {
+    QuestionProperties {
+      domain_name: DomainName::from(name),
+      record_type: RecordType::Host,
+      record_class: RecordClass::Internet,
+    }
+  }
+
+  #[test]
+  fn it_picks_the_closest_zone_and_refuses_the_rest() {
+    let mut catalog = Catalog::new();
+    catalog.insert(zone(
+      "example.com",
+      "$TTL 60\n@ SOA ns h 1 1 1 1 1\nwww.sub A 192.0.2.1\n",
+    ));
+    catalog.insert(zone(
+      "sub.example.com",
+      "$TTL 60\n@ SOA ns h 2 1 1 1 1\nwww A 192.0.2.2\n",
+    ));
+
+    let child = catalog.lookup(&question("www.sub.example.com"), false);
+    let outside = catalog.lookup(&question("www.abc.com"), false);
+
+    assert_eq!(child.answers[0].data, vec![192, 0, 2, 2]);
+    assert_eq!(outside.response_code, ResponseCode::Refused);
+    assert_eq!(outside.authoritative, false);
+  }
+
+  #[test]
+  fn it_adds_addresses_of_answer_targets() {
+    let mut catalog = Catalog::new();
+    catalog.insert(zone(
+      "example.com",
+      "$TTL 60\n@ SOA ns h 1 1 1 1 1\n@ MX 10 mail\n@ MX 20 mx.example.net.\n@ MX 30 mail\nmail A 192.0.2.25\nmail AAAA 2001:db8::25\n_sip._udp SRV 0 0 5060 mx.example.net.\n",
+    ));
+    catalog.insert(zone("example.net", "$TTL 60\n@ SOA ns h 1 1 1 1 1\nmx A 192.0.2.26\n"));
+
+    let exchanges = catalog.lookup(
+      &QuestionProperties {
+        record_type: RecordType::MailExchange,
+        ..question("example.com")
+      },
+      false,
+    );
+    let services = catalog.lookup(
+      &QuestionProperties {
+        record_type: RecordType::Service,
+        ..question("_sip._udp.example.com")
+      },
+      false,
+    );
+
+    let additionals: Vec<(String, RecordType)> = exchanges
+      .additionals
+      .iter()
+      .map(|record| (record.domain_name.0.join("."), record.record_type))
+      .collect();
+    assert_eq!(
+      additionals,
+      [
+        ("mail.example.com".into(), RecordType::Host),
+        ("mail.example.com".into(), RecordType::Ipv6Host),
+        ("mx.example.net".into(), RecordType::Host),
+      ]
+    );
+    assert_eq!(services.additionals[0].data, vec![192, 0, 2, 26]);
+  }
+
+  #[test]
+  fn it_answers_delegation_signers_from_the_parent_zone() {
+    let mut catalog = Catalog::new();
+    catalog.insert(zone(
+      "example.com",
+      "$TTL 60\n@ SOA ns h 1 1 1 1 1\nsub NS ns.sub\nsub DS 1 13 2 00ff\n",
+    ));
+    catalog.insert(zone("sub.example.com", "$TTL 60\n@ SOA ns h 2 1 1 1 1\n@ NS ns\n"));
+
+    let signer = catalog.lookup(
+      &QuestionProperties {
+        record_type: RecordType::DelegationSigner,
+        ..question("sub.example.com")
+      },
+      false,
+    );
+    let servers = catalog.lookup(
+      &QuestionProperties {
+        record_type: RecordType::NameServer,
+        ..question("sub.example.com")
+      },
+      false,
+    );
+
+    assert_eq!(signer.answers[0].record_type, RecordType::DelegationSigner);
+    assert_eq!(servers.authoritative, true);
+    assert_eq!(servers.answers[0].record_type, RecordType::NameServer);
+  }
+}
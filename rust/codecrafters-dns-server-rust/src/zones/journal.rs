@@ -0,0 +1,259 @@
+//! The history of changes to a zone, kept so that secondaries holding an older serial can be sent
+//! just the differences (RFC 1995). Every change is stored in the IXFR layout: the deleted records
+//! start with the old SOA and the added records start with the new SOA.
+//!
+//! Changes made by dynamic updates are also appended to a journal file next to the master file,
+//! so they survive restarts. Each entry is stored as:
+//! Length - 4 bytes - Size of the rest of the entry
+//! Serials - 8 bytes - Serial before and after the change
+//! Counts - 4 bytes - Number of deleted and of added records
+//! Records - variable - Deleted then added records in uncompressed wire format
+
+use std::collections::VecDeque;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::messages::*;
+use crate::zones::Zone;
+
+/// Older changes are forgotten; secondaries further behind get a full transfer instead.
+const MaxJournalChanges: usize = 100;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ZoneChange {
+  pub from_serial: u32,
+  pub to_serial: u32,
+  pub deleted: Vec<AnswerProperties>,
+  pub added: Vec<AnswerProperties>,
+}
+
+impl ZoneChange {
+  /// The records removed and added on the way from `old` to `new`.
+  pub fn between(old: &Zone, new: &Zone) -> Self {
+    let old_records: Vec<&AnswerProperties> = old.records().filter(|record| !is_soa(record)).collect();
+    let new_records: Vec<&AnswerProperties> = new.records().filter(|record| !is_soa(record)).collect();
+
+    let mut deleted = vec![old.soa().clone()];
+    deleted.extend(
+      old_records
+        .iter()
+        .filter(|record| !new_records.contains(record))
+        .map(|record| (*record).clone()),
+    );
+    let mut added = vec![new.soa().clone()];
+    added.extend(
+      new_records
+        .iter()
+        .filter(|record| !old_records.contains(record))
+        .map(|record| (*record).clone()),
+    );
+
+    Self {
+      from_serial: old.serial(),
+      to_serial: new.serial(),
+      deleted,
+      added,
+    }
+  }
+
+  /// The version of `zone` this change leads to.
+  pub fn apply(&self, zone: &Zone) -> Result<Zone, String> {
+    let mut records: Vec<AnswerProperties> = zone.records().filter(|record| !is_soa(record)).cloned().collect();
+    for deleted in self.deleted.iter().filter(|record| !is_soa(record)) {
+      let Some(position) = records.iter().position(|record| record == deleted) else {
+        return Err(format!("Change to serial {} deletes a missing record", self.to_serial));
+      };
+      records.remove(position);
+    }
+    records.extend(self.added.iter().cloned());
+
+    let mut applied = Zone::new(zone.origin.clone(), records)?;
+    applied.settings = zone.settings.clone();
+    applied.journal = zone.journal.clone();
+    applied.journal.record(self.clone());
+    Ok(applied)
+  }
+
+  fn to_bytes(&self) -> Vec<u8> {
+    let mut entry = vec![];
+    entry.extend(self.from_serial.to_be_bytes());
+    entry.extend(self.to_serial.to_be_bytes());
+    entry.extend((self.deleted.len() as u16).to_be_bytes());
+    entry.extend((self.added.len() as u16).to_be_bytes());
+    for record in self.deleted.iter().chain(&self.added) {
+      let record: Vec<u8> = record.clone().into();
+      entry.extend(record);
+    }
+
+    let mut bytes = (entry.len() as u32).to_be_bytes().to_vec();
+    bytes.extend(entry);
+    bytes
+  }
+
+  /// The change stored in `entry`, or `None` when it is cut short or holds a malformed record.
+  fn from_bytes(entry: &[u8]) -> Option<Self> {
+    let from_serial = u32::from_be_bytes(entry.get(0..4)?.try_into().ok()?);
+    let to_serial = u32::from_be_bytes(entry.get(4..8)?.try_into().ok()?);
+    let deleted_count = u16::from_be_bytes(entry.get(8..10)?.try_into().ok()?) as usize;
+    let added_count = u16::from_be_bytes(entry.get(10..12)?.try_into().ok()?) as usize;
+
+    let mut offset = 12;
+    let mut records = vec![];
+    for _ in 0..deleted_count + added_count {
+      let (record, next) = AnswerProperties::try_from_bytes(entry, offset, "journal").ok()?;
+      records.push(record);
+      offset = next;
+    }
+    let added = records.split_off(deleted_count);
+
+    Some(Self {
+      from_serial,
+      to_serial,
+      deleted: records,
+      added,
+    })
+  }
+}
+
+/// Appends a change to the journal file, creating it when missing.
+pub fn append_journal(path: &Path, change: &ZoneChange) -> io::Result<()> {
+  let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+  file.write_all(&change.to_bytes())?;
+  file.sync_data()
+}
+
+/// The changes stored in a journal file, none when it does not exist. An entry cut short by an
+/// interrupted write at the end of the file is ignored, while a corrupt one is an `InvalidData`
+/// error.
+pub fn read_journal(path: &Path) -> io::Result<Vec<ZoneChange>> {
+  let bytes = match fs::read(path) {
+    Ok(bytes) => bytes,
+    Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+    Err(error) => return Err(error),
+  };
+
+  let mut changes = vec![];
+  let mut offset = 0;
+  while offset + 4 <= bytes.len() {
+    let length = u32::from_be_bytes(bytes[offset..offset + 4].try_into().unwrap()) as usize;
+    let Some(entry) = bytes.get(offset + 4..offset + 4 + length) else {
+      break;
+    };
+
+    let change = ZoneChange::from_bytes(entry).ok_or_else(|| {
+      io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Corrupt journal entry at offset {}", offset),
+      )
+    })?;
+    changes.push(change);
+    offset += 4 + length;
+  }
+
+  Ok(changes)
+}
+
+fn is_soa(record: &AnswerProperties) -> bool {
+  record.record_type == RecordType::StartOfAuthority
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Journal {
+  changes: VecDeque<ZoneChange>,
+}
+
+impl Journal {
+  pub fn record(&mut self, change: ZoneChange) {
+    if self.changes.len() == MaxJournalChanges {
+      self.changes.pop_front();
+    }
+    self.changes.push_back(change);
+  }
+
+  pub fn changes(&self) -> impl Iterator<Item = &ZoneChange> {
+    self.changes.iter()
+  }
+
+  /// The unbroken sequence of changes leading from `serial` to the latest version, if the journal
+  /// still reaches back that far.
+  pub fn since(&self, serial: u32) -> Option<Vec<&ZoneChange>> {
+    let start = self.changes.iter().position(|change| change.from_serial == serial)?;
+
+    let changes: Vec<&ZoneChange> = self.changes.iter().skip(start).collect();
+    let connected = changes.windows(2).all(|pair| pair[0].to_serial == pair[1].from_serial);
+
+    connected.then_some(changes)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::zones::parse_zone;
+
+  fn zone(text: &str) -> Zone {
+    let origin = DomainName::from("example.com");
+
+    Zone::new(origin.clone(), parse_zone(text, &origin).unwrap()).unwrap()
+  }
+
+  #[test]
+  fn it_records_differences_between_versions() {
+    let first = zone("$TTL 60\n@ SOA ns h 1 1 1 1 1\nwww A 192.0.2.1\nmail A 192.0.2.25\n");
+    let second = zone("$TTL 60\n@ SOA ns h 2 1 1 1 1\nwww A 192.0.2.2\nmail A 192.0.2.25\n");
+    let third = zone("$TTL 60\n@ SOA ns h 3 1 1 1 1\nwww A 192.0.2.2\n");
+
+    let mut journal = Journal::default();
+    journal.record(ZoneChange::between(&first, &second));
+    journal.record(ZoneChange::between(&second, &third));
+
+    let changes = journal.since(1).unwrap();
+    assert_eq!(changes.len(), 2);
+    assert_eq!(changes[0].deleted[0].record_type, RecordType::StartOfAuthority);
+    assert_eq!(changes[0].deleted[1].data, vec![192, 0, 2, 1]);
+    assert_eq!(changes[0].added[1].data, vec![192, 0, 2, 2]);
+    assert_eq!(changes[1].deleted[1].data, vec![192, 0, 2, 25]);
+    assert_eq!(changes[1].added.len(), 1);
+    assert_eq!(journal.since(2).unwrap().len(), 1);
+    assert!(journal.since(7).is_none());
+  }
+
+  #[test]
+  fn it_stores_changes_in_journal_files() {
+    let path = std::env::temp_dir().join(format!("journal-{}.jnl", std::process::id()));
+    let first = zone("$TTL 60\n@ SOA ns h 1 1 1 1 1\nwww A 192.0.2.1\n");
+    let second = zone("$TTL 60\n@ SOA ns h 2 1 1 1 1\nwww A 192.0.2.2\nftp CNAME www\n");
+    let change = ZoneChange::between(&first, &second);
+
+    append_journal(&path, &change).unwrap();
+    append_journal(&path, &change).unwrap();
+    let mut bytes = fs::read(&path).unwrap();
+    bytes.truncate(bytes.len() - 3);
+    fs::write(&path, bytes).unwrap();
+    let stored = read_journal(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let applied = stored[0].apply(&first).unwrap();
+    assert_eq!(stored, [change]);
+    assert_eq!(applied.serial(), 2);
+    assert_eq!(applied.records().count(), 3);
+    assert_eq!(applied.journal.since(1).unwrap().len(), 1);
+    assert!(read_journal(&path).unwrap().is_empty());
+  }
+
+  #[test]
+  fn it_reports_corrupt_journal_entries() {
+    let path = std::env::temp_dir().join(format!("corrupt-{}.jnl", std::process::id()));
+
+    for entry in [
+      &[0, 0, 0, 2, 1, 2][..],
+      &[0, 0, 0, 14, 0, 0, 0, 1, 0, 0, 0, 2, 0, 1, 0, 0, 0xc0, 0],
+    ] {
+      fs::write(&path, entry).unwrap();
+      let error = read_journal(&path).unwrap_err();
+      assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+    fs::remove_file(&path).unwrap();
+  }
+}
@@ -0,0 +1,22 @@
+pub mod acl;
+pub mod catalog;
+pub mod journal;
+pub mod parser;
+pub mod secure;
+pub mod serial;
+pub mod signing;
+pub mod transfer;
+pub mod update;
+pub mod writer;
+pub mod zone;
+
+pub use acl::*;
+pub use catalog::*;
+pub use journal::*;
+pub use parser::*;
+pub use serial::*;
+pub use signing::*;
+pub use transfer::*;
+pub use update::*;
+pub use writer::*;
+pub use zone::*;
@@ -0,0 +1,407 @@
+//! Master file parser (RFC 1035 section 5) producing the records of a zone. Besides records it
+//! understands the `$ORIGIN`, `$TTL` (RFC 2308), `$INCLUDE` and BIND's `$GENERATE` directives.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+use crate::messages::*;
+
+const MaxIncludeDepth: usize = 8;
+const MaxGeneratedRecords: u32 = 65536;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("{file}:{line}: {message}")]
+pub struct ZoneError {
+  pub file: String,
+  pub line: usize,
+  pub message: String,
+}
+
+/// Parses master file text, resolving relative names against `origin`.
+pub fn parse_zone(text: &str, origin: &DomainName) -> Result<Vec<AnswerProperties>, ZoneError> {
+  let mut parser = Parser::new(origin);
+  parser.parse(text, "<zone>", Path::new("."), 0)?;
+
+  Ok(parser.records)
+}
+
+/// Reads and parses a master file; `$INCLUDE` paths are relative to the including file.
+pub fn load_zone_file(path: &Path, origin: &DomainName) -> Result<Vec<AnswerProperties>, ZoneError> {
+  let mut parser = Parser::new(origin);
+  parser.include(path, 0, 0, "<command line>")?;
+
+  Ok(parser.records)
+}
+
+struct Parser {
+  origin: DomainName,
+  default_ttl: Option<u32>,
+  last_ttl: Option<u32>,
+  last_owner: Option<DomainName>,
+  last_class: RecordClass,
+  records: Vec<AnswerProperties>,
+}
+
+impl Parser {
+  fn new(origin: &DomainName) -> Self {
+    Self {
+      origin: origin.clone(),
+      default_ttl: None,
+      last_ttl: None,
+      last_owner: None,
+      last_class: RecordClass::Internet,
+      records: vec![],
+    }
+  }
+
+  fn include(&mut self, path: &Path, depth: usize, line: usize, from: &str) -> Result<(), ZoneError> {
+    let error = |message: String| ZoneError {
+      file: from.to_string(),
+      line,
+      message,
+    };
+
+    if depth > MaxIncludeDepth {
+      return Err(error(format!("Includes nested deeper than {} files", MaxIncludeDepth)));
+    }
+    let text = fs::read_to_string(path).map_err(|e| error(format!("Cannot read {}: {}", path.display(), e)))?;
+    let directory = path.parent().map(Path::to_path_buf).unwrap_or_default();
+
+    self.parse(&text, &path.display().to_string(), &directory, depth)
+  }
+
+  fn parse(&mut self, text: &str, file: &str, directory: &Path, depth: usize) -> Result<(), ZoneError> {
+    let error = |line: usize, message: String| ZoneError {
+      file: file.to_string(),
+      line,
+      message,
+    };
+
+    let entries = tokenize(text).map_err(|(line, message)| error(line, message))?;
+    for entry in entries {
+      let line = entry.line;
+      let first = &entry.tokens[0];
+      let argument = |index: usize| {
+        entry
+          .tokens
+          .get(index)
+          .map(|token| token.text.as_str())
+          .ok_or_else(|| error(line, format!("Missing argument to {}", first.text)))
+      };
+
+      match (entry.blank_owner, first.text.to_ascii_uppercase().as_str()) {
+        (false, "$ORIGIN") => {
+          self.origin = parse_name(argument(1)?, &self.origin).map_err(|e| error(line, e))?;
+        }
+        (false, "$TTL") => {
+          self.default_ttl = Some(parse_ttl(argument(1)?).map_err(|e| error(line, e))?);
+        }
+        (false, "$INCLUDE") => {
+          let path = directory.join(PathBuf::from(argument(1)?));
+          let origin = self.origin.clone();
+          if entry.tokens.len() > 2 {
+            self.origin = parse_name(argument(2)?, &origin).map_err(|e| error(line, e))?;
+          }
+
+          self.include(&path, depth + 1, line, file)?;
+          // The origin reverts once the included file ends (RFC 1035 section 5.1).
+          self.origin = origin;
+        }
+        (false, "$GENERATE") => self.generate(&entry).map_err(|e| error(line, e))?,
+        (false, directive) if directive.starts_with('$') => {
+          return Err(error(line, format!("Unknown directive {}", first.text)));
+        }
+        _ => self.record(&entry).map_err(|e| error(line, e))?,
+      }
+    }
+
+    Ok(())
+  }
+
+  fn record(&mut self, entry: &Entry) -> Result<(), String> {
+    let mut tokens = entry.tokens.iter();
+
+    let domain_name = match entry.blank_owner {
+      true => self.last_owner.clone().ok_or("No previous owner to inherit")?,
+      false => parse_name(&tokens.next().unwrap().text, &self.origin)?,
+    };
+
+    // TTL and class may come in either order before the type.
+    let mut time_to_live = None;
+    let mut record_class = None;
+    let record_type = loop {
+      let token = tokens.next().ok_or("Missing record type")?;
+      if time_to_live.is_none() && token.text.starts_with(|char: char| char.is_ascii_digit()) {
+        time_to_live = Some(parse_ttl(&token.text)?);
+      } else if let Some(class) = record_class
+        .is_none()
+        .then(|| RecordClass::from_mnemonic(&token.text))
+        .flatten()
+      {
+        record_class = Some(class);
+      } else {
+        break RecordType::from_mnemonic(&token.text).ok_or_else(|| format!("Unknown record type '{}'", token.text))?;
+      }
+    };
+
+    if record_type == RecordType::Options {
+      return Err("OPT records cannot appear in zone files".to_string());
+    }
+
+    let time_to_live = match time_to_live.or(self.default_ttl).or(self.last_ttl) {
+      Some(ttl) => ttl,
+      None => return Err("No TTL given and no $TTL in effect".to_string()),
+    };
+    let record_class = record_class.unwrap_or(self.last_class);
+    let rest: Vec<Token> = tokens.cloned().collect();
+    let data = parse_record_data(record_type, &rest, &self.origin)?;
+
+    self.last_owner = Some(domain_name.clone());
+    self.last_ttl = Some(time_to_live);
+    self.last_class = record_class;
+    self.records.push(AnswerProperties {
+      domain_name,
+      record_type,
+      record_class,
+      time_to_live,
+      data,
+    });
+
+    Ok(())
+  }
+
+  /// `$GENERATE start-stop[/step] lhs [ttl] [class] type rhs`, substituting the iterator into the
+  /// owner and data templates.
+  fn generate(&mut self, entry: &Entry) -> Result<(), String> {
+    let range = entry.tokens.get(1).ok_or("Missing range")?;
+    let (bounds, step) = match range.text.split_once('/') {
+      Some((bounds, step)) => (bounds, step.parse().map_err(|_| format!("Invalid step '{}'", step))?),
+      None => (range.text.as_str(), 1),
+    };
+    let (start, stop) = bounds
+      .split_once('-')
+      .and_then(|(start, stop)| Some((start.parse::<u32>().ok()?, stop.parse::<u32>().ok()?)))
+      .ok_or_else(|| format!("Invalid range '{}'", range.text))?;
+    if start > stop || step == 0 || (stop - start) / step >= MaxGeneratedRecords {
+      return Err(format!("Invalid range '{}'", range.text));
+    }
+    if entry.tokens.len() < 5 {
+      return Err("$GENERATE needs an owner, a type and data".to_string());
+    }
+
+    let templates = &entry.tokens[2..];
+    for value in (start..=stop).step_by(step as usize) {
+      let mut tokens = vec![];
+      for (index, template) in templates.iter().enumerate() {
+        let substitute = index == 0 || index == templates.len() - 1;
+        tokens.push(Token {
+          text: match substitute {
+            true => substitute_iterator(&template.text, value)?,
+            false => template.text.clone(),
+          },
+          ..template.clone()
+        });
+      }
+
+      self.record(&Entry {
+        blank_owner: false,
+        line: entry.line,
+        tokens,
+      })?;
+    }
+
+    Ok(())
+  }
+}
+
+/// Replaces `$` with the iterator, or `${offset,width,base}` with a formatted variant of it; base is
+/// one of `d`, `o`, `x`, `X`, or `n`/`N` for dot separated reversed nibbles as used below `ip6.arpa`.
+fn substitute_iterator(template: &str, value: u32) -> Result<String, String> {
+  let mut output = String::new();
+  let mut chars = template.chars().peekable();
+
+  while let Some(char) = chars.next() {
+    match char {
+      '\\' if chars.peek() == Some(&'$') => output.push(chars.next().unwrap()),
+      '\\' => {
+        output.push(char);
+        output.extend(chars.next());
+      }
+      '$' if chars.peek() == Some(&'{') => {
+        chars.next();
+        let modifier: String = chars.by_ref().take_while(|&char| char != '}').collect();
+        output.push_str(&format_iterator(&modifier, value)?);
+      }
+      '$' => output.push_str(&value.to_string()),
+      _ => output.push(char),
+    }
+  }
+
+  Ok(output)
+}
+
+fn format_iterator(modifier: &str, value: u32) -> Result<String, String> {
+  let invalid = || format!("Invalid $GENERATE modifier '{}'", modifier);
+  let mut parts = modifier.split(',');
+
+  let offset: i64 = parts.next().unwrap_or("0").parse().map_err(|_| invalid())?;
+  let width: usize = parts.next().unwrap_or("0").parse().map_err(|_| invalid())?;
+  let base = parts.next().unwrap_or("d");
+  if parts.next().is_some() {
+    return Err(invalid());
+  }
+
+  let value = u32::try_from(value as i64 + offset).map_err(|_| invalid())?;
+  let formatted = match base {
+    "d" => format!("{:0width$}", value),
+    "o" => format!("{:0width$o}", value),
+    "x" => format!("{:0width$x}", value),
+    "X" => format!("{:0width$X}", value),
+    "n" | "N" => {
+      let nibbles = format!("{:0width$x}", value);
+      let nibbles = match base {
+        "N" => nibbles.to_ascii_uppercase(),
+        _ => nibbles,
+      };
+      nibbles.chars().rev().map(String::from).collect::<Vec<_>>().join(".")
+    }
+    _ => return Err(invalid()),
+  };
+
+  Ok(formatted)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn origin() -> DomainName {
+    DomainName::from("example.com")
+  }
+
+  fn summary(records: &[AnswerProperties]) -> Vec<(String, RecordType, u32)> {
+    records
+      .iter()
+      .map(|record| (record.domain_name.0.join("."), record.record_type, record.time_to_live))
+      .collect()
+  }
+
+  #[test]
+  fn it_parses_a_zone() {
+    let zone = "\
+$TTL 1h
+@         IN  SOA   ns1 hostmaster (
+                    2024010101 ; serial
+                    2h 30m 1w 5m )
+          IN  NS    ns1
+          NS        ns2.provider.net.
+ns1   300     A     192.0.2.1
+www       CNAME     @
+$ORIGIN sub
+host  IN  30  AAAA  2001:db8::1
+      TXT           \"v=spf1 -all\" plain
+";
+
+    let records = parse_zone(zone, &origin()).unwrap();
+
+    assert_eq!(
+      summary(&records),
+      [
+        ("example.com".into(), RecordType::StartOfAuthority, 3600),
+        ("example.com".into(), RecordType::NameServer, 3600),
+        ("example.com".into(), RecordType::NameServer, 3600),
+        ("ns1.example.com".into(), RecordType::Host, 300),
+        ("www.example.com".into(), RecordType::CanonicalName, 3600),
+        ("host.sub.example.com".into(), RecordType::Ipv6Host, 30),
+        ("host.sub.example.com".into(), RecordType::Text, 3600),
+      ]
+    );
+    assert_eq!(
+      records[0].record_data(),
+      RecordData::StartOfAuthority {
+        primary: DomainName::from("ns1.example.com"),
+        mailbox: DomainName::from("hostmaster.example.com"),
+        serial: 2024010101,
+        refresh: 7200,
+        retry: 1800,
+        expire: 604800,
+        minimum: 300,
+      }
+    );
+    assert_eq!(
+      records[6].record_data(),
+      RecordData::Text(vec![b"v=spf1 -all".to_vec(), b"plain".to_vec()])
+    );
+  }
+
+  #[test]
+  fn it_falls_back_to_the_last_ttl_without_a_default() {
+    let records = parse_zone("a 120 A 192.0.2.1\nb A 192.0.2.2\n", &origin()).unwrap();
+
+    assert_eq!(records[1].time_to_live, 120);
+    assert_eq!(
+      parse_zone("a A 192.0.2.1\n", &origin()).unwrap_err().message,
+      "No TTL given and no $TTL in effect"
+    );
+  }
+
+  #[test]
+  fn it_generates_records() {
+    let zone = "$TTL 60\n$GENERATE 1-5/2 host-${10,3} A 10.0.0.$\n$GENERATE 10-11 ${0,4,n}.rev PTR \\$host\n";
+
+    let records = parse_zone(zone, &origin()).unwrap();
+
+    assert_eq!(
+      summary(&records)
+        .into_iter()
+        .map(|(name, _, _)| name)
+        .collect::<Vec<_>>(),
+      [
+        "host-011.example.com",
+        "host-013.example.com",
+        "host-015.example.com",
+        "a.0.0.0.rev.example.com",
+        "b.0.0.0.rev.example.com",
+      ]
+    );
+    assert_eq!(records[2].data, vec![10, 0, 0, 5]);
+    assert_eq!(
+      records[3].record_data(),
+      RecordData::Pointer(DomainName::from("$host.example.com"))
+    );
+  }
+
+  #[test]
+  fn it_includes_files_relative_to_the_including_file() {
+    let directory = std::env::temp_dir().join(format!("zones-{}", std::process::id()));
+    fs::create_dir_all(directory.join("parts")).unwrap();
+    fs::write(
+      directory.join("main.zone"),
+      "$TTL 60\n$INCLUDE parts/hosts.zone hosts\nafter A 192.0.2.9\n",
+    )
+    .unwrap();
+    fs::write(directory.join("parts/hosts.zone"), "a A 192.0.2.1\n").unwrap();
+
+    let records = load_zone_file(&directory.join("main.zone"), &origin());
+    fs::remove_dir_all(&directory).unwrap();
+
+    assert_eq!(
+      summary(&records.unwrap())
+        .into_iter()
+        .map(|(name, _, _)| name)
+        .collect::<Vec<_>>(),
+      ["a.hosts.example.com", "after.example.com"]
+    );
+  }
+
+  #[test]
+  fn it_reports_errors_with_line_numbers() {
+    let zone = "$TTL 60\nok A 192.0.2.1\n\nbad A 300.0.0.1\n";
+
+    let error = parse_zone(zone, &origin()).unwrap_err();
+
+    assert_eq!(error.to_string(), "<zone>:4: Invalid IPv4 address '300.0.0.1'");
+  }
+}
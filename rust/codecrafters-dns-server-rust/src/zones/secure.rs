@@ -0,0 +1,456 @@
+//! Answers from signed zones to clients setting the DO bit (RFC 4035 section 3.1): every RRset
+//! goes out with its RRSIGs, and negative answers, wildcard expansions and referrals carry the
+//! NSEC (RFC 4035 section 3.1.3) or NSEC3 (RFC 5155 section 7.2) records proving them.
+
+use std::cmp::Ordering;
+
+use crate::dnssec::*;
+use crate::messages::*;
+use crate::zones::{Lookup, Zone};
+
+impl Zone {
+  pub fn is_signed(&self) -> bool {
+    self
+      .records_at(&self.origin)
+      .iter()
+      .any(|record| record.record_type == RecordType::Signature)
+  }
+
+  /// Adds the signatures and denial proofs for the lookup of `name` and `record_type`.
+  pub fn add_dnssec(&self, name: &DomainName, record_type: RecordType, lookup: &mut Lookup) {
+    let mut answers = vec![];
+    for (owner, covered) in rrsets(&lookup.answers) {
+      if self.contains(&owner) {
+        answers.extend(self.signatures(&owner, covered));
+        continue;
+      }
+
+      // The answer was expanded from a wildcard, whose signatures are given for the query name
+      // together with proof that no closer match exists (RFC 4035 section 3.1.3.3).
+      let closest_encloser = self.closest_encloser(&owner);
+      let source = wildcard(&closest_encloser);
+      let signatures = self.signatures(&source, covered);
+      if signatures.is_empty() {
+        continue;
+      }
+      answers.extend(signatures.into_iter().map(|signature| AnswerProperties {
+        domain_name: owner.clone(),
+        ..signature
+      }));
+      let proof = match self.nsec3_parameters() {
+        Some(_) => self.nsec3_covering(&next_closer(&owner, &closest_encloser)),
+        None => self.nsec_covering(&owner),
+      };
+      push_signed(&mut lookup.authorities, self, proof);
+    }
+    for answer in answers {
+      push(&mut lookup.answers, answer);
+    }
+
+    let mut additionals = vec![];
+    for (owner, covered) in rrsets(&lookup.additionals) {
+      additionals.extend(self.signatures(&owner, covered));
+    }
+    for additional in additionals {
+      push(&mut lookup.additionals, additional);
+    }
+
+    let referral = lookup
+      .authorities
+      .iter()
+      .find(|record| record.record_type == RecordType::NameServer)
+      .map(|record| record.domain_name.clone());
+    let negative = lookup
+      .authorities
+      .iter()
+      .any(|record| record.record_type == RecordType::StartOfAuthority);
+    if let Some(cut) = referral {
+      self.prove_delegation(&cut, lookup);
+    } else if negative {
+      for signature in self.signatures(&self.origin, RecordType::StartOfAuthority) {
+        push(&mut lookup.authorities, signature);
+      }
+      self.prove_negative(&final_name(name, &lookup.answers), record_type, lookup);
+    }
+  }
+
+  /// The DS RRset of a secure delegation, or proof that it has none (RFC 4035 section 3.1.4).
+  fn prove_delegation(&self, cut: &DomainName, lookup: &mut Lookup) {
+    let delegation_signers = self.rrset(cut, RecordType::DelegationSigner);
+    if !delegation_signers.is_empty() {
+      for record in delegation_signers {
+        push(&mut lookup.authorities, record);
+      }
+      for signature in self.signatures(cut, RecordType::DelegationSigner) {
+        push(&mut lookup.authorities, signature);
+      }
+      return;
+    }
+
+    match self.nsec3_parameters() {
+      Some(_) => match self.nsec3_matching(cut) {
+        Some(matching) => push_signed(&mut lookup.authorities, self, Some(matching)),
+        None => self.prove_closest_encloser(cut, lookup),
+      },
+      None => push_signed(&mut lookup.authorities, self, self.nsec_matching(cut)),
+    }
+  }
+
+  /// Proof of NXDOMAIN or NODATA for `name`, the last name the lookup reached.
+  fn prove_negative(&self, name: &DomainName, record_type: RecordType, lookup: &mut Lookup) {
+    let closest_encloser = self.closest_encloser(name);
+    let source = wildcard(&closest_encloser);
+    let nsec3 = self.nsec3_parameters().is_some();
+
+    match (lookup.response_code, self.contains(name), nsec3) {
+      (ResponseCode::NameError, _, false) => {
+        push_signed(&mut lookup.authorities, self, self.nsec_covering(name));
+        push_signed(&mut lookup.authorities, self, self.nsec_covering(&source));
+      }
+      (ResponseCode::NameError, _, true) => {
+        self.prove_closest_encloser(name, lookup);
+        push_signed(&mut lookup.authorities, self, self.nsec3_covering(&source));
+      }
+      // Empty non-terminals own no NSEC; the record before them in the chain covers them.
+      (_, true, false) => {
+        let proof = self.nsec_matching(name).or_else(|| self.nsec_covering(name));
+        push_signed(&mut lookup.authorities, self, proof);
+      }
+      // An insecure delegation left out of an opt-out chain is proven by its closest encloser.
+      (_, true, true) => match self.nsec3_matching(name) {
+        Some(matching) => push_signed(&mut lookup.authorities, self, Some(matching)),
+        None if record_type == RecordType::DelegationSigner => self.prove_closest_encloser(name, lookup),
+        None => {}
+      },
+      // Wildcard NODATA (RFC 4035 section 3.1.3.4, RFC 5155 section 7.2.5).
+      (_, false, false) => {
+        push_signed(&mut lookup.authorities, self, self.nsec_covering(name));
+        push_signed(&mut lookup.authorities, self, self.nsec_matching(&source));
+      }
+      (_, false, true) => {
+        self.prove_closest_encloser(name, lookup);
+        push_signed(&mut lookup.authorities, self, self.nsec3_matching(&source));
+      }
+    }
+  }
+
+  /// The NSEC3 record matching the closest encloser of `name` and the one covering the next closer
+  /// name (RFC 5155 section 7.2.1).
+  fn prove_closest_encloser(&self, name: &DomainName, lookup: &mut Lookup) {
+    let mut encloser = name.parent().unwrap_or_else(DomainName::root);
+    while encloser.label_count() > self.origin.label_count() && self.nsec3_matching(&encloser).is_none() {
+      encloser = encloser.parent().unwrap();
+    }
+
+    push_signed(&mut lookup.authorities, self, self.nsec3_matching(&encloser));
+    push_signed(
+      &mut lookup.authorities,
+      self,
+      self.nsec3_covering(&next_closer(name, &encloser)),
+    );
+  }
+
+  /// The longest ancestor of `name`, or `name` itself, that exists in the zone.
+  fn closest_encloser(&self, name: &DomainName) -> DomainName {
+    (self.origin.label_count()..=name.label_count())
+      .rev()
+      .map(|count| name.suffix(count))
+      .find(|ancestor| self.contains(ancestor))
+      .unwrap_or_else(|| self.origin.clone())
+  }
+
+  fn signatures(&self, owner: &DomainName, covered: RecordType) -> Vec<AnswerProperties> {
+    self
+      .records_at(owner)
+      .iter()
+      .filter(|record| {
+        record.record_type == RecordType::Signature
+          && matches!(record.record_data(), RecordData::Signature { type_covered, .. } if type_covered == covered)
+      })
+      .cloned()
+      .collect()
+  }
+
+  fn nsec_matching(&self, name: &DomainName) -> Option<AnswerProperties> {
+    self.rrset(name, RecordType::NextSecure).into_iter().next()
+  }
+
+  /// The NSEC record whose span from its owner to the next name holds `name`.
+  fn nsec_covering(&self, name: &DomainName) -> Option<AnswerProperties> {
+    self
+      .records()
+      .filter(|record| record.record_type == RecordType::NextSecure)
+      .find(|record| {
+        let RecordData::NextSecure { next, .. } = record.record_data() else {
+          return false;
+        };
+        let after_owner = canonical_cmp(&record.domain_name, name) == Ordering::Less;
+        let before_next = canonical_cmp(name, &next) == Ordering::Less;
+        let last = canonical_cmp(&next, &record.domain_name) != Ordering::Greater;
+
+        after_owner && (before_next || last)
+      })
+      .cloned()
+  }
+
+  /// The salt and iterations of the zone's NSEC3 chain, `None` for zones chained with NSEC.
+  fn nsec3_parameters(&self) -> Option<(Vec<u8>, u16)> {
+    self
+      .rrset(&self.origin, RecordType::NextSecure3Parameters)
+      .iter()
+      .find_map(|record| match record.record_data() {
+        RecordData::NextSecure3Parameters { salt, iterations, .. } => Some((salt, iterations)),
+        _ => None,
+      })
+  }
+
+  fn nsec3_matching(&self, name: &DomainName) -> Option<AnswerProperties> {
+    let (salt, iterations) = self.nsec3_parameters()?;
+    let owner = hashed_name(name, &self.origin, &salt, iterations);
+
+    self.rrset(&owner, RecordType::NextSecure3).into_iter().next()
+  }
+
+  fn nsec3_covering(&self, name: &DomainName) -> Option<AnswerProperties> {
+    let (salt, iterations) = self.nsec3_parameters()?;
+    let hash = nsec3_hash(name, &salt, iterations);
+
+    self
+      .records()
+      .filter(|record| record.record_type == RecordType::NextSecure3)
+      .find(|record| match (owner_hash(&record.domain_name), record.record_data()) {
+        (Some(owner), RecordData::NextSecure3 { next_hashed, .. }) => covers(&owner, &next_hashed, &hash),
+        _ => false,
+      })
+      .cloned()
+  }
+}
+
+/// The distinct owner and type pairs of the records, skipping signatures.
+fn rrsets(records: &[AnswerProperties]) -> Vec<(DomainName, RecordType)> {
+  let mut rrsets: Vec<(DomainName, RecordType)> = vec![];
+  for record in records
+    .iter()
+    .filter(|record| record.record_type != RecordType::Signature)
+  {
+    if !rrsets
+      .iter()
+      .any(|(owner, covered)| *covered == record.record_type && owner.eq_ignore_case(&record.domain_name))
+    {
+      rrsets.push((record.domain_name.clone(), record.record_type));
+    }
+  }
+
+  rrsets
+}
+
+/// The name the lookup of `name` ended at after following the aliases among the answers.
+fn final_name(name: &DomainName, answers: &[AnswerProperties]) -> DomainName {
+  let mut name = name.clone();
+  for answer in answers {
+    if let RecordData::CanonicalName(target) = answer.record_data()
+      && answer.domain_name.eq_ignore_case(&name)
+    {
+      name = target;
+    }
+  }
+
+  name
+}
+
+fn wildcard(encloser: &DomainName) -> DomainName {
+  let mut source = encloser.clone();
+  source.0.insert(0, "*".to_string());
+  source
+}
+
+/// The ancestor of `name` one label longer than its closest encloser.
+fn next_closer(name: &DomainName, closest_encloser: &DomainName) -> DomainName {
+  name.suffix(closest_encloser.label_count() + 1)
+}
+
+fn push(records: &mut Vec<AnswerProperties>, record: AnswerProperties) {
+  if !records.contains(&record) {
+    records.push(record);
+  }
+}
+
+/// Adds a denial record followed by its signatures.
+fn push_signed(records: &mut Vec<AnswerProperties>, zone: &Zone, record: Option<AnswerProperties>) {
+  let Some(record) = record else {
+    return;
+  };
+
+  let signatures = zone.signatures(&record.domain_name, record.record_type);
+  push(records, record);
+  for signature in signatures {
+    push(records, signature);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::zones::{Catalog, Denial, SigningPolicy, parse_zone, sign_zone};
+
+  const Example: &str = "\
+$TTL 3600
+@            SOA    ns1 hostmaster 1 7200 900 1209600 300
+             NS     ns1
+ns1          A      192.0.2.1
+www          A      192.0.2.2
+a.b.c        TXT    \"deep\"
+*.wild       TXT    \"wild\"
+secure       NS     ns.secure
+             DS     12345 13 2 0123456789abcdef
+ns.secure    A      192.0.2.53
+insecure     NS     ns.abc.com.
+";
+
+  fn catalog(denial: &str) -> Catalog {
+    let origin = DomainName::from("example.com");
+    let key = SigningKey::generate(origin.clone(), SecurityAlgorithm::Ed25519, SigningKey::ZoneSigningKey).unwrap();
+    let policy = SigningPolicy::new(vec![key], Denial::parse(denial).unwrap());
+    let zone = Zone::new(origin.clone(), parse_zone(Example, &origin).unwrap()).unwrap();
+
+    let mut catalog = Catalog::new();
+    catalog.insert(sign_zone(&zone, &policy, timestamp_now()).unwrap());
+    catalog
+  }
+
+  fn lookup(catalog: &Catalog, name: &str, record_type: RecordType) -> Lookup {
+    let question = QuestionProperties {
+      domain_name: DomainName::from(name),
+      record_type,
+      record_class: RecordClass::Internet,
+    };
+
+    catalog.lookup(&question, true)
+  }
+
+  fn names(records: &[AnswerProperties]) -> Vec<(String, RecordType)> {
+    records
+      .iter()
+      .map(|record| (record.domain_name.0.join("."), record.record_type))
+      .collect()
+  }
+
+  #[test]
+  fn it_adds_signatures_and_nsec_proofs_for_dnssec_clients() {
+    let catalog = catalog("nsec");
+    let question = QuestionProperties {
+      domain_name: DomainName::from("www.example.com"),
+      record_type: RecordType::Host,
+      record_class: RecordClass::Internet,
+    };
+
+    let plain = catalog.lookup(&question, false);
+    let positive = lookup(&catalog, "www.example.com", RecordType::Host);
+    let nxdomain = lookup(&catalog, "nope.example.com", RecordType::Host);
+    let empty_non_terminal = lookup(&catalog, "b.c.example.com", RecordType::Host);
+    let wildcard = lookup(&catalog, "x.wild.example.com", RecordType::Text);
+    let secure = lookup(&catalog, "www.secure.example.com", RecordType::Host);
+    let insecure = lookup(&catalog, "www.insecure.example.com", RecordType::Host);
+
+    assert_eq!(names(&plain.answers), [("www.example.com".into(), RecordType::Host)]);
+    assert_eq!(
+      names(&positive.answers),
+      [
+        ("www.example.com".into(), RecordType::Host),
+        ("www.example.com".into(), RecordType::Signature)
+      ]
+    );
+    assert_eq!(nxdomain.response_code, ResponseCode::NameError);
+    assert_eq!(
+      names(&nxdomain.authorities),
+      [
+        ("example.com".into(), RecordType::StartOfAuthority),
+        ("example.com".into(), RecordType::Signature),
+        ("insecure.example.com".into(), RecordType::NextSecure),
+        ("insecure.example.com".into(), RecordType::Signature),
+        ("example.com".into(), RecordType::NextSecure),
+        ("example.com".into(), RecordType::Signature),
+      ]
+    );
+    assert_eq!(
+      names(&empty_non_terminal.authorities)[2..],
+      [
+        ("example.com".into(), RecordType::NextSecure),
+        ("example.com".into(), RecordType::Signature),
+      ]
+    );
+    assert_eq!(
+      names(&wildcard.answers),
+      [
+        ("x.wild.example.com".into(), RecordType::Text),
+        ("x.wild.example.com".into(), RecordType::Signature)
+      ]
+    );
+    assert!(matches!(
+      wildcard.answers[1].record_data(),
+      RecordData::Signature { labels: 3, .. }
+    ));
+    assert_eq!(
+      names(&wildcard.authorities),
+      [
+        ("*.wild.example.com".into(), RecordType::NextSecure),
+        ("*.wild.example.com".into(), RecordType::Signature),
+      ]
+    );
+    assert_eq!(
+      names(&secure.authorities),
+      [
+        ("secure.example.com".into(), RecordType::NameServer),
+        ("secure.example.com".into(), RecordType::DelegationSigner),
+        ("secure.example.com".into(), RecordType::Signature),
+      ]
+    );
+    assert_eq!(
+      names(&insecure.authorities)[1..],
+      [
+        ("insecure.example.com".into(), RecordType::NextSecure),
+        ("insecure.example.com".into(), RecordType::Signature),
+      ]
+    );
+  }
+
+  #[test]
+  fn it_proves_denials_with_nsec3() {
+    let catalog = catalog("nsec3:0:-:opt-out");
+    let zone = catalog.zone(&DomainName::from("example.com")).unwrap();
+    let hashed = |name: &str| hashed_name(&DomainName::from(name), &zone.origin, &[], 0);
+    let proofs = |lookup: &Lookup| -> Vec<DomainName> {
+      lookup
+        .authorities
+        .iter()
+        .filter(|record| record.record_type == RecordType::NextSecure3)
+        .map(|record| record.domain_name.clone())
+        .collect()
+    };
+
+    let nxdomain = lookup(&catalog, "a.nope.example.com", RecordType::Host);
+    let empty_non_terminal = lookup(&catalog, "b.c.example.com", RecordType::Host);
+    let insecure = lookup(&catalog, "www.insecure.example.com", RecordType::Host);
+
+    let nxdomain_proofs = proofs(&nxdomain);
+    assert_eq!(nxdomain_proofs[0], hashed("example.com"));
+    assert!(nxdomain_proofs.len() >= 2);
+    let signatures = nxdomain
+      .authorities
+      .iter()
+      .filter(|record| record.record_type == RecordType::Signature)
+      .count();
+    assert_eq!(signatures, nxdomain_proofs.len() + 1);
+
+    assert_eq!(proofs(&empty_non_terminal), [hashed("b.c.example.com")]);
+    // Left out of the opt-out chain, the delegation is proven insecure by its closest encloser.
+    assert_eq!(proofs(&insecure)[0], hashed("example.com"));
+    assert!(matches!(
+      insecure.authorities.last().unwrap().record_data(),
+      RecordData::Signature {
+        type_covered: RecordType::NextSecure3,
+        ..
+      }
+    ));
+  }
+}
@@ -0,0 +1,23 @@
+//! Serial number arithmetic (RFC 1982) for SOA serials, which may wrap around past 2^32 - 1.
+
+/// Whether serial `a` is newer than serial `b`. Serials exactly 2^31 apart are left undefined by
+/// RFC 1982 and compare as neither.
+pub fn serial_newer(a: u32, b: u32) -> bool {
+  a != b && (a.wrapping_sub(b) as i32) > 0
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn it_compares_serials_across_the_wrap_around() {
+    assert_eq!(serial_newer(2, 1), true);
+    assert_eq!(serial_newer(1, 2), false);
+    assert_eq!(serial_newer(1, 1), false);
+    assert_eq!(serial_newer(5, u32::MAX - 5), true);
+    assert_eq!(serial_newer(u32::MAX - 5, 5), false);
+    assert_eq!(serial_newer(1 << 31, 0), false);
+    assert_eq!(serial_newer(0, 1 << 31), false);
+  }
+}
@@ -0,0 +1,583 @@
+//! Online signing (RFC 4035 section 2). The apex gets a DNSKEY RRset with the zone's keys, names
+//! are chained with NSEC records (RFC 4034 section 4) or hashed NSEC3 records (RFC 5155 section
+//! 7.1) proving what does not exist, and every authoritative RRset gets an RRSIG from each key.
+//! The DNSKEY RRset is signed by the key signing keys and everything else by the zone signing
+//! keys, when the zone has both. Occluded names below a zone cut are neither chained nor signed,
+//! and at a cut only the DS and NSEC RRsets are, the NS RRset belonging to the child.
+
+use data_encoding::HEXLOWER_PERMISSIVE;
+
+use crate::dnssec::*;
+use crate::messages::*;
+use crate::zones::Zone;
+
+/// How long signatures stay valid by default, in seconds.
+pub const DefaultSignatureValidity: u32 = 14 * 86400;
+/// How long before the earliest signature expires the zone is signed again by default, in seconds.
+pub const DefaultResignWindow: u32 = 3 * 86400;
+/// Signatures start an hour before they are made, for validators with slow clocks.
+const InceptionSkew: u32 = 3600;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Denial {
+  Nsec,
+  /// Hashed denial, leaving unsigned delegations out of the chain with `opt_out`.
+  Nsec3 {
+    iterations: u16,
+    salt: Vec<u8>,
+    opt_out: bool,
+  },
+}
+
+impl Denial {
+  /// Parses `nsec` or `nsec3:<iterations>:<salt in hex or ->[:opt-out]`, e.g. `nsec3:0:-`.
+  pub fn parse(text: &str) -> Result<Self, String> {
+    let parts: Vec<&str> = text.split(':').collect();
+    let invalid = || {
+      format!(
+        "Invalid denial '{}', expected nsec or nsec3:<iterations>:<salt>[:opt-out]",
+        text
+      )
+    };
+
+    match parts[..] {
+      ["nsec"] => Ok(Denial::Nsec),
+      ["nsec3", iterations, salt] | ["nsec3", iterations, salt, "opt-out"] => Ok(Denial::Nsec3 {
+        iterations: iterations.parse().map_err(|_| invalid())?,
+        salt: match salt {
+          "-" => vec![],
+          salt => HEXLOWER_PERMISSIVE.decode(salt.as_bytes()).map_err(|_| invalid())?,
+        },
+        opt_out: parts.len() == 4,
+      }),
+      _ => Err(invalid()),
+    }
+  }
+}
+
+#[derive(Clone, Debug)]
+pub struct SigningPolicy {
+  pub keys: Vec<SigningKey>,
+  pub denial: Denial,
+  /// How long signatures stay valid, in seconds.
+  pub validity: u32,
+  /// How long before the earliest signature expires the zone is signed again, in seconds.
+  pub refresh: u32,
+}
+
+impl SigningPolicy {
+  pub fn new(keys: Vec<SigningKey>, denial: Denial) -> Self {
+    Self {
+      keys,
+      denial,
+      validity: DefaultSignatureValidity,
+      refresh: DefaultResignWindow,
+    }
+  }
+}
+
+/// Whether the record is made by signing rather than taken from the zone's data.
+fn is_generated(record: &AnswerProperties) -> bool {
+  [
+    RecordType::Signature,
+    RecordType::NextSecure,
+    RecordType::NextSecure3,
+    RecordType::NextSecure3Parameters,
+  ]
+  .contains(&record.record_type)
+}
+
+/// The zone without the records signing with `policy` adds, i.e. as its master file has it.
+pub fn unsigned(zone: &Zone, policy: &SigningPolicy) -> Zone {
+  let dnskeys: Vec<Vec<u8>> = policy.keys.iter().map(|key| key.dnskey(0).data).collect();
+  let records = zone
+    .records()
+    .filter(|record| !is_generated(record))
+    .filter(|record| !(record.record_type == RecordType::DnsKey && dnskeys.contains(&record.data)))
+    .cloned()
+    .collect();
+
+  let mut stripped = Zone::new(zone.origin.clone(), records).expect("The SOA record is kept");
+  stripped.settings = zone.settings.clone();
+  stripped.journal = zone.journal.clone();
+  stripped
+}
+
+/// The zone signed with the keys of `policy`, with signatures valid from an hour before `now`,
+/// replacing any signatures and chains it had.
+pub fn sign_zone(zone: &Zone, policy: &SigningPolicy, now: u32) -> Result<Zone, String> {
+  let origin = &zone.origin;
+  if policy.keys.is_empty() {
+    return Err(format!("No keys to sign zone {}", origin));
+  }
+
+  let stripped = unsigned(zone, policy);
+  let soa = stripped.soa().clone();
+  // Denial records are cached like negative answers (RFC 9077 section 3).
+  let negative_ttl = match soa.record_data() {
+    RecordData::StartOfAuthority { minimum, .. } => soa.time_to_live.min(minimum),
+    _ => soa.time_to_live,
+  };
+
+  let mut records: Vec<AnswerProperties> = stripped.records().cloned().collect();
+  records.extend(policy.keys.iter().map(|key| key.dnskey(soa.time_to_live)));
+  if let Denial::Nsec3 { iterations, salt, .. } = &policy.denial {
+    records.push(AnswerProperties {
+      domain_name: origin.clone(),
+      record_type: RecordType::NextSecure3Parameters,
+      record_class: RecordClass::Internet,
+      time_to_live: 0,
+      data: RecordData::NextSecure3Parameters {
+        algorithm: Nsec3Sha1,
+        flags: 0,
+        iterations: *iterations,
+        salt: salt.clone(),
+      }
+      .to_bytes(),
+    });
+  }
+  let keyed = Zone::new(origin.clone(), records)?;
+
+  let chain = match &policy.denial {
+    Denial::Nsec => nsec_chain(&keyed, negative_ttl),
+    Denial::Nsec3 {
+      iterations,
+      salt,
+      opt_out,
+    } => nsec3_chain(&keyed, *iterations, salt, *opt_out, negative_ttl)?,
+  };
+  let mut records: Vec<AnswerProperties> = keyed.records().cloned().collect();
+  records.extend(chain);
+  let chained = Zone::new(origin.clone(), records)?;
+
+  let inception = now.saturating_sub(InceptionSkew);
+  let expiration = now.saturating_add(policy.validity);
+  let role = |key_signing: bool| {
+    let keys: Vec<&SigningKey> = policy
+      .keys
+      .iter()
+      .filter(|key| key.is_key_signing() == key_signing)
+      .collect();
+    match keys.is_empty() {
+      true => policy.keys.iter().collect(),
+      false => keys,
+    }
+  };
+  let (key_signing, zone_signing) = (role(true), role(false));
+
+  let mut signatures = vec![];
+  for name in chained.names().filter(|name| is_authoritative(&chained, name)) {
+    let at_cut = is_cut(&chained, name);
+    let records = chained.records_at(name);
+    let mut types: Vec<RecordType> = records.iter().map(|record| record.record_type).collect();
+    types.sort();
+    types.dedup();
+
+    for record_type in types {
+      if at_cut && record_type != RecordType::DelegationSigner && record_type != RecordType::NextSecure {
+        continue;
+      }
+
+      let rrset: Vec<AnswerProperties> = records
+        .iter()
+        .filter(|record| record.record_type == record_type)
+        .cloned()
+        .collect();
+      let keys = match record_type {
+        RecordType::DnsKey => &key_signing,
+        _ => &zone_signing,
+      };
+      signatures.extend(keys.iter().map(|key| sign_rrset(&rrset, key, inception, expiration)));
+    }
+  }
+
+  let mut records: Vec<AnswerProperties> = chained.records().cloned().collect();
+  records.extend(signatures);
+  let mut signed = Zone::new(origin.clone(), records)?;
+  signed.settings = zone.settings.clone();
+  signed.journal = zone.journal.clone();
+  Ok(signed)
+}
+
+/// Whether the zone has no signatures yet or one of them expires within the policy's window.
+pub fn needs_resigning(zone: &Zone, policy: &SigningPolicy, now: u32) -> bool {
+  zone
+    .records()
+    .filter_map(|record| match record.record_type {
+      RecordType::Signature => match record.record_data() {
+        RecordData::Signature { expiration, .. } => Some(expiration),
+        _ => None,
+      },
+      _ => None,
+    })
+    .min()
+    .is_none_or(|expiration| expiration <= now.saturating_add(policy.refresh))
+}
+
+/// The zone with its serial increased and fresh signatures, so secondaries pick them up.
+pub fn resign(zone: &Zone, policy: &SigningPolicy, now: u32) -> Result<Zone, String> {
+  let mut records: Vec<AnswerProperties> = zone.records().cloned().collect();
+  for record in records
+    .iter_mut()
+    .filter(|record| record.record_type == RecordType::StartOfAuthority)
+  {
+    if let RecordData::StartOfAuthority {
+      primary,
+      mailbox,
+      serial,
+      refresh,
+      retry,
+      expire,
+      minimum,
+    } = record.record_data()
+    {
+      record.data = RecordData::StartOfAuthority {
+        primary,
+        mailbox,
+        serial: serial.wrapping_add(1),
+        refresh,
+        retry,
+        expire,
+        minimum,
+      }
+      .to_bytes();
+    }
+  }
+
+  let mut next = Zone::new(zone.origin.clone(), records)?;
+  next.settings = zone.settings.clone();
+  next.journal = zone.journal.clone();
+  sign_zone(&next, policy, now)
+}
+
+/// Whether `name` is not occluded, i.e. does not lie strictly below a zone cut.
+fn is_authoritative(zone: &Zone, name: &DomainName) -> bool {
+  zone.delegation(name).is_none_or(|cut| cut.eq_ignore_case(name))
+}
+
+fn is_cut(zone: &Zone, name: &DomainName) -> bool {
+  zone.delegation(name).is_some_and(|cut| cut.eq_ignore_case(name))
+}
+
+/// The types the zone is authoritative for at `name`; a cut only has its NS and DS RRsets listed
+/// (RFC 4035 section 2.3).
+fn authoritative_types(zone: &Zone, name: &DomainName) -> Vec<RecordType> {
+  let at_cut = is_cut(zone, name);
+
+  zone
+    .records_at(name)
+    .iter()
+    .map(|record| record.record_type)
+    .filter(|record_type| {
+      !at_cut || *record_type == RecordType::NameServer || *record_type == RecordType::DelegationSigner
+    })
+    .collect()
+}
+
+/// NSEC records linking the authoritative names that own records in canonical order, the last
+/// one pointing back at the apex.
+fn nsec_chain(zone: &Zone, time_to_live: u32) -> Vec<AnswerProperties> {
+  let mut names: Vec<&DomainName> = zone
+    .names()
+    .filter(|name| !zone.records_at(name).is_empty() && is_authoritative(zone, name))
+    .collect();
+  names.sort_by(|first, second| canonical_cmp(first, second));
+
+  names
+    .iter()
+    .enumerate()
+    .map(|(index, name)| {
+      let mut types = authoritative_types(zone, name);
+      types.extend([RecordType::Signature, RecordType::NextSecure]);
+
+      AnswerProperties {
+        domain_name: (*name).clone(),
+        record_type: RecordType::NextSecure,
+        record_class: RecordClass::Internet,
+        time_to_live,
+        data: RecordData::NextSecure {
+          next: names[(index + 1) % names.len()].clone(),
+          types,
+        }
+        .to_bytes(),
+      }
+    })
+    .collect()
+}
+
+/// NSEC3 records linking the hashes of the authoritative names, empty non-terminals included. With
+/// opt-out, delegations without a DS RRset are left out.
+fn nsec3_chain(
+  zone: &Zone,
+  iterations: u16,
+  salt: &[u8],
+  opt_out: bool,
+  time_to_live: u32,
+) -> Result<Vec<AnswerProperties>, String> {
+  let mut hashes: Vec<(Vec<u8>, Vec<RecordType>)> = vec![];
+  for name in zone.names().filter(|name| is_authoritative(zone, name)) {
+    let mut types = authoritative_types(zone, name);
+    let insecure = is_cut(zone, name) && !types.contains(&RecordType::DelegationSigner);
+    if insecure && opt_out {
+      continue;
+    }
+    if !types.is_empty() && !insecure {
+      types.push(RecordType::Signature);
+    }
+
+    hashes.push((nsec3_hash(name, salt, iterations), types));
+  }
+  hashes.sort();
+  if hashes.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+    return Err(format!("NSEC3 hash collision in zone {}", zone.origin));
+  }
+
+  let flags = if opt_out { Nsec3OptOut } else { 0 };
+  let chain = hashes
+    .iter()
+    .enumerate()
+    .map(|(index, (hash, types))| AnswerProperties {
+      domain_name: hashed_owner(hash, &zone.origin),
+      record_type: RecordType::NextSecure3,
+      record_class: RecordClass::Internet,
+      time_to_live,
+      data: RecordData::NextSecure3 {
+        algorithm: Nsec3Sha1,
+        flags,
+        iterations,
+        salt: salt.to_vec(),
+        next_hashed: hashes[(index + 1) % hashes.len()].0.clone(),
+        types: types.clone(),
+      }
+      .to_bytes(),
+    })
+    .collect();
+
+  Ok(chain)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::zones::{ZoneChange, parse_zone};
+  use ring::signature::{ECDSA_P256_SHA256_FIXED, ED25519, UnparsedPublicKey};
+
+  const Example: &str = "\
+$TTL 3600
+@            SOA    ns1 hostmaster 1 7200 900 1209600 300
+             NS     ns1
+ns1          A      192.0.2.1
+www          A      192.0.2.2
+a.b.c        TXT    \"deep\"
+*.wild       TXT    \"wild\"
+secure       NS     ns.secure
+             DS     12345 13 2 0123456789abcdef
+ns.secure    A      192.0.2.53
+insecure     NS     ns.abc.com.
+";
+
+  fn policy(denial: Denial) -> SigningPolicy {
+    let origin = DomainName::from("example.com");
+    let keys = vec![
+      SigningKey::generate(
+        origin.clone(),
+        SecurityAlgorithm::EcdsaP256Sha256,
+        SigningKey::KeySigningKey,
+      )
+      .unwrap(),
+      SigningKey::generate(origin, SecurityAlgorithm::Ed25519, SigningKey::ZoneSigningKey).unwrap(),
+    ];
+
+    SigningPolicy::new(keys, denial)
+  }
+
+  fn zone() -> Zone {
+    let origin = DomainName::from("example.com");
+
+    Zone::new(origin.clone(), parse_zone(Example, &origin).unwrap()).unwrap()
+  }
+
+  fn covered(zone: &Zone, name: &str) -> Vec<RecordType> {
+    let mut types: Vec<RecordType> = zone
+      .rrset(&DomainName::from(name), RecordType::Signature)
+      .iter()
+      .filter_map(|record| match record.record_data() {
+        RecordData::Signature { type_covered, .. } => Some(type_covered),
+        _ => None,
+      })
+      .collect();
+    types.sort();
+    types
+  }
+
+  /// Checks every signature in the zone against the DNSKEY it names.
+  fn verify_all(zone: &Zone, policy: &SigningPolicy) -> usize {
+    let mut verified = 0;
+    for record in zone
+      .records()
+      .filter(|record| record.record_type == RecordType::Signature)
+    {
+      let data = record.record_data();
+      let RecordData::Signature {
+        type_covered,
+        key_tag,
+        signature,
+        ..
+      } = &data
+      else {
+        panic!("Expected a signature");
+      };
+      let key = policy.keys.iter().find(|key| key.key_tag() == *key_tag).unwrap();
+      let public_key = match key.algorithm {
+        SecurityAlgorithm::EcdsaP256Sha256 => {
+          let mut point = vec![4];
+          point.extend(key.public_key());
+          UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, point)
+        }
+        _ => UnparsedPublicKey::new(&ED25519, key.public_key()),
+      };
+
+      let rrset = zone.rrset(&record.domain_name, *type_covered);
+      assert!(
+        public_key
+          .verify(&signed_data(&data, &rrset).unwrap(), signature)
+          .is_ok()
+      );
+      verified += 1;
+    }
+
+    verified
+  }
+
+  #[test]
+  fn it_signs_zones_with_an_nsec_chain() {
+    let policy = policy(Denial::Nsec);
+    let signed = sign_zone(&zone(), &policy, 1_000_000).unwrap();
+
+    let mut name = DomainName::from("example.com");
+    let mut chain = vec![];
+    loop {
+      let [nsec] = &signed.rrset(&name, RecordType::NextSecure)[..] else {
+        panic!("Missing NSEC at {:?}", name);
+      };
+      assert_eq!(nsec.time_to_live, 300);
+      let RecordData::NextSecure { next, types } = nsec.record_data() else {
+        panic!("Expected NSEC data");
+      };
+      chain.push((name.0.join("."), types));
+      name = next;
+      if name.eq_ignore_case(&signed.origin) {
+        break;
+      }
+    }
+
+    let names: Vec<&str> = chain.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(
+      names,
+      [
+        "example.com",
+        "a.b.c.example.com",
+        "insecure.example.com",
+        "ns1.example.com",
+        "secure.example.com",
+        "*.wild.example.com",
+        "www.example.com"
+      ]
+    );
+    assert_eq!(
+      chain[4].1,
+      [
+        RecordType::NameServer,
+        RecordType::DelegationSigner,
+        RecordType::Signature,
+        RecordType::NextSecure
+      ]
+    );
+    assert_eq!(
+      chain[2].1,
+      [RecordType::NameServer, RecordType::Signature, RecordType::NextSecure]
+    );
+
+    assert_eq!(signed.rrset(&signed.origin, RecordType::DnsKey).len(), 2);
+    let dnskey_signatures = signed
+      .rrset(&signed.origin, RecordType::Signature)
+      .into_iter()
+      .filter(|record| matches!(record.record_data(), RecordData::Signature { type_covered, key_tag, .. } if type_covered == RecordType::DnsKey && key_tag == policy.keys[0].key_tag()))
+      .count();
+    assert_eq!(dnskey_signatures, 1);
+    assert_eq!(
+      covered(&signed, "secure.example.com"),
+      [RecordType::DelegationSigner, RecordType::NextSecure]
+    );
+    assert_eq!(covered(&signed, "insecure.example.com"), [RecordType::NextSecure]);
+    assert!(covered(&signed, "ns.secure.example.com").is_empty());
+    assert_eq!(verify_all(&signed, &policy), 15);
+  }
+
+  #[test]
+  fn it_signs_zones_with_an_opt_out_nsec3_chain() {
+    let policy = policy(Denial::parse("nsec3:5:aabbccdd:opt-out").unwrap());
+    let signed = sign_zone(&zone(), &policy, 1_000_000).unwrap();
+
+    let chain: Vec<AnswerProperties> = signed
+      .records()
+      .filter(|record| record.record_type == RecordType::NextSecure3)
+      .cloned()
+      .collect();
+    // The apex, ns1, www, a.b.c, b.c, c, *.wild, wild and secure; insecure is opted out.
+    assert_eq!(chain.len(), 9);
+    for record in &chain {
+      assert!(matches!(
+        record.record_data(),
+        RecordData::NextSecure3 {
+          flags: Nsec3OptOut,
+          iterations: 5,
+          ..
+        }
+      ));
+    }
+
+    let salt = [0xaa, 0xbb, 0xcc, 0xdd];
+    let types = |name: &str| {
+      let owner = hashed_name(&DomainName::from(name), &signed.origin, &salt, 5);
+      match signed.rrset(&owner, RecordType::NextSecure3)[0].record_data() {
+        RecordData::NextSecure3 { types, .. } => types,
+        _ => panic!("Expected NSEC3 data"),
+      }
+    };
+    assert_eq!(types("c.example.com"), []);
+    assert_eq!(
+      types("secure.example.com"),
+      [
+        RecordType::NameServer,
+        RecordType::DelegationSigner,
+        RecordType::Signature
+      ]
+    );
+    assert!(types("example.com").contains(&RecordType::NextSecure3Parameters));
+    assert_eq!(signed.rrset(&signed.origin, RecordType::NextSecure3Parameters).len(), 1);
+    assert_eq!(verify_all(&signed, &policy), 18);
+  }
+
+  #[test]
+  fn it_signs_again_with_a_new_serial_before_signatures_expire() {
+    let policy = policy(Denial::Nsec);
+    let original = zone();
+    let signed = sign_zone(&original, &policy, 1_000_000).unwrap();
+
+    assert_eq!(needs_resigning(&original, &policy, 1_000_000), true);
+    assert_eq!(needs_resigning(&signed, &policy, 1_000_000), false);
+    assert_eq!(
+      needs_resigning(&signed, &policy, 1_000_000 + policy.validity - policy.refresh),
+      true
+    );
+
+    let resigned = resign(&signed, &policy, 2_000_000).unwrap();
+    assert_eq!(resigned.serial(), 2);
+    assert_eq!(needs_resigning(&resigned, &policy, 2_000_000), false);
+
+    let change = ZoneChange::between(&unsigned(&signed, &policy), &unsigned(&resigned, &policy));
+    assert_eq!((change.deleted.len(), change.added.len()), (1, 1));
+    assert_eq!(unsigned(&signed, &policy).records().count(), original.records().count());
+  }
+}
@@ -0,0 +1,206 @@
+//! Outgoing zone transfers over TCP. AXFR sends the whole zone bracketed by its SOA record
+//! (RFC 5936 section 2.2); IXFR sends the journal entries a client is missing (RFC 1995 section 4)
+//! and falls back to the AXFR layout when the journal does not reach back to the client's serial.
+//! The records are spread over as many messages as needed, with the question in the first one.
+
+use crate::messages::*;
+use crate::zones::{Zone, serial_newer};
+
+/// Messages are kept well below the 65535 bytes a TCP length prefix allows.
+const MaxTransferMessageSize: usize = 16 * 1024;
+
+/// The messages answering an AXFR or IXFR query for `zone`.
+pub fn transfer(zone: &Zone, request: &MessageProperties) -> Vec<MessageProperties> {
+  let incremental = request
+    .questions
+    .first()
+    .is_some_and(|question| question.record_type == RecordType::IncrementalTransfer);
+
+  let records = match incremental {
+    true => {
+      let Some(serial) = request
+        .authorities
+        .iter()
+        .find_map(|record| match record.record_data() {
+          RecordData::StartOfAuthority { serial, .. } => Some(serial),
+          _ => None,
+        })
+      else {
+        let mut error = message(request, vec![], true);
+        error.set_response_code(ResponseCode::FormatError);
+        return vec![error];
+      };
+
+      incremental_records(zone, serial)
+    }
+    false => full_records(zone),
+  };
+
+  let mut messages = vec![];
+  let mut records = records.into_iter().peekable();
+  while messages.is_empty() || records.peek().is_some() {
+    let mut chunk = vec![];
+    let mut size = message(request, vec![], messages.is_empty()).size_of();
+    while let Some(record) =
+      records.next_if(|record| chunk.is_empty() || size + record.size_of() <= MaxTransferMessageSize)
+    {
+      size += record.size_of();
+      chunk.push(record);
+    }
+
+    messages.push(message(request, chunk, messages.is_empty()));
+  }
+
+  messages
+}
+
+/// The SOA, every other record of the zone and the SOA again.
+pub fn full_records(zone: &Zone) -> Vec<AnswerProperties> {
+  let soa = zone.soa().clone();
+
+  let mut records = vec![soa.clone()];
+  records.extend(
+    zone
+      .records()
+      .filter(|record| record.record_type != RecordType::StartOfAuthority)
+      .cloned(),
+  );
+  records.push(soa);
+  records
+}
+
+/// The records bringing a client at `serial` up to date: only the current SOA when it already is,
+/// the journal entries since its serial, or the whole zone when those are no longer known.
+pub fn incremental_records(zone: &Zone, serial: u32) -> Vec<AnswerProperties> {
+  let soa = zone.soa().clone();
+  if !serial_newer(zone.serial(), serial) {
+    return vec![soa];
+  }
+
+  let Some(changes) = zone.journal.since(serial) else {
+    return full_records(zone);
+  };
+
+  let mut records = vec![soa.clone()];
+  for change in changes {
+    records.extend(change.deleted.iter().cloned());
+    records.extend(change.added.iter().cloned());
+  }
+  records.push(soa);
+  records
+}
+
+fn message(request: &MessageProperties, answers: Vec<AnswerProperties>, first: bool) -> MessageProperties {
+  let questions = match first {
+    true => request.questions.clone(),
+    false => vec![],
+  };
+
+  MessageProperties {
+    header: HeaderProperties {
+      id: request.header.id,
+      flags: Flags::Response | Flags::Authoritative,
+      question_count: questions.len() as u16,
+      answer_record_count: answers.len() as u16,
+      authority_record_count: 0,
+      additional_record_count: 0,
+    },
+    questions,
+    answers,
+    authorities: vec![],
+    additionals: vec![],
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::zones::{Catalog, parse_zone};
+
+  fn zone(serial: u32, body: &str) -> Zone {
+    let origin = DomainName::from("example.com");
+    let text = format!("$TTL 60\n@ SOA ns h {} 1 1 1 1\n@ NS ns\n{}", serial, body);
+
+    Zone::new(origin.clone(), parse_zone(&text, &origin).unwrap()).unwrap()
+  }
+
+  fn request(record_type: RecordType, serial: Option<u32>) -> MessageProperties {
+    let authorities: Vec<AnswerProperties> = serial
+      .map(|serial| zone(serial, "").soa().clone())
+      .into_iter()
+      .collect();
+
+    MessageProperties {
+      header: HeaderProperties {
+        id: 7,
+        flags: Flags::Query,
+        question_count: 1,
+        answer_record_count: 0,
+        authority_record_count: authorities.len() as u16,
+        additional_record_count: 0,
+      },
+      questions: vec![QuestionProperties {
+        domain_name: DomainName::from("example.com"),
+        record_type,
+        record_class: RecordClass::Internet,
+      }],
+      answers: vec![],
+      authorities,
+      additionals: vec![],
+    }
+  }
+
+  fn types(messages: &[MessageProperties]) -> Vec<RecordType> {
+    messages
+      .iter()
+      .flat_map(|message| &message.answers)
+      .map(|record| record.record_type)
+      .collect()
+  }
+
+  #[test]
+  fn it_streams_full_transfers_over_several_messages() {
+    let body: String = (0..2000).map(|index| format!("host{} A 192.0.2.1\n", index)).collect();
+    let zone = zone(1, &body);
+
+    let messages = transfer(&zone, &request(RecordType::ZoneTransfer, None));
+    let types = types(&messages);
+
+    assert!(messages.len() > 1);
+    assert_eq!(messages[0].questions.len(), 1);
+    assert!(messages[1..].iter().all(|message| message.questions.is_empty()));
+    assert!(
+      messages
+        .iter()
+        .all(|message| message.size_of() <= MaxTransferMessageSize)
+    );
+    assert!(messages.iter().all(|message| message.header.is_authoritative()));
+    assert_eq!(types.len(), 2003);
+    assert_eq!(types[0], RecordType::StartOfAuthority);
+    assert_eq!(types[2002], RecordType::StartOfAuthority);
+  }
+
+  #[test]
+  fn it_sends_journal_differences_for_incremental_transfers() {
+    let mut catalog = Catalog::new();
+    catalog.insert(zone(1, "www A 192.0.2.1\n"));
+    catalog.insert(zone(2, "www A 192.0.2.2\n"));
+    let zone = catalog.zone(&DomainName::from("example.com")).unwrap();
+
+    let incremental = transfer(zone, &request(RecordType::IncrementalTransfer, Some(1)));
+    let current = transfer(zone, &request(RecordType::IncrementalTransfer, Some(2)));
+    let unknown = transfer(zone, &request(RecordType::IncrementalTransfer, Some(0)));
+    let malformed = transfer(zone, &request(RecordType::IncrementalTransfer, None));
+
+    let soa = RecordType::StartOfAuthority;
+    assert_eq!(
+      types(&incremental),
+      [soa, soa, RecordType::Host, soa, RecordType::Host, soa]
+    );
+    assert_eq!(incremental[0].answers[2].data, vec![192, 0, 2, 1]);
+    assert_eq!(incremental[0].answers[4].data, vec![192, 0, 2, 2]);
+    assert_eq!(types(&current), [soa]);
+    assert_eq!(types(&unknown).len(), 4);
+    assert_eq!(malformed[0].header.response_code(), ResponseCode::FormatError);
+  }
+}
@@ -0,0 +1,441 @@
+//! Dynamic updates (RFC 2136). The zone section names the zone, the prerequisite section must hold
+//! before anything changes (section 3.2), and the update section adds records, deletes RRsets,
+//! deletes all records of a name or deletes single records (section 3.4). Updates are applied all
+//! or nothing, and the SOA serial is increased whenever the zone changed.
+
+use crate::messages::*;
+use crate::zones::{Zone, serial_newer};
+
+/// The new version of `zone` after the update, `None` when it left the zone as it was, or the
+/// response code rejecting it.
+pub fn apply_update(zone: &Zone, request: &MessageProperties) -> Result<Option<Zone>, u8> {
+  check_prerequisites(zone, &request.answers)?;
+  prescan(zone, &request.authorities)?;
+
+  let original: Vec<AnswerProperties> = zone.records().cloned().collect();
+  let mut records = original.clone();
+  for update in &request.authorities {
+    apply(zone, &mut records, update);
+  }
+  if records == original {
+    return Ok(None);
+  }
+
+  let serial = |records: &[AnswerProperties]| {
+    records.iter().find_map(|record| match record.record_data() {
+      RecordData::StartOfAuthority { serial, .. } => Some(serial),
+      _ => None,
+    })
+  };
+  if serial(&records) == Some(zone.serial()) {
+    let soa = records
+      .iter_mut()
+      .find(|record| record.record_type == RecordType::StartOfAuthority)
+      .ok_or(ResponseCode::ServerFailure)?;
+    if let RecordData::StartOfAuthority {
+      primary,
+      mailbox,
+      serial,
+      refresh,
+      retry,
+      expire,
+      minimum,
+    } = soa.record_data()
+    {
+      soa.data = RecordData::StartOfAuthority {
+        primary,
+        mailbox,
+        serial: serial.wrapping_add(1),
+        refresh,
+        retry,
+        expire,
+        minimum,
+      }
+      .to_bytes();
+    }
+  }
+
+  let mut updated = Zone::new(zone.origin.clone(), records).map_err(|_| ResponseCode::ServerFailure)?;
+  updated.settings = zone.settings.clone();
+  Ok(Some(updated))
+}
+
+/// RFC 2136 section 3.2.
+fn check_prerequisites(zone: &Zone, prerequisites: &[AnswerProperties]) -> Result<(), u8> {
+  let mut expected: Vec<(&DomainName, RecordType, Vec<&[u8]>)> = vec![];
+
+  for prerequisite in prerequisites {
+    let name = &prerequisite.domain_name;
+    let record_type = prerequisite.record_type;
+    if prerequisite.time_to_live != 0 {
+      return Err(ResponseCode::FormatError);
+    }
+    if !name.is_subdomain_of(&zone.origin) {
+      return Err(ResponseCode::NotInZone);
+    }
+
+    match prerequisite.record_class {
+      RecordClass::Any | RecordClass::None if !prerequisite.data.is_empty() => return Err(ResponseCode::FormatError),
+      RecordClass::Any if record_type == RecordType::Any => {
+        if zone.records_at(name).is_empty() {
+          return Err(ResponseCode::NameError);
+        }
+      }
+      RecordClass::Any => {
+        if zone.rrset(name, record_type).is_empty() {
+          return Err(ResponseCode::RecordSetMissing);
+        }
+      }
+      RecordClass::None if record_type == RecordType::Any => {
+        if !zone.records_at(name).is_empty() {
+          return Err(ResponseCode::NameExists);
+        }
+      }
+      RecordClass::None => {
+        if !zone.rrset(name, record_type).is_empty() {
+          return Err(ResponseCode::RecordSetExists);
+        }
+      }
+      RecordClass::Internet => {
+        match expected
+          .iter_mut()
+          .find(|(known, known_type, _)| known.eq_ignore_case(name) && *known_type == record_type)
+        {
+          Some((_, _, data)) => data.push(&prerequisite.data),
+          None => expected.push((name, record_type, vec![&prerequisite.data])),
+        }
+      }
+      _ => return Err(ResponseCode::FormatError),
+    }
+  }
+
+  // Value dependent prerequisites compare whole RRsets (section 3.2.3).
+  for (name, record_type, mut data) in expected {
+    let rrset = zone.rrset(name, record_type);
+    let mut existing: Vec<&[u8]> = rrset.iter().map(|record| record.data.as_slice()).collect();
+    data.sort();
+    data.dedup();
+    existing.sort();
+    if data != existing {
+      return Err(ResponseCode::RecordSetMissing);
+    }
+  }
+
+  Ok(())
+}
+
+/// RFC 2136 section 3.4.1, rejecting the whole update before any of it is applied.
+fn prescan(zone: &Zone, updates: &[AnswerProperties]) -> Result<(), u8> {
+  for update in updates {
+    if !update.domain_name.is_subdomain_of(&zone.origin) {
+      return Err(ResponseCode::NotInZone);
+    }
+
+    let meta = is_meta(update.record_type);
+    let valid = match update.record_class {
+      RecordClass::Internet => !meta && update.record_type != RecordType::Any,
+      RecordClass::Any => {
+        update.time_to_live == 0 && update.data.is_empty() && (!meta || update.record_type == RecordType::Any)
+      }
+      RecordClass::None => update.time_to_live == 0 && !meta && update.record_type != RecordType::Any,
+      _ => false,
+    };
+    if !valid {
+      return Err(ResponseCode::FormatError);
+    }
+  }
+
+  Ok(())
+}
+
+/// Query and pseudo record types, which never appear as zone data.
+fn is_meta(record_type: RecordType) -> bool {
+  record_type == RecordType::Options || (128..=255).contains(&record_type.0)
+}
+
+/// RFC 2136 section 3.4.2.
+fn apply(zone: &Zone, records: &mut Vec<AnswerProperties>, update: &AnswerProperties) {
+  let name = &update.domain_name;
+  let apex = name.eq_ignore_case(&zone.origin);
+  let at_name = |record: &AnswerProperties| record.domain_name.eq_ignore_case(name);
+  let protected = |record_type: RecordType| {
+    apex && (record_type == RecordType::StartOfAuthority || record_type == RecordType::NameServer)
+  };
+
+  match update.record_class {
+    RecordClass::Any if update.record_type == RecordType::Any => {
+      records.retain(|record| !at_name(record) || protected(record.record_type));
+    }
+    RecordClass::Any => {
+      if !protected(update.record_type) {
+        records.retain(|record| !(at_name(record) && record.record_type == update.record_type));
+      }
+    }
+    RecordClass::None => {
+      if update.record_type == RecordType::StartOfAuthority {
+        return;
+      }
+      let servers = records
+        .iter()
+        .filter(|record| at_name(record) && record.record_type == RecordType::NameServer)
+        .count();
+      if apex && update.record_type == RecordType::NameServer && servers <= 1 {
+        return;
+      }
+
+      records
+        .retain(|record| !(at_name(record) && record.record_type == update.record_type && record.data == update.data));
+    }
+    _ => add(zone, records, update),
+  }
+}
+
+fn add(zone: &Zone, records: &mut Vec<AnswerProperties>, update: &AnswerProperties) {
+  let name = &update.domain_name;
+  let at_name = |record: &AnswerProperties| record.domain_name.eq_ignore_case(name);
+
+  match update.record_type {
+    RecordType::StartOfAuthority => {
+      let newer = match update.record_data() {
+        RecordData::StartOfAuthority { serial, .. } => serial_newer(serial, zone.serial()),
+        _ => false,
+      };
+      if name.eq_ignore_case(&zone.origin) && newer {
+        records.retain(|record| record.record_type != RecordType::StartOfAuthority);
+        records.push(update.clone());
+      }
+      return;
+    }
+    RecordType::CanonicalName => {
+      if records
+        .iter()
+        .any(|record| at_name(record) && record.record_type != RecordType::CanonicalName)
+      {
+        return;
+      }
+      records.retain(|record| !(at_name(record) && record.record_type == RecordType::CanonicalName));
+    }
+    _ => {
+      if records
+        .iter()
+        .any(|record| at_name(record) && record.record_type == RecordType::CanonicalName)
+      {
+        return;
+      }
+    }
+  }
+
+  // A record already present only takes the TTL of the update.
+  match records
+    .iter_mut()
+    .find(|record| at_name(record) && record.record_type == update.record_type && record.data == update.data)
+  {
+    Some(existing) => existing.time_to_live = update.time_to_live,
+    None => records.push(update.clone()),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::zones::parse_zone;
+
+  const Example: &str = "\
+$TTL 60
+@    SOA    ns h 10 1 1 1 1
+     NS     ns
+ns   A      192.0.2.53
+www  A      192.0.2.1
+     A      192.0.2.2
+ftp  CNAME  www
+";
+
+  fn zone() -> Zone {
+    let origin = DomainName::from("example.com");
+
+    Zone::new(origin.clone(), parse_zone(Example, &origin).unwrap()).unwrap()
+  }
+
+  fn record(name: &str, class: RecordClass, record_type: RecordType, ttl: u32, data: &[u8]) -> AnswerProperties {
+    AnswerProperties {
+      domain_name: DomainName::from(name),
+      record_type,
+      record_class: class,
+      time_to_live: ttl,
+      data: data.to_vec(),
+    }
+  }
+
+  fn update(prerequisites: Vec<AnswerProperties>, updates: Vec<AnswerProperties>) -> MessageProperties {
+    MessageProperties {
+      header: HeaderProperties {
+        id: 1,
+        flags: Flags::from_operation_code(OperationCode::Update),
+        question_count: 1,
+        answer_record_count: prerequisites.len() as u16,
+        authority_record_count: updates.len() as u16,
+        additional_record_count: 0,
+      },
+      questions: vec![QuestionProperties {
+        domain_name: DomainName::from("example.com"),
+        record_type: RecordType::StartOfAuthority,
+        record_class: RecordClass::Internet,
+      }],
+      answers: prerequisites,
+      authorities: updates,
+      additionals: vec![],
+    }
+  }
+
+  fn data(zone: &Zone, name: &str, record_type: RecordType) -> Vec<Vec<u8>> {
+    let mut data: Vec<Vec<u8>> = zone
+      .rrset(&DomainName::from(name), record_type)
+      .into_iter()
+      .map(|record| record.data)
+      .collect();
+    data.sort();
+    data
+  }
+
+  #[test]
+  fn it_checks_prerequisites_before_changing_anything() {
+    let zone = zone();
+    let add = record(
+      "new.example.com",
+      RecordClass::Internet,
+      RecordType::Host,
+      60,
+      &[192, 0, 2, 9],
+    );
+    let check = |prerequisite: AnswerProperties| apply_update(&zone, &update(vec![prerequisite], vec![add.clone()]));
+
+    let www = "www.example.com";
+    let (any, none, internet) = (RecordClass::Any, RecordClass::None, RecordClass::Internet);
+    assert!(check(record(www, any, RecordType::Host, 0, &[])).is_ok());
+    assert_eq!(
+      check(record(www, any, RecordType::Text, 0, &[])).unwrap_err(),
+      ResponseCode::RecordSetMissing
+    );
+    assert_eq!(
+      check(record("nope.example.com", any, RecordType::Any, 0, &[])).unwrap_err(),
+      ResponseCode::NameError
+    );
+    assert_eq!(
+      check(record(www, none, RecordType::Any, 0, &[])).unwrap_err(),
+      ResponseCode::NameExists
+    );
+    assert_eq!(
+      check(record(www, none, RecordType::Host, 0, &[])).unwrap_err(),
+      ResponseCode::RecordSetExists
+    );
+    assert!(check(record(www, internet, RecordType::Host, 0, &[192, 0, 2, 2])).is_err());
+    assert!(check(record(www, internet, RecordType::Host, 0, &[192, 0, 2, 1])).is_err());
+    assert_eq!(
+      check(record(www, any, RecordType::Host, 60, &[])).unwrap_err(),
+      ResponseCode::FormatError
+    );
+    assert_eq!(
+      check(record("www.abc.com", any, RecordType::Host, 0, &[])).unwrap_err(),
+      ResponseCode::NotInZone
+    );
+
+    let both = update(
+      vec![
+        record(www, internet, RecordType::Host, 0, &[192, 0, 2, 2]),
+        record(www, internet, RecordType::Host, 0, &[192, 0, 2, 1]),
+      ],
+      vec![add],
+    );
+    assert!(apply_update(&zone, &both).unwrap().is_some());
+  }
+
+  #[test]
+  fn it_adds_and_deletes_records_with_a_serial_bump() {
+    let zone = zone();
+    let request = update(
+      vec![],
+      vec![
+        record(
+          "new.example.com",
+          RecordClass::Internet,
+          RecordType::Host,
+          60,
+          &[192, 0, 2, 9],
+        ),
+        record(
+          "www.example.com",
+          RecordClass::None,
+          RecordType::Host,
+          0,
+          &[192, 0, 2, 1],
+        ),
+        record("ftp.example.com", RecordClass::Any, RecordType::Any, 0, &[]),
+        record("example.com", RecordClass::Any, RecordType::NameServer, 0, &[]),
+        record(
+          "ftp.example.com",
+          RecordClass::Internet,
+          RecordType::Text,
+          60,
+          b"\x02hi",
+        ),
+        record(
+          "www.example.com",
+          RecordClass::Internet,
+          RecordType::CanonicalName,
+          60,
+          &[0],
+        ),
+      ],
+    );
+
+    let updated = apply_update(&zone, &request).unwrap().unwrap();
+
+    assert_eq!(updated.serial(), 11);
+    assert_eq!(
+      data(&updated, "new.example.com", RecordType::Host),
+      [vec![192, 0, 2, 9]]
+    );
+    assert_eq!(
+      data(&updated, "www.example.com", RecordType::Host),
+      [vec![192, 0, 2, 2]]
+    );
+    assert!(data(&updated, "www.example.com", RecordType::CanonicalName).is_empty());
+    assert!(data(&updated, "ftp.example.com", RecordType::CanonicalName).is_empty());
+    assert_eq!(data(&updated, "ftp.example.com", RecordType::Text).len(), 1);
+    assert_eq!(data(&updated, "example.com", RecordType::NameServer).len(), 1);
+  }
+
+  #[test]
+  fn it_leaves_unchanged_zones_and_rejects_meta_types() {
+    let zone = zone();
+    let existing = record(
+      "www.example.com",
+      RecordClass::Internet,
+      RecordType::Host,
+      60,
+      &[192, 0, 2, 1],
+    );
+    let last_server = record(
+      "example.com",
+      RecordClass::None,
+      RecordType::NameServer,
+      0,
+      &zone.rrset(&zone.origin, RecordType::NameServer)[0].data,
+    );
+    let old_soa = AnswerProperties {
+      record_class: RecordClass::Internet,
+      ..zone.soa().clone()
+    };
+    let meta = record("www.example.com", RecordClass::Internet, RecordType::Any, 60, &[]);
+
+    assert!(
+      apply_update(&zone, &update(vec![], vec![existing, last_server, old_soa]))
+        .unwrap()
+        .is_none()
+    );
+    assert_eq!(
+      apply_update(&zone, &update(vec![], vec![meta])).unwrap_err(),
+      ResponseCode::FormatError
+    );
+  }
+}
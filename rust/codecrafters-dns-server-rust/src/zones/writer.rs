@@ -0,0 +1,110 @@
+//! Master file output (RFC 1035 section 5.1), the inverse of the parser. Every record takes one
+//! line with its owner written out in full, so the text reads back into the same records whatever
+//! origin it is parsed with. Data the record model does not know is written in the RFC 3597
+//! generic `\# <length> <hex>` format. The fields themselves are the presentation format the
+//! messages display in.
+
+use std::cmp::Ordering;
+
+use crate::dnssec::canonical_cmp;
+use crate::messages::*;
+use crate::zones::Zone;
+
+/// One master file line: owner, TTL, class, type and data.
+pub fn format_record(record: &AnswerProperties) -> String {
+  format!(
+    "{}\t{}\t{}\t{}\t{}",
+    record.domain_name,
+    record.time_to_live,
+    record.record_class,
+    record.record_type,
+    record.record_data()
+  )
+}
+
+/// The zone as a master file: the SOA record first, then the names in canonical order with each
+/// RRset followed by its signatures.
+pub fn write_zone(zone: &Zone) -> String {
+  let mut records: Vec<&AnswerProperties> = zone.records().collect();
+  records.sort_by(|first, second| compare_records(zone, first, second));
+
+  let mut text = format!("$ORIGIN {}\n", zone.origin);
+  for record in records {
+    text += &format_record(record);
+    text.push('\n');
+  }
+
+  text
+}
+
+fn compare_records(zone: &Zone, first: &AnswerProperties, second: &AnswerProperties) -> Ordering {
+  let is_soa = |record: &AnswerProperties| {
+    record.record_type == RecordType::StartOfAuthority && record.domain_name.eq_ignore_case(&zone.origin)
+  };
+  // Signatures sort right after the RRset they cover.
+  let position = |record: &AnswerProperties| match record.record_data() {
+    RecordData::Signature { type_covered, .. } => (type_covered, true),
+    _ => (record.record_type, false),
+  };
+
+  is_soa(second)
+    .cmp(&is_soa(first))
+    .then_with(|| canonical_cmp(&first.domain_name, &second.domain_name))
+    .then_with(|| position(first).cmp(&position(second)))
+    .then_with(|| first.data.cmp(&second.data))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::dnssec::*;
+  use crate::zones::*;
+
+  #[test]
+  fn it_writes_text_the_parser_reads() {
+    let name = DomainName(vec!["a.b".to_string(), "semi;colon\\".to_string(), "com".to_string()]);
+    let string = b"say \"hi\"\x07";
+
+    assert_eq!(parse_name(&name.to_string(), &DomainName::root()), Ok(name));
+    assert_eq!(
+      parse_character_string(format_character_string(string).trim_matches('"')),
+      Ok(string.to_vec())
+    );
+    assert_eq!(parse_timestamp(&format_timestamp(4_000_000_000)), Ok(4_000_000_000));
+  }
+
+  #[test]
+  fn it_writes_zones_that_parse_back() {
+    let origin = DomainName::from("example.com");
+    let records = parse_zone(
+      "$TTL 3600
+@ SOA ns1 hostmaster 1 7200 3600 1209600 300
+@ NS ns1
+ns1 A 192.0.2.1
+ns1 AAAA 2001:db8::1
+@ MX 10 mail
+@ TXT \"v=spf1 -all\" \"second; string\"
+_sip._tcp SRV 0 5 5060 sip
+sub NS ns.sub
+ns.sub A 192.0.2.2
+sub DS 12345 13 2 49FD46E6C4B45C55D4AC49FD46E6C4B45C55D4AC49FD46E6C4B45C55D4ACAABB
+@ CAA 0 issue \"ca.example\"
+odd TYPE65280 \\# 3 010203
+",
+      &origin,
+    )
+    .unwrap();
+    let zone = Zone::new(origin.clone(), records).unwrap();
+    let key = SigningKey::generate(origin.clone(), SecurityAlgorithm::Ed25519, SigningKey::KeySigningKey).unwrap();
+    let policy = SigningPolicy::new(vec![key], Denial::parse("nsec3:1:abcd").unwrap());
+    let signed = sign_zone(&zone, &policy, timestamp_now()).unwrap();
+
+    let text = write_zone(&signed);
+    let parsed = parse_zone(&text, &DomainName::from("elsewhere")).unwrap();
+    let mut written: Vec<AnswerProperties> = signed.records().cloned().collect();
+    written.sort_by(|first, second| compare_records(&signed, first, second));
+
+    assert_eq!(text.lines().nth(1).unwrap().split('\t').nth(3), Some("SOA"));
+    assert_eq!(parsed, written);
+  }
+}
@@ -0,0 +1,593 @@
+//! A loaded zone answering queries authoritatively following RFC 1034 section 4.3.2, with
+//! negative answers carrying the SOA as described in RFC 2308.
+
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use crate::messages::*;
+use crate::resolver::MaxCanonicalNames;
+use crate::tsig::TsigKey;
+use crate::zones::{AccessList, Journal, SigningPolicy};
+
+#[derive(Clone, Debug, Default)]
+pub struct Lookup {
+  pub response_code: u8,
+  pub authoritative: bool,
+  pub answers: Vec<AnswerProperties>,
+  pub authorities: Vec<AnswerProperties>,
+  pub additionals: Vec<AnswerProperties>,
+}
+
+impl Lookup {
+  pub fn refused() -> Self {
+    Self {
+      response_code: ResponseCode::Refused,
+      ..Default::default()
+    }
+  }
+}
+
+/// Per zone configuration given on the command line.
+#[derive(Clone, Debug, Default)]
+pub struct ZoneSettings {
+  /// Clients allowed to transfer the zone with AXFR or IXFR.
+  pub allow_transfer: AccessList,
+  /// Secondaries notified of new serials besides the hosts in the apex NS records.
+  pub also_notify: Vec<SocketAddr>,
+  /// Clients allowed to change the zone with UPDATE.
+  pub allow_update: AccessList,
+  /// Where changes made by UPDATE are appended, for zones loaded from a master file.
+  pub journal_file: Option<PathBuf>,
+  /// Signs the NOTIFY messages sent for the zone and, for secondary zones, the requests to the
+  /// primary, whose NOTIFY messages must then be signed with it too.
+  pub key: Option<TsigKey>,
+  /// Keys and denial method the zone is signed with online.
+  pub signing: Option<SigningPolicy>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Zone {
+  pub origin: DomainName,
+  pub settings: ZoneSettings,
+  pub journal: Journal,
+  /// Records keyed by their lowercased owner name.
+  nodes: HashMap<DomainName, Vec<AnswerProperties>>,
+  /// Owner names together with the empty non-terminals between them and the origin.
+  names: HashSet<DomainName>,
+}
+
+impl Zone {
+  pub fn new(origin: DomainName, records: Vec<AnswerProperties>) -> Result<Self, String> {
+    let mut zone = Self {
+      origin,
+      settings: ZoneSettings::default(),
+      journal: Journal::default(),
+      nodes: HashMap::new(),
+      names: HashSet::new(),
+    };
+
+    for record in records {
+      if !record.domain_name.is_subdomain_of(&zone.origin) {
+        return Err(format!("{} is outside of zone {}", record.domain_name, zone.origin));
+      }
+
+      let mut name = record.domain_name.to_lowercase();
+      zone.nodes.entry(name.clone()).or_default().push(record);
+      while zone.names.insert(name.clone()) && name.label_count() > zone.origin.label_count() {
+        name = name.parent().unwrap();
+      }
+    }
+
+    let soa_count = zone.rrset(&zone.origin, RecordType::StartOfAuthority).len();
+    if soa_count != 1 {
+      return Err(format!(
+        "Zone {} needs exactly one SOA record at its apex, found {}",
+        zone.origin, soa_count
+      ));
+    }
+
+    Ok(zone)
+  }
+
+  pub fn soa(&self) -> &AnswerProperties {
+    self
+      .records_at(&self.origin)
+      .iter()
+      .find(|record| record.record_type == RecordType::StartOfAuthority)
+      .expect("Zones are created with an SOA record")
+  }
+
+  pub fn serial(&self) -> u32 {
+    match self.soa().record_data() {
+      RecordData::StartOfAuthority { serial, .. } => serial,
+      _ => 0,
+    }
+  }
+
+  pub fn records(&self) -> impl Iterator<Item = &AnswerProperties> {
+    self.nodes.values().flatten()
+  }
+
+  pub fn records_at(&self, name: &DomainName) -> &[AnswerProperties] {
+    self
+      .nodes
+      .get(&name.to_lowercase())
+      .map(Vec::as_slice)
+      .unwrap_or_default()
+  }
+
+  /// Lowercased owner names and empty non-terminals.
+  pub fn names(&self) -> impl Iterator<Item = &DomainName> {
+    self.names.iter()
+  }
+
+  /// Whether `name` owns records or is an empty non-terminal.
+  pub fn contains(&self, name: &DomainName) -> bool {
+    self.names.contains(&name.to_lowercase())
+  }
+
+  pub fn rrset(&self, name: &DomainName, record_type: RecordType) -> Vec<AnswerProperties> {
+    self
+      .records_at(name)
+      .iter()
+      .filter(|record| record.record_type == record_type)
+      .cloned()
+      .collect()
+  }
+
+  pub fn lookup(&self, name: &DomainName, record_type: RecordType) -> Lookup {
+    let mut lookup = Lookup {
+      authoritative: true,
+      ..Default::default()
+    };
+    let mut name = name.clone();
+
+    for _ in 0..=MaxCanonicalNames {
+      let cut = self.delegation(&name);
+      if let Some(redirection) = self.redirection(&name)
+        && cut
+          .as_ref()
+          .is_none_or(|cut| redirection.domain_name.label_count() < cut.label_count())
+      {
+        let Some(target) = self.redirect(&mut lookup, &name, redirection) else {
+          return lookup;
+        };
+        name = target;
+        continue;
+      }
+
+      // DS records live on the parent side of a cut, so they are answered rather than referred.
+      if let Some(cut) = cut
+        && !(cut.eq_ignore_case(&name) && record_type == RecordType::DelegationSigner)
+      {
+        return self.referral(lookup, &cut);
+      }
+
+      let records = match self.contains(&name) {
+        true => self.records_at(&name).to_vec(),
+        false => match self.synthesize(&name) {
+          Some(records) => records,
+          None => return self.negative(lookup, ResponseCode::NameError),
+        },
+      };
+      let alias = records
+        .iter()
+        .find(|record| record.record_type == RecordType::CanonicalName);
+      if let Some(alias) = alias.filter(|_| record_type != RecordType::CanonicalName && record_type != RecordType::Any)
+      {
+        lookup.answers.push(alias.clone());
+
+        let RecordData::CanonicalName(target) = alias.record_data() else {
+          return lookup;
+        };
+        // Targets in other zones are left for the client to chase.
+        if !target.is_subdomain_of(&self.origin) {
+          return lookup;
+        }
+        name = target;
+        continue;
+      }
+
+      let matching: Vec<AnswerProperties> = records
+        .iter()
+        .filter(|record| record_type == RecordType::Any || record.record_type == record_type)
+        .cloned()
+        .collect();
+      if matching.is_empty() {
+        return self.negative(lookup, ResponseCode::NoError);
+      }
+
+      lookup.answers.extend(matching);
+      return lookup;
+    }
+
+    lookup.response_code = ResponseCode::ServerFailure;
+    lookup
+  }
+
+  /// Records of the wildcard at the closest encloser of a name missing from the zone, owned by that
+  /// name (RFC 4592 section 3.3). Existing names, empty non-terminals included, are never matched.
+  fn synthesize(&self, name: &DomainName) -> Option<Vec<AnswerProperties>> {
+    let closest_encloser = (self.origin.label_count()..name.label_count())
+      .rev()
+      .map(|count| name.suffix(count))
+      .find(|ancestor| self.contains(ancestor))?;
+
+    let mut source = closest_encloser;
+    source.0.insert(0, "*".to_string());
+    let records = self.records_at(&source);
+    if records.is_empty() {
+      return None;
+    }
+
+    let synthesized = records
+      .iter()
+      .map(|record| AnswerProperties {
+        domain_name: name.clone(),
+        ..record.clone()
+      })
+      .collect();
+
+    Some(synthesized)
+  }
+
+  /// The topmost DNAME owned by a proper ancestor of `name` (RFC 6672 section 2.3).
+  fn redirection(&self, name: &DomainName) -> Option<&AnswerProperties> {
+    (self.origin.label_count()..name.label_count())
+      .map(|count| name.suffix(count))
+      .find_map(|ancestor| {
+        self
+          .records_at(&ancestor)
+          .iter()
+          .find(|record| record.record_type == RecordType::DelegationName)
+      })
+  }
+
+  /// Answers with the DNAME and the CNAME it synthesizes for `name`, returning the new target when
+  /// the lookup continues inside this zone.
+  fn redirect(&self, lookup: &mut Lookup, name: &DomainName, redirection: &AnswerProperties) -> Option<DomainName> {
+    lookup.answers.push(redirection.clone());
+    let RecordData::DelegationName(target) = redirection.record_data() else {
+      return None;
+    };
+
+    let target = name.replace_suffix(&redirection.domain_name, &target);
+    if target.size_of() > DomainName::MaxLength {
+      lookup.response_code = ResponseCode::NameExists;
+      return None;
+    }
+
+    lookup.answers.push(AnswerProperties {
+      domain_name: name.clone(),
+      record_type: RecordType::CanonicalName,
+      record_class: redirection.record_class,
+      time_to_live: redirection.time_to_live,
+      data: target.to_bytes(),
+    });
+
+    target.is_subdomain_of(&self.origin).then_some(target)
+  }
+
+  /// The topmost zone cut at or above `name`, i.e. a name below the origin owning NS records.
+  pub fn delegation(&self, name: &DomainName) -> Option<DomainName> {
+    (self.origin.label_count() + 1..=name.label_count())
+      .map(|count| name.suffix(count))
+      .find(|ancestor| {
+        self
+          .records_at(ancestor)
+          .iter()
+          .any(|record| record.record_type == RecordType::NameServer)
+      })
+  }
+
+  /// Refers the client to the servers of a delegated child zone, with the addresses of the servers
+  /// named inside this zone as glue. Only an answer already gathered from aliases is authoritative.
+  fn referral(&self, mut lookup: Lookup, cut: &DomainName) -> Lookup {
+    let servers = self.rrset(cut, RecordType::NameServer);
+
+    let mut targets: Vec<DomainName> = vec![];
+    for server in &servers {
+      if let RecordData::NameServer(target) = server.record_data()
+        && target.is_subdomain_of(&self.origin)
+        && !targets.iter().any(|known| known.eq_ignore_case(&target))
+      {
+        targets.push(target);
+      }
+    }
+    for target in targets {
+      lookup.additionals.extend(self.rrset(&target, RecordType::Host));
+      lookup.additionals.extend(self.rrset(&target, RecordType::Ipv6Host));
+    }
+
+    lookup.authoritative = !lookup.answers.is_empty();
+    lookup.authorities.extend(servers);
+    lookup
+  }
+
+  /// NXDOMAIN or NODATA with the SOA in the authority section, its TTL capped by the SOA minimum
+  /// as the negative caching TTL (RFC 2308 section 3).
+  fn negative(&self, mut lookup: Lookup, response_code: u8) -> Lookup {
+    let mut soa = self.soa().clone();
+    if let RecordData::StartOfAuthority { minimum, .. } = soa.record_data() {
+      soa.time_to_live = soa.time_to_live.min(minimum);
+    }
+
+    lookup.response_code = response_code;
+    lookup.authorities.push(soa);
+    lookup
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::zones::parse_zone;
+
+  const Example: &str = "\
+$TTL 3600
+@            SOA    ns1 hostmaster 1 7200 900 1209600 300
+             NS     ns1
+ns1          A      192.0.2.1
+www          CNAME  web
+web          A      192.0.2.2
+             AAAA   2001:db8::2
+loop1        CNAME  loop2
+loop2        CNAME  loop1
+outside      CNAME  www.abc.com.
+dangling     CNAME  missing
+a.b.c        TXT    \"deep\"
+child        NS     ns.child
+             NS     ns.abc.com.
+             DS     12345 13 2 0123456789abcdef
+ns.child     A      192.0.2.53
+             AAAA   2001:db8::53
+unsigned     NS     ns.abc.com.
+";
+
+  fn zone() -> Zone {
+    let origin = DomainName::from("example.com");
+
+    Zone::new(origin.clone(), parse_zone(Example, &origin).unwrap()).unwrap()
+  }
+
+  fn names(records: &[AnswerProperties]) -> Vec<(String, RecordType)> {
+    records
+      .iter()
+      .map(|record| (record.domain_name.0.join("."), record.record_type))
+      .collect()
+  }
+
+  #[test]
+  fn it_answers_exact_matches_case_insensitively() {
+    let lookup = zone().lookup(&DomainName::from("WEB.Example.com"), RecordType::Ipv6Host);
+
+    assert_eq!(lookup.response_code, ResponseCode::NoError);
+    assert_eq!(lookup.authoritative, true);
+    assert_eq!(
+      names(&lookup.answers),
+      [("web.example.com".into(), RecordType::Ipv6Host)]
+    );
+    assert!(lookup.authorities.is_empty());
+  }
+
+  #[test]
+  fn it_follows_canonical_names_within_the_zone() {
+    let zone = zone();
+
+    let chained = zone.lookup(&DomainName::from("www.example.com"), RecordType::Host);
+    let outside = zone.lookup(&DomainName::from("outside.example.com"), RecordType::Host);
+    let dangling = zone.lookup(&DomainName::from("dangling.example.com"), RecordType::Host);
+    let alias = zone.lookup(&DomainName::from("www.example.com"), RecordType::CanonicalName);
+
+    assert_eq!(
+      names(&chained.answers),
+      [
+        ("www.example.com".into(), RecordType::CanonicalName),
+        ("web.example.com".into(), RecordType::Host),
+      ]
+    );
+    assert_eq!(
+      names(&outside.answers),
+      [("outside.example.com".into(), RecordType::CanonicalName)]
+    );
+    assert_eq!(dangling.response_code, ResponseCode::NameError);
+    assert_eq!(dangling.answers.len(), 1);
+    assert_eq!(alias.answers.len(), 1);
+  }
+
+  #[test]
+  fn it_answers_nodata_and_nxdomain_with_the_soa() {
+    let zone = zone();
+
+    let nodata = zone.lookup(&DomainName::from("web.example.com"), RecordType::MailExchange);
+    let empty_non_terminal = zone.lookup(&DomainName::from("b.c.example.com"), RecordType::Host);
+    let nxdomain = zone.lookup(&DomainName::from("nope.example.com"), RecordType::Host);
+
+    assert_eq!(nodata.response_code, ResponseCode::NoError);
+    assert_eq!(empty_non_terminal.response_code, ResponseCode::NoError);
+    assert_eq!(nxdomain.response_code, ResponseCode::NameError);
+    for lookup in [&nodata, &empty_non_terminal, &nxdomain] {
+      assert!(lookup.answers.is_empty());
+      assert_eq!(
+        names(&lookup.authorities),
+        [("example.com".into(), RecordType::StartOfAuthority)]
+      );
+      assert_eq!(lookup.authorities[0].time_to_live, 300);
+    }
+  }
+
+  #[test]
+  fn it_refers_to_delegated_children_with_glue() {
+    let zone = zone();
+
+    let below = zone.lookup(&DomainName::from("www.child.example.com"), RecordType::Host);
+    let cut = zone.lookup(&DomainName::from("child.example.com"), RecordType::NameServer);
+    let glue_name = zone.lookup(&DomainName::from("ns.child.example.com"), RecordType::Host);
+
+    for referral in [&below, &cut, &glue_name] {
+      assert_eq!(referral.response_code, ResponseCode::NoError);
+      assert_eq!(referral.authoritative, false);
+      assert!(referral.answers.is_empty());
+      assert_eq!(
+        names(&referral.authorities),
+        [
+          ("child.example.com".into(), RecordType::NameServer),
+          ("child.example.com".into(), RecordType::NameServer),
+        ]
+      );
+      assert_eq!(
+        names(&referral.additionals),
+        [
+          ("ns.child.example.com".into(), RecordType::Host),
+          ("ns.child.example.com".into(), RecordType::Ipv6Host),
+        ]
+      );
+    }
+  }
+
+  #[test]
+  fn it_answers_delegation_signers_from_the_parent_side() {
+    let zone = zone();
+
+    let signer = zone.lookup(&DomainName::from("child.example.com"), RecordType::DelegationSigner);
+    let unsigned = zone.lookup(&DomainName::from("unsigned.example.com"), RecordType::DelegationSigner);
+
+    assert_eq!(signer.authoritative, true);
+    assert_eq!(
+      names(&signer.answers),
+      [("child.example.com".into(), RecordType::DelegationSigner)]
+    );
+    assert_eq!(unsigned.authoritative, true);
+    assert_eq!(unsigned.response_code, ResponseCode::NoError);
+    assert_eq!(
+      names(&unsigned.authorities),
+      [("example.com".into(), RecordType::StartOfAuthority)]
+    );
+  }
+
+  #[test]
+  fn it_synthesizes_answers_from_wildcards() {
+    // The example zone of RFC 4592 section 2.2.1.
+    let origin = DomainName::from("example");
+    let text = "\
+$TTL 3600
+@                  SOA    ns1 hostmaster 1 7200 900 1209600 300
+                   NS     ns1
+*                  TXT    \"this is a wildcard\"
+*                  MX     10 host1
+host1              A      192.0.2.1
+_ssh._tcp.host1    SRV    0 0 22 host1
+_ssh._tcp.host2    SRV    0 0 22 host2
+subdel             NS     ns.abc.com.
+sub.*              MX     10 host1
+alias.wild         CNAME  host1
+*.alias            CNAME  host1
+";
+    let zone = Zone::new(origin.clone(), parse_zone(text, &origin).unwrap()).unwrap();
+    let lookup = |name: &str, record_type| zone.lookup(&DomainName::from(name), record_type);
+
+    let host3 = lookup("host3.example", RecordType::MailExchange);
+    let deep = lookup("foo.bar.example", RecordType::Text);
+    let nodata = lookup("host3.example", RecordType::Host);
+    let alias = lookup("x.alias.example", RecordType::Host);
+
+    assert_eq!(
+      names(&host3.answers),
+      [("host3.example".into(), RecordType::MailExchange)]
+    );
+    assert_eq!(names(&deep.answers), [("foo.bar.example".into(), RecordType::Text)]);
+    assert_eq!(nodata.response_code, ResponseCode::NoError);
+    assert!(nodata.answers.is_empty());
+    assert_eq!(
+      names(&alias.answers),
+      [
+        ("x.alias.example".into(), RecordType::CanonicalName),
+        ("host1.example".into(), RecordType::Host),
+      ]
+    );
+
+    // Existing names, empty non-terminals, names below other nodes and cuts are not matched.
+    assert!(lookup("host1.example", RecordType::MailExchange).answers.is_empty());
+    assert_eq!(
+      lookup("_tcp.host1.example", RecordType::MailExchange).response_code,
+      ResponseCode::NoError
+    );
+    assert!(
+      lookup("_tcp.host1.example", RecordType::MailExchange)
+        .answers
+        .is_empty()
+    );
+    assert_eq!(
+      lookup("_telnet._tcp.host1.example", RecordType::Text).response_code,
+      ResponseCode::NameError
+    );
+    assert_eq!(
+      lookup("ghost.*.example", RecordType::MailExchange).response_code,
+      ResponseCode::NameError
+    );
+    assert_eq!(lookup("host.subdel.example", RecordType::Host).authoritative, false);
+    assert_eq!(
+      names(&lookup("_ssh._tcp.host3.example", RecordType::Service).authorities),
+      [("example".into(), RecordType::StartOfAuthority)]
+    );
+  }
+
+  #[test]
+  fn it_synthesizes_canonical_names_from_delegation_names() {
+    let origin = DomainName::from("example.com");
+    let text = format!(
+      "$TTL 60\n@ SOA ns1 h 1 1 1 1 1\nold DNAME new\nwww.new A 192.0.2.1\nout DNAME abc.com.\nlong DNAME {}\n",
+      ["a".repeat(63), "b".repeat(63), "c".repeat(63), "d".repeat(40)].join(".")
+    );
+    let zone = Zone::new(origin.clone(), parse_zone(&text, &origin).unwrap()).unwrap();
+
+    let inside = zone.lookup(&DomainName::from("www.old.example.com"), RecordType::Host);
+    let outside = zone.lookup(&DomainName::from("www.out.example.com"), RecordType::Host);
+    let owner = zone.lookup(&DomainName::from("old.example.com"), RecordType::DelegationName);
+    let too_long = zone.lookup(
+      &DomainName::from(&*format!("{}.long.example.com", "x".repeat(20))),
+      RecordType::Host,
+    );
+
+    assert_eq!(
+      names(&inside.answers),
+      [
+        ("old.example.com".into(), RecordType::DelegationName),
+        ("www.old.example.com".into(), RecordType::CanonicalName),
+        ("www.new.example.com".into(), RecordType::Host),
+      ]
+    );
+    assert_eq!(
+      outside.answers[1].record_data(),
+      RecordData::CanonicalName(DomainName::from("www.abc.com"))
+    );
+    assert_eq!(outside.answers.len(), 2);
+    assert_eq!(
+      names(&owner.answers),
+      [("old.example.com".into(), RecordType::DelegationName)]
+    );
+    assert_eq!(too_long.response_code, ResponseCode::NameExists);
+  }
+
+  #[test]
+  fn it_stops_canonical_name_loops() {
+    let lookup = zone().lookup(&DomainName::from("loop1.example.com"), RecordType::Host);
+
+    assert_eq!(lookup.response_code, ResponseCode::ServerFailure);
+  }
+
+  #[test]
+  fn it_requires_an_soa_and_in_zone_records() {
+    let origin = DomainName::from("example.com");
+
+    let missing_soa = Zone::new(origin.clone(), parse_zone("$TTL 1\n@ NS ns1\n", &origin).unwrap());
+    let outside = Zone::new(
+      origin.clone(),
+      parse_zone("$TTL 1\n@ SOA ns1 h 1 1 1 1 1\nabc.com. A 192.0.2.1\n", &origin).unwrap(),
+    );
+
+    assert!(missing_soa.is_err());
+    assert!(outside.is_err());
+  }
+}